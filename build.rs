@@ -179,6 +179,7 @@ fn make_opcode(os: &mut File, lines: &[String]) {
 // This file is automatically generated at build time. DO NOT EDIT DIRECTLY.
 //
 use crate::{{leb128::*, BrTableVec, WasmBlockType, WasmCompileErrorKind, WasmMemArg}};
+use crate::_prelude_::*;
 use core::fmt;
 
 /// WebAssembly Opcode
@@ -305,6 +306,64 @@ impl WasmOpcode {{
         }}
     }}
 
+    /// Writes the bytes consumed by [`WasmOpcode::fetch`]
+    pub fn encode(&self, output: &mut Vec<u8>) -> Result<(), WriteError> {{
+        let mut writer = Leb128Writer::new();
+        match self {{
+"
+    )
+    .unwrap();
+
+    for binary in opcode_order.iter() {
+        let id = b2id.get(binary).unwrap();
+        let opcode: &Opcode = opcodes.get(id).unwrap();
+
+        let args = (1..=opcode.params.len())
+            .map(|v| format!("a{}", v))
+            .collect::<Vec<_>>();
+        if args.is_empty() {
+            writeln!(os, "            Self::{} => {{", opcode.identifier).unwrap();
+        } else {
+            writeln!(
+                os,
+                "            Self::{}({}) => {{",
+                opcode.identifier,
+                args.join(", ")
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            os,
+            "                writer.write_bytes(&[0x{:02X}])?;",
+            opcode.leading
+        )
+        .unwrap();
+        if let Some(trailing) = opcode.trailing {
+            writeln!(
+                os,
+                "                writer.write_unsigned(0x{:02X})?;",
+                trailing
+            )
+            .unwrap();
+        }
+        for (param, arg) in opcode.params.iter().zip(args.iter()) {
+            if param == "br_table" {
+                writeln!(os, "                writer.write({})?;", arg).unwrap();
+            } else {
+                writeln!(os, "                writer.write(*{})?;", arg).unwrap();
+            }
+        }
+        writeln!(os, "            }}").unwrap();
+    }
+
+    write!(
+        os,
+        "        }}
+        output.extend_from_slice(writer.as_slice());
+        Ok(())
+    }}
+
     #[inline]
     pub const fn as_str(&self) -> &'static str {{
         self.mnemonic().as_str()
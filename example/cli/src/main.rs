@@ -19,13 +19,13 @@ fn main() {
         println!("Imports: ")
     }
     for import in module.imports() {
-        println!("  {:?} {:?} {:?}", import.module, import.name, import.kind);
+        println!("  {:?} {:?} {:?}", import.module, import.name, import.desc);
     }
 
     if module.exports().count() > 0 {
         println!("Exports: ")
     }
     for export in module.exports() {
-        println!("  {:?} {:?}", export.name, export.kind);
+        println!("  {:?} {:?}", export.name, export.desc);
     }
 }
@@ -69,6 +69,9 @@ pub fn wasm_env(attr: TokenStream, input: TokenStream) -> TokenStream {
                     };
                     let param_type =
                         ParsedType::new(pat.ty.as_ref(), ParseOption::IMPORTS_PARAM_TYPE).unwrap();
+                    if matches!(param_type, ParsedType::IntrinsicType(IntrinsicType::String)) {
+                        unexpected_token!(pat.ty.span(), "type")
+                    }
                     params.push((param_name, param_type));
                 }
             }
@@ -107,9 +110,10 @@ pub fn wasm_env(attr: TokenStream, input: TokenStream) -> TokenStream {
                     IntrinsicType::WasmInstance => {
                         call_params.push(var_instance.to_string());
                     }
+                    IntrinsicType::String => unreachable!(),
                     IntrinsicType::Str => {
                         call_params.push(param.0.clone());
-                        func_body.push(format!("let {var_memory} = {var_instance}.memory(0).unwrap().try_borrow()?;
+                        func_body.push(format!("let {var_memory} = {var_instance}.memory(0)?.try_borrow()?;
 let {} = {{
     let base = args.next::<WasmPtr<u8>>()?;
     let len = args.next::<u32>().map(|v| v as usize)?;
@@ -180,6 +184,15 @@ let {} = {{
 }
 
 /// A Macro to automatically generate WebAssembly exports from `trait`
+///
+/// A function returning `String` takes a buffer and its capacity as two extra `i32` parameters,
+/// writes the string into the buffer and returns its length.
+/// The buffer is reserved with `WasmInstance::alloc` and released with `WasmInstance::dealloc`,
+/// so it uses the allocator exports set by `WasmInstance::set_allocator`.
+/// If the length exceeds the capacity, the function is called again with a larger buffer.
+///
+/// A function returning a tuple of primitives such as `(i32, i32)` maps to a function
+/// with multiple results.
 #[proc_macro_attribute]
 pub fn wasm_exports(_attr: TokenStream, input: TokenStream) -> TokenStream {
     // println!("INPUT: {:?}", input.to_string());
@@ -220,7 +233,7 @@ pub fn wasm_exports(_attr: TokenStream, input: TokenStream) -> TokenStream {
 
         let result_type = match &func.sig.output {
             syn::ReturnType::Default => None,
-            syn::ReturnType::Type(_, ty) => ParsedType::new(ty, ParseOption::RESULT_TYPE),
+            syn::ReturnType::Type(_, ty) => ParsedType::new(ty, ParseOption::EXPORTS_RESULT_TYPE),
         };
 
         let mut push_args = Vec::new();
@@ -241,6 +254,82 @@ pub fn wasm_exports(_attr: TokenStream, input: TokenStream) -> TokenStream {
         output_trait.push(format!("{output_sig};"));
 
         output_impl.push(format!("{output_sig} {{"));
+
+        match result_type {
+            Some(ParsedType::IntrinsicType(IntrinsicType::String)) => {
+                output_impl.push(format!(
+                    "let instance = self.instance();
+let call = |ptr: WasmPtr<u8>, cap: u32| -> WasmResult<u32> {{
+    instance.exports().get({func_name:?})
+        .ok_or(WasmRuntimeErrorKind::NoMethod.into())
+        .and_then(|v| v.invoke(&[{push_args} WasmValue::from(ptr), WasmValue::from(cap)]))
+        .and_then(|v| match v {{
+            Some(v) => v.get_u32().map_err(|e| e.into()),
+            None => Err(WasmRuntimeErrorKind::TypeMismatch.into()),
+        }})
+}};
+let read = |ptr: WasmPtr<u8>, len: u32| -> WasmResult<String> {{
+    let memory = instance.memory(0)?.try_borrow()?;
+    let bytes = memory.slice(ptr, len as usize)?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| WasmRuntimeErrorKind::InvalidParameter.into())
+}};
+let mut cap = 256u32;
+let mut ptr = instance.alloc(cap as usize)?;
+let mut len = call(ptr, cap);
+if let Ok(required) = len {{
+    if required > cap {{
+        instance.dealloc(ptr, cap as usize)?;
+        cap = required;
+        ptr = instance.alloc(cap as usize)?;
+        len = call(ptr, cap);
+    }}
+}}
+let result = len.and_then(|len| {{
+    if len > cap {{
+        Err(WasmRuntimeErrorKind::InvalidParameter.into())
+    }} else {{
+        read(ptr, len)
+    }}
+}});
+instance.dealloc(ptr, cap as usize)?;
+result
+}}",
+                    push_args = push_args
+                        .iter()
+                        .map(|v| format!("{v},"))
+                        .collect::<String>(),
+                ));
+                continue;
+            }
+            Some(ParsedType::IntrinsicType(_)) => {
+                unexpected_token!(func.sig.output.span(), "result type")
+            }
+            Some(ParsedType::Tuple(ref result_types)) => {
+                output_impl.push(format!("let args = [{}];", push_args.join(",")));
+                output_impl.push(format!(
+                    "self.instance().exports().get({func_name:?})
+                .ok_or(WasmRuntimeErrorKind::NoMethod.into())
+                .and_then(|v| v.invoke_multi(&args))
+                .and_then(|v| match v.as_slice() {{
+                    [{bindings}] => Ok(({results})),
+                    _ => Err(WasmRuntimeErrorKind::TypeMismatch.into()),
+                }})
+}}",
+                    bindings = (0..result_types.len())
+                        .map(|i| format!("v{i}"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    results = result_types
+                        .iter()
+                        .enumerate()
+                        .map(|(i, v)| format!("v{i}.get_{}()?,", v.to_string()))
+                        .collect::<String>(),
+                ));
+                continue;
+            }
+            _ => {}
+        }
+
         output_impl.push(format!("let args = [{}];", push_args.join(",")));
         output_impl.push(format!(
             "self.instance().exports().get({:?})
@@ -286,6 +375,7 @@ enum ParsedType {
     Ptr(Box<Self>),
     PtrMut(Box<Self>),
     Result(Option<Box<Self>>),
+    Tuple(Vec<Self>),
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -294,6 +384,7 @@ struct ParseOption {
     allow_intrinsics: bool,
     allow_reference: bool,
     allow_nil: bool,
+    allow_tuple: bool,
 }
 
 impl ParseOption {
@@ -302,6 +393,7 @@ impl ParseOption {
         allow_intrinsics: false,
         allow_reference: false,
         allow_nil: false,
+        allow_tuple: false,
     };
 
     const RESULT_TYPE: Self = Self {
@@ -309,6 +401,15 @@ impl ParseOption {
         allow_intrinsics: false,
         allow_reference: false,
         allow_nil: true,
+        allow_tuple: false,
+    };
+
+    const EXPORTS_RESULT_TYPE: Self = Self {
+        primitive_only: true,
+        allow_intrinsics: true,
+        allow_reference: false,
+        allow_nil: true,
+        allow_tuple: true,
     };
 
     const EXPORTS_PARAM_TYPE: Self = Self {
@@ -316,6 +417,7 @@ impl ParseOption {
         allow_intrinsics: false,
         allow_reference: true,
         allow_nil: false,
+        allow_tuple: false,
     };
 
     const IMPORTS_PARAM_TYPE: Self = Self {
@@ -323,6 +425,7 @@ impl ParseOption {
         allow_intrinsics: true,
         allow_reference: true,
         allow_nil: false,
+        allow_tuple: false,
     };
 
     fn extend<F>(&self, f: F) -> Self
@@ -382,6 +485,22 @@ impl ParsedType {
             syn::Type::Tuple(tuple) => {
                 if options.allow_nil && tuple.elems.is_empty() {
                     None
+                } else if options.allow_tuple && tuple.elems.len() > 1 {
+                    let options = options.extend(|v| {
+                        v.allow_intrinsics = false;
+                        v.allow_nil = false;
+                        v.allow_tuple = false;
+                    });
+                    Some(ParsedType::Tuple(
+                        tuple
+                            .elems
+                            .iter()
+                            .map(|v| match Self::new(v, options) {
+                                Some(v @ ParsedType::Primitive(_)) => v,
+                                _ => unexpected_token!(v.span(), "primitive type"),
+                            })
+                            .collect(),
+                    ))
                 } else {
                     unexpected_token!(ty.span(), "type")
                 }
@@ -492,6 +611,13 @@ impl ParsedType {
                 Some(v) => Cow::Owned(format!("WasmResult<{}>", v.to_string())),
                 None => Cow::Borrowed("WasmResult<()>"),
             },
+            Self::Tuple(v) => Cow::Owned(format!(
+                "({})",
+                v.iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
         }
     }
 
@@ -500,7 +626,7 @@ impl ParsedType {
         match _self.as_ref() {
             Self::Primitive(v) => v.signature(),
             Self::IntrinsicType(v) => v.signature(),
-            Self::NonPrimitive(_) => "_",
+            Self::NonPrimitive(_) | Self::Tuple(_) => "_",
             Self::Result(v) => Self::signature(v.as_ref()),
             _ => Primitive::POINTER_TYPE.signature(),
         }
@@ -600,6 +726,7 @@ impl Primitive {
 enum IntrinsicType {
     WasmInstance,
     Str,
+    String,
 }
 
 impl IntrinsicType {
@@ -611,6 +738,7 @@ impl IntrinsicType {
         match v.as_str() {
             "&WasmInstance" => Some(Self::WasmInstance),
             "&str" => Some(Self::Str),
+            "String" => Some(Self::String),
             _ => None,
         }
     }
@@ -619,12 +747,14 @@ impl IntrinsicType {
         match self {
             Self::WasmInstance => Cow::Borrowed("&WasmInstance"),
             Self::Str => Cow::Borrowed("&str"),
+            Self::String => Cow::Borrowed("String"),
         }
     }
 
     pub fn signature(&self) -> &'static str {
         match self {
             Self::Str => "ii",
+            Self::String => "i",
             _ => "_",
         }
     }
@@ -2,6 +2,7 @@
 // This file is automatically generated at build time. DO NOT EDIT DIRECTLY.
 //
 use crate::{leb128::*, BrTableVec, WasmBlockType, WasmCompileErrorKind, WasmMemArg};
+use crate::_prelude_::*;
 use core::fmt;
 
 /// WebAssembly Opcode
@@ -1290,6 +1291,881 @@ impl WasmOpcode {
         }
     }
 
+    /// Writes the bytes consumed by [`WasmOpcode::fetch`]
+    pub fn encode(&self, output: &mut Vec<u8>) -> Result<(), WriteError> {
+        let mut writer = Leb128Writer::new();
+        match self {
+            Self::Unreachable => {
+                writer.write_bytes(&[0x00])?;
+            }
+            Self::Nop => {
+                writer.write_bytes(&[0x01])?;
+            }
+            Self::Block(a1) => {
+                writer.write_bytes(&[0x02])?;
+                writer.write(*a1)?;
+            }
+            Self::Loop(a1) => {
+                writer.write_bytes(&[0x03])?;
+                writer.write(*a1)?;
+            }
+            Self::If(a1) => {
+                writer.write_bytes(&[0x04])?;
+                writer.write(*a1)?;
+            }
+            Self::Else => {
+                writer.write_bytes(&[0x05])?;
+            }
+            Self::End => {
+                writer.write_bytes(&[0x0B])?;
+            }
+            Self::Br(a1) => {
+                writer.write_bytes(&[0x0C])?;
+                writer.write(*a1)?;
+            }
+            Self::BrIf(a1) => {
+                writer.write_bytes(&[0x0D])?;
+                writer.write(*a1)?;
+            }
+            Self::BrTable(a1) => {
+                writer.write_bytes(&[0x0E])?;
+                writer.write(a1)?;
+            }
+            Self::Return => {
+                writer.write_bytes(&[0x0F])?;
+            }
+            Self::Call(a1) => {
+                writer.write_bytes(&[0x10])?;
+                writer.write(*a1)?;
+            }
+            Self::CallIndirect(a1, a2) => {
+                writer.write_bytes(&[0x11])?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::ReturnCall => {
+                writer.write_bytes(&[0x12])?;
+            }
+            Self::ReturnCallIndirect => {
+                writer.write_bytes(&[0x13])?;
+            }
+            Self::CallRef => {
+                writer.write_bytes(&[0x14])?;
+            }
+            Self::ReturnCallRef => {
+                writer.write_bytes(&[0x15])?;
+            }
+            Self::Drop => {
+                writer.write_bytes(&[0x1A])?;
+            }
+            Self::Select => {
+                writer.write_bytes(&[0x1B])?;
+            }
+            Self::SelectT(a1) => {
+                writer.write_bytes(&[0x1C])?;
+                writer.write(*a1)?;
+            }
+            Self::LocalGet(a1) => {
+                writer.write_bytes(&[0x20])?;
+                writer.write(*a1)?;
+            }
+            Self::LocalSet(a1) => {
+                writer.write_bytes(&[0x21])?;
+                writer.write(*a1)?;
+            }
+            Self::LocalTee(a1) => {
+                writer.write_bytes(&[0x22])?;
+                writer.write(*a1)?;
+            }
+            Self::GlobalGet(a1) => {
+                writer.write_bytes(&[0x23])?;
+                writer.write(*a1)?;
+            }
+            Self::GlobalSet(a1) => {
+                writer.write_bytes(&[0x24])?;
+                writer.write(*a1)?;
+            }
+            Self::TableGet(a1) => {
+                writer.write_bytes(&[0x25])?;
+                writer.write(*a1)?;
+            }
+            Self::TableSet(a1) => {
+                writer.write_bytes(&[0x26])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Load(a1) => {
+                writer.write_bytes(&[0x28])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Load(a1) => {
+                writer.write_bytes(&[0x29])?;
+                writer.write(*a1)?;
+            }
+            Self::F32Load(a1) => {
+                writer.write_bytes(&[0x2A])?;
+                writer.write(*a1)?;
+            }
+            Self::F64Load(a1) => {
+                writer.write_bytes(&[0x2B])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Load8S(a1) => {
+                writer.write_bytes(&[0x2C])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Load8U(a1) => {
+                writer.write_bytes(&[0x2D])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Load16S(a1) => {
+                writer.write_bytes(&[0x2E])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Load16U(a1) => {
+                writer.write_bytes(&[0x2F])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Load8S(a1) => {
+                writer.write_bytes(&[0x30])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Load8U(a1) => {
+                writer.write_bytes(&[0x31])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Load16S(a1) => {
+                writer.write_bytes(&[0x32])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Load16U(a1) => {
+                writer.write_bytes(&[0x33])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Load32S(a1) => {
+                writer.write_bytes(&[0x34])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Load32U(a1) => {
+                writer.write_bytes(&[0x35])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Store(a1) => {
+                writer.write_bytes(&[0x36])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Store(a1) => {
+                writer.write_bytes(&[0x37])?;
+                writer.write(*a1)?;
+            }
+            Self::F32Store(a1) => {
+                writer.write_bytes(&[0x38])?;
+                writer.write(*a1)?;
+            }
+            Self::F64Store(a1) => {
+                writer.write_bytes(&[0x39])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Store8(a1) => {
+                writer.write_bytes(&[0x3A])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Store16(a1) => {
+                writer.write_bytes(&[0x3B])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Store8(a1) => {
+                writer.write_bytes(&[0x3C])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Store16(a1) => {
+                writer.write_bytes(&[0x3D])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Store32(a1) => {
+                writer.write_bytes(&[0x3E])?;
+                writer.write(*a1)?;
+            }
+            Self::MemorySize(a1) => {
+                writer.write_bytes(&[0x3F])?;
+                writer.write(*a1)?;
+            }
+            Self::MemoryGrow(a1) => {
+                writer.write_bytes(&[0x40])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Const(a1) => {
+                writer.write_bytes(&[0x41])?;
+                writer.write(*a1)?;
+            }
+            Self::I64Const(a1) => {
+                writer.write_bytes(&[0x42])?;
+                writer.write(*a1)?;
+            }
+            Self::F32Const(a1) => {
+                writer.write_bytes(&[0x43])?;
+                writer.write(*a1)?;
+            }
+            Self::F64Const(a1) => {
+                writer.write_bytes(&[0x44])?;
+                writer.write(*a1)?;
+            }
+            Self::I32Eqz => {
+                writer.write_bytes(&[0x45])?;
+            }
+            Self::I32Eq => {
+                writer.write_bytes(&[0x46])?;
+            }
+            Self::I32Ne => {
+                writer.write_bytes(&[0x47])?;
+            }
+            Self::I32LtS => {
+                writer.write_bytes(&[0x48])?;
+            }
+            Self::I32LtU => {
+                writer.write_bytes(&[0x49])?;
+            }
+            Self::I32GtS => {
+                writer.write_bytes(&[0x4A])?;
+            }
+            Self::I32GtU => {
+                writer.write_bytes(&[0x4B])?;
+            }
+            Self::I32LeS => {
+                writer.write_bytes(&[0x4C])?;
+            }
+            Self::I32LeU => {
+                writer.write_bytes(&[0x4D])?;
+            }
+            Self::I32GeS => {
+                writer.write_bytes(&[0x4E])?;
+            }
+            Self::I32GeU => {
+                writer.write_bytes(&[0x4F])?;
+            }
+            Self::I64Eqz => {
+                writer.write_bytes(&[0x50])?;
+            }
+            Self::I64Eq => {
+                writer.write_bytes(&[0x51])?;
+            }
+            Self::I64Ne => {
+                writer.write_bytes(&[0x52])?;
+            }
+            Self::I64LtS => {
+                writer.write_bytes(&[0x53])?;
+            }
+            Self::I64LtU => {
+                writer.write_bytes(&[0x54])?;
+            }
+            Self::I64GtS => {
+                writer.write_bytes(&[0x55])?;
+            }
+            Self::I64GtU => {
+                writer.write_bytes(&[0x56])?;
+            }
+            Self::I64LeS => {
+                writer.write_bytes(&[0x57])?;
+            }
+            Self::I64LeU => {
+                writer.write_bytes(&[0x58])?;
+            }
+            Self::I64GeS => {
+                writer.write_bytes(&[0x59])?;
+            }
+            Self::I64GeU => {
+                writer.write_bytes(&[0x5A])?;
+            }
+            Self::F32Eq => {
+                writer.write_bytes(&[0x5B])?;
+            }
+            Self::F32Ne => {
+                writer.write_bytes(&[0x5C])?;
+            }
+            Self::F32Lt => {
+                writer.write_bytes(&[0x5D])?;
+            }
+            Self::F32Gt => {
+                writer.write_bytes(&[0x5E])?;
+            }
+            Self::F32Le => {
+                writer.write_bytes(&[0x5F])?;
+            }
+            Self::F32Ge => {
+                writer.write_bytes(&[0x60])?;
+            }
+            Self::F64Eq => {
+                writer.write_bytes(&[0x61])?;
+            }
+            Self::F64Ne => {
+                writer.write_bytes(&[0x62])?;
+            }
+            Self::F64Lt => {
+                writer.write_bytes(&[0x63])?;
+            }
+            Self::F64Gt => {
+                writer.write_bytes(&[0x64])?;
+            }
+            Self::F64Le => {
+                writer.write_bytes(&[0x65])?;
+            }
+            Self::F64Ge => {
+                writer.write_bytes(&[0x66])?;
+            }
+            Self::I32Clz => {
+                writer.write_bytes(&[0x67])?;
+            }
+            Self::I32Ctz => {
+                writer.write_bytes(&[0x68])?;
+            }
+            Self::I32Popcnt => {
+                writer.write_bytes(&[0x69])?;
+            }
+            Self::I32Add => {
+                writer.write_bytes(&[0x6A])?;
+            }
+            Self::I32Sub => {
+                writer.write_bytes(&[0x6B])?;
+            }
+            Self::I32Mul => {
+                writer.write_bytes(&[0x6C])?;
+            }
+            Self::I32DivS => {
+                writer.write_bytes(&[0x6D])?;
+            }
+            Self::I32DivU => {
+                writer.write_bytes(&[0x6E])?;
+            }
+            Self::I32RemS => {
+                writer.write_bytes(&[0x6F])?;
+            }
+            Self::I32RemU => {
+                writer.write_bytes(&[0x70])?;
+            }
+            Self::I32And => {
+                writer.write_bytes(&[0x71])?;
+            }
+            Self::I32Or => {
+                writer.write_bytes(&[0x72])?;
+            }
+            Self::I32Xor => {
+                writer.write_bytes(&[0x73])?;
+            }
+            Self::I32Shl => {
+                writer.write_bytes(&[0x74])?;
+            }
+            Self::I32ShrS => {
+                writer.write_bytes(&[0x75])?;
+            }
+            Self::I32ShrU => {
+                writer.write_bytes(&[0x76])?;
+            }
+            Self::I32Rotl => {
+                writer.write_bytes(&[0x77])?;
+            }
+            Self::I32Rotr => {
+                writer.write_bytes(&[0x78])?;
+            }
+            Self::I64Clz => {
+                writer.write_bytes(&[0x79])?;
+            }
+            Self::I64Ctz => {
+                writer.write_bytes(&[0x7A])?;
+            }
+            Self::I64Popcnt => {
+                writer.write_bytes(&[0x7B])?;
+            }
+            Self::I64Add => {
+                writer.write_bytes(&[0x7C])?;
+            }
+            Self::I64Sub => {
+                writer.write_bytes(&[0x7D])?;
+            }
+            Self::I64Mul => {
+                writer.write_bytes(&[0x7E])?;
+            }
+            Self::I64DivS => {
+                writer.write_bytes(&[0x7F])?;
+            }
+            Self::I64DivU => {
+                writer.write_bytes(&[0x80])?;
+            }
+            Self::I64RemS => {
+                writer.write_bytes(&[0x81])?;
+            }
+            Self::I64RemU => {
+                writer.write_bytes(&[0x82])?;
+            }
+            Self::I64And => {
+                writer.write_bytes(&[0x83])?;
+            }
+            Self::I64Or => {
+                writer.write_bytes(&[0x84])?;
+            }
+            Self::I64Xor => {
+                writer.write_bytes(&[0x85])?;
+            }
+            Self::I64Shl => {
+                writer.write_bytes(&[0x86])?;
+            }
+            Self::I64ShrS => {
+                writer.write_bytes(&[0x87])?;
+            }
+            Self::I64ShrU => {
+                writer.write_bytes(&[0x88])?;
+            }
+            Self::I64Rotl => {
+                writer.write_bytes(&[0x89])?;
+            }
+            Self::I64Rotr => {
+                writer.write_bytes(&[0x8A])?;
+            }
+            Self::F32Abs => {
+                writer.write_bytes(&[0x8B])?;
+            }
+            Self::F32Neg => {
+                writer.write_bytes(&[0x8C])?;
+            }
+            Self::F32Ceil => {
+                writer.write_bytes(&[0x8D])?;
+            }
+            Self::F32Floor => {
+                writer.write_bytes(&[0x8E])?;
+            }
+            Self::F32Trunc => {
+                writer.write_bytes(&[0x8F])?;
+            }
+            Self::F32Nearest => {
+                writer.write_bytes(&[0x90])?;
+            }
+            Self::F32Sqrt => {
+                writer.write_bytes(&[0x91])?;
+            }
+            Self::F32Add => {
+                writer.write_bytes(&[0x92])?;
+            }
+            Self::F32Sub => {
+                writer.write_bytes(&[0x93])?;
+            }
+            Self::F32Mul => {
+                writer.write_bytes(&[0x94])?;
+            }
+            Self::F32Div => {
+                writer.write_bytes(&[0x95])?;
+            }
+            Self::F32Min => {
+                writer.write_bytes(&[0x96])?;
+            }
+            Self::F32Max => {
+                writer.write_bytes(&[0x97])?;
+            }
+            Self::F32Copysign => {
+                writer.write_bytes(&[0x98])?;
+            }
+            Self::F64Abs => {
+                writer.write_bytes(&[0x99])?;
+            }
+            Self::F64Neg => {
+                writer.write_bytes(&[0x9A])?;
+            }
+            Self::F64Ceil => {
+                writer.write_bytes(&[0x9B])?;
+            }
+            Self::F64Floor => {
+                writer.write_bytes(&[0x9C])?;
+            }
+            Self::F64Trunc => {
+                writer.write_bytes(&[0x9D])?;
+            }
+            Self::F64Nearest => {
+                writer.write_bytes(&[0x9E])?;
+            }
+            Self::F64Sqrt => {
+                writer.write_bytes(&[0x9F])?;
+            }
+            Self::F64Add => {
+                writer.write_bytes(&[0xA0])?;
+            }
+            Self::F64Sub => {
+                writer.write_bytes(&[0xA1])?;
+            }
+            Self::F64Mul => {
+                writer.write_bytes(&[0xA2])?;
+            }
+            Self::F64Div => {
+                writer.write_bytes(&[0xA3])?;
+            }
+            Self::F64Min => {
+                writer.write_bytes(&[0xA4])?;
+            }
+            Self::F64Max => {
+                writer.write_bytes(&[0xA5])?;
+            }
+            Self::F64Copysign => {
+                writer.write_bytes(&[0xA6])?;
+            }
+            Self::I32WrapI64 => {
+                writer.write_bytes(&[0xA7])?;
+            }
+            Self::I32TruncF32S => {
+                writer.write_bytes(&[0xA8])?;
+            }
+            Self::I32TruncF32U => {
+                writer.write_bytes(&[0xA9])?;
+            }
+            Self::I32TruncF64S => {
+                writer.write_bytes(&[0xAA])?;
+            }
+            Self::I32TruncF64U => {
+                writer.write_bytes(&[0xAB])?;
+            }
+            Self::I64ExtendI32S => {
+                writer.write_bytes(&[0xAC])?;
+            }
+            Self::I64ExtendI32U => {
+                writer.write_bytes(&[0xAD])?;
+            }
+            Self::I64TruncF32S => {
+                writer.write_bytes(&[0xAE])?;
+            }
+            Self::I64TruncF32U => {
+                writer.write_bytes(&[0xAF])?;
+            }
+            Self::I64TruncF64S => {
+                writer.write_bytes(&[0xB0])?;
+            }
+            Self::I64TruncF64U => {
+                writer.write_bytes(&[0xB1])?;
+            }
+            Self::F32ConvertI32S => {
+                writer.write_bytes(&[0xB2])?;
+            }
+            Self::F32ConvertI32U => {
+                writer.write_bytes(&[0xB3])?;
+            }
+            Self::F32ConvertI64S => {
+                writer.write_bytes(&[0xB4])?;
+            }
+            Self::F32ConvertI64U => {
+                writer.write_bytes(&[0xB5])?;
+            }
+            Self::F32DemoteF64 => {
+                writer.write_bytes(&[0xB6])?;
+            }
+            Self::F64ConvertI32S => {
+                writer.write_bytes(&[0xB7])?;
+            }
+            Self::F64ConvertI32U => {
+                writer.write_bytes(&[0xB8])?;
+            }
+            Self::F64ConvertI64S => {
+                writer.write_bytes(&[0xB9])?;
+            }
+            Self::F64ConvertI64U => {
+                writer.write_bytes(&[0xBA])?;
+            }
+            Self::F64PromoteF32 => {
+                writer.write_bytes(&[0xBB])?;
+            }
+            Self::I32ReinterpretF32 => {
+                writer.write_bytes(&[0xBC])?;
+            }
+            Self::I64ReinterpretF64 => {
+                writer.write_bytes(&[0xBD])?;
+            }
+            Self::F32ReinterpretI32 => {
+                writer.write_bytes(&[0xBE])?;
+            }
+            Self::F64ReinterpretI64 => {
+                writer.write_bytes(&[0xBF])?;
+            }
+            Self::I32Extend8S => {
+                writer.write_bytes(&[0xC0])?;
+            }
+            Self::I32Extend16S => {
+                writer.write_bytes(&[0xC1])?;
+            }
+            Self::I64Extend8S => {
+                writer.write_bytes(&[0xC2])?;
+            }
+            Self::I64Extend16S => {
+                writer.write_bytes(&[0xC3])?;
+            }
+            Self::I64Extend32S => {
+                writer.write_bytes(&[0xC4])?;
+            }
+            Self::RefNull(a1) => {
+                writer.write_bytes(&[0xD0])?;
+                writer.write(*a1)?;
+            }
+            Self::RefIsNull => {
+                writer.write_bytes(&[0xD1])?;
+            }
+            Self::RefFunc(a1) => {
+                writer.write_bytes(&[0xD2])?;
+                writer.write(*a1)?;
+            }
+            Self::RefAsNonNull => {
+                writer.write_bytes(&[0xD3])?;
+            }
+            Self::BrOnNull => {
+                writer.write_bytes(&[0xD4])?;
+            }
+            Self::RefEq(a1) => {
+                writer.write_bytes(&[0xD5])?;
+                writer.write(*a1)?;
+            }
+            Self::BrOnNonNull(a1) => {
+                writer.write_bytes(&[0xD6])?;
+                writer.write(*a1)?;
+            }
+            Self::StructNew(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x00)?;
+                writer.write(*a1)?;
+            }
+            Self::StructNewDefault(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x01)?;
+                writer.write(*a1)?;
+            }
+            Self::StructGet(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x02)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::StructGetS(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x03)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::StructGetU(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x04)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::StructSet(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x05)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::ArrayNew(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x06)?;
+                writer.write(*a1)?;
+            }
+            Self::ArrayNewDefault(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x07)?;
+                writer.write(*a1)?;
+            }
+            Self::ArrayNewFixed(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x08)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::ArrayNewData(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x09)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::ArrayNewElem(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x0A)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::ArrayGet(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x0B)?;
+                writer.write(*a1)?;
+            }
+            Self::ArrayGetS(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x0C)?;
+                writer.write(*a1)?;
+            }
+            Self::ArrayGetU(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x0D)?;
+                writer.write(*a1)?;
+            }
+            Self::ArraySet(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x0E)?;
+                writer.write(*a1)?;
+            }
+            Self::ArrayLen => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x0F)?;
+            }
+            Self::ArrayFill(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x10)?;
+                writer.write(*a1)?;
+            }
+            Self::ArrayCopy(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x11)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::ArrayInitData(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x12)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::ArrayInitElem(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x13)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::RefTestRefHt(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x14)?;
+                writer.write(*a1)?;
+            }
+            Self::RefTestRefNullHt(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x15)?;
+                writer.write(*a1)?;
+            }
+            Self::RefCastRefHt(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x16)?;
+                writer.write(*a1)?;
+            }
+            Self::RefCastRefNullHt(a1) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x17)?;
+                writer.write(*a1)?;
+            }
+            Self::BrOnCast(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x18)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::BrOnCastFail(a1, a2) => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x19)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::AnyConvertExtern => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x1A)?;
+            }
+            Self::ExternConvertAny => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x1B)?;
+            }
+            Self::RefI31 => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x1C)?;
+            }
+            Self::I31GetS => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x1D)?;
+            }
+            Self::I31GetU => {
+                writer.write_bytes(&[0xFB])?;
+                writer.write_unsigned(0x1E)?;
+            }
+            Self::I32TruncSatF32S => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x00)?;
+            }
+            Self::I32TruncSatF32U => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x01)?;
+            }
+            Self::I32TruncSatF64S => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x02)?;
+            }
+            Self::I32TruncSatF64U => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x03)?;
+            }
+            Self::I64TruncSatF32S => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x04)?;
+            }
+            Self::I64TruncSatF32U => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x05)?;
+            }
+            Self::I64TruncSatF64S => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x06)?;
+            }
+            Self::I64TruncSatF64U => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x07)?;
+            }
+            Self::MemoryInit(a1) => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x08)?;
+                writer.write(*a1)?;
+            }
+            Self::DataDrop(a1) => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x09)?;
+                writer.write(*a1)?;
+            }
+            Self::MemoryCopy => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x0A)?;
+            }
+            Self::MemoryFill => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x0B)?;
+            }
+            Self::TableInit(a1, a2) => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x0C)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::ElemDrop(a1) => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x0D)?;
+                writer.write(*a1)?;
+            }
+            Self::TableCopy(a1, a2) => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x0E)?;
+                writer.write(*a1)?;
+                writer.write(*a2)?;
+            }
+            Self::TableGrow(a1) => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x0F)?;
+                writer.write(*a1)?;
+            }
+            Self::TableSize(a1) => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x10)?;
+                writer.write(*a1)?;
+            }
+            Self::TableFill(a1) => {
+                writer.write_bytes(&[0xFC])?;
+                writer.write_unsigned(0x11)?;
+                writer.write(*a1)?;
+            }
+        }
+        output.extend_from_slice(writer.as_slice());
+        Ok(())
+    }
+
     #[inline]
     pub const fn as_str(&self) -> &'static str {
         self.mnemonic().as_str()
@@ -1,5 +1,6 @@
 use super::{GlobalVarIndex, LocalVarIndex, StackLevel};
 use crate::{opcode::WasmMnemonic, BrTableVec, ExceptionPosition, WasmTypeIndex};
+use core::fmt;
 
 /// Intermediate instruction for Webassembly interpreter
 #[non_exhaustive]
@@ -29,7 +30,7 @@ pub enum WasmImInstruction {
     ReturnF,
 
     Call(usize, ExceptionPosition),
-    CallIndirect(WasmTypeIndex, ExceptionPosition),
+    CallIndirect(WasmTypeIndex, usize, ExceptionPosition),
 
     SelectI,
     SelectF,
@@ -46,41 +47,51 @@ pub enum WasmImInstruction {
     GlobalGetF(GlobalVarIndex),
     GlobalSetF(GlobalVarIndex),
 
-    I32Load(u32, ExceptionPosition),
-    I64Load(u32, ExceptionPosition),
-    I32Load8S(u32, ExceptionPosition),
-    I32Load8U(u32, ExceptionPosition),
-    I32Load16S(u32, ExceptionPosition),
-    I32Load16U(u32, ExceptionPosition),
-    I64Load8S(u32, ExceptionPosition),
-    I64Load8U(u32, ExceptionPosition),
-    I64Load16S(u32, ExceptionPosition),
-    I64Load16U(u32, ExceptionPosition),
-    I64Load32S(u32, ExceptionPosition),
-    I64Load32U(u32, ExceptionPosition),
-    F32Load(u32, ExceptionPosition),
-    F64Load(u32, ExceptionPosition),
-
-    I32Store(u32, ExceptionPosition),
-    I64Store(u32, ExceptionPosition),
-    I32Store8(u32, ExceptionPosition),
-    I32Store16(u32, ExceptionPosition),
-    I64Store8(u32, ExceptionPosition),
-    I64Store16(u32, ExceptionPosition),
-    I64Store32(u32, ExceptionPosition),
-    F32Store(u32, ExceptionPosition),
-    F64Store(u32, ExceptionPosition),
-
-    MemorySize,
-    MemoryGrow,
-    MemoryCopy(ExceptionPosition),
-    MemoryFill(ExceptionPosition),
+    TableGet(usize, ExceptionPosition),
+    TableSet(usize, ExceptionPosition),
+
+    // (offset, memory index, position)
+    I32Load(u32, u32, ExceptionPosition),
+    I64Load(u32, u32, ExceptionPosition),
+    I32Load8S(u32, u32, ExceptionPosition),
+    I32Load8U(u32, u32, ExceptionPosition),
+    I32Load16S(u32, u32, ExceptionPosition),
+    I32Load16U(u32, u32, ExceptionPosition),
+    I64Load8S(u32, u32, ExceptionPosition),
+    I64Load8U(u32, u32, ExceptionPosition),
+    I64Load16S(u32, u32, ExceptionPosition),
+    I64Load16U(u32, u32, ExceptionPosition),
+    I64Load32S(u32, u32, ExceptionPosition),
+    I64Load32U(u32, u32, ExceptionPosition),
+    F32Load(u32, u32, ExceptionPosition),
+    F64Load(u32, u32, ExceptionPosition),
+
+    I32Store(u32, u32, ExceptionPosition),
+    I64Store(u32, u32, ExceptionPosition),
+    I32Store8(u32, u32, ExceptionPosition),
+    I32Store16(u32, u32, ExceptionPosition),
+    I64Store8(u32, u32, ExceptionPosition),
+    I64Store16(u32, u32, ExceptionPosition),
+    I64Store32(u32, u32, ExceptionPosition),
+    F32Store(u32, u32, ExceptionPosition),
+    F64Store(u32, u32, ExceptionPosition),
+
+    MemorySize(u32),
+    MemoryGrow(u32),
+    MemoryCopy(u32, u32, ExceptionPosition),
+    MemoryFill(u32, ExceptionPosition),
+    MemoryInit(u32, u32, ExceptionPosition),
+    DataDrop(u32),
 
     I32Const(i32),
     I64Const(i64),
     F32Const(f32),
     F64Const(f64),
 
+    RefNull,
+    RefIsNull,
+    RefFunc(usize),
+
     I32Eqz,
     I32Eq,
     I32Ne,
@@ -191,14 +202,14 @@ pub enum WasmImInstruction {
     I32WrapI64,
     I32Extend8S,
     I32Extend16S,
-    I32TruncF32S,
-    I32TruncF32U,
-    I32TruncF64S,
-    I32TruncF64U,
-    I64TruncF32S,
-    I64TruncF32U,
-    I64TruncF64S,
-    I64TruncF64U,
+    I32TruncF32S(ExceptionPosition),
+    I32TruncF32U(ExceptionPosition),
+    I32TruncF64S(ExceptionPosition),
+    I32TruncF64U(ExceptionPosition),
+    I64TruncF32S(ExceptionPosition),
+    I64TruncF32U(ExceptionPosition),
+    I64TruncF64S(ExceptionPosition),
+    I64TruncF64U(ExceptionPosition),
     F32ConvertI32S,
     F32ConvertI32U,
     F32ConvertI64S,
@@ -241,6 +252,31 @@ pub enum WasmImInstruction {
     FusedI32SetConst(LocalVarIndex, i32),
     FusedI64SetConst(LocalVarIndex, i64),
 
+    /// Fusion of the following 4 instructions
+    ///
+    /// ```plain
+    /// local.get $base
+    /// i32.const N
+    /// i32.add
+    /// i32.load offset=M
+    /// ```
+    ///
+    /// `N` wraps around as `i32.add` does, while `M` does not, so they are kept apart.
+    FusedI32LoadAt(LocalVarIndex, u32, u32, ExceptionPosition),
+
+    /// Fusion of the following 5 instructions
+    ///
+    /// ```plain
+    /// local.get $base
+    /// i32.const N
+    /// i32.add
+    /// (local.get $value | i32.const V)
+    /// i32.store offset=M
+    /// ```
+    ///
+    /// The value is left at the stack level above the unused address slot.
+    FusedI32StoreAt(LocalVarIndex, u32, u32, ExceptionPosition),
+
     /// Fusion of the following 2 instructions
     ///
     /// ```plain
@@ -302,6 +338,361 @@ impl WasmImInstruction {
         // TODO:
         self
     }
+
+    /// Returns the opcode that the instruction was generated from.
+    ///
+    /// Specialized instructions return their generic opcode, and fused instructions
+    /// return the arithmetic or comparison opcode they contain.
+    pub const fn mnemonic(&self) -> WasmMnemonic {
+        match *self {
+            Self::NotSupported(mnemonic, _) => mnemonic,
+            Self::Marker(..) => WasmMnemonic::Nop,
+            Self::Unreachable(..) => WasmMnemonic::Unreachable,
+            Self::If(..) => WasmMnemonic::If,
+            Self::Br(..) => WasmMnemonic::Br,
+            Self::BrIf(..) => WasmMnemonic::BrIf,
+            Self::BrTable(..) => WasmMnemonic::BrTable,
+            Self::BrUnwind(..) => WasmMnemonic::Br,
+            Self::BrIfUnwind(..) => WasmMnemonic::BrIf,
+            Self::ReturnN => WasmMnemonic::Return,
+            Self::ReturnI => WasmMnemonic::Return,
+            Self::ReturnF => WasmMnemonic::Return,
+            Self::Call(..) => WasmMnemonic::Call,
+            Self::CallIndirect(..) => WasmMnemonic::CallIndirect,
+            Self::SelectI => WasmMnemonic::Select,
+            Self::SelectF => WasmMnemonic::Select,
+            Self::LocalGetI(..) => WasmMnemonic::LocalGet,
+            Self::LocalSetI(..) => WasmMnemonic::LocalSet,
+            Self::LocalTeeI(..) => WasmMnemonic::LocalTee,
+            Self::GlobalGetI(..) => WasmMnemonic::GlobalGet,
+            Self::GlobalSetI(..) => WasmMnemonic::GlobalSet,
+            Self::LocalGetF(..) => WasmMnemonic::LocalGet,
+            Self::LocalSetF(..) => WasmMnemonic::LocalSet,
+            Self::LocalTeeF(..) => WasmMnemonic::LocalTee,
+            Self::GlobalGetF(..) => WasmMnemonic::GlobalGet,
+            Self::GlobalSetF(..) => WasmMnemonic::GlobalSet,
+            Self::TableGet(..) => WasmMnemonic::TableGet,
+            Self::TableSet(..) => WasmMnemonic::TableSet,
+            Self::I32Load(..) => WasmMnemonic::I32Load,
+            Self::I64Load(..) => WasmMnemonic::I64Load,
+            Self::I32Load8S(..) => WasmMnemonic::I32Load8S,
+            Self::I32Load8U(..) => WasmMnemonic::I32Load8U,
+            Self::I32Load16S(..) => WasmMnemonic::I32Load16S,
+            Self::I32Load16U(..) => WasmMnemonic::I32Load16U,
+            Self::I64Load8S(..) => WasmMnemonic::I64Load8S,
+            Self::I64Load8U(..) => WasmMnemonic::I64Load8U,
+            Self::I64Load16S(..) => WasmMnemonic::I64Load16S,
+            Self::I64Load16U(..) => WasmMnemonic::I64Load16U,
+            Self::I64Load32S(..) => WasmMnemonic::I64Load32S,
+            Self::I64Load32U(..) => WasmMnemonic::I64Load32U,
+            Self::F32Load(..) => WasmMnemonic::F32Load,
+            Self::F64Load(..) => WasmMnemonic::F64Load,
+            Self::I32Store(..) => WasmMnemonic::I32Store,
+            Self::I64Store(..) => WasmMnemonic::I64Store,
+            Self::I32Store8(..) => WasmMnemonic::I32Store8,
+            Self::I32Store16(..) => WasmMnemonic::I32Store16,
+            Self::I64Store8(..) => WasmMnemonic::I64Store8,
+            Self::I64Store16(..) => WasmMnemonic::I64Store16,
+            Self::I64Store32(..) => WasmMnemonic::I64Store32,
+            Self::F32Store(..) => WasmMnemonic::F32Store,
+            Self::F64Store(..) => WasmMnemonic::F64Store,
+            Self::MemorySize(..) => WasmMnemonic::MemorySize,
+            Self::MemoryGrow(..) => WasmMnemonic::MemoryGrow,
+            Self::MemoryCopy(..) => WasmMnemonic::MemoryCopy,
+            Self::MemoryFill(..) => WasmMnemonic::MemoryFill,
+            Self::MemoryInit(..) => WasmMnemonic::MemoryInit,
+            Self::DataDrop(..) => WasmMnemonic::DataDrop,
+            Self::I32Const(..) => WasmMnemonic::I32Const,
+            Self::I64Const(..) => WasmMnemonic::I64Const,
+            Self::F32Const(..) => WasmMnemonic::F32Const,
+            Self::F64Const(..) => WasmMnemonic::F64Const,
+            Self::RefNull => WasmMnemonic::RefNull,
+            Self::RefIsNull => WasmMnemonic::RefIsNull,
+            Self::RefFunc(..) => WasmMnemonic::RefFunc,
+            Self::I32Eqz => WasmMnemonic::I32Eqz,
+            Self::I32Eq => WasmMnemonic::I32Eq,
+            Self::I32Ne => WasmMnemonic::I32Ne,
+            Self::I32LtS => WasmMnemonic::I32LtS,
+            Self::I32LtU => WasmMnemonic::I32LtU,
+            Self::I32GtS => WasmMnemonic::I32GtS,
+            Self::I32GtU => WasmMnemonic::I32GtU,
+            Self::I32LeS => WasmMnemonic::I32LeS,
+            Self::I32LeU => WasmMnemonic::I32LeU,
+            Self::I32GeS => WasmMnemonic::I32GeS,
+            Self::I32GeU => WasmMnemonic::I32GeU,
+            Self::I32Clz => WasmMnemonic::I32Clz,
+            Self::I32Ctz => WasmMnemonic::I32Ctz,
+            Self::I32Popcnt => WasmMnemonic::I32Popcnt,
+            Self::I32Add => WasmMnemonic::I32Add,
+            Self::I32Sub => WasmMnemonic::I32Sub,
+            Self::I32Mul => WasmMnemonic::I32Mul,
+            Self::I32DivS(..) => WasmMnemonic::I32DivS,
+            Self::I32DivU(..) => WasmMnemonic::I32DivU,
+            Self::I32RemS(..) => WasmMnemonic::I32RemS,
+            Self::I32RemU(..) => WasmMnemonic::I32RemU,
+            Self::I32And => WasmMnemonic::I32And,
+            Self::I32Or => WasmMnemonic::I32Or,
+            Self::I32Xor => WasmMnemonic::I32Xor,
+            Self::I32Shl => WasmMnemonic::I32Shl,
+            Self::I32ShrS => WasmMnemonic::I32ShrS,
+            Self::I32ShrU => WasmMnemonic::I32ShrU,
+            Self::I32Rotl => WasmMnemonic::I32Rotl,
+            Self::I32Rotr => WasmMnemonic::I32Rotr,
+            Self::I64Eqz => WasmMnemonic::I64Eqz,
+            Self::I64Eq => WasmMnemonic::I64Eq,
+            Self::I64Ne => WasmMnemonic::I64Ne,
+            Self::I64LtS => WasmMnemonic::I64LtS,
+            Self::I64LtU => WasmMnemonic::I64LtU,
+            Self::I64GtS => WasmMnemonic::I64GtS,
+            Self::I64GtU => WasmMnemonic::I64GtU,
+            Self::I64LeS => WasmMnemonic::I64LeS,
+            Self::I64LeU => WasmMnemonic::I64LeU,
+            Self::I64GeS => WasmMnemonic::I64GeS,
+            Self::I64GeU => WasmMnemonic::I64GeU,
+            Self::I64Clz => WasmMnemonic::I64Clz,
+            Self::I64Ctz => WasmMnemonic::I64Ctz,
+            Self::I64Popcnt => WasmMnemonic::I64Popcnt,
+            Self::I64Add => WasmMnemonic::I64Add,
+            Self::I64Sub => WasmMnemonic::I64Sub,
+            Self::I64Mul => WasmMnemonic::I64Mul,
+            Self::I64DivS(..) => WasmMnemonic::I64DivS,
+            Self::I64DivU(..) => WasmMnemonic::I64DivU,
+            Self::I64RemS(..) => WasmMnemonic::I64RemS,
+            Self::I64RemU(..) => WasmMnemonic::I64RemU,
+            Self::I64And => WasmMnemonic::I64And,
+            Self::I64Or => WasmMnemonic::I64Or,
+            Self::I64Xor => WasmMnemonic::I64Xor,
+            Self::I64Shl => WasmMnemonic::I64Shl,
+            Self::I64ShrS => WasmMnemonic::I64ShrS,
+            Self::I64ShrU => WasmMnemonic::I64ShrU,
+            Self::I64Rotl => WasmMnemonic::I64Rotl,
+            Self::I64Rotr => WasmMnemonic::I64Rotr,
+            Self::F32Eq => WasmMnemonic::F32Eq,
+            Self::F32Ne => WasmMnemonic::F32Ne,
+            Self::F32Lt => WasmMnemonic::F32Lt,
+            Self::F32Gt => WasmMnemonic::F32Gt,
+            Self::F32Le => WasmMnemonic::F32Le,
+            Self::F32Ge => WasmMnemonic::F32Ge,
+            Self::F32Abs => WasmMnemonic::F32Abs,
+            Self::F32Neg => WasmMnemonic::F32Neg,
+            Self::F32Ceil => WasmMnemonic::F32Ceil,
+            Self::F32Floor => WasmMnemonic::F32Floor,
+            Self::F32Trunc => WasmMnemonic::F32Trunc,
+            Self::F32Nearest => WasmMnemonic::F32Nearest,
+            Self::F32Sqrt => WasmMnemonic::F32Sqrt,
+            Self::F32Add => WasmMnemonic::F32Add,
+            Self::F32Sub => WasmMnemonic::F32Sub,
+            Self::F32Mul => WasmMnemonic::F32Mul,
+            Self::F32Div => WasmMnemonic::F32Div,
+            Self::F32Min => WasmMnemonic::F32Min,
+            Self::F32Max => WasmMnemonic::F32Max,
+            Self::F32Copysign => WasmMnemonic::F32Copysign,
+            Self::F64Eq => WasmMnemonic::F64Eq,
+            Self::F64Ne => WasmMnemonic::F64Ne,
+            Self::F64Lt => WasmMnemonic::F64Lt,
+            Self::F64Gt => WasmMnemonic::F64Gt,
+            Self::F64Le => WasmMnemonic::F64Le,
+            Self::F64Ge => WasmMnemonic::F64Ge,
+            Self::F64Abs => WasmMnemonic::F64Abs,
+            Self::F64Neg => WasmMnemonic::F64Neg,
+            Self::F64Ceil => WasmMnemonic::F64Ceil,
+            Self::F64Floor => WasmMnemonic::F64Floor,
+            Self::F64Trunc => WasmMnemonic::F64Trunc,
+            Self::F64Nearest => WasmMnemonic::F64Nearest,
+            Self::F64Sqrt => WasmMnemonic::F64Sqrt,
+            Self::F64Add => WasmMnemonic::F64Add,
+            Self::F64Sub => WasmMnemonic::F64Sub,
+            Self::F64Mul => WasmMnemonic::F64Mul,
+            Self::F64Div => WasmMnemonic::F64Div,
+            Self::F64Min => WasmMnemonic::F64Min,
+            Self::F64Max => WasmMnemonic::F64Max,
+            Self::F64Copysign => WasmMnemonic::F64Copysign,
+            Self::I64Extend8S => WasmMnemonic::I64Extend8S,
+            Self::I64Extend16S => WasmMnemonic::I64Extend16S,
+            Self::I64Extend32S => WasmMnemonic::I64Extend32S,
+            Self::I64ExtendI32S => WasmMnemonic::I64ExtendI32S,
+            Self::I64ExtendI32U => WasmMnemonic::I64ExtendI32U,
+            Self::I32WrapI64 => WasmMnemonic::I32WrapI64,
+            Self::I32Extend8S => WasmMnemonic::I32Extend8S,
+            Self::I32Extend16S => WasmMnemonic::I32Extend16S,
+            Self::I32TruncF32S(..) => WasmMnemonic::I32TruncF32S,
+            Self::I32TruncF32U(..) => WasmMnemonic::I32TruncF32U,
+            Self::I32TruncF64S(..) => WasmMnemonic::I32TruncF64S,
+            Self::I32TruncF64U(..) => WasmMnemonic::I32TruncF64U,
+            Self::I64TruncF32S(..) => WasmMnemonic::I64TruncF32S,
+            Self::I64TruncF32U(..) => WasmMnemonic::I64TruncF32U,
+            Self::I64TruncF64S(..) => WasmMnemonic::I64TruncF64S,
+            Self::I64TruncF64U(..) => WasmMnemonic::I64TruncF64U,
+            Self::F32ConvertI32S => WasmMnemonic::F32ConvertI32S,
+            Self::F32ConvertI32U => WasmMnemonic::F32ConvertI32U,
+            Self::F32ConvertI64S => WasmMnemonic::F32ConvertI64S,
+            Self::F32ConvertI64U => WasmMnemonic::F32ConvertI64U,
+            Self::F32DemoteF64 => WasmMnemonic::F32DemoteF64,
+            Self::F64ConvertI32S => WasmMnemonic::F64ConvertI32S,
+            Self::F64ConvertI32U => WasmMnemonic::F64ConvertI32U,
+            Self::F64ConvertI64S => WasmMnemonic::F64ConvertI64S,
+            Self::F64ConvertI64U => WasmMnemonic::F64ConvertI64U,
+            Self::F64PromoteF32 => WasmMnemonic::F64PromoteF32,
+            Self::I32ReinterpretF32 => WasmMnemonic::I32ReinterpretF32,
+            Self::I64ReinterpretF64 => WasmMnemonic::I64ReinterpretF64,
+            Self::F32ReinterpretI32 => WasmMnemonic::F32ReinterpretI32,
+            Self::F64ReinterpretI64 => WasmMnemonic::F64ReinterpretI64,
+            Self::I32TruncSatF32S => WasmMnemonic::I32TruncSatF32S,
+            Self::I32TruncSatF32U => WasmMnemonic::I32TruncSatF32U,
+            Self::I32TruncSatF64S => WasmMnemonic::I32TruncSatF64S,
+            Self::I32TruncSatF64U => WasmMnemonic::I32TruncSatF64U,
+            Self::I64TruncSatF32S => WasmMnemonic::I64TruncSatF32S,
+            Self::I64TruncSatF32U => WasmMnemonic::I64TruncSatF32U,
+            Self::I64TruncSatF64S => WasmMnemonic::I64TruncSatF64S,
+            Self::I64TruncSatF64U => WasmMnemonic::I64TruncSatF64U,
+            Self::FusedI32AddConst(..) => WasmMnemonic::I32Add,
+            Self::FusedI32SetConst(..) => WasmMnemonic::I32Const,
+            Self::FusedI64SetConst(..) => WasmMnemonic::I64Const,
+            Self::FusedI32LoadAt(..) => WasmMnemonic::I32Load,
+            Self::FusedI32StoreAt(..) => WasmMnemonic::I32Store,
+            Self::FusedI32AddI(..) => WasmMnemonic::I32Add,
+            Self::FusedI32AndI(..) => WasmMnemonic::I32And,
+            Self::FusedI32OrI(..) => WasmMnemonic::I32Or,
+            Self::FusedI32XorI(..) => WasmMnemonic::I32Xor,
+            Self::FusedI32ShlI(..) => WasmMnemonic::I32Shl,
+            Self::FusedI32ShrSI(..) => WasmMnemonic::I32ShrS,
+            Self::FusedI32ShrUI(..) => WasmMnemonic::I32ShrU,
+            Self::FusedI64AddI(..) => WasmMnemonic::I64Add,
+            Self::FusedI64AndI(..) => WasmMnemonic::I64And,
+            Self::FusedI64OrI(..) => WasmMnemonic::I64Or,
+            Self::FusedI64XorI(..) => WasmMnemonic::I64Xor,
+            Self::FusedI64ShlI(..) => WasmMnemonic::I64Shl,
+            Self::FusedI64ShrSI(..) => WasmMnemonic::I64ShrS,
+            Self::FusedI64ShrUI(..) => WasmMnemonic::I64ShrU,
+            Self::FusedI32BrZ(..) => WasmMnemonic::I32Eqz,
+            Self::FusedI32BrEq(..) => WasmMnemonic::I32Eq,
+            Self::FusedI32BrNe(..) => WasmMnemonic::I32Ne,
+            Self::FusedI32BrLtS(..) => WasmMnemonic::I32LtS,
+            Self::FusedI32BrLtU(..) => WasmMnemonic::I32LtU,
+            Self::FusedI32BrGtS(..) => WasmMnemonic::I32GtS,
+            Self::FusedI32BrGtU(..) => WasmMnemonic::I32GtU,
+            Self::FusedI32BrLeS(..) => WasmMnemonic::I32LeS,
+            Self::FusedI32BrLeU(..) => WasmMnemonic::I32LeU,
+            Self::FusedI32BrGeS(..) => WasmMnemonic::I32GeS,
+            Self::FusedI32BrGeU(..) => WasmMnemonic::I32GeU,
+            Self::FusedI64BrZ(..) => WasmMnemonic::I64Eqz,
+            Self::FusedI64BrEq(..) => WasmMnemonic::I64Eq,
+            Self::FusedI64BrNe(..) => WasmMnemonic::I64Ne,
+            Self::FusedI64BrLtS(..) => WasmMnemonic::I64LtS,
+            Self::FusedI64BrLtU(..) => WasmMnemonic::I64LtU,
+            Self::FusedI64BrGtS(..) => WasmMnemonic::I64GtS,
+            Self::FusedI64BrGtU(..) => WasmMnemonic::I64GtU,
+            Self::FusedI64BrLeS(..) => WasmMnemonic::I64LeS,
+            Self::FusedI64BrLeU(..) => WasmMnemonic::I64LeU,
+            Self::FusedI64BrGeS(..) => WasmMnemonic::I64GeS,
+            Self::FusedI64BrGeU(..) => WasmMnemonic::I64GeU,
+        }
+    }
+
+    /// Returns whether the instruction was produced by the fusion pass.
+    pub fn is_fused(&self) -> bool {
+        matches!(
+            self,
+            Self::FusedI32AddConst(..)
+                | Self::FusedI32SetConst(..)
+                | Self::FusedI64SetConst(..)
+                | Self::FusedI32LoadAt(..)
+                | Self::FusedI32StoreAt(..)
+                | Self::FusedI32AddI(..)
+                | Self::FusedI32AndI(..)
+                | Self::FusedI32OrI(..)
+                | Self::FusedI32XorI(..)
+                | Self::FusedI32ShlI(..)
+                | Self::FusedI32ShrSI(..)
+                | Self::FusedI32ShrUI(..)
+                | Self::FusedI64AddI(..)
+                | Self::FusedI64AndI(..)
+                | Self::FusedI64OrI(..)
+                | Self::FusedI64XorI(..)
+                | Self::FusedI64ShlI(..)
+                | Self::FusedI64ShrSI(..)
+                | Self::FusedI64ShrUI(..)
+                | Self::FusedI32BrZ(..)
+                | Self::FusedI32BrEq(..)
+                | Self::FusedI32BrNe(..)
+                | Self::FusedI32BrLtS(..)
+                | Self::FusedI32BrLtU(..)
+                | Self::FusedI32BrGtS(..)
+                | Self::FusedI32BrGtU(..)
+                | Self::FusedI32BrLeS(..)
+                | Self::FusedI32BrLeU(..)
+                | Self::FusedI32BrGeS(..)
+                | Self::FusedI32BrGeU(..)
+                | Self::FusedI64BrZ(..)
+                | Self::FusedI64BrEq(..)
+                | Self::FusedI64BrNe(..)
+                | Self::FusedI64BrLtS(..)
+                | Self::FusedI64BrLtU(..)
+                | Self::FusedI64BrGtS(..)
+                | Self::FusedI64BrGtU(..)
+                | Self::FusedI64BrLeS(..)
+                | Self::FusedI64BrLeU(..)
+                | Self::FusedI64BrGeS(..)
+                | Self::FusedI64BrGeU(..)
+        )
+    }
+
+    /// Returns the position in the source bytecode, if the instruction can trap.
+    pub fn position(&self) -> Option<ExceptionPosition> {
+        match *self {
+            Self::Unreachable(position)
+            | Self::MemoryCopy(_, _, position)
+            | Self::MemoryFill(_, position)
+            | Self::MemoryInit(_, _, position)
+            | Self::I32DivS(position)
+            | Self::I32DivU(position)
+            | Self::I32RemS(position)
+            | Self::I32RemU(position)
+            | Self::I64DivS(position)
+            | Self::I64DivU(position)
+            | Self::I64RemS(position)
+            | Self::I64RemU(position)
+            | Self::I32TruncF32S(position)
+            | Self::I32TruncF32U(position)
+            | Self::I32TruncF64S(position)
+            | Self::I32TruncF64U(position)
+            | Self::I64TruncF32S(position)
+            | Self::I64TruncF32U(position)
+            | Self::I64TruncF64S(position)
+            | Self::I64TruncF64U(position) => Some(position),
+            Self::NotSupported(_, position)
+            | Self::Call(_, position)
+            | Self::CallIndirect(_, _, position)
+            | Self::TableGet(_, position)
+            | Self::TableSet(_, position)
+            | Self::I32Load(_, _, position)
+            | Self::I64Load(_, _, position)
+            | Self::I32Load8S(_, _, position)
+            | Self::I32Load8U(_, _, position)
+            | Self::I32Load16S(_, _, position)
+            | Self::I32Load16U(_, _, position)
+            | Self::I64Load8S(_, _, position)
+            | Self::I64Load8U(_, _, position)
+            | Self::I64Load16S(_, _, position)
+            | Self::I64Load16U(_, _, position)
+            | Self::I64Load32S(_, _, position)
+            | Self::I64Load32U(_, _, position)
+            | Self::F32Load(_, _, position)
+            | Self::F64Load(_, _, position)
+            | Self::I32Store(_, _, position)
+            | Self::I64Store(_, _, position)
+            | Self::I32Store8(_, _, position)
+            | Self::I32Store16(_, _, position)
+            | Self::I64Store8(_, _, position)
+            | Self::I64Store16(_, _, position)
+            | Self::I64Store32(_, _, position)
+            | Self::F32Store(_, _, position)
+            | Self::F64Store(_, _, position)
+            | Self::FusedI32LoadAt(_, _, _, position)
+            | Self::FusedI32StoreAt(_, _, _, position) => Some(position),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -468,6 +859,27 @@ impl WasmImc {
     }
 }
 
+impl fmt::Display for WasmImc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fused = if self.instruction.is_fused() {
+            '*'
+        } else {
+            ' '
+        };
+        write!(
+            f,
+            "[{:3}] {}{:?}",
+            self.stack_level.as_usize(),
+            fused,
+            self.instruction
+        )?;
+        if let Some(position) = self.instruction.position() {
+            write!(f, " @{:#x}", position.position())?;
+        }
+        Ok(())
+    }
+}
+
 impl From<WasmImInstruction> for WasmImc {
     #[inline]
     fn from(val: WasmImInstruction) -> Self {
@@ -7,17 +7,19 @@ use crate::stack::*;
 use crate::wasm::*;
 use crate::*;
 use core::error::Error;
-use core::iter;
 use core::mem::{size_of, transmute};
 use core::ops::Neg;
 use libm::{ceil, ceilf, floor, floorf, rint, rintf, trunc, truncf};
 
-const INITIAL_VALUE_STACK_SIZE: usize = 512;
+/// A callback that observes runtime errors raised by the interpreter
+pub type WasmTrapHook<'a> = Box<dyn FnMut(&WasmRuntimeError) + 'a>;
 
 /// Wasm Intermediate Code Interpreter
 pub struct WasmInterpreter<'a> {
     instance: &'a WasmInstance,
     func_index: usize,
+    trap_hook: Option<WasmTrapHook<'a>>,
+    loop_watchdog: Option<u32>,
 }
 
 impl<'a> WasmInterpreter<'a> {
@@ -26,8 +28,29 @@ impl<'a> WasmInterpreter<'a> {
         Self {
             instance,
             func_index: 0,
+            trap_hook: None,
+            loop_watchdog: instance.loop_watchdog(),
         }
     }
+
+    /// Sets a callback that is invoked once for each trap, just before it is returned.
+    ///
+    /// The callback can only observe the error; execution cannot be resumed from it.
+    #[inline]
+    pub fn on_trap(&mut self, hook: WasmTrapHook<'a>) {
+        self.trap_hook = Some(hook);
+    }
+
+    /// Traps with [`WasmRuntimeErrorKind::PossibleInfiniteLoop`] when a function branches back
+    /// to the same loop header `iterations` times in a row without changing its local variables
+    /// or the values on its stack.
+    ///
+    /// This is a heuristic: changes to the memory or globals are not counted as progress.
+    /// The default is taken from [`WasmInstance::set_loop_watchdog`].
+    #[inline]
+    pub fn set_loop_watchdog(&mut self, iterations: u32) {
+        self.loop_watchdog = Some(iterations);
+    }
 }
 
 impl WasmInterpreter<'_> {
@@ -51,14 +74,28 @@ impl WasmInterpreter<'_> {
             .unwrap_or(0)
             + ex_position.position();
 
-        Box::new(WasmRuntimeError {
+        let error = WasmRuntimeError {
             kind,
             file_position,
             function: self.func_index,
             function_name,
             position: ex_position.position(),
             mnemonic,
-        })
+        };
+        if let Some(hook) = self.trap_hook.as_mut() {
+            hook(&error);
+        }
+
+        Box::new(error)
+    }
+
+    /// Reports an access past the end of a table as a table trap rather than a memory trap.
+    #[inline]
+    fn table_error(kind: WasmRuntimeErrorKind) -> WasmRuntimeErrorKind {
+        match kind {
+            WasmRuntimeErrorKind::OutOfBounds => WasmRuntimeErrorKind::TableOutOfBounds,
+            kind => kind,
+        }
     }
 
     #[inline]
@@ -69,43 +106,127 @@ impl WasmInterpreter<'_> {
         locals: &[WasmUnionValue],
         result_types: &[WasmValType],
     ) -> Result<Option<WasmValue>, Box<dyn Error>> {
-        if locals.len() < code_block.local_types().len() {
+        self._invoke(
+            func_index,
+            code_block,
+            locals,
+            result_types.len(),
+            |results| {
+                results
+                    .first()
+                    .zip(result_types.first())
+                    .map(|(v, t)| unsafe { v.get_by_type(*t) })
+            },
+        )
+    }
+
+    /// Same as [`WasmInterpreter::invoke`], but returns all the results in order.
+    #[inline]
+    pub fn invoke_multi(
+        &mut self,
+        func_index: usize,
+        code_block: &WasmCodeBlock,
+        locals: &[WasmUnionValue],
+        result_types: &[WasmValType],
+    ) -> Result<Vec<WasmValue>, Box<dyn Error>> {
+        self._invoke(
+            func_index,
+            code_block,
+            locals,
+            result_types.len(),
+            |results| {
+                results
+                    .iter()
+                    .zip(result_types)
+                    .map(|(v, t)| unsafe { v.get_by_type(*t) })
+                    .collect()
+            },
+        )
+    }
+
+    /// Invokes the function at `func_index` of the instance with `args`.
+    ///
+    /// Unlike [`WasmInterpreter::invoke`], the compiled code block of the function is
+    /// looked up from the instance, and the arguments are checked against its parameter types.
+    pub fn invoke_function(
+        &mut self,
+        func_index: usize,
+        args: &[WasmValue],
+    ) -> Result<Option<WasmValue>, Box<dyn Error>> {
+        let instance = self.instance;
+        let runnable = instance.function_by_index(func_index)?;
+        let (code_block, locals) = runnable.prepare(args)?;
+        self.invoke(
+            func_index,
+            code_block,
+            locals.as_slice(),
+            runnable.function().result_types(),
+        )
+    }
+
+    fn _invoke<F, R>(
+        &mut self,
+        func_index: usize,
+        code_block: &WasmCodeBlock,
+        locals: &[WasmUnionValue],
+        n_results: usize,
+        f: F,
+    ) -> Result<R, Box<dyn Error>>
+    where
+        F: FnOnce(&[WasmUnionValue]) -> R,
+    {
+        let layout = code_block.frame_layout();
+        if locals.len() < layout.locals_size() {
             return Err(WasmRuntimeErrorKind::InvalidParameter.into());
         }
+        // Each invocation owns its stack, so a host function may call back into the instance.
         let mut heap = StackHeap::with_capacity(0x10000);
-        let local2 = heap.alloc_slice(locals.len(), WasmUnionValue::zero());
+        let frame = heap.alloc_slice(
+            locals.len() + layout.value_stack_size(),
+            WasmUnionValue::zero(),
+        );
+        let (local2, value_stack) = frame.split_at_mut(locals.len());
         local2.copy_from_slice(locals);
-        self._interpret(
+        let result_stack_level = self._interpret(
             func_index,
             code_block,
             LocalVariables::new(local2),
-            result_types,
+            StackFrame::new(value_stack),
             &mut heap,
-        )
+        )?;
+        let offset = result_stack_level.as_usize();
+        Ok(f(&value_stack[offset..offset + n_results]))
     }
 
+    /// Returns the stack level of the first result on success.
     fn _interpret(
         &mut self,
         func_index: usize,
         code_block: &WasmCodeBlock,
         mut locals: LocalVariables,
-        result_types: &[WasmValType],
+        mut value_stack: StackFrame,
         heap: &mut StackHeap,
-    ) -> Result<Option<WasmValue>, Box<dyn Error>> {
+    ) -> Result<StackLevel, Box<dyn Error>> {
+        // borrowed in place of memory 0 by modules without memory, which never access it
+        let no_memory = WasmMemory::zero();
         macro_rules! GET_MEMORY {
             ($self:ident) => {
+                Ok::<_, WasmRuntimeErrorKind>($self.instance.memory(0).unwrap_or(&no_memory))
+            };
+            ($self:ident, $memory_index:expr) => {
                 $self
                     .instance
-                    .module()
-                    .memories()
-                    .get(0)
-                    .ok_or(WasmRuntimeErrorKind::OutOfMemory)
+                    .memory($memory_index as usize)
+                    .map_err(|_| WasmRuntimeErrorKind::OutOfMemory)
             };
         }
 
         macro_rules! BORROW_MEMORY {
             ($self:ident) => {
-                GET_MEMORY!($self).and_then(|v| v.try_borrow())
+                GET_MEMORY!($self).and_then(|v| v.try_borrow_committed())
+            };
+            ($self:ident, $memory_index:expr) => {
+                GET_MEMORY!($self, $memory_index).and_then(|v| v.try_borrow_committed())
             };
         }
 
@@ -114,53 +235,163 @@ impl WasmInterpreter<'_> {
         let mut codes = WasmIntermediateCodeStream::from_codes(code_block.intermediate_codes())
             .ok_or(WasmRuntimeErrorKind::InternalInconsistency)?;
 
-        let mut value_stack = StackFrame::new(heap.alloc_slice(
-            code_block.max_value_stack().as_usize(),
-            WasmUnionValue::zero(),
-        ));
+        let mut watchdog = self.loop_watchdog.map(LoopWatchdog::new);
+
+        macro_rules! BRANCH {
+            ($self:ident, $target:expr, $live_level:expr) => {
+                let target = $target;
+                if let Some(watchdog) = watchdog.as_mut() {
+                    let live_level = $live_level;
+                    if target < codes.position()
+                        && watchdog.is_stuck(target, &locals, value_stack.values_below(live_level))
+                    {
+                        return Err($self.error(
+                            WasmRuntimeErrorKind::PossibleInfiniteLoop,
+                            WasmMnemonic::Loop,
+                            ExceptionPosition::UNKNOWN,
+                        ));
+                    }
+                }
+                codes.set_position(target)?;
+            };
+        }
 
         let mut result_stack_level = StackLevel::zero();
 
         let mut memory = BORROW_MEMORY!(self)?;
 
+        macro_rules! MEM_READ {
+            ($data_type:ident, $mnemonic:ident, $offset:ident, $memory_index:ident, $ex_position:ident, $index:ident, $memory:ident, ) => {
+                match WasmMemory::effective_address::<$data_type>($offset, $index, $memory.len()) {
+                    Ok(ea) => unsafe {
+                        let p = $memory.as_ptr().byte_add(ea) as *const $data_type;
+                        p.read_unaligned()
+                    },
+                    Err(_) => {
+                        // Pages not yet allocated by a lazy memory read as zero
+                        let byte_len = GET_MEMORY!(self, $memory_index)?.byte_len();
+                        let ea =
+                            WasmMemory::effective_address::<$data_type>($offset, $index, byte_len)
+                                .map_err(|e| {
+                                    self.error(e, WasmMnemonic::$mnemonic, $ex_position)
+                                })?;
+                        // but an access across the end keeps the bytes already allocated
+                        let mut bytes = [0u8; size_of::<$data_type>()];
+                        if let Some(committed) = $memory.get(ea..) {
+                            bytes[..committed.len()].copy_from_slice(committed);
+                        }
+                        <$data_type>::from_le_bytes(bytes)
+                    }
+                }
+            };
+        }
+
         macro_rules! MEM_LOAD {
-            ($stor_type:ident, $data_type:ident, $mnemonic:ident, $offset:ident, $ex_position:ident, $code:ident, $value_stack:ident, $memory:ident, ) => {
+            ($stor_type:ident, $data_type:ident, $mnemonic:ident, $offset:ident, $memory_index:ident, $ex_position:ident, $code:ident, $value_stack:ident, $memory:ident, ) => {
                 #[cfg(test)]
-                assert_matches!($code.instruction(), WasmImInstruction::$mnemonic(_, _));
+                assert_matches!($code.instruction(), WasmImInstruction::$mnemonic(_, _, _));
 
                 let var = $value_stack.get_mut($code.base_stack_level());
                 let index = unsafe { var.get_u32() };
-                let ea = WasmMemory::effective_address::<$data_type>($offset, index, $memory.len())
-                    .map_err(|e| self.error(e, WasmMnemonic::$mnemonic, $ex_position))?;
+                let data = if $memory_index == 0 {
+                    MEM_READ!(
+                        $data_type,
+                        $mnemonic,
+                        $offset,
+                        $memory_index,
+                        $ex_position,
+                        index,
+                        $memory,
+                    )
+                } else {
+                    let other = BORROW_MEMORY!(self, $memory_index)?;
+                    MEM_READ!(
+                        $data_type,
+                        $mnemonic,
+                        $offset,
+                        $memory_index,
+                        $ex_position,
+                        index,
+                        other,
+                    )
+                };
+                *var = (data as $stor_type).into();
+            };
+        }
 
+        macro_rules! MEM_WRITE {
+            ($data_type:ident, $mnemonic:ident, $offset:ident, $memory_index:ident, $ex_position:ident, $index:ident, $storage:ident, $memory:ident, ) => {
+                let ea = match WasmMemory::effective_address::<$data_type>(
+                    $offset,
+                    $index,
+                    $memory.len(),
+                ) {
+                    Ok(ea) => ea,
+                    Err(_) => {
+                        // A lazy memory allocates pages on the first write
+                        drop($memory);
+                        let mem = GET_MEMORY!(self, $memory_index)?;
+                        let ea = WasmMemory::effective_address::<$data_type>(
+                            $offset,
+                            $index,
+                            mem.byte_len(),
+                        )
+                        .and_then(|ea| mem.commit(ea + size_of::<$data_type>()).map(|_| ea))
+                        .map_err(|e| self.error(e, WasmMnemonic::$mnemonic, $ex_position))?;
+                        $memory = BORROW_MEMORY!(self, $memory_index)?;
+                        ea
+                    }
+                };
+                if $memory_index == 0 {
+                    self.instance
+                        .check_protection(ea, size_of::<$data_type>())
+                        .map_err(|e| self.error(e, WasmMnemonic::$mnemonic, $ex_position))?;
+                }
                 unsafe {
-                    let p = $memory.as_ptr().byte_add(ea) as *const $data_type;
-                    let data = p.read_volatile() as $stor_type;
-                    *var = data.into();
+                    let p = $memory.as_mut_ptr().byte_add(ea) as *mut $data_type;
+                    p.write_unaligned($storage as $data_type);
                 }
             };
         }
 
         macro_rules! MEM_STORE {
-            ($stor_type:ident, $data_type:ident, $mnemonic:ident, $offset:ident, $ex_position:ident, $code:ident, $value_stack:ident, $memory:ident, ) => {
+            ($stor_type:ident, $data_type:ident, $mnemonic:ident, $offset:ident, $memory_index:ident, $ex_position:ident, $code:ident, $value_stack:ident, $memory:ident, ) => {
                 #[cfg(test)]
-                assert_matches!($code.instruction(), WasmImInstruction::$mnemonic(_, _));
+                assert_matches!($code.instruction(), WasmImInstruction::$mnemonic(_, _, _));
 
                 let stack_level = $code.base_stack_level();
                 let index = unsafe { $value_stack.get(stack_level).get_u32() };
                 let storage: $stor_type =
                     unsafe { $value_stack.get(stack_level.succ(1)).unsafe_into() };
-                let ea = WasmMemory::effective_address::<$data_type>($offset, index, $memory.len())
-                    .map_err(|e| self.error(e, WasmMnemonic::$mnemonic, $ex_position))?;
-                unsafe {
-                    let p = $memory.as_mut_ptr().byte_add(ea) as *mut $data_type;
-                    p.write_volatile(storage as $data_type);
+                if $memory_index == 0 {
+                    MEM_WRITE!(
+                        $data_type,
+                        $mnemonic,
+                        $offset,
+                        $memory_index,
+                        $ex_position,
+                        index,
+                        storage,
+                        $memory,
+                    );
+                } else {
+                    let mut other = BORROW_MEMORY!(self, $memory_index)?;
+                    MEM_WRITE!(
+                        $data_type,
+                        $mnemonic,
+                        $offset,
+                        $memory_index,
+                        $ex_position,
+                        index,
+                        storage,
+                        other,
+                    );
                 }
             };
         }
 
         macro_rules! DIV_OP {
-            ($map_lhs:ident, $get_rhs:ident, $mnemonic:ident, $opr:ident, $ex_position:ident, $code:ident, $value_stack:ident, ) => {
+            ($map_lhs:ident, $get_rhs:ident, $mnemonic:ident, $opr:ident, $ex_position:ident, $code:ident, $value_stack:ident, $($min:expr)?) => {
                 #[cfg(test)]
                 assert_matches!($code.instruction(), WasmImInstruction::$mnemonic(_));
 
@@ -174,12 +405,45 @@ impl WasmInterpreter<'_> {
                         $ex_position,
                     ));
                 }
+                // MIN / -1 is not representable in signed division
+                $(
+                    if rhs == -1 && unsafe { lhs.$get_rhs() } == $min {
+                        return Err(self.error(
+                            WasmRuntimeErrorKind::IntegerOverflow,
+                            WasmMnemonic::$mnemonic,
+                            $ex_position,
+                        ));
+                    }
+                )?
                 unsafe {
                     lhs.$map_lhs(|lhs| lhs.$opr(rhs));
                 }
             };
         }
 
+        macro_rules! TRUNC_OP {
+            ($get:ident, $write:ident, $to:ty, $min:literal, $max:literal, $mnemonic:ident, $ex_position:ident, $code:ident, $value_stack:ident, ) => {
+                #[cfg(test)]
+                assert_matches!($code.instruction(), WasmImInstruction::$mnemonic(_));
+
+                // Both bounds are exclusive and exactly representable in the source type
+                let result = Self::unary_op($code, &mut $value_stack, |var| unsafe {
+                    let val = var.$get();
+                    if val.is_nan() {
+                        Err(WasmRuntimeErrorKind::InvalidConversionToInteger)
+                    } else if val > $min && val < $max {
+                        var.$write(val.to_int_unchecked::<$to>());
+                        Ok(())
+                    } else {
+                        Err(WasmRuntimeErrorKind::IntegerOverflow)
+                    }
+                });
+                if let Err(kind) = result {
+                    return Err(self.error(kind, WasmMnemonic::$mnemonic, $ex_position));
+                }
+            };
+        }
+
         loop {
             let code = codes.fetch();
 
@@ -207,16 +471,16 @@ impl WasmInterpreter<'_> {
                 WasmImInstruction::If(target) => {
                     let cc = unsafe { value_stack.get(code.base_stack_level()).get_bool() };
                     if !cc {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::Br(target) => {
-                    codes.set_position(target)?;
+                    BRANCH!(self, target, code.base_stack_level());
                 }
                 WasmImInstruction::BrIf(target) => {
                     let cc = unsafe { value_stack.get(code.base_stack_level()).get_bool() };
                     if cc {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::BrTable(ref table) => {
@@ -224,20 +488,20 @@ impl WasmInterpreter<'_> {
                         value_stack.get(code.base_stack_level()).get_u32() as usize
                     });
                     let target = unsafe { *table.get_unchecked(index) };
-                    codes.set_position(target)?;
+                    BRANCH!(self, target, code.base_stack_level());
                 }
 
                 WasmImInstruction::BrUnwind(target, target_stack_level) => {
                     let source_stack_level = code.base_stack_level();
                     value_stack.set(target_stack_level, *value_stack.get(source_stack_level));
-                    codes.set_position(target)?;
+                    BRANCH!(self, target, unsafe { target_stack_level.succ(1) });
                 }
                 WasmImInstruction::BrIfUnwind(target, target_stack_level) => {
                     let cc = unsafe { value_stack.get(code.base_stack_level().succ(1)).get_bool() };
                     if cc {
                         let source_stack_level = code.base_stack_level();
                         value_stack.set(target_stack_level, *value_stack.get(source_stack_level));
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, unsafe { target_stack_level.succ(1) });
                     }
                 }
 
@@ -263,18 +527,21 @@ impl WasmInterpreter<'_> {
                     )?;
                     memory = BORROW_MEMORY!(self)?;
                 }
-                WasmImInstruction::CallIndirect(type_index, ex_position) => {
+                WasmImInstruction::CallIndirect(type_index, table_index, ex_position) => {
                     let opcode = WasmMnemonic::CallIndirect;
                     let index =
                         unsafe { value_stack.get(code.base_stack_level()).get_i32() as usize };
-                    let func = self.instance.module().elem_get(index).ok_or(self.error(
-                        WasmRuntimeErrorKind::NoMethod,
-                        opcode,
-                        ex_position,
-                    ))?;
-                    if func.type_index() != type_index {
+                    let func = self
+                        .instance
+                        .module()
+                        .elem_get(table_index, index)
+                        .map_err(|kind| self.error(kind, opcode, ex_position))?;
+                    // identical types declared at different indices are the same type
+                    if func.type_index() != type_index
+                        && func.func_type() != self.instance.module().type_by_index(type_index)
+                    {
                         return Err(self.error(
-                            WasmRuntimeErrorKind::TypeMismatch,
+                            WasmRuntimeErrorKind::IndirectCallTypeMismatch,
                             opcode,
                             ex_position,
                         ));
@@ -331,155 +598,296 @@ impl WasmInterpreter<'_> {
                     global.set_raw_value(*ref_a);
                 }
 
-                WasmImInstruction::I32Load(offset, ex_position) => {
+                WasmImInstruction::TableGet(table_index, ex_position) => {
+                    let table = &self.instance.module().tables()[table_index];
+                    let var = value_stack.get_mut(code.base_stack_level());
+                    let index = unsafe { var.get_u32() } as usize;
+                    let value = table
+                        .get(index)
+                        .map_err(Self::table_error)
+                        .map_err(|e| self.error(e, WasmMnemonic::TableGet, ex_position))?;
+                    *var = WasmUnionValue::from_func_ref(value);
+                }
+                WasmImInstruction::TableSet(table_index, ex_position) => {
+                    let table = &self.instance.module().tables()[table_index];
+                    let stack_level = code.base_stack_level();
+                    let index = unsafe { value_stack.get(stack_level).get_u32() } as usize;
+                    let value = unsafe { value_stack.get(stack_level.succ(1)).get_func_ref() };
+                    table
+                        .set(index, value)
+                        .map_err(Self::table_error)
+                        .map_err(|e| self.error(e, WasmMnemonic::TableSet, ex_position))?;
+                }
+
+                WasmImInstruction::I32Load(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(u32, u32, I32Load, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(u32, u32, I32Load, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I32Load8S(offset, ex_position) => {
+                WasmImInstruction::I32Load8S(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(i32, i8, I32Load8S, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(i32, i8, I32Load8S, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I32Load8U(offset, ex_position) => {
+                WasmImInstruction::I32Load8U(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(u32, u8, I32Load8U, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(u32, u8, I32Load8U, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I32Load16S(offset, ex_position) => {
+                WasmImInstruction::I32Load16S(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(i32, i16, I32Load16S, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(i32, i16, I32Load16S, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I32Load16U(offset, ex_position) => {
+                WasmImInstruction::I32Load16U(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(u32, u16, I32Load16U, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(u32, u16, I32Load16U, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
 
-                WasmImInstruction::I64Load(offset, ex_position) => {
+                WasmImInstruction::I64Load(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(u64, u64, I64Load, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(u64, u64, I64Load, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I64Load8S(offset, ex_position) => {
+                WasmImInstruction::I64Load8S(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(i64, i8, I64Load8S, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(i64, i8, I64Load8S, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I64Load8U(offset, ex_position) => {
+                WasmImInstruction::I64Load8U(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(u64, u8, I64Load8U, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(u64, u8, I64Load8U, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I64Load16S(offset, ex_position) => {
+                WasmImInstruction::I64Load16S(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(i64, i16, I64Load16S, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(i64, i16, I64Load16S, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I64Load16U(offset, ex_position) => {
+                WasmImInstruction::I64Load16U(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(u64, u16, I64Load16U, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(u64, u16, I64Load16U, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I64Load32S(offset, ex_position) => {
+                WasmImInstruction::I64Load32S(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(i64, i32, I64Load32S, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(i64, i32, I64Load32S, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I64Load32U(offset, ex_position) => {
+                WasmImInstruction::I64Load32U(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(u64, u32, I64Load32U, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(u64, u32, I64Load32U, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
 
-                WasmImInstruction::I32Store(offset, ex_position) => {
+                WasmImInstruction::I32Store(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(u32, u32, I32Store, offset, ex_position, code, value_stack, memory, );
+                    MEM_STORE!(u32, u32, I32Store, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I32Store8(offset, ex_position) => {
+                WasmImInstruction::I32Store8(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(u32, u8, I32Store8, offset, ex_position, code, value_stack, memory, );
+                    MEM_STORE!(u32, u8, I32Store8, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I32Store16(offset, ex_position) => {
+                WasmImInstruction::I32Store16(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(u32, u16, I32Store16, offset, ex_position, code, value_stack, memory, );
+                    MEM_STORE!(u32, u16, I32Store16, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
 
-                WasmImInstruction::I64Store(offset, ex_position) => {
+                WasmImInstruction::I64Store(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(u64, u64, I64Store, offset, ex_position, code, value_stack, memory, );
+                    MEM_STORE!(u64, u64, I64Store, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I64Store8(offset, ex_position) => {
+                WasmImInstruction::I64Store8(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(u64, u8, I64Store8, offset, ex_position, code, value_stack, memory, );
+                    MEM_STORE!(u64, u8, I64Store8, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I64Store16(offset, ex_position) => {
+                WasmImInstruction::I64Store16(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(u64, u16, I64Store16, offset, ex_position, code, value_stack, memory, );
+                    MEM_STORE!(u64, u16, I64Store16, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::I64Store32(offset, ex_position) => {
+                WasmImInstruction::I64Store32(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(u64, u32, I64Store32, offset, ex_position, code, value_stack, memory, );
+                    MEM_STORE!(u64, u32, I64Store32, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
 
-                WasmImInstruction::F32Load(offset, ex_position) => {
+                WasmImInstruction::F32Load(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(f32, f32, F32Load, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(f32, f32, F32Load, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::F32Store(offset, ex_position) => {
+                WasmImInstruction::F32Store(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(f32, f32, F32Store, offset, ex_position, code, value_stack, memory, );
+                    MEM_STORE!(f32, f32, F32Store, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
 
-                WasmImInstruction::F64Load(offset, ex_position) => {
+                WasmImInstruction::F64Load(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(f64, f64, F64Load, offset, ex_position, code, value_stack, memory, );
+                    MEM_LOAD!(f64, f64, F64Load, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
-                WasmImInstruction::F64Store(offset, ex_position) => {
+                WasmImInstruction::F64Store(offset, memory_index, ex_position) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(f64, f64, F64Store, offset, ex_position, code, value_stack, memory, );
+                    MEM_STORE!(f64, f64, F64Store, offset, memory_index, ex_position, code, value_stack, memory, );
                 }
 
-                WasmImInstruction::MemorySize => {
+                WasmImInstruction::MemorySize(memory_index) => {
                     let ref_a = value_stack.get_mut(code.base_stack_level());
-                    ref_a.write_i32((memory.len() / WebAssembly::PAGE_SIZE) as i32);
-                }
-                WasmImInstruction::MemoryGrow => {
+                    let mem = if memory_index == 0 {
+                        GET_MEMORY!(self)?
+                    } else {
+                        GET_MEMORY!(self, memory_index)?
+                    };
+                    ref_a.write_i32(mem.size() as i32);
+                }
+                WasmImInstruction::MemoryGrow(memory_index) => {
                     let ref_a = value_stack.get_mut(code.base_stack_level());
                     drop(memory);
 
-                    let mem = GET_MEMORY!(self)?;
+                    let mem = if memory_index == 0 {
+                        GET_MEMORY!(self)?
+                    } else {
+                        GET_MEMORY!(self, memory_index)?
+                    };
                     unsafe {
                         ref_a.map_u32(|v| mem.grow(v).unwrap_or(u32::MAX));
                     }
 
                     memory = BORROW_MEMORY!(self)?;
                 }
-                WasmImInstruction::MemoryCopy(ex_position) => {
+                WasmImInstruction::MemoryCopy(memory_dst, memory_src, ex_position) => {
                     let stack_level = code.base_stack_level();
                     let dest = unsafe { value_stack.get(stack_level).get_u32() } as usize;
                     let src = unsafe { value_stack.get(stack_level.succ(1)).get_u32() } as usize;
                     let count = unsafe { value_stack.get(stack_level.succ(2)).get_u32() } as usize;
 
-                    WasmMemory::check_bound(dest as u64, count, memory.len())
+                    let dst_mem = GET_MEMORY!(self, memory_dst)
+                        .map_err(|k| self.error(k, WasmMnemonic::MemoryCopy, ex_position))?;
+                    let src_mem = GET_MEMORY!(self, memory_src)
                         .map_err(|k| self.error(k, WasmMnemonic::MemoryCopy, ex_position))?;
-                    WasmMemory::check_bound(src as u64, count, memory.len())
+                    WasmMemory::check_bound(dest as u64, count, dst_mem.byte_len())
+                        .map_err(|k| self.error(k, WasmMnemonic::MemoryCopy, ex_position))?;
+                    WasmMemory::check_bound(src as u64, count, src_mem.byte_len())
                         .map_err(|k| self.error(k, WasmMnemonic::MemoryCopy, ex_position))?;
 
-                    if count > 0 {
-                        unsafe {
-                            memory
-                                .as_mut_ptr()
-                                .add(dest)
-                                .copy_from(memory.as_ptr().add(src), count);
+                    if memory_dst == 0 {
+                        self.instance
+                            .check_protection(dest, count)
+                            .map_err(|k| self.error(k, WasmMnemonic::MemoryCopy, ex_position))?;
+                    }
+
+                    if memory_dst == 0 && memory_src == 0 {
+                        let end = dest.max(src) + count;
+                        if end > memory.len() {
+                            drop(memory);
+                            dst_mem.commit(end).map_err(|k| {
+                                self.error(k, WasmMnemonic::MemoryCopy, ex_position)
+                            })?;
+                            memory = BORROW_MEMORY!(self)?;
+                        }
+
+                        if count > 0 {
+                            unsafe {
+                                memory
+                                    .as_mut_ptr()
+                                    .add(dest)
+                                    .copy_from(memory.as_ptr().add(src), count);
+                            }
                         }
+                    } else if count > 0 {
+                        // committing needs the write lock, so memory 0 is released meanwhile
+                        drop(memory);
+                        dst_mem
+                            .commit(dest + count)
+                            .map_err(|k| self.error(k, WasmMnemonic::MemoryCopy, ex_position))?;
+                        src_mem
+                            .commit(src + count)
+                            .map_err(|k| self.error(k, WasmMnemonic::MemoryCopy, ex_position))?;
+                        {
+                            let dst_data = BORROW_MEMORY!(self, memory_dst)?;
+                            let src_data = BORROW_MEMORY!(self, memory_src)?;
+                            unsafe {
+                                dst_data
+                                    .as_mut_ptr()
+                                    .add(dest)
+                                    .copy_from(src_data.as_ptr().add(src), count);
+                            }
+                        }
+                        memory = BORROW_MEMORY!(self)?;
                     }
                 }
-                WasmImInstruction::MemoryFill(ex_position) => {
+                WasmImInstruction::MemoryFill(memory_index, ex_position) => {
                     let stack_level = code.base_stack_level();
                     let base = unsafe { value_stack.get(stack_level).get_u32() } as usize;
                     let val = unsafe { value_stack.get(stack_level.succ(1)).get_u8() };
                     let count = unsafe { value_stack.get(stack_level.succ(2)).get_u32() } as usize;
 
-                    WasmMemory::check_bound(base as u64, count, memory.len())
+                    let mem = GET_MEMORY!(self, memory_index)
+                        .map_err(|k| self.error(k, WasmMnemonic::MemoryFill, ex_position))?;
+                    WasmMemory::check_bound(base as u64, count, mem.byte_len())
                         .map_err(|k| self.error(k, WasmMnemonic::MemoryFill, ex_position))?;
 
-                    if count > 0 {
+                    if memory_index == 0 {
+                        self.instance
+                            .check_protection(base, count)
+                            .map_err(|k| self.error(k, WasmMnemonic::MemoryFill, ex_position))?;
+                        if base + count > memory.len() {
+                            drop(memory);
+                            mem.commit(base + count).map_err(|k| {
+                                self.error(k, WasmMnemonic::MemoryFill, ex_position)
+                            })?;
+                            memory = BORROW_MEMORY!(self)?;
+                        }
+                        if count > 0 {
+                            unsafe {
+                                memory.as_mut_ptr().add(base).write_bytes(val, count);
+                            }
+                        }
+                    } else if count > 0 {
+                        mem.commit(base + count)
+                            .map_err(|k| self.error(k, WasmMnemonic::MemoryFill, ex_position))?;
+                        let other = BORROW_MEMORY!(self, memory_index)?;
                         unsafe {
-                            memory.as_mut_ptr().add(base).write_bytes(val, count);
+                            other.as_mut_ptr().add(base).write_bytes(val, count);
                         }
                     }
                 }
 
+                WasmImInstruction::MemoryInit(data_index, memory_index, ex_position) => {
+                    let stack_level = code.base_stack_level();
+                    let dest = unsafe { value_stack.get(stack_level).get_u32() } as usize;
+                    let src = unsafe { value_stack.get(stack_level.succ(1)).get_u32() } as usize;
+                    let count = unsafe { value_stack.get(stack_level.succ(2)).get_u32() } as usize;
+
+                    // a dropped segment is empty, so only a zero-length copy succeeds
+                    let data = self.instance.data_segment(data_index as usize);
+                    WasmMemory::check_bound(src as u64, count, data.len())
+                        .map_err(|k| self.error(k, WasmMnemonic::MemoryInit, ex_position))?;
+                    let mem = GET_MEMORY!(self, memory_index)
+                        .map_err(|k| self.error(k, WasmMnemonic::MemoryInit, ex_position))?;
+                    WasmMemory::check_bound(dest as u64, count, mem.byte_len())
+                        .map_err(|k| self.error(k, WasmMnemonic::MemoryInit, ex_position))?;
+
+                    if memory_index == 0 {
+                        self.instance
+                            .check_protection(dest, count)
+                            .map_err(|k| self.error(k, WasmMnemonic::MemoryInit, ex_position))?;
+                        if dest + count > memory.len() {
+                            drop(memory);
+                            mem.commit(dest + count).map_err(|k| {
+                                self.error(k, WasmMnemonic::MemoryInit, ex_position)
+                            })?;
+                            memory = BORROW_MEMORY!(self)?;
+                        }
+                        if count > 0 {
+                            unsafe {
+                                memory
+                                    .as_mut_ptr()
+                                    .add(dest)
+                                    .copy_from_nonoverlapping(data.as_ptr().add(src), count);
+                            }
+                        }
+                    } else if count > 0 {
+                        mem.commit(dest + count)
+                            .map_err(|k| self.error(k, WasmMnemonic::MemoryInit, ex_position))?;
+                        let other = BORROW_MEMORY!(self, memory_index)?;
+                        unsafe {
+                            other
+                                .as_mut_ptr()
+                                .add(dest)
+                                .copy_from_nonoverlapping(data.as_ptr().add(src), count);
+                        }
+                    }
+                }
+                WasmImInstruction::DataDrop(data_index) => {
+                    self.instance.drop_data_segment(data_index as usize);
+                }
                 WasmImInstruction::I32Const(val) => {
                     let ref_a = value_stack.get_mut(code.base_stack_level());
                     ref_a.write_i32(val);
@@ -497,6 +905,20 @@ impl WasmInterpreter<'_> {
                     ref_a.write_f64(val);
                 }
 
+                WasmImInstruction::RefNull => {
+                    let ref_a = value_stack.get_mut(code.base_stack_level());
+                    *ref_a = WasmUnionValue::from_func_ref(None);
+                }
+                WasmImInstruction::RefIsNull => {
+                    let ref_a = value_stack.get_mut(code.base_stack_level());
+                    let is_null = unsafe { ref_a.get_func_ref() }.is_none();
+                    ref_a.write_bool(is_null);
+                }
+                WasmImInstruction::RefFunc(func_index) => {
+                    let ref_a = value_stack.get_mut(code.base_stack_level());
+                    *ref_a = WasmUnionValue::from_func_ref(Some(func_index));
+                }
+
                 WasmImInstruction::I32Eqz => {
                     Self::unary_op(code, &mut value_stack, |var| unsafe {
                         var.write_bool(var.get_i32() == 0)
@@ -587,7 +1009,7 @@ impl WasmInterpreter<'_> {
 
                 WasmImInstruction::I32DivS(ex_position) => {
                     #[rustfmt::skip]
-                    DIV_OP!(map_i32, get_i32, I32DivS, wrapping_div, ex_position, code, value_stack, );
+                    DIV_OP!(map_i32, get_i32, I32DivS, wrapping_div, ex_position, code, value_stack, i32::MIN);
                 }
                 WasmImInstruction::I32DivU(ex_position) => {
                     #[rustfmt::skip]
@@ -732,7 +1154,7 @@ impl WasmInterpreter<'_> {
 
                 WasmImInstruction::I64DivS(ex_position) => {
                     #[rustfmt::skip]
-                    DIV_OP!(map_i64, get_i64, I64DivS, wrapping_div, ex_position, code, value_stack, );
+                    DIV_OP!(map_i64, get_i64, I64DivS, wrapping_div, ex_position, code, value_stack, i64::MIN);
                 }
                 WasmImInstruction::I64DivU(ex_position) => {
                     #[rustfmt::skip]
@@ -1030,46 +1452,38 @@ impl WasmInterpreter<'_> {
                     });
                 }
 
-                WasmImInstruction::I32TruncF32S => {
-                    Self::unary_op(code, &mut value_stack, |var| unsafe {
-                        var.write_i32(var.get_f32().to_int_unchecked());
-                    });
+                WasmImInstruction::I32TruncF32S(ex_position) => {
+                    #[rustfmt::skip]
+                    TRUNC_OP!(get_f32, write_i32, i32, -2147483904.0, 2147483648.0, I32TruncF32S, ex_position, code, value_stack, );
                 }
-                WasmImInstruction::I32TruncF32U => {
-                    Self::unary_op(code, &mut value_stack, |var| unsafe {
-                        var.write_u32(var.get_f32().to_int_unchecked());
-                    });
+                WasmImInstruction::I32TruncF32U(ex_position) => {
+                    #[rustfmt::skip]
+                    TRUNC_OP!(get_f32, write_u32, u32, -1.0, 4294967296.0, I32TruncF32U, ex_position, code, value_stack, );
                 }
-                WasmImInstruction::I32TruncF64S => {
-                    Self::unary_op(code, &mut value_stack, |var| unsafe {
-                        var.write_i32(var.get_f64().to_int_unchecked());
-                    });
+                WasmImInstruction::I32TruncF64S(ex_position) => {
+                    #[rustfmt::skip]
+                    TRUNC_OP!(get_f64, write_i32, i32, -2147483649.0, 2147483648.0, I32TruncF64S, ex_position, code, value_stack, );
                 }
-                WasmImInstruction::I32TruncF64U => {
-                    Self::unary_op(code, &mut value_stack, |var| unsafe {
-                        var.write_u32(var.get_f64().to_int_unchecked());
-                    });
+                WasmImInstruction::I32TruncF64U(ex_position) => {
+                    #[rustfmt::skip]
+                    TRUNC_OP!(get_f64, write_u32, u32, -1.0, 4294967296.0, I32TruncF64U, ex_position, code, value_stack, );
                 }
 
-                WasmImInstruction::I64TruncF32S => {
-                    Self::unary_op(code, &mut value_stack, |var| unsafe {
-                        var.write_i64(var.get_f32().to_int_unchecked());
-                    });
+                WasmImInstruction::I64TruncF32S(ex_position) => {
+                    #[rustfmt::skip]
+                    TRUNC_OP!(get_f32, write_i64, i64, -9223373136366403584.0, 9223372036854775808.0, I64TruncF32S, ex_position, code, value_stack, );
                 }
-                WasmImInstruction::I64TruncF32U => {
-                    Self::unary_op(code, &mut value_stack, |var| unsafe {
-                        var.write_u64(var.get_f32().to_int_unchecked());
-                    });
+                WasmImInstruction::I64TruncF32U(ex_position) => {
+                    #[rustfmt::skip]
+                    TRUNC_OP!(get_f32, write_u64, u64, -1.0, 18446744073709551616.0, I64TruncF32U, ex_position, code, value_stack, );
                 }
-                WasmImInstruction::I64TruncF64S => {
-                    Self::unary_op(code, &mut value_stack, |var| unsafe {
-                        var.write_i64(var.get_f64().to_int_unchecked());
-                    });
+                WasmImInstruction::I64TruncF64S(ex_position) => {
+                    #[rustfmt::skip]
+                    TRUNC_OP!(get_f64, write_i64, i64, -9223372036854777856.0, 9223372036854775808.0, I64TruncF64S, ex_position, code, value_stack, );
                 }
-                WasmImInstruction::I64TruncF64U => {
-                    Self::unary_op(code, &mut value_stack, |var| unsafe {
-                        var.write_u64(var.get_f64().to_int_unchecked());
-                    });
+                WasmImInstruction::I64TruncF64U(ex_position) => {
+                    #[rustfmt::skip]
+                    TRUNC_OP!(get_f64, write_u64, u64, -1.0, 18446744073709551616.0, I64TruncF64U, ex_position, code, value_stack, );
                 }
 
                 WasmImInstruction::I32TruncSatF32S => {
@@ -1202,6 +1616,24 @@ impl WasmInterpreter<'_> {
                     *local = val.into();
                 }
 
+                WasmImInstruction::FusedI32LoadAt(local_index, val, offset, ex_position) => {
+                    let memory_index = 0;
+                    let index =
+                        unsafe { locals.get_local(local_index).get_u32() }.wrapping_add(val);
+                    #[rustfmt::skip]
+                    let data = MEM_READ!(u32, I32Load, offset, memory_index, ex_position, index, memory, );
+                    *value_stack.get_mut(code.base_stack_level()) = data.into();
+                }
+                WasmImInstruction::FusedI32StoreAt(local_index, val, offset, ex_position) => {
+                    let memory_index = 0;
+                    let index =
+                        unsafe { locals.get_local(local_index).get_u32() }.wrapping_add(val);
+                    let storage =
+                        unsafe { value_stack.get(code.base_stack_level().succ(1)).get_u32() };
+                    #[rustfmt::skip]
+                    MEM_WRITE!(u32, I32Store, offset, memory_index, ex_position, index, storage, memory, );
+                }
+
                 WasmImInstruction::FusedI32AddI(val) => {
                     Self::unary_op(code, &mut value_stack, |lhs| unsafe {
                         lhs.map_i32(|lhs| lhs.wrapping_add(val));
@@ -1277,7 +1709,7 @@ impl WasmInterpreter<'_> {
                 WasmImInstruction::FusedI32BrZ(target) => {
                     let cc = unsafe { value_stack.get_mut(code.base_stack_level()).get_i32() == 0 };
                     if cc {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrEq(target) => {
@@ -1285,7 +1717,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u32() == rhs.get_u32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrNe(target) => {
@@ -1293,7 +1725,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u32() != rhs.get_u32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrLtS(target) => {
@@ -1301,7 +1733,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_i32() < rhs.get_i32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrLtU(target) => {
@@ -1309,7 +1741,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u32() < rhs.get_u32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrGtS(target) => {
@@ -1317,7 +1749,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_i32() > rhs.get_i32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrGtU(target) => {
@@ -1325,7 +1757,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u32() > rhs.get_u32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrLeS(target) => {
@@ -1333,7 +1765,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_i32() <= rhs.get_i32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrLeU(target) => {
@@ -1341,7 +1773,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u32() <= rhs.get_u32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrGeS(target) => {
@@ -1349,7 +1781,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_i32() >= rhs.get_i32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI32BrGeU(target) => {
@@ -1357,14 +1789,14 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u32() >= rhs.get_u32() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
 
                 WasmImInstruction::FusedI64BrZ(target) => {
                     let cc = unsafe { value_stack.get_mut(code.base_stack_level()).get_i64() == 0 };
                     if cc {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrEq(target) => {
@@ -1372,7 +1804,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u64() == rhs.get_u64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrNe(target) => {
@@ -1380,7 +1812,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u64() != rhs.get_u64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrLtS(target) => {
@@ -1388,7 +1820,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_i64() < rhs.get_i64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrLtU(target) => {
@@ -1396,7 +1828,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u64() < rhs.get_u64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrGtS(target) => {
@@ -1404,7 +1836,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_i64() > rhs.get_i64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrGtU(target) => {
@@ -1412,7 +1844,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u64() > rhs.get_u64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrLeS(target) => {
@@ -1420,7 +1852,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_i64() <= rhs.get_i64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrLeU(target) => {
@@ -1428,7 +1860,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u64() <= rhs.get_u64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrGeS(target) => {
@@ -1436,7 +1868,7 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_i64() >= rhs.get_i64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
                 WasmImInstruction::FusedI64BrGeU(target) => {
@@ -1444,17 +1876,12 @@ impl WasmInterpreter<'_> {
                     let rhs = unsafe { *value_stack.get(stack_level.succ(1)) };
                     let lhs = *value_stack.get(stack_level);
                     if unsafe { lhs.get_u64() >= rhs.get_u64() } {
-                        codes.set_position(target)?;
+                        BRANCH!(self, target, code.base_stack_level());
                     }
                 }
             }
         }
-        if let Some(result_type) = result_types.first() {
-            let val = value_stack.get(result_stack_level);
-            Ok(Some(unsafe { val.get_by_type(*result_type) }))
-        } else {
-            Ok(None)
-        }
+        Ok(result_stack_level)
     }
 
     #[inline(always)]
@@ -1495,48 +1922,35 @@ impl WasmInterpreter<'_> {
 
         match target.content() {
             WasmFunctionContent::CodeBlock(code_block) => heap.snapshot(|heap| {
-                let local_len = code_block.local_types().len();
-
-                let mut locals = if value_stack.len() >= (stack_under.as_usize() + local_len) {
-                    let (_, mut locals) =
-                        unsafe { value_stack.split_at_mut_unchecked(stack_under) };
-
-                    for local in locals.iter_mut().skip(param_len) {
-                        *local = WasmUnionValue::zero();
+                let layout = code_block.frame_layout();
+                let n_results = result_types.len();
+
+                if value_stack.len() >= (stack_under.as_usize() + layout.slots()) {
+                    let (_, mut frame) = unsafe { value_stack.split_at_mut_unchecked(stack_under) };
+                    let results = self.call_frame(target, code_block, &mut frame, heap)?;
+                    for index in 0..n_results {
+                        let value = *frame.get(StackLevel::new(results.as_usize() + index));
+                        frame.set(StackLevel::new(index), value);
                     }
-
-                    locals
                 } else {
-                    let mut locals = StackFrame::new(heap.alloc_slice(
-                        local_len.max(INITIAL_VALUE_STACK_SIZE),
-                        WasmUnionValue::zero(),
-                    ));
+                    let mut frame =
+                        StackFrame::new(heap.alloc_slice(layout.slots(), WasmUnionValue::zero()));
 
-                    for (local, value) in locals
+                    for (local, value) in frame
                         .iter_mut()
                         .zip(unsafe { value_stack.get_range(stack_under, param_len) }.iter())
                     {
                         *local = *value;
                     }
 
-                    locals
-                };
-
-                self._interpret(
-                    target.index(),
-                    code_block,
-                    locals.as_locals(),
-                    result_types,
-                    heap,
-                )
-                .and_then(|v| {
-                    if let Some(result) = v {
-                        let var = value_stack.get_mut(stack_under);
-                        *var = WasmUnionValue::from(result);
+                    let results = self.call_frame(target, code_block, &mut frame, heap)?;
+                    for index in 0..n_results {
+                        let value = *frame.get(StackLevel::new(results.as_usize() + index));
+                        value_stack.set(StackLevel::new(stack_under.as_usize() + index), value);
                     }
-                    self.func_index = current_function;
-                    Ok(())
-                })
+                }
+                self.func_index = current_function;
+                Ok(())
             }),
             WasmFunctionContent::Dynamic(func) => {
                 let locals = unsafe { value_stack.get_range(stack_under, param_len) };
@@ -1567,6 +1981,35 @@ impl WasmInterpreter<'_> {
             }
         }
     }
+
+    /// Runs the function in `frame`, whose parameters are already placed at its beginning.
+    ///
+    /// Returns the position of the first result in `frame`.
+    #[inline]
+    fn call_frame(
+        &mut self,
+        target: &WasmFunction,
+        code_block: &WasmCodeBlock,
+        frame: &mut StackFrame,
+        heap: &mut StackHeap,
+    ) -> Result<StackLevel, Box<dyn Error>> {
+        let layout = code_block.frame_layout();
+        let param_len = target.param_types().len();
+
+        for (local, val_type) in frame
+            .iter_mut()
+            .zip(code_block.local_types())
+            .skip(param_len)
+        {
+            *local = WasmUnionValue::default_for(*val_type);
+        }
+
+        let (mut locals, stack) =
+            unsafe { frame.split_at_mut_unchecked(StackLevel::new(layout.locals_size())) };
+
+        self._interpret(target.index(), code_block, locals.as_locals(), stack, heap)
+            .map(|v| unsafe { v.add(StackOffset::new(layout.locals_size())) })
+    }
 }
 
 struct WasmIntermediateCodeStream<'a> {
@@ -1594,6 +2037,11 @@ impl WasmIntermediateCodeStream<'_> {
         code
     }
 
+    #[inline]
+    const fn position(&self) -> u32 {
+        self.position
+    }
+
     #[inline]
     fn set_position(&mut self, val: u32) -> Result<(), WasmRuntimeErrorKind> {
         if (val as usize) < self.codes.len() {
@@ -1605,8 +2053,49 @@ impl WasmIntermediateCodeStream<'_> {
     }
 }
 
-impl WasmInvocation for WasmRunnable<'_> {
-    fn invoke(&self, params: &[WasmValue]) -> Result<Option<WasmValue>, Box<dyn Error>> {
+/// Tracks the backward branches for [`WasmInterpreter::set_loop_watchdog`]
+struct LoopWatchdog {
+    limit: u32,
+    header: u32,
+    count: u32,
+    /// Local variables followed by the live values on the stack
+    values: Vec<u64>,
+}
+
+impl LoopWatchdog {
+    #[inline]
+    fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            header: u32::MAX,
+            count: 0,
+            values: Vec::new(),
+        }
+    }
+
+    /// Records a backward branch to `header` and returns `true` if the limit is reached.
+    ///
+    /// `stack` is the live part of the value stack, which includes the loop parameters.
+    fn is_stuck(&mut self, header: u32, locals: &LocalVariables, stack: &[WasmUnionValue]) -> bool {
+        let fingerprint = || locals.0.iter().chain(stack).map(|v| unsafe { v.get_u64() });
+        let unchanged = self.header == header && self.values.iter().copied().eq(fingerprint());
+        if unchanged {
+            self.count += 1;
+            return self.count >= self.limit;
+        }
+        self.header = header;
+        self.count = 0;
+        self.values.clear();
+        self.values.extend(fingerprint());
+        false
+    }
+}
+
+impl WasmRunnable<'_> {
+    fn prepare(
+        &self,
+        params: &[WasmValue],
+    ) -> Result<(&WasmCodeBlock, Vec<WasmUnionValue>), Box<dyn Error>> {
         let function = self.function();
 
         let code_block = match function.content() {
@@ -1615,29 +2104,56 @@ impl WasmInvocation for WasmRunnable<'_> {
             WasmFunctionContent::Unresolved => Err(WasmRuntimeErrorKind::NoMethod),
         }?;
 
-        let mut locals =
-            Vec::with_capacity(function.param_types().len() + code_block.local_types().len());
+        let layout = code_block.frame_layout();
+        let mut locals = Vec::with_capacity(layout.locals_size());
 
         for (index, param_type) in function.param_types().iter().enumerate() {
             let param = params
                 .get(index)
                 .ok_or(WasmRuntimeErrorKind::InvalidParameter)?;
-            if !param.is_valid_type(*param_type) {
-                return Err(WasmRuntimeErrorKind::InvalidParameter.into());
+            let param = if self.coerces_args() {
+                param.truncate_to(*param_type)
+            } else {
+                param.is_valid_type(*param_type).then_some(*param)
             }
-            locals.push(WasmUnionValue::from(*param));
+            .ok_or(WasmRuntimeErrorKind::TypeMismatch)?;
+            locals.push(WasmUnionValue::from(param));
         }
 
-        locals.extend(iter::repeat(WasmUnionValue::zero()).take(code_block.local_types().len()));
+        locals.extend(
+            code_block.local_types()[locals.len()..]
+                .iter()
+                .map(|v| WasmUnionValue::default_for(*v)),
+        );
 
-        let result_types = function.result_types();
+        Ok((code_block, locals))
+    }
+}
+
+impl WasmInvocation for WasmRunnable<'_> {
+    fn invoke(&self, params: &[WasmValue]) -> Result<Option<WasmValue>, Box<dyn Error>> {
+        let function = self.function();
+        let (code_block, locals) = self.prepare(params)?;
 
         let mut interp = WasmInterpreter::new(self.instance());
         interp.invoke(
             function.index(),
             code_block,
             locals.as_slice(),
-            result_types,
+            function.result_types(),
+        )
+    }
+
+    fn invoke_multi(&self, params: &[WasmValue]) -> Result<Vec<WasmValue>, Box<dyn Error>> {
+        let function = self.function();
+        let (code_block, locals) = self.prepare(params)?;
+
+        let mut interp = WasmInterpreter::new(self.instance());
+        interp.invoke_multi(
+            function.index(),
+            code_block,
+            locals.as_slice(),
+            function.result_types(),
         )
     }
 }
@@ -1812,6 +2328,12 @@ impl<'a> StackFrame<'a> {
         unsafe { self.0.get_unchecked_mut(index.as_usize()) }
     }
 
+    /// Returns the values below `level`.
+    #[inline]
+    pub fn values_below(&self, level: StackLevel) -> &[WasmUnionValue] {
+        &self.0[..level.as_usize()]
+    }
+
     #[inline]
     pub fn set(&mut self, index: StackLevel, value: WasmUnionValue) {
         *(self.get_mut(index)) = value;
@@ -22,7 +22,9 @@ pub struct WasmCodeBlock {
     file_position: usize,
     local_types: SmallVec<[WasmValType; 16]>,
     max_stack_level: StackLevel,
+    frame_layout: FrameLayout,
     flags: WasmBlockFlag,
+    proposals: ProposalSet,
     int_codes: Box<[WasmImc]>,
 }
 
@@ -54,12 +56,24 @@ impl WasmCodeBlock {
         self.max_stack_level
     }
 
+    /// Returns the layout of the stack frame required to call this function.
+    #[inline]
+    pub const fn frame_layout(&self) -> FrameLayout {
+        self.frame_layout
+    }
+
     /// Returns whether or not this function block does not call any other functions.
     #[inline]
     pub const fn is_leaf(&self) -> bool {
         self.flags.contains(WasmBlockFlag::LEAF_FUNCTION)
     }
 
+    /// Returns the proposals whose instructions appear in this function.
+    #[inline]
+    pub const fn proposals(&self) -> ProposalSet {
+        self.proposals
+    }
+
     /// Returns an intermediate code block.
     #[inline]
     pub const fn intermediate_codes(&self) -> &[WasmImc] {
@@ -74,6 +88,27 @@ impl WasmCodeBlock {
         param_types: &[WasmValType],
         result_types: &[WasmValType],
         module: &WasmModule,
+    ) -> Result<Self, WasmCompileError> {
+        Self::generate_with_scratch(
+            func_index,
+            file_position,
+            reader,
+            param_types,
+            result_types,
+            module,
+            &mut WasmCodeScratch::new(),
+        )
+    }
+
+    /// Same as [`WasmCodeBlock::generate`], but reuses the working buffers in `scratch`.
+    pub fn generate_with_scratch(
+        func_index: usize,
+        file_position: usize,
+        reader: &mut Leb128Reader,
+        param_types: &[WasmValType],
+        result_types: &[WasmValType],
+        module: &WasmModule,
+        scratch: &mut WasmCodeScratch,
     ) -> Result<Self, WasmCompileError> {
         let mut ex_position = ExceptionPosition::UNKNOWN;
         Self::_generate(
@@ -84,26 +119,56 @@ impl WasmCodeBlock {
             result_types,
             module,
             &mut ex_position,
+            scratch,
         )
         .map_err(|err| {
             if matches!(err.source(), CompileErrorSource::Unknown) {
-                reader.set_position(ex_position.position());
-                let bc = WasmOpcode::fetch(reader).ok();
+                let start = ex_position.position();
+                reader.set_position(0);
+                let in_locals = Self::code_start(reader).is_none_or(|v| start < v);
+                reader.set_position(start);
+                let bc = if in_locals {
+                    // the number of declarations, or a declaration of a repeat count and a type
+                    reader.skip_leb128();
+                    if start > 0 {
+                        let _ = reader.read_byte();
+                    }
+                    None
+                } else {
+                    let bc = WasmOpcode::fetch(reader).ok();
+                    if bc.is_none() {
+                        reader.skip_leb128();
+                    }
+                    bc
+                };
+                let end = reader.position();
                 let name = match module.names() {
                     Some(v) => v.func_by_index(func_index).map(|v| v.to_string()),
                     None => None,
                 };
                 WasmCompileError::new(
                     err.kind().clone(),
-                    ExceptionPosition::new(file_position + ex_position.position()),
+                    ExceptionPosition::new(file_position + start),
                     CompileErrorSource::Function(func_index, name, ex_position, bc),
                 )
+                .with_byte_range(file_position + start..file_position + end)
             } else {
                 err
             }
         })
     }
 
+    /// Returns the position of the first instruction after the local declarations.
+    fn code_start(reader: &mut Leb128Reader) -> Option<usize> {
+        let n_local_var_types: usize = reader.read().ok()?;
+        for _ in 0..n_local_var_types {
+            reader.skip_leb128();
+            reader.read_byte().ok()?;
+        }
+        Some(reader.position())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn _generate(
         func_index: usize,
         file_position: usize,
@@ -112,148 +177,154 @@ impl WasmCodeBlock {
         result_types: &[WasmValType],
         module: &WasmModule,
         position: &mut ExceptionPosition,
+        scratch: &mut WasmCodeScratch,
     ) -> Result<Self, WasmCompileError> {
         let local_types = {
+            let max_locals = module.limits().max_locals;
+            *position = ExceptionPosition::new(reader.position());
             let n_local_var_types: usize = reader.read()?;
-            let mut local_var_types = Vec::with_capacity(n_local_var_types);
+            let mut vec: SmallVec<[WasmValType; 16]> = SmallVec::from_slice(param_types);
             for _ in 0..n_local_var_types {
+                *position = ExceptionPosition::new(reader.position());
                 let repeat = reader.read_unsigned()?;
                 let val = WasmValType::from_u8(reader.read_byte()?)?;
-                for _ in 0..repeat {
-                    local_var_types.push(val);
+                if (vec.len() as u64).saturating_add(repeat) > max_locals as u64 {
+                    return Err(
+                        WasmCompileErrorKind::LimitsExceeded(CompileLimitKind::Locals).into(),
+                    );
                 }
+                vec.extend(core::iter::repeat_n(val, repeat as usize));
             }
-
-            let mut vec = SmallVec::with_capacity(param_types.len() + local_var_types.len());
-            vec.extend_from_slice(&param_types);
-            vec.extend_from_slice(local_var_types.as_slice());
             vec
         };
 
-        let mut blocks = Vec::new();
-        let mut block_stack = Vec::new();
+        scratch.clear();
+        let WasmCodeScratch {
+            blocks,
+            block_stack,
+            value_stack,
+            int_codes,
+        } = scratch;
         let mut max_block_level = 0;
-        let mut value_stack = ValueStackVerifier::new();
         let mut base_stack_level = StackLevel::new(0);
         let mut flags = WasmBlockFlag::LEAF_FUNCTION;
-        let mut int_codes = Vec::new();
+        let mut proposals = ProposalSet::new();
 
         macro_rules! MEM_LOAD {
-            ($val_type:ident, $mnemonic:ident, $bytecode:ident, $arg:expr, $module:ident, $reader:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
+            ($mnemonic:ident, $bytecode:ident, $arg:expr, $module:ident, $reader:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
                 #[cfg(test)]
                 assert_matches!($bytecode, WasmOpcode::$mnemonic(_));
 
-                if !$module.has_memory() {
+                if !$module.has_memory() || ($arg.memory as usize) >= $module.memories().len() {
                     return Err(WasmCompileErrorKind::OutOfMemory.into());
                 }
-                let a = $value_stack.pop()?;
-                if a != WasmValType::I32 {
-                    return Err(WasmCompileErrorKind::TypeMismatch.into());
+                if $arg.align > natural_alignment(WasmMnemonic::$mnemonic) {
+                    return Err(WasmCompileErrorKind::InvalidAlignment.into());
                 }
+                let results = $value_stack.pop_params(WasmMnemonic::$mnemonic)?;
                 $int_codes.push(WasmImc::new(
-                    WasmImInstruction::$mnemonic($arg.offset, *($position)).normalized(),
+                    WasmImInstruction::$mnemonic($arg.offset, $arg.memory, *($position))
+                        .normalized(),
                     $value_stack.stack_level(),
                 ));
-                $value_stack.push(WasmValType::$val_type);
+                $value_stack.push_results(results);
             };
         }
         macro_rules! MEM_STORE {
-            ($val_type:ident, $mnemonic:ident, $bytecode:ident, $arg:expr, $module:ident, $reader:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
+            ($mnemonic:ident, $bytecode:ident, $arg:expr, $module:ident, $reader:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
                 #[cfg(test)]
                 assert_matches!($bytecode, WasmOpcode::$mnemonic(_));
 
-                if !$module.has_memory() {
+                if !$module.has_memory() || ($arg.memory as usize) >= $module.memories().len() {
                     return Err(WasmCompileErrorKind::OutOfMemory.into());
                 }
-                let d = $value_stack.pop()?;
-                let i = $value_stack.pop()?;
-                if i != WasmValType::I32 && d != WasmValType::$val_type {
-                    return Err(WasmCompileErrorKind::TypeMismatch.into());
+                if $arg.align > natural_alignment(WasmMnemonic::$mnemonic) {
+                    return Err(WasmCompileErrorKind::InvalidAlignment.into());
                 }
+                $value_stack.pop_params(WasmMnemonic::$mnemonic)?;
                 $int_codes.push(WasmImc::new(
-                    WasmImInstruction::$mnemonic($arg.offset, *($position)).normalized(),
+                    WasmImInstruction::$mnemonic($arg.offset, $arg.memory, *($position))
+                        .normalized(),
                     $value_stack.stack_level(),
                 ));
             };
         }
         macro_rules! UNARY {
-            ($val_type:ident, $mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
+            ($mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
                 #[cfg(test)]
                 assert_matches!($bytecode, WasmOpcode::$mnemonic);
 
-                let a = *$value_stack.last()?;
-                if a != WasmValType::$val_type {
-                    return Err(WasmCompileErrorKind::TypeMismatch.into());
-                }
+                let results = $value_stack.pop_params(WasmMnemonic::$mnemonic)?;
                 $int_codes.push(WasmImc::new(
                     WasmImInstruction::$mnemonic,
-                    $value_stack.stack_level_m1()?,
+                    $value_stack.stack_level(),
                 ));
+                $value_stack.push_results(results);
             };
         }
         macro_rules! UNARY2 {
-            ($in_type:ident, $out_type:ident, $mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
+            ($mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
                 #[cfg(test)]
                 assert_matches!($bytecode, WasmOpcode::$mnemonic);
 
-                let a = $value_stack.pop()?;
-                if a != WasmValType::$in_type {
-                    return Err(WasmCompileErrorKind::TypeMismatch.into());
-                }
+                let results = $value_stack.pop_params(WasmMnemonic::$mnemonic)?;
                 $int_codes.push(WasmImc::new(
                     WasmImInstruction::$mnemonic,
                     $value_stack.stack_level(),
                 ));
-                $value_stack.push(WasmValType::$out_type);
+                $value_stack.push_results(results);
+            };
+        }
+        macro_rules! TRUNC {
+            ($mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
+                #[cfg(test)]
+                assert_matches!($bytecode, WasmOpcode::$mnemonic);
+
+                let results = $value_stack.pop_params(WasmMnemonic::$mnemonic)?;
+                $int_codes.push(WasmImc::new(
+                    WasmImInstruction::$mnemonic(*($position)),
+                    $value_stack.stack_level(),
+                ));
+                $value_stack.push_results(results);
             };
         }
         macro_rules! BIN_CMP {
-            ($val_type:ident, $mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
+            ($mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
                 #[cfg(test)]
                 assert_matches!($bytecode, WasmOpcode::$mnemonic);
 
-                let a = $value_stack.pop()?;
-                let b = $value_stack.pop()?;
-                if a != b || a != WasmValType::$val_type {
-                    return Err(WasmCompileErrorKind::TypeMismatch.into());
-                }
+                let results = $value_stack.pop_params(WasmMnemonic::$mnemonic)?;
                 $int_codes.push(WasmImc::new(
                     WasmImInstruction::$mnemonic,
                     $value_stack.stack_level(),
                 ));
-                $value_stack.push(WasmValType::I32);
+                $value_stack.push_results(results);
             };
         }
         macro_rules! BIN_OP {
-            ($val_type:ident, $mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
+            ($mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
                 #[cfg(test)]
                 assert_matches!($bytecode, WasmOpcode::$mnemonic);
 
-                let a = $value_stack.pop()?;
-                let b = *$value_stack.last()?;
-                if a != b || a != WasmValType::$val_type {
-                    return Err(WasmCompileErrorKind::TypeMismatch.into());
-                }
+                let results = $value_stack.pop_params(WasmMnemonic::$mnemonic)?;
                 $int_codes.push(WasmImc::new(
                     WasmImInstruction::$mnemonic,
-                    $value_stack.stack_level_m1()?,
+                    $value_stack.stack_level(),
                 ));
+                $value_stack.push_results(results);
             };
         }
         macro_rules! BIN_DIV {
-            ($val_type:ident, $mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
+            ($mnemonic:ident, $bytecode:ident, $position:ident, $int_codes:ident, $value_stack:ident,) => {
                 #[cfg(test)]
                 assert_matches!($bytecode, WasmOpcode::$mnemonic);
 
-                let a = $value_stack.pop()?;
-                let b = *$value_stack.last()?;
-                if a != b || a != WasmValType::$val_type {
-                    return Err(WasmCompileErrorKind::TypeMismatch.into());
-                }
+                let results = $value_stack.pop_params(WasmMnemonic::$mnemonic)?;
                 $int_codes.push(WasmImc::new(
                     WasmImInstruction::$mnemonic(*($position)),
-                    $value_stack.stack_level_m1()?,
+                    $value_stack.stack_level(),
                 ));
+                $value_stack.push_results(results);
             };
         }
 
@@ -261,24 +332,34 @@ impl WasmCodeBlock {
             max_block_level = max_block_level.max(block_stack.len());
             *position = ExceptionPosition::new(reader.position());
             let bytecode = WasmOpcode::fetch(reader)?;
+            let proposal = bytecode.mnemonic().proposal();
+            if !module.options().is_allowed(proposal) {
+                return Err(WasmCompileErrorKind::ProposalNotAllowed(proposal).into());
+            }
+            proposals.insert(proposal);
             match bytecode {
                 WasmOpcode::Unreachable => {
                     int_codes.push(WasmImc::new(
                         WasmImInstruction::Unreachable(*position),
                         value_stack.stack_level(),
                     ));
+                    value_stack.set_unreachable();
                 }
 
                 WasmOpcode::Nop => (),
 
                 WasmOpcode::Block(block_type) => {
+                    let (param_type, result_type) =
+                        block_signature(module, block_type, WasmMnemonic::Block)?;
                     let block_index = blocks.len();
                     let block = RefCell::new(BlockContext::new(
                         BlockInstType::Block,
-                        block_type,
-                        value_stack.stack_level(),
+                        param_type,
+                        result_type,
+                        block_entry_level(param_type, value_stack)?,
                     ));
                     base_stack_level = block.borrow().stack_level();
+                    value_stack.enter_block(base_stack_level);
                     block_stack.push(block_index);
                     blocks.push(block);
                     int_codes.push(WasmImc::new(
@@ -287,13 +368,17 @@ impl WasmCodeBlock {
                     ));
                 }
                 WasmOpcode::Loop(block_type) => {
+                    let (param_type, result_type) =
+                        block_signature(module, block_type, WasmMnemonic::Loop)?;
                     let block_index = blocks.len();
                     let block = RefCell::new(BlockContext::new(
                         BlockInstType::Loop,
-                        block_type,
-                        value_stack.stack_level(),
+                        param_type,
+                        result_type,
+                        block_entry_level(param_type, value_stack)?,
                     ));
                     base_stack_level = block.borrow().stack_level();
+                    value_stack.enter_block(base_stack_level);
                     block_stack.push(block_index);
                     blocks.push(block);
                     int_codes.push(WasmImc::new(
@@ -306,13 +391,17 @@ impl WasmCodeBlock {
                     if cc != WasmValType::I32 {
                         return Err(WasmCompileErrorKind::TypeMismatch.into());
                     }
+                    let (param_type, result_type) =
+                        block_signature(module, block_type, WasmMnemonic::If)?;
                     let block_index = blocks.len();
                     let block = RefCell::new(BlockContext::new(
                         BlockInstType::If,
-                        block_type,
-                        value_stack.stack_level(),
+                        param_type,
+                        result_type,
+                        block_entry_level(param_type, value_stack)?,
                     ));
                     base_stack_level = block.borrow().stack_level();
+                    value_stack.enter_block(base_stack_level);
                     block_stack.push(block_index);
                     blocks.push(block);
                     int_codes.push(WasmImc::new(
@@ -330,23 +419,23 @@ impl WasmCodeBlock {
                     }
                     block.flags |= BlockContext::ELSE_EXISTS;
 
-                    if let Some(block_type) = block.block_type.into_type() {
+                    if let Some(block_type) = block.result_type {
                         if value_stack.stack_level() < block.stack_level() {
                             return Err(WasmCompileErrorKind::InvalidStackLevel.into());
                         }
                         let block_type2 = value_stack.pop()?;
-                        if block_type != block_type2 {
+                        if block_type2 != block_type {
                             return Err(WasmCompileErrorKind::TypeMismatch.into());
                         }
-                        if block.is_control_unreachable() {
-                            value_stack.unwind(block.stack_level())?;
-                        } else {
-                            if value_stack.stack_level() != block.stack_level() {
-                                return Err(WasmCompileErrorKind::InvalidStackLevel.into());
-                            }
-                        }
-                    } else {
-                        value_stack.unwind(block.stack_level())?;
+                    }
+                    if value_stack.stack_level() != block.stack_level() {
+                        return Err(WasmCompileErrorKind::InvalidStackLevel.into());
+                    }
+                    // the else branch starts reachable with the parameter again
+                    value_stack.leave_block();
+                    value_stack.enter_block(block.stack_level());
+                    if let Some(param_type) = block.param_type {
+                        value_stack.push(param_type);
                     }
 
                     base_stack_level = block.stack_level();
@@ -360,44 +449,27 @@ impl WasmCodeBlock {
                         let block_index = block_stack
                             .pop()
                             .ok_or(WasmCompileErrorKind::BlockMismatch)?;
-                        let mut block = blocks.get(block_index).unwrap().borrow_mut();
-                        if int_codes.last().unwrap().is_control_unreachable() {
-                            block.flags |= BlockContext::UNREACHABLE_END;
-                        }
+                        let block = blocks.get(block_index).unwrap().borrow();
 
-                        if let Some(block_type) = block.block_type.into_type() {
+                        if let Some(block_type) = block.result_type {
                             if block.inst_type == BlockInstType::If && !block.else_exists() {
                                 return Err(WasmCompileErrorKind::ElseNotExists.into());
                             }
                             if value_stack.stack_level() < block.stack_level() {
                                 return Err(WasmCompileErrorKind::InvalidStackLevel.into());
                             }
-                            if matches!(block.inst_type, BlockInstType::Loop | BlockInstType::If)
-                                && int_codes.last().unwrap().is_control_unreachable()
-                            {
-                                value_stack.unwind(block.stack_level())?;
-                            } else {
-                                let block_type2 = value_stack.pop()?;
-                                if block_type != block_type2 {
-                                    return Err(WasmCompileErrorKind::TypeMismatch.into());
-                                }
-                                if block.is_control_unreachable() {
-                                    value_stack.unwind(block.stack_level())?;
-                                } else {
-                                    if value_stack.stack_level() != block.stack_level() {
-                                        return Err(WasmCompileErrorKind::InvalidStackLevel.into());
-                                    }
-                                }
+                            let block_type2 = value_stack.pop()?;
+                            if block_type2 != block_type {
+                                return Err(WasmCompileErrorKind::TypeMismatch.into());
                             }
+                        }
+                        // unreachable code may leave fewer operands than the results, but not more
+                        if value_stack.stack_level() != block.stack_level() {
+                            return Err(WasmCompileErrorKind::InvalidStackLevel.into());
+                        }
+                        value_stack.leave_block();
+                        if let Some(block_type) = block.result_type {
                             value_stack.push(block_type);
-                        } else {
-                            if block.is_control_unreachable() {
-                                value_stack.unwind(block.stack_level())?;
-                            } else {
-                                if value_stack.stack_level() != block.stack_level() {
-                                    return Err(WasmCompileErrorKind::InvalidStackLevel.into());
-                                }
-                            }
                         }
                         int_codes.push(WasmImc::new(
                             WasmImInstruction::Marker(MarkerKind::End, block_index as u32),
@@ -408,37 +480,13 @@ impl WasmCodeBlock {
                             None => StackLevel::new(0),
                         };
                     } else {
-                        if int_codes
-                            .last()
-                            .map(|v| v.is_control_unreachable())
-                            .unwrap_or(false)
-                        {
-                            break;
+                        let code = verify_return(result_types, value_stack)?;
+                        // as at the end of a block, no operands may remain besides the results
+                        if value_stack.stack_level() != StackLevel::zero() {
+                            return Err(WasmCompileErrorKind::InvalidStackLevel.into());
                         }
-                        if let Some(result_type) = result_types.first() {
-                            let result_type2 = value_stack.pop()?;
-                            if *result_type != result_type2 {
-                                return Err(WasmCompileErrorKind::TypeMismatch.into());
-                            }
-                            match result_type {
-                                WasmValType::I32 | WasmValType::I64 => {
-                                    int_codes.push(WasmImc::new(
-                                        WasmImInstruction::ReturnI,
-                                        value_stack.stack_level(),
-                                    ))
-                                }
-                                WasmValType::F32 | WasmValType::F64 => {
-                                    int_codes.push(WasmImc::new(
-                                        WasmImInstruction::ReturnF,
-                                        value_stack.stack_level(),
-                                    ))
-                                }
-                            }
-                        } else {
-                            int_codes.push(WasmImc::new(
-                                WasmImInstruction::ReturnN,
-                                value_stack.stack_level(),
-                            ));
+                        if !value_stack.is_polymorphic() {
+                            int_codes.push(code);
                         }
                         break;
                     }
@@ -449,19 +497,21 @@ impl WasmCodeBlock {
                         .get(block_stack.len() - (label_index as usize) - 1)
                         .ok_or(WasmCompileErrorKind::OutOfBranch)?;
                     let block = blocks.get(*block_index).unwrap().borrow();
-                    if block.block_type == WasmBlockType::Empty
-                        || block.inst_type == BlockInstType::Loop
-                    {
+                    if let Some(label_type) = block.label_type() {
+                        if *value_stack.last()? != label_type {
+                            return Err(WasmCompileErrorKind::TypeMismatch.into());
+                        }
                         int_codes.push(WasmImc::new(
-                            WasmImInstruction::Br(*block_index as u32),
-                            value_stack.stack_level(),
+                            WasmImInstruction::BrUnwind(*block_index as u32, block.stack_level()),
+                            value_stack.stack_level_m1()?,
                         ));
                     } else {
                         int_codes.push(WasmImc::new(
-                            WasmImInstruction::BrUnwind(*block_index as u32, block.stack_level()),
-                            value_stack.stack_level_m1()?,
+                            WasmImInstruction::Br(*block_index as u32),
+                            value_stack.stack_level(),
                         ));
                     }
+                    value_stack.set_unreachable();
                 }
                 WasmOpcode::BrIf(label_index) => {
                     let block_index = block_stack
@@ -472,17 +522,18 @@ impl WasmCodeBlock {
                         return Err(WasmCompileErrorKind::TypeMismatch.into());
                     }
                     let block = blocks.get(*block_index).unwrap().borrow();
-                    if block.block_type == WasmBlockType::Empty
-                        || block.inst_type == BlockInstType::Loop
-                    {
+                    if let Some(label_type) = block.label_type() {
+                        if *value_stack.last()? != label_type {
+                            return Err(WasmCompileErrorKind::TypeMismatch.into());
+                        }
                         int_codes.push(WasmImc::new(
-                            WasmImInstruction::BrIf(*block_index as u32),
-                            value_stack.stack_level(),
+                            WasmImInstruction::BrIfUnwind(*block_index as u32, block.stack_level()),
+                            value_stack.stack_level_m1()?,
                         ));
                     } else {
                         int_codes.push(WasmImc::new(
-                            WasmImInstruction::BrIfUnwind(*block_index as u32, block.stack_level()),
-                            value_stack.stack_level_m1()?,
+                            WasmImInstruction::BrIf(*block_index as u32),
+                            value_stack.stack_level(),
                         ));
                     }
                 }
@@ -491,26 +542,31 @@ impl WasmCodeBlock {
                     if cc != WasmValType::I32 {
                         return Err(WasmCompileErrorKind::TypeMismatch.into());
                     }
-                    let mut block_type = None;
+                    let mut label_type = None;
                     for item in table.iter_mut() {
                         let block_index = block_stack
                             .get(block_stack.len() - (*item as usize) - 1)
                             .ok_or(WasmCompileErrorKind::OutOfBranch)?;
                         let block = blocks.get(*block_index).unwrap().borrow();
-                        match block_type {
-                            Some(block_type) => {
-                                if block_type != block.block_type {
+                        match label_type {
+                            Some(label_type) => {
+                                if label_type != block.label_type() {
                                     return Err(WasmCompileErrorKind::TypeMismatch.into());
                                 }
                             }
                             None => {
-                                block_type = Some(block.block_type);
+                                label_type = Some(block.label_type());
                             }
                         }
                         *item = *block_index as u32;
                     }
-                    match block_type {
-                        None | Some(WasmBlockType::Empty) => {
+                    if let Some(Some(label_type)) = label_type {
+                        if *value_stack.last()? != label_type {
+                            return Err(WasmCompileErrorKind::TypeMismatch.into());
+                        }
+                    }
+                    match label_type {
+                        None | Some(None) => {
                             int_codes.push(WasmImc::new(
                                 WasmImInstruction::BrTable(table),
                                 value_stack.stack_level(),
@@ -523,30 +579,12 @@ impl WasmCodeBlock {
                             ));
                         }
                     }
+                    value_stack.set_unreachable();
                 }
 
                 WasmOpcode::Return => {
-                    if let Some(result_type) = result_types.first() {
-                        let result_type2 = value_stack.pop()?;
-                        if *result_type != result_type2 {
-                            return Err(WasmCompileErrorKind::TypeMismatch.into());
-                        }
-                        match result_type {
-                            WasmValType::I32 | WasmValType::I64 => int_codes.push(WasmImc::new(
-                                WasmImInstruction::ReturnI,
-                                value_stack.stack_level(),
-                            )),
-                            WasmValType::F32 | WasmValType::F64 => int_codes.push(WasmImc::new(
-                                WasmImInstruction::ReturnF,
-                                value_stack.stack_level(),
-                            )),
-                        }
-                    } else {
-                        int_codes.push(WasmImc::new(
-                            WasmImInstruction::ReturnN,
-                            value_stack.stack_level(),
-                        ));
-                    }
+                    int_codes.push(verify_return(result_types, value_stack)?);
+                    value_stack.set_unreachable();
                 }
 
                 WasmOpcode::Call(func_index) => {
@@ -567,8 +605,15 @@ impl WasmCodeBlock {
                         value_stack.push(result.clone());
                     }
                 }
-                WasmOpcode::CallIndirect(type_index, _reserved) => {
+                WasmOpcode::CallIndirect(type_index, table_index) => {
                     flags.remove(WasmBlockFlag::LEAF_FUNCTION);
+                    let table = module
+                        .tables()
+                        .get(table_index as usize)
+                        .ok_or(WasmCompileErrorKind::InvalidData)?;
+                    if table.elem_type() != WasmRefType::FuncRef {
+                        return Err(WasmCompileErrorKind::TypeMismatch.into());
+                    }
                     let type_index = WasmTypeIndex::new(module, type_index)
                         .ok_or(WasmCompileErrorKind::InvalidData)?;
                     let func_type = module.type_by_index(type_index);
@@ -577,7 +622,11 @@ impl WasmCodeBlock {
                         return Err(WasmCompileErrorKind::TypeMismatch.into());
                     }
                     int_codes.push(WasmImc::new(
-                        WasmImInstruction::CallIndirect(type_index, *position),
+                        WasmImInstruction::CallIndirect(
+                            type_index,
+                            table_index as usize,
+                            *position,
+                        ),
                         value_stack.stack_level(),
                     ));
                     // TODO: type check
@@ -604,7 +653,24 @@ impl WasmCodeBlock {
                         WasmImInstruction::SelectI,
                         value_stack.stack_level(),
                     ));
-                    value_stack.push(a);
+                    value_stack.push(a.or(b));
+                }
+                WasmOpcode::SelectT(n_types) => {
+                    if n_types != 1 {
+                        return Err(WasmCompileErrorKind::InvalidData.into());
+                    }
+                    let val_type = WasmValType::from_u8(reader.read_byte()?)?;
+                    let cc = value_stack.pop()?;
+                    let b = value_stack.pop()?;
+                    let a = value_stack.pop()?;
+                    if a != val_type || b != val_type || cc != WasmValType::I32 {
+                        return Err(WasmCompileErrorKind::TypeMismatch.into());
+                    }
+                    int_codes.push(WasmImc::new(
+                        WasmImInstruction::SelectI,
+                        value_stack.stack_level(),
+                    ));
+                    value_stack.push(val_type);
                 }
 
                 WasmOpcode::LocalGet(local_index) => {
@@ -634,14 +700,15 @@ impl WasmCodeBlock {
                     let val = *local_types
                         .get(local_index as usize)
                         .ok_or(WasmCompileErrorKind::InvalidLocal)?;
-                    let stack = *value_stack.last()?;
+                    let stack = value_stack.pop()?;
                     if stack != val {
                         return Err(WasmCompileErrorKind::TypeMismatch.into());
                     }
                     int_codes.push(WasmImc::new(
                         WasmImInstruction::LocalTeeI(unsafe { LocalVarIndex::new(local_index) }),
-                        value_stack.stack_level_m1()?,
+                        value_stack.stack_level(),
                     ));
+                    value_stack.push(val);
                 }
 
                 WasmOpcode::GlobalGet(global_index) => {
@@ -676,100 +743,137 @@ impl WasmCodeBlock {
                     ));
                 }
 
+                WasmOpcode::TableGet(table_index) => {
+                    let table = module
+                        .tables()
+                        .get(table_index as usize)
+                        .ok_or(WasmCompileErrorKind::InvalidData)?;
+                    if table.elem_type() != WasmRefType::FuncRef {
+                        return Err(WasmCompileErrorKind::TypeMismatch.into());
+                    }
+                    let index = value_stack.pop()?;
+                    if index != WasmValType::I32 {
+                        return Err(WasmCompileErrorKind::TypeMismatch.into());
+                    }
+                    int_codes.push(WasmImc::new(
+                        WasmImInstruction::TableGet(table_index as usize, *position),
+                        value_stack.stack_level(),
+                    ));
+                    value_stack.push(WasmValType::FuncRef);
+                }
+                WasmOpcode::TableSet(table_index) => {
+                    let table = module
+                        .tables()
+                        .get(table_index as usize)
+                        .ok_or(WasmCompileErrorKind::InvalidData)?;
+                    if table.elem_type() != WasmRefType::FuncRef {
+                        return Err(WasmCompileErrorKind::TypeMismatch.into());
+                    }
+                    let value = value_stack.pop()?;
+                    let index = value_stack.pop()?;
+                    if index != WasmValType::I32 || value != WasmValType::FuncRef {
+                        return Err(WasmCompileErrorKind::TypeMismatch.into());
+                    }
+                    int_codes.push(WasmImc::new(
+                        WasmImInstruction::TableSet(table_index as usize, *position),
+                        value_stack.stack_level(),
+                    ));
+                }
+
                 WasmOpcode::I32Load(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I32, I32Load, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I32Load, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I32Load8S(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I32, I32Load8S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I32Load8S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I32Load8U(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I32, I32Load8U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I32Load8U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I32Load16S(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I32, I32Load16S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I32Load16S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I32Load16U(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I32, I32Load16U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I32Load16U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
 
                 WasmOpcode::I64Load(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I64, I64Load, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I64Load, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Load8S(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I64, I64Load8S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I64Load8S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Load8U(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I64, I64Load8U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I64Load8U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Load16S(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I64, I64Load16S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I64Load16S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Load16U(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I64, I64Load16U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I64Load16U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Load32S(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I64, I64Load32S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I64Load32S, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Load32U(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(I64, I64Load32U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(I64Load32U, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
 
                 WasmOpcode::I32Store(memarg) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(I32, I32Store, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_STORE!(I32Store, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I32Store8(memarg) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(I32, I32Store8, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_STORE!(I32Store8, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I32Store16(memarg) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(I32, I32Store16, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_STORE!(I32Store16, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Store(memarg) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(I64, I64Store, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_STORE!(I64Store, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Store8(memarg) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(I64, I64Store8, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_STORE!(I64Store8, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Store16(memarg) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(I64, I64Store16, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_STORE!(I64Store16, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::I64Store32(memarg) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(I64, I64Store32, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_STORE!(I64Store32, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
 
                 WasmOpcode::F32Load(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(F32, F32Load, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(F32Load, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::F64Load(memarg) => {
                     #[rustfmt::skip]
-                    MEM_LOAD!(F64, F64Load, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_LOAD!(F64Load, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::F32Store(memarg) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(F32, F32Store, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_STORE!(F32Store, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
                 WasmOpcode::F64Store(memarg) => {
                     #[rustfmt::skip]
-                    MEM_STORE!(F64, F64Store, bytecode, memarg, module, reader, position, int_codes, value_stack, );
+                    MEM_STORE!(F64Store, bytecode, memarg, module, reader, position, int_codes, value_stack, );
                 }
 
                 WasmOpcode::MemorySize(index) => {
@@ -777,7 +881,7 @@ impl WasmCodeBlock {
                         return Err(WasmCompileErrorKind::OutOfMemory.into());
                     }
                     int_codes.push(WasmImc::new(
-                        WasmImInstruction::MemorySize,
+                        WasmImInstruction::MemorySize(index),
                         value_stack.stack_level(),
                     ));
                     value_stack.push(WasmValType::I32);
@@ -787,14 +891,15 @@ impl WasmCodeBlock {
                     if (index as usize) >= module.memories().len() {
                         return Err(WasmCompileErrorKind::OutOfMemory.into());
                     }
-                    int_codes.push(WasmImc::new(
-                        WasmImInstruction::MemoryGrow,
-                        value_stack.stack_level_m1()?,
-                    ));
-                    let a = *value_stack.last()?;
+                    let a = value_stack.pop()?;
                     if a != WasmValType::I32 {
                         return Err(WasmCompileErrorKind::TypeMismatch.into());
                     }
+                    int_codes.push(WasmImc::new(
+                        WasmImInstruction::MemoryGrow(index),
+                        value_stack.stack_level(),
+                    ));
+                    value_stack.push(WasmValType::I32);
                 }
 
                 WasmOpcode::I32Const(val) => {
@@ -826,497 +931,508 @@ impl WasmCodeBlock {
                     value_stack.push(WasmValType::F64);
                 }
 
+                WasmOpcode::RefNull(ref_type) => {
+                    if ref_type != 0x70 {
+                        return Err(WasmCompileErrorKind::UnexpectedToken.into());
+                    }
+                    int_codes.push(WasmImc::new(
+                        WasmImInstruction::RefNull,
+                        value_stack.stack_level(),
+                    ));
+                    value_stack.push(WasmValType::FuncRef);
+                }
+                WasmOpcode::RefIsNull => {
+                    let a = value_stack.pop()?;
+                    if a != WasmValType::FuncRef {
+                        return Err(WasmCompileErrorKind::TypeMismatch.into());
+                    }
+                    int_codes.push(WasmImc::new(
+                        WasmImInstruction::RefIsNull,
+                        value_stack.stack_level(),
+                    ));
+                    value_stack.push(WasmValType::I32);
+                }
+                WasmOpcode::RefFunc(func_index) => {
+                    if func_index as usize >= module.functions().len() {
+                        return Err(WasmCompileErrorKind::InvalidData.into());
+                    }
+                    int_codes.push(WasmImc::new(
+                        WasmImInstruction::RefFunc(func_index as usize),
+                        value_stack.stack_level(),
+                    ));
+                    value_stack.push(WasmValType::FuncRef);
+                }
+
                 // unary operator [i32] -> [i32]
                 WasmOpcode::I32Eqz => {
-                    UNARY!(I32, I32Eqz, bytecode, position, int_codes, value_stack,);
+                    UNARY!(I32Eqz, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Clz => {
-                    UNARY!(I32, I32Clz, bytecode, position, int_codes, value_stack,);
+                    UNARY!(I32Clz, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Ctz => {
-                    UNARY!(I32, I32Ctz, bytecode, position, int_codes, value_stack,);
+                    UNARY!(I32Ctz, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Popcnt => {
-                    UNARY!(I32, I32Popcnt, bytecode, position, int_codes, value_stack,);
+                    UNARY!(I32Popcnt, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Extend8S => {
-                    UNARY!(I32, I32Extend8S, bytecode, position, int_codes, value_stack,);
+                    UNARY!(I32Extend8S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Extend16S => {
-                    UNARY!(
-                        I32,
-                        I32Extend16S,
-                        bytecode,
-                        position,
-                        int_codes,
-                        value_stack,
-                    );
+                    UNARY!(I32Extend16S, bytecode, position, int_codes, value_stack,);
                 }
 
                 // binary operator [i32, i32] -> [i32]
                 WasmOpcode::I32Eq => {
-                    BIN_CMP!(I32, I32Eq, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32Eq, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Ne => {
-                    BIN_CMP!(I32, I32Ne, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32Ne, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32LtS => {
-                    BIN_CMP!(I32, I32LtS, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32LtS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32LtU => {
-                    BIN_CMP!(I32, I32LtU, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32LtU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32GtS => {
-                    BIN_CMP!(I32, I32GtS, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32GtS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32GtU => {
-                    BIN_CMP!(I32, I32GtU, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32GtU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32LeS => {
-                    BIN_CMP!(I32, I32LeS, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32LeS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32LeU => {
-                    BIN_CMP!(I32, I32LeU, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32LeU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32GeS => {
-                    BIN_CMP!(I32, I32GeS, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32GeS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32GeU => {
-                    BIN_CMP!(I32, I32GeU, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I32GeU, bytecode, position, int_codes, value_stack,);
                 }
 
                 WasmOpcode::I32Add => {
-                    BIN_OP!(I32, I32Add, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32Add, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Sub => {
-                    BIN_OP!(I32, I32Sub, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32Sub, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Mul => {
-                    BIN_OP!(I32, I32Mul, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32Mul, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32DivS => {
-                    BIN_DIV!(I32, I32DivS, bytecode, position, int_codes, value_stack,);
+                    BIN_DIV!(I32DivS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32DivU => {
-                    BIN_DIV!(I32, I32DivU, bytecode, position, int_codes, value_stack,);
+                    BIN_DIV!(I32DivU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32RemS => {
-                    BIN_DIV!(I32, I32RemS, bytecode, position, int_codes, value_stack,);
+                    BIN_DIV!(I32RemS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32RemU => {
-                    BIN_DIV!(I32, I32RemU, bytecode, position, int_codes, value_stack,);
+                    BIN_DIV!(I32RemU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32And => {
-                    BIN_OP!(I32, I32And, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32And, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Or => {
-                    BIN_OP!(I32, I32Or, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32Or, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Xor => {
-                    BIN_OP!(I32, I32Xor, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32Xor, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Shl => {
-                    BIN_OP!(I32, I32Shl, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32Shl, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32ShrS => {
-                    BIN_OP!(I32, I32ShrS, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32ShrS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32ShrU => {
-                    BIN_OP!(I32, I32ShrU, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32ShrU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Rotl => {
-                    BIN_OP!(I32, I32Rotl, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32Rotl, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32Rotr => {
-                    BIN_OP!(I32, I32Rotr, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I32Rotr, bytecode, position, int_codes, value_stack,);
                 }
 
                 // binary operator [i64, i64] -> [i32]
                 WasmOpcode::I64Eq => {
-                    BIN_CMP!(I64, I64Eq, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64Eq, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Ne => {
-                    BIN_CMP!(I64, I64Ne, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64Ne, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64LtS => {
-                    BIN_CMP!(I64, I64LtS, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64LtS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64LtU => {
-                    BIN_CMP!(I64, I64LtU, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64LtU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64GtS => {
-                    BIN_CMP!(I64, I64GtS, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64GtS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64GtU => {
-                    BIN_CMP!(I64, I64GtU, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64GtU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64LeS => {
-                    BIN_CMP!(I64, I64LeS, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64LeS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64LeU => {
-                    BIN_CMP!(I64, I64LeU, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64LeU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64GeS => {
-                    BIN_CMP!(I64, I64GeS, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64GeS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64GeU => {
-                    BIN_CMP!(I64, I64GeU, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(I64GeU, bytecode, position, int_codes, value_stack,);
                 }
 
                 // unary operator [i64] -> [i64]
                 WasmOpcode::I64Clz => {
-                    UNARY!(I64, I64Clz, bytecode, position, int_codes, value_stack,);
+                    UNARY!(I64Clz, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Ctz => {
-                    UNARY!(I64, I64Ctz, bytecode, position, int_codes, value_stack,);
+                    UNARY!(I64Ctz, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Popcnt => {
-                    UNARY!(I64, I64Popcnt, bytecode, position, int_codes, value_stack,);
+                    UNARY!(I64Popcnt, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Extend8S => {
-                    UNARY!(I64, I64Extend8S, bytecode, position, int_codes, value_stack,);
+                    UNARY!(I64Extend8S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Extend16S => {
-                    UNARY!(
-                        I64,
-                        I64Extend16S,
-                        bytecode,
-                        position,
-                        int_codes,
-                        value_stack,
-                    );
+                    UNARY!(I64Extend16S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Extend32S => {
-                    UNARY!(
-                        I64,
-                        I64Extend32S,
-                        bytecode,
-                        position,
-                        int_codes,
-                        value_stack,
-                    );
+                    UNARY!(I64Extend32S, bytecode, position, int_codes, value_stack,);
                 }
 
                 // binary operator [i64, i64] -> [i64]
                 WasmOpcode::I64Add => {
-                    BIN_OP!(I64, I64Add, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64Add, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Sub => {
-                    BIN_OP!(I64, I64Sub, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64Sub, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Mul => {
-                    BIN_OP!(I64, I64Mul, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64Mul, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64DivS => {
-                    BIN_DIV!(I64, I64DivS, bytecode, position, int_codes, value_stack,);
+                    BIN_DIV!(I64DivS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64DivU => {
-                    BIN_DIV!(I64, I64DivU, bytecode, position, int_codes, value_stack,);
+                    BIN_DIV!(I64DivU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64RemS => {
-                    BIN_DIV!(I64, I64RemS, bytecode, position, int_codes, value_stack,);
+                    BIN_DIV!(I64RemS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64RemU => {
-                    BIN_DIV!(I64, I64RemU, bytecode, position, int_codes, value_stack,);
+                    BIN_DIV!(I64RemU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64And => {
-                    BIN_OP!(I64, I64And, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64And, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Or => {
-                    BIN_OP!(I64, I64Or, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64Or, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Xor => {
-                    BIN_OP!(I64, I64Xor, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64Xor, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Shl => {
-                    BIN_OP!(I64, I64Shl, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64Shl, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64ShrS => {
-                    BIN_OP!(I64, I64ShrS, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64ShrS, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64ShrU => {
-                    BIN_OP!(I64, I64ShrU, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64ShrU, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Rotl => {
-                    BIN_OP!(I64, I64Rotl, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64Rotl, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64Rotr => {
-                    BIN_OP!(I64, I64Rotr, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(I64Rotr, bytecode, position, int_codes, value_stack,);
                 }
 
                 // [i64] -> [i32]
                 WasmOpcode::I64Eqz => {
-                    UNARY2!(I64, I32, I64Eqz, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I64Eqz, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32WrapI64 => {
                     #[rustfmt::skip]
-                    UNARY2!(I64, I32, I32WrapI64, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I32WrapI64, bytecode, position, int_codes, value_stack,);
                 }
 
                 // [i32] -> [i64]
                 WasmOpcode::I64ExtendI32S => {
                     #[rustfmt::skip]
-                    UNARY2!(I32, I64, I64ExtendI32S, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I64ExtendI32S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64ExtendI32U => {
                     #[rustfmt::skip]
-                    UNARY2!(I32, I64, I64ExtendI32U, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I64ExtendI32U, bytecode, position, int_codes, value_stack,);
                 }
 
                 // [f32, f32] -> [i32]
                 WasmOpcode::F32Eq => {
-                    BIN_CMP!(F32, F32Eq, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F32Eq, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Ne => {
-                    BIN_CMP!(F32, F32Ne, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F32Ne, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Lt => {
-                    BIN_CMP!(F32, F32Lt, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F32Lt, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Gt => {
-                    BIN_CMP!(F32, F32Gt, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F32Gt, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Le => {
-                    BIN_CMP!(F32, F32Le, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F32Le, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Ge => {
-                    BIN_CMP!(F32, F32Ge, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F32Ge, bytecode, position, int_codes, value_stack,);
                 }
 
                 // [f32] -> [f32]
                 WasmOpcode::F32Abs => {
-                    UNARY!(F32, F32Abs, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F32Abs, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Neg => {
-                    UNARY!(F32, F32Neg, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F32Neg, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Ceil => {
-                    UNARY!(F32, F32Ceil, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F32Ceil, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Floor => {
-                    UNARY!(F32, F32Floor, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F32Floor, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Trunc => {
-                    UNARY!(F32, F32Trunc, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F32Trunc, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Nearest => {
-                    UNARY!(F32, F32Nearest, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F32Nearest, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Sqrt => {
-                    UNARY!(F32, F32Sqrt, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F32Sqrt, bytecode, position, int_codes, value_stack,);
                 }
 
                 // [f32, f32] -> [f32]
                 WasmOpcode::F32Add => {
-                    BIN_OP!(F32, F32Add, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F32Add, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Sub => {
-                    BIN_OP!(F32, F32Sub, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F32Sub, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Mul => {
-                    BIN_OP!(F32, F32Mul, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F32Mul, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Div => {
-                    BIN_OP!(F32, F32Div, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F32Div, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Min => {
-                    BIN_OP!(F32, F32Min, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F32Min, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Max => {
-                    BIN_OP!(F32, F32Max, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F32Max, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32Copysign => {
-                    BIN_OP!(F32, F32Copysign, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F32Copysign, bytecode, position, int_codes, value_stack,);
                 }
 
                 // [f64, f64] -> [i32]
                 WasmOpcode::F64Eq => {
-                    BIN_CMP!(F64, F64Eq, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F64Eq, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Ne => {
-                    BIN_CMP!(F64, F64Ne, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F64Ne, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Lt => {
-                    BIN_CMP!(F64, F64Lt, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F64Lt, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Gt => {
-                    BIN_CMP!(F64, F64Gt, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F64Gt, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Le => {
-                    BIN_CMP!(F64, F64Le, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F64Le, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Ge => {
-                    BIN_CMP!(F64, F64Ge, bytecode, position, int_codes, value_stack,);
+                    BIN_CMP!(F64Ge, bytecode, position, int_codes, value_stack,);
                 }
 
                 // [f64] -> [f64]
                 WasmOpcode::F64Abs => {
-                    UNARY!(F64, F64Abs, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F64Abs, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Neg => {
-                    UNARY!(F64, F64Neg, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F64Neg, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Ceil => {
-                    UNARY!(F64, F64Ceil, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F64Ceil, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Floor => {
-                    UNARY!(F64, F64Floor, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F64Floor, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Trunc => {
-                    UNARY!(F64, F64Trunc, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F64Trunc, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Nearest => {
-                    UNARY!(F64, F64Nearest, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F64Nearest, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Sqrt => {
-                    UNARY!(F64, F64Sqrt, bytecode, position, int_codes, value_stack,);
+                    UNARY!(F64Sqrt, bytecode, position, int_codes, value_stack,);
                 }
 
                 // [f64, f64] -> [f64]
                 WasmOpcode::F64Add => {
-                    BIN_OP!(F64, F64Add, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F64Add, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Sub => {
-                    BIN_OP!(F64, F64Sub, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F64Sub, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Mul => {
-                    BIN_OP!(F64, F64Mul, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F64Mul, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Div => {
-                    BIN_OP!(F64, F64Div, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F64Div, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Min => {
-                    BIN_OP!(F64, F64Min, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F64Min, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Max => {
-                    BIN_OP!(F64, F64Max, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F64Max, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64Copysign => {
-                    BIN_OP!(F64, F64Copysign, bytecode, position, int_codes, value_stack,);
+                    BIN_OP!(F64Copysign, bytecode, position, int_codes, value_stack,);
                 }
 
                 // [f32] -> [i32]
                 WasmOpcode::I32TruncF32S => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, I32, I32TruncF32S, bytecode, position, int_codes, value_stack,);
+                    TRUNC!(I32TruncF32S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32TruncF32U => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, I32, I32TruncF32U, bytecode, position, int_codes, value_stack,);
+                    TRUNC!(I32TruncF32U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32TruncF64S => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, I32, I32TruncF64S, bytecode, position, int_codes, value_stack,);
+                    TRUNC!(I32TruncF64S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32TruncF64U => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, I32, I32TruncF64U, bytecode, position, int_codes, value_stack,);
+                    TRUNC!(I32TruncF64U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64TruncF32S => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, I64, I64TruncF32S, bytecode, position, int_codes, value_stack,);
+                    TRUNC!(I64TruncF32S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64TruncF32U => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, I64, I64TruncF32U, bytecode, position, int_codes, value_stack,);
+                    TRUNC!(I64TruncF32U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64TruncF64S => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, I64, I64TruncF64S, bytecode, position, int_codes, value_stack,);
+                    TRUNC!(I64TruncF64S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64TruncF64U => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, I64, I64TruncF64U, bytecode, position, int_codes, value_stack,);
+                    TRUNC!(I64TruncF64U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32ConvertI32S => {
                     #[rustfmt::skip]
-                    UNARY2!(I32, F32, F32ConvertI32S, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F32ConvertI32S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32ConvertI32U => {
                     #[rustfmt::skip]
-                    UNARY2!(I32, F32, F32ConvertI32U, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F32ConvertI32U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32ConvertI64S => {
                     #[rustfmt::skip]
-                    UNARY2!(I64, F32, F32ConvertI64S, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F32ConvertI64S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32ConvertI64U => {
                     #[rustfmt::skip]
-                    UNARY2!(I64, F32, F32ConvertI64U, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F32ConvertI64U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32DemoteF64 => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, F32, F32DemoteF64, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F32DemoteF64, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64ConvertI32S => {
                     #[rustfmt::skip]
-                    UNARY2!(I32, F64, F64ConvertI32S, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F64ConvertI32S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64ConvertI32U => {
                     #[rustfmt::skip]
-                    UNARY2!(I32, F64, F64ConvertI32U, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F64ConvertI32U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64ConvertI64S => {
                     #[rustfmt::skip]
-                    UNARY2!(I64, F64, F64ConvertI64S, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F64ConvertI64S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64ConvertI64U => {
                     #[rustfmt::skip]
-                    UNARY2!(I64, F64, F64ConvertI64U, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F64ConvertI64U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64PromoteF32 => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, F64, F64PromoteF32, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F64PromoteF32, bytecode, position, int_codes, value_stack,);
                 }
 
                 WasmOpcode::I32ReinterpretF32 => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, I32, I32ReinterpretF32, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I32ReinterpretF32, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64ReinterpretF64 => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, I64, I64ReinterpretF64, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I64ReinterpretF64, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F32ReinterpretI32 => {
                     #[rustfmt::skip]
-                    UNARY2!(I32, F32, F32ReinterpretI32, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F32ReinterpretI32, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::F64ReinterpretI64 => {
                     #[rustfmt::skip]
-                    UNARY2!(I64, F64, F64ReinterpretI64, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(F64ReinterpretI64, bytecode, position, int_codes, value_stack,);
                 }
 
                 WasmOpcode::I32TruncSatF32S => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, I32, I32TruncSatF32S, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I32TruncSatF32S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32TruncSatF32U => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, I32, I32TruncSatF32U, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I32TruncSatF32U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32TruncSatF64S => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, I32, I32TruncSatF64S, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I32TruncSatF64S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I32TruncSatF64U => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, I32, I32TruncSatF64U, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I32TruncSatF64U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64TruncSatF32S => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, I64, I64TruncSatF32S, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I64TruncSatF32S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64TruncSatF32U => {
                     #[rustfmt::skip]
-                    UNARY2!(F32, I64, I64TruncSatF32U, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I64TruncSatF32U, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64TruncSatF64S => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, I64, I64TruncSatF64S, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I64TruncSatF64S, bytecode, position, int_codes, value_stack,);
                 }
                 WasmOpcode::I64TruncSatF64U => {
                     #[rustfmt::skip]
-                    UNARY2!(F64, I64, I64TruncSatF64U, bytecode, position, int_codes, value_stack,);
+                    UNARY2!(I64TruncSatF64U, bytecode, position, int_codes, value_stack,);
                 }
 
                 WasmOpcode::MemoryCopy => {
@@ -1340,7 +1456,11 @@ impl WasmCodeBlock {
                     }
 
                     int_codes.push(WasmImc::new(
-                        WasmImInstruction::MemoryCopy(*position),
+                        WasmImInstruction::MemoryCopy(
+                            memory_dst as u32,
+                            memory_src as u32,
+                            *position,
+                        ),
                         value_stack.stack_level(),
                     ));
                 }
@@ -1362,11 +1482,48 @@ impl WasmCodeBlock {
                     }
 
                     int_codes.push(WasmImc::new(
-                        WasmImInstruction::MemoryFill(*position),
+                        WasmImInstruction::MemoryFill(index as u32, *position),
                         value_stack.stack_level(),
                     ));
                 }
 
+                WasmOpcode::MemoryInit(data_index) | WasmOpcode::DataDrop(data_index) => {
+                    // requires the data count section
+                    if module
+                        .data_count()
+                        .is_none_or(|count| data_index as usize >= count)
+                    {
+                        return Err(WasmCompileErrorKind::InvalidData.into());
+                    }
+
+                    if matches!(bytecode, WasmOpcode::DataDrop(_)) {
+                        int_codes.push(WasmImc::new(
+                            WasmImInstruction::DataDrop(data_index),
+                            value_stack.stack_level(),
+                        ));
+                    } else {
+                        if !module.has_memory() {
+                            return Err(WasmCompileErrorKind::OutOfMemory.into());
+                        }
+                        let index = reader.read_unsigned()? as usize;
+                        if index >= module.memories().len() {
+                            return Err(WasmCompileErrorKind::OutOfMemory.into());
+                        }
+
+                        let a = value_stack.pop()?;
+                        let b = value_stack.pop()?;
+                        let c = value_stack.pop()?;
+                        if a != WasmValType::I32 || b != WasmValType::I32 || c != WasmValType::I32 {
+                            return Err(WasmCompileErrorKind::TypeMismatch.into());
+                        }
+
+                        int_codes.push(WasmImc::new(
+                            WasmImInstruction::MemoryInit(data_index, index as u32, *position),
+                            value_stack.stack_level(),
+                        ));
+                    }
+                }
+
                 _ => return Err(WasmCompileErrorKind::UnsupportedBytecode(bytecode.into()).into()),
             }
 
@@ -1395,7 +1552,7 @@ impl WasmCodeBlock {
         }
 
         // fused instructions
-        if int_codes.len() > 2 {
+        if module.options().fusion() && int_codes.len() > 2 {
             let limit = int_codes.len() - 1;
             for i in 0..limit {
                 use WasmImInstruction::*;
@@ -1408,6 +1565,13 @@ impl WasmCodeBlock {
                         }
                         let next2_op = int_codes[i + 2].instruction();
                         let next3_op = int_codes[i + 3].instruction();
+                        // the stack level of the address computed by `i32.add`
+                        let address_level = int_codes[i].stack_level;
+                        let lines_up = |index: usize, level: StackLevel| {
+                            int_codes
+                                .get(index)
+                                .is_some_and(|code| code.stack_level == level)
+                        };
                         match (next2_op, next3_op) {
                             (WasmImInstruction::I32Add, WasmImInstruction::LocalSetI(index2)) => {
                                 if *index1 == *index2 {
@@ -1418,6 +1582,52 @@ impl WasmCodeBlock {
                                     int_codes[i + 3].instruction = WasmImInstruction::NOP;
                                 }
                             }
+                            (
+                                WasmImInstruction::I32Add,
+                                WasmImInstruction::I32Load(offset, 0, position),
+                            ) => {
+                                if lines_up(i + 2, address_level) && lines_up(i + 3, address_level)
+                                {
+                                    int_codes[i + 3].instruction =
+                                        WasmImInstruction::FusedI32LoadAt(
+                                            *index1,
+                                            *val as u32,
+                                            *offset,
+                                            *position,
+                                        );
+                                    int_codes[i].instruction = WasmImInstruction::NOP;
+                                    int_codes[i + 1].instruction = WasmImInstruction::NOP;
+                                    int_codes[i + 2].instruction = WasmImInstruction::NOP;
+                                }
+                            }
+                            (
+                                WasmImInstruction::I32Add,
+                                WasmImInstruction::LocalGetI(_) | WasmImInstruction::I32Const(_),
+                            ) => {
+                                let Some(WasmImInstruction::I32Store(offset, 0, position)) =
+                                    int_codes.get(i + 4).map(|v| v.instruction())
+                                else {
+                                    continue;
+                                };
+                                if lines_up(i + 2, address_level)
+                                    && lines_up(
+                                        i + 3,
+                                        StackLevel::new(address_level.as_usize() + 1),
+                                    )
+                                    && lines_up(i + 4, address_level)
+                                {
+                                    int_codes[i + 4].instruction =
+                                        WasmImInstruction::FusedI32StoreAt(
+                                            *index1,
+                                            *val as u32,
+                                            *offset,
+                                            *position,
+                                        );
+                                    int_codes[i].instruction = WasmImInstruction::NOP;
+                                    int_codes[i + 1].instruction = WasmImInstruction::NOP;
+                                    int_codes[i + 2].instruction = WasmImInstruction::NOP;
+                                }
+                            }
                             _ => (),
                         }
                     }
@@ -1552,8 +1762,8 @@ impl WasmCodeBlock {
         }
 
         // compaction and block adjustment
-        let mut compacted = Vec::new();
-        for code in int_codes {
+        let mut compacted = Vec::with_capacity(int_codes.len());
+        for code in int_codes.drain(..) {
             match *code.instruction() {
                 WasmImInstruction::Marker(marker, target) => match marker {
                     MarkerKind::Nop => (),
@@ -1598,7 +1808,15 @@ impl WasmCodeBlock {
                         *target = blocks
                             .get(*target as usize)
                             .ok_or(WasmCompileErrorKind::OutOfBranch)
-                            .map(|block: &RefCell<BlockContext>| block.borrow().else_position)?;
+                            .map(|block: &RefCell<BlockContext>| {
+                                let block = block.borrow();
+                                // without `else`, a false condition skips the whole block
+                                if block.else_exists() {
+                                    block.else_position
+                                } else {
+                                    block.end_position
+                                }
+                            })?;
                     }
                     _ => {
                         *target = blocks
@@ -1611,17 +1829,235 @@ impl WasmCodeBlock {
             })?;
         }
 
+        let max_stack_level = value_stack.max_stack_level();
+        let frame_layout = FrameLayout::new(local_types.len(), max_stack_level.as_usize());
+
         Ok(Self {
             func_index,
             file_position,
             local_types,
-            max_stack_level: value_stack.max_stack_level(),
+            max_stack_level,
+            frame_layout,
             flags,
+            proposals,
             int_codes: int_codes.into_boxed_slice(),
         })
     }
 }
 
+/// Pops the results of the function and returns the instruction to return them.
+///
+/// The results are left on the value stack in order, starting from the returned stack level.
+fn verify_return(
+    result_types: &[WasmValType],
+    value_stack: &mut ValueStackVerifier,
+) -> Result<WasmImc, WasmCompileErrorKind> {
+    for result_type in result_types.iter().rev() {
+        if value_stack.pop()? != *result_type {
+            return Err(WasmCompileErrorKind::TypeMismatch);
+        }
+    }
+    let instruction = match result_types {
+        [] => WasmImInstruction::ReturnN,
+        [WasmValType::F32 | WasmValType::F64] => WasmImInstruction::ReturnF,
+        _ => WasmImInstruction::ReturnI,
+    };
+    Ok(WasmImc::new(instruction, value_stack.stack_level()))
+}
+
+/// Resolves the block type into its parameter and result types.
+///
+/// Only signatures with at most one parameter and at most one result are supported.
+fn block_signature(
+    module: &WasmModule,
+    block_type: WasmBlockType,
+    mnemonic: WasmMnemonic,
+) -> Result<(Option<WasmValType>, Option<WasmValType>), WasmCompileErrorKind> {
+    let Some(type_index) = block_type.type_index() else {
+        return Ok((None, block_type.into_type()));
+    };
+    let type_index =
+        WasmTypeIndex::new(module, type_index).ok_or(WasmCompileErrorKind::InvalidData)?;
+    let block_type = module.type_by_index(type_index);
+    match (block_type.param_types(), block_type.result_types()) {
+        ([] | [_], [] | [_]) => Ok((
+            block_type.param_types().first().copied(),
+            block_type.result_types().first().copied(),
+        )),
+        _ => Err(WasmCompileErrorKind::UnsupportedBytecode(mnemonic)),
+    }
+}
+
+/// Checks the block parameter on the value stack and returns the stack level below it.
+fn block_entry_level(
+    param_type: Option<WasmValType>,
+    value_stack: &mut ValueStackVerifier,
+) -> Result<StackLevel, WasmCompileErrorKind> {
+    match param_type {
+        Some(param_type) => {
+            if value_stack.pop()? != param_type {
+                return Err(WasmCompileErrorKind::TypeMismatch);
+            }
+            let level = value_stack.stack_level();
+            value_stack.push(param_type);
+            Ok(level)
+        }
+        None => Ok(value_stack.stack_level()),
+    }
+}
+
+/// Returns the natural alignment of the memory access instruction as an exponent of 2.
+const fn natural_alignment(mnemonic: WasmMnemonic) -> u32 {
+    use WasmMnemonic::*;
+    match mnemonic {
+        I32Load8S | I32Load8U | I64Load8S | I64Load8U | I32Store8 | I64Store8 => 0,
+        I32Load16S | I32Load16U | I64Load16S | I64Load16U | I32Store16 | I64Store16 => 1,
+        I32Load | F32Load | I64Load32S | I64Load32U | I32Store | F32Store | I64Store32 => 2,
+        _ => 3,
+    }
+}
+
+/// Returns the parameter and result types of an instruction whose typing doesn't depend on
+/// the module or the enclosing blocks, or `None` for the others.
+const fn stack_signature(
+    mnemonic: WasmMnemonic,
+) -> Option<(&'static [WasmValType], &'static [WasmValType])> {
+    use WasmMnemonic::*;
+    use WasmValType::{FuncRef, F32, F64, I32, I64};
+    match mnemonic {
+        Nop => Some((&[], &[])),
+        I32Const | MemorySize => Some((&[], &[I32])),
+        I64Const => Some((&[], &[I64])),
+        F32Const => Some((&[], &[F32])),
+        F64Const => Some((&[], &[F64])),
+        RefNull => Some((&[], &[FuncRef])),
+        RefIsNull => Some((&[FuncRef], &[I32])),
+        MemoryCopy | MemoryFill => Some((&[I32, I32, I32], &[])),
+        MemoryGrow | I32Load | I32Load8S | I32Load8U | I32Load16S | I32Load16U | I32Eqz
+        | I32Clz | I32Ctz | I32Popcnt | I32Extend8S | I32Extend16S => Some((&[I32], &[I32])),
+        I64Load | I64Load8S | I64Load8U | I64Load16S | I64Load16U | I64Load32S | I64Load32U
+        | I64ExtendI32S | I64ExtendI32U => Some((&[I32], &[I64])),
+        I32Store | I32Store8 | I32Store16 => Some((&[I32, I32], &[])),
+        I64Store | I64Store8 | I64Store16 | I64Store32 => Some((&[I32, I64], &[])),
+        F32Load | F32ConvertI32S | F32ConvertI32U | F32ReinterpretI32 => Some((&[I32], &[F32])),
+        F64Load | F64ConvertI32S | F64ConvertI32U => Some((&[I32], &[F64])),
+        F32Store => Some((&[I32, F32], &[])),
+        F64Store => Some((&[I32, F64], &[])),
+        I32Eq | I32Ne | I32LtS | I32LtU | I32GtS | I32GtU | I32LeS | I32LeU | I32GeS | I32GeU
+        | I32Add | I32Sub | I32Mul | I32DivS | I32DivU | I32RemS | I32RemU | I32And | I32Or
+        | I32Xor | I32Shl | I32ShrS | I32ShrU | I32Rotl | I32Rotr => Some((&[I32, I32], &[I32])),
+        I64Eq | I64Ne | I64LtS | I64LtU | I64GtS | I64GtU | I64LeS | I64LeU | I64GeS | I64GeU => {
+            Some((&[I64, I64], &[I32]))
+        }
+        I64Clz | I64Ctz | I64Popcnt | I64Extend8S | I64Extend16S | I64Extend32S => {
+            Some((&[I64], &[I64]))
+        }
+        I64Add | I64Sub | I64Mul | I64DivS | I64DivU | I64RemS | I64RemU | I64And | I64Or
+        | I64Xor | I64Shl | I64ShrS | I64ShrU | I64Rotl | I64Rotr => Some((&[I64, I64], &[I64])),
+        I64Eqz | I32WrapI64 => Some((&[I64], &[I32])),
+        F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge => Some((&[F32, F32], &[I32])),
+        F32Abs | F32Neg | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Sqrt => {
+            Some((&[F32], &[F32]))
+        }
+        F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign => {
+            Some((&[F32, F32], &[F32]))
+        }
+        F64Eq | F64Ne | F64Lt | F64Gt | F64Le | F64Ge => Some((&[F64, F64], &[I32])),
+        F64Abs | F64Neg | F64Ceil | F64Floor | F64Trunc | F64Nearest | F64Sqrt => {
+            Some((&[F64], &[F64]))
+        }
+        F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Copysign => {
+            Some((&[F64, F64], &[F64]))
+        }
+        I32TruncF32S | I32TruncF32U | I32ReinterpretF32 | I32TruncSatF32S | I32TruncSatF32U => {
+            Some((&[F32], &[I32]))
+        }
+        I32TruncF64S | I32TruncF64U | I32TruncSatF64S | I32TruncSatF64U => Some((&[F64], &[I32])),
+        I64TruncF32S | I64TruncF32U | I64TruncSatF32S | I64TruncSatF32U => Some((&[F32], &[I64])),
+        I64TruncF64S | I64TruncF64U | I64ReinterpretF64 | I64TruncSatF64S | I64TruncSatF64U => {
+            Some((&[F64], &[I64]))
+        }
+        F32ConvertI64S | F32ConvertI64U => Some((&[I64], &[F32])),
+        F32DemoteF64 => Some((&[F64], &[F32])),
+        F64ConvertI64S | F64ConvertI64U | F64ReinterpretI64 => Some((&[I64], &[F64])),
+        F64PromoteF32 => Some((&[F32], &[F64])),
+        _ => None,
+    }
+}
+
+impl WasmOpcode {
+    /// Applies the instruction to a value stack of the given types and returns the resulting stack.
+    ///
+    /// This performs the same type check as the compiler without a module, so it supports only
+    /// instructions that don't refer to locals, globals, functions, tables, or blocks.
+    /// The others fail with [`WasmCompileErrorKind::UnsupportedBytecode`].
+    /// Memory instructions are checked as if the module had a memory.
+    pub fn type_transition(
+        &self,
+        stack: &[WasmValType],
+    ) -> Result<Vec<WasmValType>, WasmCompileErrorKind> {
+        let mnemonic = self.mnemonic();
+        let (params, results) =
+            stack_signature(mnemonic).ok_or(WasmCompileErrorKind::UnsupportedBytecode(mnemonic))?;
+        let base = stack
+            .len()
+            .checked_sub(params.len())
+            .ok_or(WasmCompileErrorKind::OutOfStack)?;
+        if &stack[base..] != params {
+            return Err(WasmCompileErrorKind::TypeMismatch);
+        }
+        let mut output = stack[..base].to_vec();
+        output.extend_from_slice(results);
+        Ok(output)
+    }
+}
+
+/// Stack frame layout of a function
+///
+/// A frame consists of the local variables (including parameters)
+/// followed by the value stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLayout {
+    locals_size: usize,
+    value_stack_size: usize,
+    slots: usize,
+}
+
+impl FrameLayout {
+    #[inline]
+    pub const fn new(locals_size: usize, value_stack_size: usize) -> Self {
+        Self {
+            locals_size,
+            value_stack_size,
+            slots: locals_size + value_stack_size,
+        }
+    }
+
+    /// Returns the number of local variables, including parameters.
+    #[inline]
+    pub const fn locals_size(&self) -> usize {
+        self.locals_size
+    }
+
+    /// Returns the maximum number of values on the value stack.
+    #[inline]
+    pub const fn value_stack_size(&self) -> usize {
+        self.value_stack_size
+    }
+
+    /// Returns the total number of slots in the frame.
+    #[inline]
+    pub const fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Returns the worst-case size of the frame in bytes.
+    #[inline]
+    pub const fn size_in_bytes(&self) -> usize {
+        self.slots * size_of::<WasmUnionValue>()
+    }
+}
+
 impl fmt::Debug for WasmCodeBlock {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("WasmCodeBlock")
@@ -1634,6 +2070,22 @@ impl fmt::Debug for WasmCodeBlock {
     }
 }
 
+/// One line per intermediate code: index, base stack level, `*` for fused
+/// instructions, and the source offset of instructions that can trap.
+impl fmt::Display for WasmCodeBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "func {} @{:#x} locals {:?}",
+            self.func_index, self.file_position, self.local_types
+        )?;
+        for (index, code) in self.int_codes.iter().enumerate() {
+            writeln!(f, "{:4}: {}", index, code)?;
+        }
+        Ok(())
+    }
+}
+
 /// A type of block instruction (e.g., `block`, `loop`, `if`).
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum BlockInstType {
@@ -1642,10 +2094,38 @@ enum BlockInstType {
     If,
 }
 
+/// Reusable working buffers for [`WasmCodeBlock::generate_with_scratch`]
+///
+/// The buffers are cleared before each use, so that one instance can be shared
+/// by all the functions in a module to reduce allocations.
+#[derive(Default)]
+pub struct WasmCodeScratch {
+    blocks: Vec<RefCell<BlockContext>>,
+    block_stack: Vec<usize>,
+    value_stack: ValueStackVerifier,
+    int_codes: Vec<WasmImc>,
+}
+
+impl WasmCodeScratch {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    fn clear(&mut self) {
+        self.blocks.clear();
+        self.block_stack.clear();
+        self.value_stack.clear();
+        self.int_codes.clear();
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 struct BlockContext {
     inst_type: BlockInstType,
-    block_type: WasmBlockType,
+    param_type: Option<WasmValType>,
+    result_type: Option<WasmValType>,
     stack_level: StackLevel,
     start_position: u32,
     end_position: u32,
@@ -1654,18 +2134,19 @@ struct BlockContext {
 }
 
 impl BlockContext {
-    const UNREACHABLE_END: usize = 0b0001;
     const ELSE_EXISTS: usize = 0b0010;
 
     #[inline]
     pub fn new(
         inst_type: BlockInstType,
-        block_type: WasmBlockType,
+        param_type: Option<WasmValType>,
+        result_type: Option<WasmValType>,
         stack_level: StackLevel,
     ) -> Self {
         Self {
             inst_type,
-            block_type,
+            param_type,
+            result_type,
             stack_level,
             start_position: 0,
             end_position: 0,
@@ -1683,14 +2164,22 @@ impl BlockContext {
         }
     }
 
+    /// Returns the type of the value that a branch to this block must supply.
+    ///
+    /// A branch to a loop goes back to its head, so it carries the loop parameter rather than the result.
     #[inline]
-    pub fn stack_level(&self) -> StackLevel {
-        self.stack_level
+    pub fn label_type(&self) -> Option<WasmValType> {
+        if self.inst_type == BlockInstType::Loop {
+            self.param_type
+        } else {
+            self.result_type
+        }
     }
 
+    /// Returns the stack level below the block parameters.
     #[inline]
-    pub fn is_control_unreachable(&self) -> bool {
-        (self.flags & Self::UNREACHABLE_END) != 0
+    pub fn stack_level(&self) -> StackLevel {
+        self.stack_level
     }
 
     #[inline]
@@ -1746,33 +2235,121 @@ impl StackOffset {
     }
 }
 
+/// The type of an operand on the value stack during verification
+///
+/// The operands popped from the polymorphic stack of unreachable code have an unknown type,
+/// which matches any type.
+#[derive(Debug, Clone, Copy)]
+pub enum OperandType {
+    Known(WasmValType),
+    Unknown,
+}
+
+impl OperandType {
+    /// Returns `self` if the type is known, otherwise `other`.
+    #[inline]
+    pub const fn or(self, other: Self) -> Self {
+        match self {
+            Self::Known(_) => self,
+            Self::Unknown => other,
+        }
+    }
+}
+
+impl From<WasmValType> for OperandType {
+    #[inline]
+    fn from(value: WasmValType) -> Self {
+        Self::Known(value)
+    }
+}
+
+impl PartialEq for OperandType {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Known(lhs), Self::Known(rhs)) => lhs == rhs,
+            _ => true,
+        }
+    }
+}
+
+impl PartialEq<WasmValType> for OperandType {
+    #[inline]
+    fn eq(&self, other: &WasmValType) -> bool {
+        *self == Self::Known(*other)
+    }
+}
+
+#[derive(Default)]
 pub struct ValueStackVerifier {
-    inner: Vec<WasmValType>,
+    inner: Vec<OperandType>,
     max_stack_level: usize,
+    /// The stack level of the innermost block
+    floor: usize,
+    /// Whether the rest of the innermost block is unreachable
+    is_polymorphic: bool,
+    /// `floor` and `is_polymorphic` of the enclosing blocks
+    frames: Vec<(usize, bool)>,
 }
 
 impl ValueStackVerifier {
     #[inline]
     pub fn new() -> Self {
-        Self {
-            inner: Vec::new(),
-            max_stack_level: 0,
-        }
+        Self::default()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.max_stack_level = 0;
+        self.floor = 0;
+        self.is_polymorphic = false;
+        self.frames.clear();
     }
 
     #[inline]
-    pub fn push(&mut self, value: WasmValType) {
-        self.inner.push(value);
+    pub fn push<T: Into<OperandType>>(&mut self, value: T) {
+        self.inner.push(value.into());
         self.max_stack_level = self.max_stack_level.max(self.inner.len())
     }
 
+    /// Pops an operand, which is [`OperandType::Unknown`] if the stack of unreachable code is exhausted.
     #[inline]
-    pub fn pop(&mut self) -> Result<WasmValType, WasmCompileErrorKind> {
+    pub fn pop(&mut self) -> Result<OperandType, WasmCompileErrorKind> {
+        if self.is_polymorphic && self.inner.len() <= self.floor {
+            return Ok(OperandType::Unknown);
+        }
         self.inner.pop().ok_or(WasmCompileErrorKind::OutOfStack)
     }
 
+    /// Pops the parameters of a context-free instruction as [`stack_signature`] describes them,
+    /// and returns its result types to be pushed once the instruction is emitted.
+    fn pop_params(
+        &mut self,
+        mnemonic: WasmMnemonic,
+    ) -> Result<&'static [WasmValType], WasmCompileErrorKind> {
+        let (params, results) =
+            stack_signature(mnemonic).ok_or(WasmCompileErrorKind::UnsupportedBytecode(mnemonic))?;
+        for param in params.iter().rev() {
+            if self.pop()? != *param {
+                return Err(WasmCompileErrorKind::TypeMismatch);
+            }
+        }
+        Ok(results)
+    }
+
+    #[inline]
+    fn push_results(&mut self, results: &[WasmValType]) {
+        for result in results {
+            self.push(*result);
+        }
+    }
+
     #[inline]
-    pub fn last(&self) -> Result<&WasmValType, WasmCompileErrorKind> {
+    pub fn last(&self) -> Result<&OperandType, WasmCompileErrorKind> {
+        if self.is_polymorphic && self.inner.len() <= self.floor {
+            return Ok(&OperandType::Unknown);
+        }
         self.inner.last().ok_or(WasmCompileErrorKind::OutOfStack)
     }
 
@@ -1790,19 +2367,39 @@ impl ValueStackVerifier {
         let len = self.inner.len();
         if len > 0 {
             Ok(StackLevel::new(len - 1))
+        } else if self.is_polymorphic {
+            // the code is never executed
+            Ok(StackLevel::zero())
         } else {
             Err(WasmCompileErrorKind::OutOfStack)
         }
     }
 
-    fn unwind(&mut self, new_level: StackLevel) -> Result<(), WasmCompileErrorKind> {
-        let new_level = new_level.as_usize();
-        if self.inner.len() < new_level {
-            return Err(WasmCompileErrorKind::InvalidStackLevel);
-        }
-        for _ in 0..(self.inner.len() - new_level) {
-            self.pop()?;
-        }
-        Ok(())
+    /// Returns whether the rest of the innermost block is unreachable.
+    #[inline]
+    pub fn is_polymorphic(&self) -> bool {
+        self.is_polymorphic
+    }
+
+    /// Discards the operands of the innermost block after an unconditional branch,
+    /// and lets the following code pop any operands until the end of the block.
+    #[inline]
+    pub fn set_unreachable(&mut self) {
+        self.inner.truncate(self.floor);
+        self.is_polymorphic = true;
+    }
+
+    /// Starts a block whose operands are above `level`.
+    #[inline]
+    pub fn enter_block(&mut self, level: StackLevel) {
+        self.frames.push((self.floor, self.is_polymorphic));
+        self.floor = level.as_usize();
+        self.is_polymorphic = false;
+    }
+
+    /// Returns to the enclosing block.
+    #[inline]
+    pub fn leave_block(&mut self) {
+        (self.floor, self.is_polymorphic) = self.frames.pop().unwrap_or_default();
     }
 }
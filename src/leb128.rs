@@ -298,19 +298,28 @@ impl<'a> Leb128Reader<'a> {
         }
     }
 
+    #[deprecated(note = "use `sub_reader`, which reports the error")]
+    #[inline]
     pub fn sub_slice(&mut self, len: usize) -> Option<Self> {
-        self.position
-            .checked_add(len)
-            .and_then(|end| self.slice.get(self.position..end))
-            .map(|slice| {
-                self.position += len;
-                Self { slice, position: 0 }
-            })
+        self.sub_reader(len).ok()
     }
 
+    /// Returns a reader limited to the next `len` bytes and advances this reader past them.
+    ///
+    /// Reads beyond the end of the returned reader fail with [`ReadError::UnexpectedEof`]
+    /// even if this reader has more bytes, and so does this method if fewer than `len`
+    /// bytes remain.
+    #[inline]
+    pub fn sub_reader(&mut self, len: usize) -> Result<Self, ReadError> {
+        self.read_bytes(len).map(Self::from_slice)
+    }
+
+    /// Reads `size` bytes without copying, or returns [`ReadError::UnexpectedEof`]
+    /// if fewer than `size` bytes remain.
     pub fn read_bytes<'b>(&'b mut self, size: usize) -> Result<&'a [u8], ReadError> {
-        self.slice
-            .get(self.position..self.position + size)
+        self.position
+            .checked_add(size)
+            .and_then(|end| self.slice.get(self.position..end))
             .map(|v| {
                 self.position += size;
                 v
@@ -318,9 +327,11 @@ impl<'a> Leb128Reader<'a> {
             .ok_or(ReadError::UnexpectedEof)
     }
 
+    /// Reads a fixed-size array without copying. Use [`Self::read_bytes`] for a runtime length.
     pub fn read_slice<'b, const N: usize>(&'b mut self) -> Result<&'a [u8; N], ReadError> {
-        self.slice
-            .get(self.position..self.position + N)
+        self.position
+            .checked_add(N)
+            .and_then(|end| self.slice.get(self.position..end))
             .map(|v| {
                 self.position += N;
                 v.try_into().unwrap()
@@ -361,6 +372,28 @@ impl Leb128Reader<'_> {
         self.position >= self.slice.len()
     }
 
+    /// Returns the number of bytes left to read.
+    #[inline]
+    pub const fn remaining(&self) -> usize {
+        self.slice.len().saturating_sub(self.position)
+    }
+
+    /// Returns the next byte without consuming it.
+    #[inline]
+    pub fn peek_byte(&self) -> Option<u8> {
+        self.slice.get(self.position).copied()
+    }
+
+    /// Skips a LEB128 number without decoding it, however long it is.
+    pub fn skip_leb128(&mut self) {
+        while let Some(d) = self.peek_byte() {
+            self.position += 1;
+            if (d & 0x80) == 0 {
+                break;
+            }
+        }
+    }
+
     #[inline]
     pub fn read_byte(&mut self) -> Result<u8, ReadError> {
         self.slice
@@ -388,6 +421,9 @@ impl Leb128Reader<'_> {
             if (d & 0x80) == 0 {
                 break;
             }
+            if scale > 63 {
+                return Err(ReadError::InvalidData);
+            }
         }
         self.position = cursor;
         Ok(value)
@@ -410,6 +446,9 @@ impl Leb128Reader<'_> {
                 break signed;
             }
             scale += 7;
+            if scale > 63 {
+                return Err(ReadError::InvalidData);
+            }
         };
         self.position = cursor;
         if signed {
@@ -475,8 +514,14 @@ macro_rules! leb128_serialize_u {
         impl<'a> ReadLeb128<'a, $type> for Leb128Reader<'_> {
             #[inline]
             fn read(&'a mut self) -> Result<$type, ReadError> {
-                self.read_unsigned()
-                    .and_then(|v| v.try_into().map_err(|_| ReadError::OutOfBounds))
+                let position = self.position;
+                let value = self.read_unsigned()?;
+                // at most ceil(N / 7) bytes
+                if self.position - position > (<$type>::BITS as usize).div_ceil(7) {
+                    self.position = position;
+                    return Err(ReadError::InvalidData);
+                }
+                value.try_into().map_err(|_| ReadError::OutOfBounds)
             }
         }
 
@@ -494,8 +539,14 @@ macro_rules! leb128_serialize_s {
         impl<'a> ReadLeb128<'a, $type> for Leb128Reader<'_> {
             #[inline]
             fn read(&'a mut self) -> Result<$type, ReadError> {
-                self.read_signed()
-                    .and_then(|v| v.try_into().map_err(|_| ReadError::OutOfBounds))
+                let position = self.position;
+                let value = self.read_signed()?;
+                // at most ceil(N / 7) bytes
+                if self.position - position > (<$type>::BITS as usize).div_ceil(7) {
+                    self.position = position;
+                    return Err(ReadError::InvalidData);
+                }
+                value.try_into().map_err(|_| ReadError::OutOfBounds)
             }
         }
 
@@ -525,6 +576,13 @@ impl<'a> ReadLeb128<'a, u8> for Leb128Reader<'_> {
     }
 }
 
+impl WriteLeb128<u8> for Leb128Writer {
+    #[inline]
+    fn write(&mut self, value: u8) -> Result<(), WriteError> {
+        self.write_bytes(&[value])
+    }
+}
+
 impl<'a> ReadLeb128<'a, i8> for Leb128Reader<'_> {
     #[inline]
     fn read(&'a mut self) -> Result<i8, ReadError> {
@@ -532,6 +590,13 @@ impl<'a> ReadLeb128<'a, i8> for Leb128Reader<'_> {
     }
 }
 
+impl WriteLeb128<i8> for Leb128Writer {
+    #[inline]
+    fn write(&mut self, value: i8) -> Result<(), WriteError> {
+        self.write_bytes(&[value as u8])
+    }
+}
+
 impl<'a> ReadLeb128<'a, f32> for Leb128Reader<'_> {
     #[inline]
     fn read(&'a mut self) -> Result<f32, ReadError> {
@@ -539,6 +604,13 @@ impl<'a> ReadLeb128<'a, f32> for Leb128Reader<'_> {
     }
 }
 
+impl WriteLeb128<f32> for Leb128Writer {
+    #[inline]
+    fn write(&mut self, value: f32) -> Result<(), WriteError> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+}
+
 impl<'a> ReadLeb128<'a, f64> for Leb128Reader<'_> {
     #[inline]
     fn read(&'a mut self) -> Result<f64, ReadError> {
@@ -546,6 +618,13 @@ impl<'a> ReadLeb128<'a, f64> for Leb128Reader<'_> {
     }
 }
 
+impl WriteLeb128<f64> for Leb128Writer {
+    #[inline]
+    fn write(&mut self, value: f64) -> Result<(), WriteError> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -595,6 +674,59 @@ mod tests {
         assert_eq!(reader.read_byte().unwrap_err(), ReadError::UnexpectedEof);
     }
 
+    #[test]
+    fn leb128_remaining_peek() {
+        let data = [0x01, 0x02, 0x03, 0x04, 0x05];
+        let mut reader = Leb128Reader::from_slice(&data);
+
+        assert_eq!(reader.remaining(), 5);
+        assert_eq!(reader.peek_byte(), Some(0x01));
+        assert_eq!(reader.position(), 0);
+
+        assert_eq!(reader.read_bytes(2).unwrap(), &[0x01, 0x02]);
+        assert_eq!(reader.remaining(), 3);
+        assert_eq!(reader.peek_byte(), Some(0x03));
+
+        assert_eq!(reader.read_bytes(4).unwrap_err(), ReadError::UnexpectedEof);
+        assert_eq!(
+            reader.read_bytes(usize::MAX).unwrap_err(),
+            ReadError::UnexpectedEof
+        );
+        assert_eq!(reader.position(), 2);
+
+        assert_eq!(reader.read_slice::<3>().unwrap(), &[0x03, 0x04, 0x05]);
+        assert_eq!(reader.remaining(), 0);
+        assert_eq!(reader.peek_byte(), None);
+        assert!(reader.is_eof());
+        assert_eq!(reader.read_bytes(0).unwrap(), &[]);
+
+        reader.set_position(10);
+        assert_eq!(reader.remaining(), 0);
+        assert_eq!(reader.peek_byte(), None);
+        assert_eq!(reader.read_bytes(0).unwrap_err(), ReadError::UnexpectedEof);
+    }
+
+    #[test]
+    fn leb128_skip() {
+        // an overlong u32 followed by a byte
+        let data = [0x80, 0x80, 0x80, 0x80, 0x80, 0x00, 0x2A];
+        let mut reader = Leb128Reader::from_slice(&data);
+
+        // a failed read does not consume the number
+        let result: Result<u32, _> = reader.read();
+        assert_eq!(result.unwrap_err(), ReadError::InvalidData);
+        assert_eq!(reader.position(), 0);
+        reader.skip_leb128();
+        assert_eq!(reader.position(), 6);
+        assert_eq!(reader.read_byte().unwrap(), 0x2A);
+
+        // stops at the end of the stream
+        let data = [0x80, 0x80];
+        let mut reader = Leb128Reader::from_slice(&data);
+        reader.skip_leb128();
+        assert!(reader.is_eof());
+    }
+
     #[test]
     fn leb128_writer() {
         let mut writer = Leb128Writer::new();
@@ -1,6 +1,6 @@
 //! WebAssembly Interpreter
 
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![deny(unsafe_op_in_unsafe_fn)]
 //
 #![feature(slice_split_at_unchecked)]
@@ -23,6 +23,7 @@ pub mod leb128;
 pub mod memory;
 pub mod stack;
 pub mod sync;
+pub mod wasi;
 
 #[path = "_generated/opcode.rs"]
 pub mod opcode;
@@ -32,11 +33,13 @@ mod tests;
 
 pub mod prelude {
     pub use crate::cg::intr::WasmRuntimeError;
-    pub use crate::memory::{WasmMemory, WasmPtr, WasmPtrMut};
+    pub use crate::memory::{MemoryPolicy, WasmMemory, WasmPtr, WasmPtrMut};
     pub use crate::{
-        WasmArgs, WasmCompileError, WasmCompileErrorKind, WasmDynResult, WasmEnv, WasmExports,
-        WasmImportResult, WasmInstance, WasmInvocation, WasmLinkError, WasmModule, WasmResult,
-        WasmRuntimeErrorKind, WasmType, WasmValType, WasmValue, WebAssembly,
+        CompileLimitKind, CompileLimits, CompileOptions, WasmArgs, WasmCompileError,
+        WasmCompileErrorKind, WasmDynResult, WasmEnv, WasmExports, WasmExportsView,
+        WasmHostFunction, WasmImportResult, WasmInstance, WasmInvocation, WasmLinkError,
+        WasmModule, WasmResult, WasmRuntimeErrorKind, WasmSignature, WasmStreamingLoader, WasmType,
+        WasmValType, WasmValue, WebAssembly,
     };
     pub use wami_macro::*;
 }
@@ -1,17 +1,34 @@
 use crate::sync::rwlock_nb::*;
+use crate::sync::TryLockError;
 use crate::*;
 use core::cell::UnsafeCell;
+use core::fmt;
 use core::marker::PhantomData;
 use core::mem::{size_of, transmute};
 use core::ops::{Deref, DerefMut};
 use core::slice;
 use core::sync::atomic::{fence, AtomicU32, Ordering};
 
+/// How the pages of a linear memory are allocated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoryPolicy {
+    /// All pages are allocated and zeroed when the memory is created or grown.
+    #[default]
+    Eager,
+    /// Pages are allocated on the first write from the guest or the first borrow from the host.
+    ///
+    /// Until then, the guest reads them as zero.
+    Lazy,
+}
+
 /// WebAssembly memory object
 pub struct WasmMemory {
     data: RwLockNb<SharedDataStore>,
     size: AtomicU32,
+    initial: u32,
     limit: u32,
+    is_shared: bool,
+    policy: MemoryPolicy,
 }
 
 impl WasmMemory {
@@ -22,16 +39,29 @@ impl WasmMemory {
         Self {
             data: RwLockNb::new(SharedDataStore::new()),
             size: AtomicU32::new(0),
+            initial: 0,
             limit: 0,
+            is_shared: false,
+            policy: MemoryPolicy::Eager,
         }
     }
 
     #[inline]
     pub fn new(limit: WasmLimit) -> Result<Self, WasmCompileErrorKind> {
+        Self::with_policy(limit, MemoryPolicy::Eager)
+    }
+
+    pub fn with_policy(
+        limit: WasmLimit,
+        policy: MemoryPolicy,
+    ) -> Result<Self, WasmCompileErrorKind> {
         let memory = Self {
             data: RwLockNb::new(SharedDataStore::new()),
             size: AtomicU32::new(0),
+            initial: limit.min(),
             limit: limit.max().unwrap_or(u32::MAX).min(Self::MAX_LIMIT),
+            is_shared: limit.is_shared(),
+            policy,
         };
 
         if limit.is_zero() {
@@ -44,15 +74,68 @@ impl WasmMemory {
             .map_err(|_| WasmCompileErrorKind::OutOfMemory)
     }
 
+    #[inline]
+    pub const fn policy(&self) -> MemoryPolicy {
+        self.policy
+    }
+
+    /// Returns whether the memory is declared as `shared`.
+    #[inline]
+    pub const fn is_shared(&self) -> bool {
+        self.is_shared
+    }
+
+    /// Borrows the linear memory after allocating all of its pages.
     #[inline]
     pub fn try_borrow(
         &self,
+    ) -> Result<RwLockNbReadGuard<'_, SharedDataStore>, WasmRuntimeErrorKind> {
+        self.commit(self.byte_len())?;
+        self.try_borrow_committed()
+    }
+
+    /// Borrows only the pages allocated so far.
+    #[inline]
+    pub(crate) fn try_borrow_committed(
+        &self,
     ) -> Result<RwLockNbReadGuard<'_, SharedDataStore>, WasmRuntimeErrorKind> {
         self.data
             .try_read()
             .map_err(|_| WasmRuntimeErrorKind::MemoryBorrowError)
     }
 
+    /// Borrows the entire linear memory as `&[u8]`
+    ///
+    /// The memory cannot grow while the returned guard is alive.
+    #[inline]
+    pub fn borrow(&self) -> Result<WasmMemoryGuard<'_>, WasmRuntimeErrorKind> {
+        self.try_borrow().map(|guard| WasmMemoryGuard { guard })
+    }
+
+    /// Borrows the entire linear memory as `&mut [u8]`
+    ///
+    /// The memory cannot grow or be borrowed by others while the returned guard is alive.
+    #[inline]
+    pub fn borrow_mut(&self) -> Result<WasmMemoryGuardMut<'_>, WasmRuntimeErrorKind> {
+        self.commit(self.byte_len())?;
+        self.data
+            .try_write()
+            .map(|guard| WasmMemoryGuardMut { guard })
+            .map_err(|_| WasmRuntimeErrorKind::MemoryBorrowError)
+    }
+
+    /// Returns the entire linear memory without locking, as exclusive access is guaranteed.
+    ///
+    /// A memory poisoned by a panic while borrowed is [`WasmRuntimeErrorKind::MemoryBorrowError`],
+    /// as with the other accessors.
+    pub fn as_mut_slice(&mut self) -> Result<&mut [u8], WasmRuntimeErrorKind> {
+        self.commit(self.byte_len())?;
+        self.data
+            .get_mut()
+            .map(|data| data.as_mut_slice())
+            .map_err(|_| WasmRuntimeErrorKind::MemoryBorrowError)
+    }
+
     #[inline]
     pub fn borrowing<F, R>(&self, kernel: F) -> Result<R, WasmRuntimeErrorKind>
     where
@@ -70,13 +153,46 @@ impl WasmMemory {
         self.size.load(Ordering::Acquire)
     }
 
+    /// Returns the size of the memory in bytes, including pages not allocated yet.
+    #[inline]
+    pub fn byte_len(&self) -> usize {
+        self.size() as usize * WebAssembly::PAGE_SIZE
+    }
+
+    /// Returns the number of bytes actually allocated.
+    #[inline]
+    pub fn committed_len(&self) -> Result<usize, WasmRuntimeErrorKind> {
+        self.try_borrow_committed().map(|v| v.len())
+    }
+
+    /// Allocates the pages up to `end` bytes if they are not yet allocated.
+    pub(crate) fn commit(&self, end: usize) -> Result<(), WasmRuntimeErrorKind> {
+        let byte_len = self.byte_len();
+        if end > byte_len {
+            return Err(WasmRuntimeErrorKind::OutOfBounds);
+        }
+        if self.committed_len()? >= end {
+            return Ok(());
+        }
+        let mut memory = self.data.try_write().map_err(|err| match err {
+            TryLockError::WouldBlock => WasmRuntimeErrorKind::WouldBlock,
+            TryLockError::Poisoned(_) => WasmRuntimeErrorKind::MemoryBorrowError,
+        })?;
+        let new_len = end.next_multiple_of(WebAssembly::PAGE_SIZE).min(byte_len);
+        let additional = new_len.saturating_sub(memory.len());
+        memory.try_grow(additional)
+    }
+
     /// memory.grow
+    ///
+    /// Returns the previous size in pages, or [`WasmRuntimeErrorKind::InvalidParameter`]
+    /// if the memory would exceed its maximum.
     pub fn grow(&self, delta: u32) -> Result<u32, WasmRuntimeErrorKind> {
         if delta > 0 {
-            let mut memory = self
-                .data
-                .try_write()
-                .map_err(|_| WasmRuntimeErrorKind::MemoryBorrowError)?;
+            let mut memory = self.data.try_write().map_err(|err| match err {
+                TryLockError::WouldBlock => WasmRuntimeErrorKind::WouldBlock,
+                TryLockError::Poisoned(_) => WasmRuntimeErrorKind::MemoryBorrowError,
+            })?;
 
             let old_len = self.size();
             let new_len = old_len.saturating_add(delta);
@@ -87,7 +203,9 @@ impl WasmMemory {
             let additional = (delta as usize)
                 .checked_mul(WebAssembly::PAGE_SIZE)
                 .ok_or(WasmRuntimeErrorKind::InvalidParameter)?;
-            memory.try_grow(additional)?;
+            if self.policy == MemoryPolicy::Eager {
+                memory.try_grow(additional)?;
+            }
 
             self.size.store(new_len, Ordering::Release);
             Ok(old_len)
@@ -96,15 +214,30 @@ impl WasmMemory {
         }
     }
 
+    /// Shrinks the memory back to its initial size and clears it to zero.
+    pub(crate) fn reset(&self) -> Result<(), WasmRuntimeErrorKind> {
+        let mut memory = self.data.try_write().map_err(|err| match err {
+            TryLockError::WouldBlock => WasmRuntimeErrorKind::WouldBlock,
+            TryLockError::Poisoned(_) => WasmRuntimeErrorKind::MemoryBorrowError,
+        })?;
+        memory.clear();
+        if self.policy == MemoryPolicy::Eager {
+            memory.try_grow(self.initial as usize * WebAssembly::PAGE_SIZE)?;
+        }
+        self.size.store(self.initial, Ordering::Release);
+        Ok(())
+    }
+
     /// Write slice to memory
+    ///
+    /// Nothing is written unless the whole range fits in the memory.
     pub fn write_slice(&self, offset: usize, src: &[u8]) -> Result<(), WasmRuntimeErrorKind> {
-        let memory = self.try_borrow()?;
         let count = src.len();
-        let limit = memory.len();
-        let Some(end) = offset.checked_add(count) else {
-            return Err(WasmRuntimeErrorKind::OutOfBounds);
-        };
-        if offset < limit && end <= limit {
+        Self::check_bound(offset as u64, count, self.byte_len())?;
+        let end = offset + count;
+        self.commit(end)?;
+        let memory = self.try_borrow_committed()?;
+        if end <= memory.len() {
             unsafe {
                 memory
                     .as_mut_ptr()
@@ -137,6 +270,70 @@ impl WasmMemory {
     }
 }
 
+impl fmt::Debug for WasmMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmMemory")
+            .field("size", &self.size())
+            .field("limit", &self.limit)
+            .field("is_shared", &self.is_shared)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+/// A RAII guard that gives shared access to the linear memory
+#[must_use = "if unused the memory will immediately unlock"]
+pub struct WasmMemoryGuard<'a> {
+    guard: RwLockNbReadGuard<'a, SharedDataStore>,
+}
+
+impl fmt::Debug for WasmMemoryGuard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmMemoryGuard")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Deref for WasmMemoryGuard<'_> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.guard.as_slice()
+    }
+}
+
+/// A RAII guard that gives exclusive access to the linear memory
+#[must_use = "if unused the memory will immediately unlock"]
+pub struct WasmMemoryGuardMut<'a> {
+    guard: RwLockNbWriteGuard<'a, SharedDataStore>,
+}
+
+impl fmt::Debug for WasmMemoryGuardMut<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmMemoryGuardMut")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Deref for WasmMemoryGuardMut<'_> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.guard.as_slice()
+    }
+}
+
+impl DerefMut for WasmMemoryGuardMut<'_> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.guard.as_mut_slice()
+    }
+}
+
 #[repr(transparent)]
 #[derive(Debug)]
 pub struct SharedDataStore(UnsafeCell<Vec<u8>>);
@@ -243,6 +440,11 @@ impl SharedDataStore {
         Ok(())
     }
 
+    #[inline]
+    fn clear(&mut self) {
+        self.0.get_mut().clear();
+    }
+
     #[inline]
     pub fn fill(&self, value: u8) {
         self.as_mut_slice().fill(value);
@@ -324,6 +526,34 @@ impl<T> WasmPtr<T> {
     pub const fn as_usize(&self) -> usize {
         self.repr as usize
     }
+
+    /// Returns the pointer advanced by `bytes` bytes.
+    #[inline]
+    pub const fn offset(&self, bytes: u32) -> Result<Self, WasmRuntimeErrorKind> {
+        match self.repr.checked_add(bytes) {
+            Some(repr) => Ok(Self::from_u32(repr)),
+            None => Err(WasmRuntimeErrorKind::OutOfBounds),
+        }
+    }
+
+    /// Returns the pointer to the `count`-th element of an array of `T` starting here.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub const fn add(&self, count: u32) -> Result<Self, WasmRuntimeErrorKind> {
+        match (size_of::<T>() as u32).checked_mul(count) {
+            Some(bytes) => self.offset(bytes),
+            None => Err(WasmRuntimeErrorKind::OutOfBounds),
+        }
+    }
+}
+
+impl<T: Sized + Copy> WasmPtr<T> {
+    /// Reads the value pointed to by this pointer from `memory`.
+    pub fn read(&self, memory: &WasmMemory) -> Result<T, WasmRuntimeErrorKind> {
+        let guard = memory.try_borrow()?;
+        WasmMemory::check_bound(self.repr as u64, size_of::<T>(), guard.len())?;
+        Ok(unsafe { (guard.as_ptr().add(self.as_usize()) as *const T).read_unaligned() })
+    }
 }
 
 impl<T> From<WasmPtr<T>> for WasmValue {
@@ -358,6 +588,43 @@ impl<T> WasmPtrMut<T> {
     pub const fn as_usize(&self) -> usize {
         self.repr as usize
     }
+
+    /// Returns the pointer advanced by `bytes` bytes.
+    #[inline]
+    pub const fn offset(&self, bytes: u32) -> Result<Self, WasmRuntimeErrorKind> {
+        match self.repr.checked_add(bytes) {
+            Some(repr) => Ok(Self::from_u32(repr)),
+            None => Err(WasmRuntimeErrorKind::OutOfBounds),
+        }
+    }
+
+    /// Returns the pointer to the `count`-th element of an array of `T` starting here.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub const fn add(&self, count: u32) -> Result<Self, WasmRuntimeErrorKind> {
+        match (size_of::<T>() as u32).checked_mul(count) {
+            Some(bytes) => self.offset(bytes),
+            None => Err(WasmRuntimeErrorKind::OutOfBounds),
+        }
+    }
+}
+
+impl<T: Sized + Copy> WasmPtrMut<T> {
+    /// Reads the value pointed to by this pointer from `memory`.
+    #[inline]
+    pub fn read(&self, memory: &WasmMemory) -> Result<T, WasmRuntimeErrorKind> {
+        WasmPtr::from(*self).read(memory)
+    }
+
+    /// Writes `value` to the location pointed to by this pointer in `memory`.
+    pub fn write(&self, memory: &WasmMemory, value: T) -> Result<(), WasmRuntimeErrorKind> {
+        let guard = memory.try_borrow()?;
+        WasmMemory::check_bound(self.repr as u64, size_of::<T>(), guard.len())?;
+        unsafe {
+            (guard.as_mut_ptr().add(self.as_usize()) as *mut T).write_unaligned(value);
+        }
+        Ok(())
+    }
 }
 
 impl<T> From<WasmPtrMut<T>> for WasmPtr<T> {
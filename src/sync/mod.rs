@@ -1,4 +1,12 @@
+//! Synchronization primitives
+//!
+//! Locks in this module never block the calling thread on `try_*` methods.
+//! They are not re-entrant: acquiring a write lock while any guard is held
+//! (even by the same thread) fails with [`TryLockError::WouldBlock`],
+//! and the blocking variants will spin forever in that case.
+
 use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 pub mod rwlock_nb;
 
@@ -6,18 +14,48 @@ pub type LockResult<Guard> = Result<Guard, PoisonError<Guard>>;
 
 pub type TryLockResult<Guard> = Result<Guard, TryLockError<Guard>>;
 
-/// NOT YET IMPLEMENTED
-#[allow(dead_code)]
+/// A type of error which can be returned whenever a lock is acquired.
+///
+/// A lock is poisoned whenever a thread panics while holding a write lock.
+/// Poisoning can only be detected when `std` is available (`std` feature or tests).
 pub struct PoisonError<T> {
     guard: T,
 }
 
+impl<T> PoisonError<T> {
+    #[inline]
+    pub const fn new(guard: T) -> Self {
+        Self { guard }
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.guard
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.guard
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
 impl<T> fmt::Debug for PoisonError<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str("PoisonError")
     }
 }
 
+impl<T> fmt::Display for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("poisoned lock: another task failed inside")
+    }
+}
+
 #[derive(Debug)]
 pub enum TryLockError<T> {
     Poisoned(PoisonError<T>),
@@ -30,3 +68,72 @@ impl<T> From<PoisonError<T>> for TryLockError<T> {
         TryLockError::Poisoned(err)
     }
 }
+
+impl<T> fmt::Display for TryLockError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Poisoned(v) => v.fmt(f),
+            Self::WouldBlock => f.write_str("try_lock failed because the operation would block"),
+        }
+    }
+}
+
+/// Poison flag shared by lock implementations
+pub(crate) struct PoisonFlag(AtomicBool);
+
+impl PoisonFlag {
+    #[inline]
+    pub const fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    #[inline]
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn clear(&self) {
+        self.0.store(false, Ordering::Relaxed)
+    }
+
+    /// Returns the state to pass to [`PoisonFlag::done`] when the guard is released.
+    #[inline]
+    pub fn guard(&self) -> PoisonGuard {
+        PoisonGuard {
+            panicking: panicking(),
+        }
+    }
+
+    #[inline]
+    pub fn done(&self, guard: &PoisonGuard) {
+        if !guard.panicking && panicking() {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    pub fn map<T>(&self, guard: T) -> LockResult<T> {
+        if self.get() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+}
+
+pub(crate) struct PoisonGuard {
+    panicking: bool,
+}
+
+#[inline]
+fn panicking() -> bool {
+    #[cfg(any(test, feature = "std"))]
+    {
+        std::thread::panicking()
+    }
+    #[cfg(not(any(test, feature = "std")))]
+    {
+        false
+    }
+}
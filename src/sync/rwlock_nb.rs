@@ -2,12 +2,14 @@
 
 use super::*;
 use core::cell::UnsafeCell;
+use core::hint::spin_loop;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 /// A reader-writer lock like std::sync::RwLock
 pub struct RwLockNb<T: ?Sized> {
     inner: SharedXorMutable,
+    poison: PoisonFlag,
     data: UnsafeCell<T>,
 }
 
@@ -16,6 +18,7 @@ impl<T> RwLockNb<T> {
     pub const fn new(val: T) -> Self {
         Self {
             inner: SharedXorMutable::new(),
+            poison: PoisonFlag::new(),
             data: UnsafeCell::new(val),
         }
     }
@@ -26,6 +29,32 @@ unsafe impl<T: ?Sized + Send> Send for RwLockNb<T> {}
 unsafe impl<T: ?Sized + Send + Sync> Sync for RwLockNb<T> {}
 
 impl<T: ?Sized> RwLockNb<T> {
+    /// Locks this rwlock with shared read access, spinning until it can be acquired.
+    ///
+    /// Calling this while the current thread holds a write guard will never return.
+    pub fn read(&self) -> LockResult<RwLockNbReadGuard<'_, T>> {
+        loop {
+            match self.try_read() {
+                Ok(guard) => return Ok(guard),
+                Err(TryLockError::Poisoned(err)) => return Err(err),
+                Err(TryLockError::WouldBlock) => spin_loop(),
+            }
+        }
+    }
+
+    /// Locks this rwlock with exclusive write access, spinning until it can be acquired.
+    ///
+    /// Calling this while the current thread holds any guard will never return.
+    pub fn write(&self) -> LockResult<RwLockNbWriteGuard<'_, T>> {
+        loop {
+            match self.try_write() {
+                Ok(guard) => return Ok(guard),
+                Err(TryLockError::Poisoned(err)) => return Err(err),
+                Err(TryLockError::WouldBlock) => spin_loop(),
+            }
+        }
+    }
+
     #[inline]
     pub fn try_read(&self) -> TryLockResult<RwLockNbReadGuard<'_, T>> {
         unsafe {
@@ -53,15 +82,29 @@ impl<T: ?Sized> RwLockNb<T> {
     where
         T: Sized,
     {
+        let poisoned = self.poison.get();
         let data = self.data.into_inner();
-        // TODO: poison
-        Ok(data)
+        if poisoned {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
     }
 
     #[inline]
     pub fn get_mut(&mut self) -> LockResult<&mut T> {
-        // TODO: poison
-        Ok(self.data.get_mut())
+        let data = self.data.get_mut();
+        self.poison.map(data)
+    }
+
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.poison.clear();
     }
 }
 
@@ -90,7 +133,7 @@ unsafe impl<T: ?Sized + Sync> Sync for RwLockNbReadGuard<'_, T> {}
 
 impl<'rwlock, T: ?Sized> RwLockNbReadGuard<'rwlock, T> {
     unsafe fn new(lock: &'rwlock RwLockNb<T>) -> LockResult<RwLockNbReadGuard<'rwlock, T>> {
-        Ok(Self { lock })
+        lock.poison.map(Self { lock })
     }
 }
 
@@ -121,6 +164,7 @@ impl<T: fmt::Debug> fmt::Debug for RwLockNbReadGuard<'_, T> {
 #[must_use = "if unused the RwLock will immediately unlock"]
 pub struct RwLockNbWriteGuard<'a, T: ?Sized + 'a> {
     lock: &'a RwLockNb<T>,
+    poison: PoisonGuard,
 }
 
 impl<T: ?Sized> !Send for RwLockNbWriteGuard<'_, T> {}
@@ -129,7 +173,8 @@ unsafe impl<T: ?Sized + Sync> Sync for RwLockNbWriteGuard<'_, T> {}
 
 impl<'rwlock, T: ?Sized> RwLockNbWriteGuard<'rwlock, T> {
     unsafe fn new(lock: &'rwlock RwLockNb<T>) -> LockResult<RwLockNbWriteGuard<'rwlock, T>> {
-        Ok(Self { lock })
+        let poison = lock.poison.guard();
+        lock.poison.map(Self { lock, poison })
     }
 }
 
@@ -152,6 +197,7 @@ impl<T: ?Sized> DerefMut for RwLockNbWriteGuard<'_, T> {
 impl<T: ?Sized> Drop for RwLockNbWriteGuard<'_, T> {
     #[inline]
     fn drop(&mut self) {
+        self.lock.poison.done(&self.poison);
         unsafe {
             self.lock.inner.unlock_write();
         }
@@ -181,7 +227,7 @@ impl SharedXorMutable {
     #[inline]
     pub fn try_write(&self) -> Result<(), WriteError> {
         self.0
-            .compare_exchange_weak(
+            .compare_exchange(
                 Self::DEFAULT_VALUE,
                 Self::LOCK_WRITE,
                 Ordering::SeqCst,
@@ -271,4 +317,40 @@ mod tests {
             assert_eq!(*lock1, 579);
         }
     }
+
+    #[test]
+    fn rwlock_poison() {
+        let rwlock = RwLockNb::new(123);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _lock = rwlock.try_read().unwrap();
+            panic!("read lock does not poison");
+        }));
+        assert!(result.is_err());
+        assert!(!rwlock.is_poisoned());
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut lock = rwlock.try_write().unwrap();
+            *lock = 456;
+            panic!("write lock poisons");
+        }));
+        assert!(result.is_err());
+        assert!(rwlock.is_poisoned());
+
+        match rwlock.try_read() {
+            Err(TryLockError::Poisoned(err)) => assert_eq!(*err.into_inner(), 456),
+            _ => unreachable!(),
+        }
+        match rwlock.try_write() {
+            Err(TryLockError::Poisoned(err)) => assert_eq!(*err.into_inner(), 456),
+            _ => unreachable!(),
+        }
+        let err = rwlock.read().unwrap_err();
+        assert_eq!(**err.get_ref(), 456);
+        drop(err);
+
+        rwlock.clear_poison();
+        assert_eq!(*rwlock.write().unwrap(), 456);
+        assert_eq!(rwlock.into_inner().unwrap(), 456);
+    }
 }
@@ -1,8 +1,12 @@
+use crate::cg::intcode::WasmImInstruction;
 use crate::cg::intr::WasmInterpreter;
 use crate::cg::WasmCodeBlock;
-use crate::opcode::WasmMnemonic;
+use crate::opcode::{WasmMnemonic, WasmOpcode, WasmProposal};
 use crate::prelude::*;
-use crate::{leb128::*, WasmSectionId};
+use crate::{
+    leb128::*, WasmFunctionContent, WasmMemArg, WasmModuleBuilder, WasmRefType, WasmRunnable,
+    WasmSectionId,
+};
 use core::f64::consts::PI;
 use num_traits::Zero;
 use std::assert_matches::assert_matches;
@@ -32,6 +36,10 @@ impl Env {
     pub fn signature_test3() -> WasmResult<i32> {
         unreachable!()
     }
+
+    pub fn str_len(s: &str) -> i32 {
+        s.len() as i32
+    }
 }
 
 #[wasm_exports]
@@ -92,6 +100,21 @@ trait TestTask {
     fn exit_test();
 }
 
+/// Builds the module and instantiates it with [`Env`].
+fn build_instance(builder: WasmModuleBuilder) -> WasmInstance {
+    builder.build().unwrap().instantiate(&Env {}).unwrap()
+}
+
+/// Invokes the function, returning the error of a trap as a [`WasmRuntimeError`].
+fn invoke(
+    function: &WasmRunnable<'_>,
+    args: &[WasmValue],
+) -> Result<Option<WasmValue>, Box<WasmRuntimeError>> {
+    function
+        .invoke(args)
+        .map_err(|e| WasmRuntimeError::try_from_error(e).unwrap())
+}
+
 #[test]
 fn instantiate() {
     let data = [0, 97, 115, 109, 1, 0, 0, 0];
@@ -124,1157 +147,3191 @@ fn instantiate() {
 }
 
 #[test]
-fn section_order() {
-    let data = [
-        0, 97, 115, 109, 1, 0, 0, 0, 1, 1, 0, 2, 1, 0, 2, 1, 0, 3, 1, 0, 4, 1, 0, 5, 1, 0,
-    ];
-    WebAssembly::instantiate(&data, &Env {}).unwrap();
+fn module_builder() {
+    use crate::{ImportExportKind, WasmLimit};
+
+    // (func (param i32 i32) (result i32) local.get 0 local.get 1 call $add i32.const 1 i32.add)
+    let module = WasmModule::builder()
+        .import_function("env", "add", WasmType::from_signature("iii").unwrap())
+        .function(
+            WasmType::from_signature("iii").unwrap(),
+            &[0, 0x20, 0, 0x20, 1, 0x10, 0, 0x41, 1, 0x6A, 0x0B],
+        )
+        .memory(WasmLimit::new(1, Some(2)))
+        .global(WasmValue::from(123i32), true)
+        .export("add1", ImportExportKind::Function, 1)
+        .export("memory", ImportExportKind::Memory, 0)
+        .export("counter", ImportExportKind::Global, 0)
+        .build()
+        .unwrap();
+    assert_eq!(module.function_descriptors().count(), 2);
+    assert!(module.has_memory());
 
-    let data = [
-        0, 97, 115, 109, 1, 0, 0, 0, 1, 1, 0, 2, 1, 0, 2, 1, 0, 3, 1, 0, 4, 1, 0, 3, 1, 0,
-    ];
-    assert_matches!(
-        WasmCompileErrorKind::downcast_ref(&WebAssembly::instantiate(&data, &Env {}).unwrap_err())
-            .unwrap(),
-        WasmCompileErrorKind::InvalidSectionOrder(WasmSectionId::Function)
+    let instance = module.instantiate(&Env {}).unwrap();
+    let result = instance
+        .exports()
+        .get("add1")
+        .unwrap()
+        .invoke(&[2.into(), 3.into()])
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 6);
+    assert_eq!(instance.memory(0).unwrap().size(), 1);
+    assert_eq!(
+        instance.module().globals()[0].value().get_i32().unwrap(),
+        123
+    );
+
+    // the body is verified
+    let err = WasmModule::builder()
+        .function(WasmType::from_signature("iv").unwrap(), &[0, 0x42, 0, 0x0B])
+        .build()
+        .unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+
+    // exports must refer to existing items
+    let err = WasmModule::builder()
+        .function(WasmType::from_signature("vv").unwrap(), &[0, 0x0B])
+        .export("f", ImportExportKind::Function, 1)
+        .build()
+        .unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::InvalidData);
+}
+
+#[test]
+fn function_descriptors() {
+    let module = WebAssembly::compile(include_bytes!("../test/tester.wasm")).unwrap();
+    let functions = module.function_descriptors().collect::<Vec<_>>();
+
+    let add = &functions[0];
+    assert_eq!(add.index, 0);
+    assert_eq!(add.import, Some(("env", "add")));
+    assert_eq!(add.export, None);
+    assert_eq!(add.signature.signature(), "iii");
+
+    let signature_test1 = &functions[2];
+    assert_eq!(signature_test1.import, Some(("env", "signature_test1")));
+    assert_eq!(signature_test1.signature.signature(), "filfd");
+
+    let fib = functions.iter().find(|v| v.export == Some("fib")).unwrap();
+    assert_eq!(fib.import, None);
+    assert_eq!(fib.signature.param_types(), &[WasmValType::I32]);
+    assert_eq!(fib.signature.result_types(), &[WasmValType::I32]);
+    assert_eq!(fib.signature.to_string(), " (param i32) (result i32)");
+
+    let n_imports = module
+        .imports()
+        .filter(|v| v.kind == crate::ImportExportKind::Function)
+        .count();
+    assert_eq!(
+        functions.iter().filter(|v| v.import.is_some()).count(),
+        n_imports
     );
+    assert!(functions.iter().enumerate().all(|(i, v)| v.index == i));
 }
 
 #[test]
-fn i32_const() {
-    let slice = [0, 0x41, 0xf8, 0xac, 0xd1, 0x91, 0x01, 0x0B];
-    let result_types = [WasmValType::I32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn val_type_size() {
+    for (val_type, size, is_integer) in [
+        (WasmValType::I32, 4, true),
+        (WasmValType::I64, 8, true),
+        (WasmValType::F32, 4, false),
+        (WasmValType::F64, 8, false),
+    ] {
+        assert_eq!(val_type.size(), size);
+        assert_eq!(val_type.natural_alignment(), size);
+        assert_eq!(val_type.is_integer(), is_integer);
+        assert_eq!(val_type.is_float(), !is_integer);
+    }
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 0x12345678);
+    let func_ref = WasmValType::FuncRef;
+    assert_eq!(func_ref.size(), size_of::<usize>());
+    assert!(!func_ref.is_integer() && !func_ref.is_float());
 }
 
 #[test]
-fn i32_const_type_mismatch() {
-    let slice = [0, 0x41, 0x00, 0x01, 0x0B];
-    let result_types = [WasmValType::I64];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
+fn val_type_name() {
+    for val_type in [
+        WasmValType::I32,
+        WasmValType::I64,
+        WasmValType::F32,
+        WasmValType::F64,
+        WasmValType::FuncRef,
+    ] {
+        let name = val_type.to_string();
+        assert_eq!(name.parse::<WasmValType>().unwrap(), val_type);
+    }
+    assert_eq!(WasmValType::FuncRef.to_string(), "funcref");
+
+    for name in ["", "I32", "i8", "anyref"] {
+        assert_matches!(
+            name.parse::<WasmValType>(),
+            Err(WasmCompileErrorKind::UnexpectedToken)
+        );
+    }
+}
+
+#[test]
+fn streaming_loader() {
+    let bytes = include_bytes!("../test/tester.wasm");
+    let bulk = WebAssembly::compile(bytes).unwrap();
+
+    let mut loader = WasmStreamingLoader::new();
+    for byte in bytes.iter() {
+        loader.push_bytes(core::slice::from_ref(byte)).unwrap();
+    }
+    let module = loader.finish().unwrap();
+
+    assert_eq!(module.functions().len(), bulk.functions().len());
+    for index in 0..bulk.functions().len() {
+        assert_eq!(module.disassemble(index), bulk.disassemble(index));
+    }
+    assert!(module
+        .exports()
+        .map(|v| (v.name, v.kind))
+        .eq(bulk.exports().map(|v| (v.name, v.kind))));
+    assert_eq!(module.custom_sections("name"), bulk.custom_sections("name"));
+
+    // incomplete module, the error is reported by `finish`
+    let mut loader = WasmStreamingLoader::new();
+    loader.push_bytes(&bytes[..bytes.len() - 1]).unwrap();
+    let err = loader.finish().unwrap_err();
     assert_matches!(
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module())
-            .unwrap_err()
-            .kind(),
-        WasmCompileErrorKind::TypeMismatch
+        WasmCompileErrorKind::downcast_ref(&err),
+        Some(WasmCompileErrorKind::UnexpectedEof)
     );
 
-    let slice = [0, 0x41, 0x00, 0x01, 0x0B];
-    let result_types = [WasmValType::I32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+    let mut loader = WasmStreamingLoader::new();
+    loader.push_bytes(&bytes[..4]).unwrap();
+    let err = loader.finish().unwrap_err();
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(&err),
+        Some(WasmCompileErrorKind::UnexpectedEof)
+    );
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 0);
+    let mut loader = WasmStreamingLoader::new();
+    let err = loader.push_bytes(b"\0elf").unwrap_err();
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(&err),
+        Some(WasmCompileErrorKind::BadExecutable)
+    );
 
-    let result2 = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i64();
-    assert_matches!(result2, Err(WasmRuntimeErrorKind::TypeMismatch));
+    // an oversized section is rejected by its header, before its contents arrive
+    let limits = CompileLimits {
+        max_functions: 1,
+        max_code_size: 16,
+        ..Default::default()
+    };
+    let mut loader = WasmStreamingLoader::with_options(&CompileOptions::new().with_limits(limits));
+    loader.push_bytes(&bytes[..8]).unwrap();
+    let err = loader.push_bytes(&[0x0A, 0xE8, 0x07]).unwrap_err();
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(&err),
+        Some(WasmCompileErrorKind::LimitsExceeded(
+            CompileLimitKind::CodeSize
+        ))
+    );
 }
 
 #[test]
-fn i64_const() {
-    let slice = [
-        0, 0x42, 0xef, 0x9b, 0xaf, 0xcd, 0xf8, 0xac, 0xd1, 0x91, 0x01, 0x0B,
-    ];
-    let result_types = [WasmValType::I64];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn disassemble() {
+    // (func (param i32) (result i32) local.get 0 i32.const 1 i32.add local.get 0 i32.div_u)
+    let module = WasmModule::builder()
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x20, 0, 0x41, 1, 0x6A, 0x20, 0, 0x6E, 0x0B],
+        )
+        .build()
+        .unwrap();
+    let text = module.disassemble(0).unwrap();
+    assert_eq!(text.lines().next(), Some("func 0 @0x0 locals [I32]"));
+    assert_eq!(text.lines().count(), 7);
+    assert!(text
+        .lines()
+        .any(|line| line.ends_with("] *FusedI32AddI(1)")));
+    assert!(text
+        .lines()
+        .any(|line| line.contains("]  I32DivU(") && line.ends_with("@0x8")));
+    assert!(text.lines().all(|line| !line.contains("I32Add ")));
+    assert!(module.disassemble(1).is_none());
+}
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i64()
+#[test]
+fn exported_memory() {
+    use crate::{ImportExportKind, WasmLimit};
+
+    let module = WasmModule::builder()
+        .function(WasmType::from_signature("iv").unwrap(), &[0, 0x41, 1, 0x0B])
+        .memory(WasmLimit::new(1, None))
+        .export("one", ImportExportKind::Function, 0)
+        .export("heap", ImportExportKind::Memory, 0)
+        .export("memory", ImportExportKind::Memory, 0)
+        .build()
         .unwrap();
-    assert_eq!(result, 0x123456789abcdef);
+    assert_eq!(module.exported_memory(), Some((0, "memory")));
+    assert_eq!(module.exported_memory_index(), Some(0));
+    assert_eq!(module.exported_memory_name(), Some("memory"));
+    assert_eq!(module.exported_table(), None);
+
+    let module = WasmModule::builder()
+        .memory(WasmLimit::new(1, None))
+        .export("heap", ImportExportKind::Memory, 0)
+        .build()
+        .unwrap();
+    assert_eq!(module.exported_memory_name(), Some("heap"));
+
+    let module = WasmModule::builder()
+        .memory(WasmLimit::new(1, None))
+        .build()
+        .unwrap();
+    assert_eq!(module.exported_memory_index(), None);
 }
 
 #[test]
-fn i64_const_type_mismatch() {
-    let slice = [0, 0x42, 0x00, 0x01, 0x0B];
-    let result_types = [WasmValType::I32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
+fn module_without_functions() {
+    // (module
+    //   (memory (export "memory") 1)
+    //   (global (export "answer") i32 (i32.const 42)))
+    let bytes = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, // header
+        0x05, 0x03, 0x01, 0x00, 0x01, // memory
+        0x06, 0x06, 0x01, 0x7F, 0x00, 0x41, 0x2A, 0x0B, // global
+        0x07, 0x13, 0x02, // export
+        0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00, //
+        0x06, b'a', b'n', b's', b'w', b'e', b'r', 0x03, 0x00, //
+    ];
+    let instance = WebAssembly::instantiate(&bytes, &Env {}).unwrap();
+    assert_eq!(instance.module().functions().len(), 0);
+
+    assert!(instance.exports().get("_start").is_none());
+    assert!(instance.exports().get("memory").is_none());
+    assert_eq!(instance.exports().functions().count(), 0);
     assert_matches!(
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module())
-            .unwrap_err()
-            .kind(),
-        WasmCompileErrorKind::TypeMismatch
+        instance.function("_start").map(|_| ()),
+        Err(WasmRuntimeErrorKind::NoMethod)
+    );
+
+    let (name, global) = instance.exports().globals().next().unwrap();
+    assert_eq!(name, "answer");
+    assert_eq!(global.value().get_i32().unwrap(), 42);
+    assert_eq!(
+        instance
+            .global("answer")
+            .unwrap()
+            .value()
+            .get_i32()
+            .unwrap(),
+        42
     );
+    assert_eq!(instance.exports().memory().size(), 1);
 }
 
 #[test]
-fn float_const() {
-    let slice = [0, 0x43, 0, 0, 0, 0, 0x0B];
-    let result_types = [WasmValType::F32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn exports_tuple_result() {
+    #[wasm_exports]
+    trait Arith {
+        fn divmod(a: i32, b: i32) -> (i32, i32);
+    }
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_f32()
-        .unwrap();
-    assert_eq!(result, 0.0);
+    // (func (export "divmod") (param i32 i32) (result i32 i32)
+    //   (i32.div_s (local.get 0) (local.get 1))
+    //   (i32.rem_s (local.get 0) (local.get 1)))
+    let bytes = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x08, 0x01, 0x60, 0x02, 0x7F, 0x7F, 0x02, 0x7F, 0x7F, // type
+        0x03, 0x02, 0x01, 0x00, // func
+        0x07, 0x0A, 0x01, 0x06, b'd', b'i', b'v', b'm', b'o', b'd', 0x00, 0x00, // export
+        0x0A, 0x0E, 0x01, 0x0C, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6D, 0x20, 0x00, 0x20, 0x01, 0x6F,
+        0x0B, // code
+    ];
+    let instance = WebAssembly::instantiate(&bytes, &Env {}).unwrap();
 
-    let slice = [0, 0x43, 0, 0, 0xc0, 0x7f, 0x0B];
-    let result_types = [WasmValType::F32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+    assert_eq!(instance.exports().divmod(17, 5).unwrap(), (3, 2));
+    assert_eq!(instance.exports().divmod(-17, 5).unwrap(), (-3, -2));
+    assert!(instance.exports().divmod(1, 0).is_err());
+}
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_f32()
-        .unwrap();
-    assert!(result.is_nan());
+#[test]
+fn memory_address_overflow() {
+    use crate::WasmLimit;
+
+    // (func (param i32) (result ?) local.get 0 (op) align offset)
+    let load = |signature: &str, opcode: u8, offset: &[u8], fusion: bool| {
+        let body = [&[0, 0x20, 0, opcode, 0][..], offset, &[0x0B]].concat();
+        build_instance(
+            WasmModule::builder()
+                .options(CompileOptions::new().with_fusion(fusion))
+                .function(WasmType::from_signature(signature).unwrap(), &body)
+                .memory(WasmLimit::new(1, None)),
+        )
+    };
+    // (func (param i32) local.get 0 (const) (op) align offset)
+    let store = |value: &[u8], opcode: u8, offset: &[u8], fusion: bool| {
+        let body = [&[0, 0x20, 0], value, &[opcode, 0], offset, &[0x0B]].concat();
+        build_instance(
+            WasmModule::builder()
+                .options(CompileOptions::new().with_fusion(fusion))
+                .function(WasmType::from_signature("vi").unwrap(), &body)
+                .memory(WasmLimit::new(1, None)),
+        )
+    };
+    let assert_trap = |instance: &WasmInstance, base: u32, position: usize| {
+        instance
+            .memory(0)
+            .unwrap()
+            .write_slice(0, &[0x55; 16])
+            .unwrap();
+        let result = instance
+            .function_by_index(0)
+            .unwrap()
+            .invoke(&[WasmValue::from(base as i32)]);
+        let err = WasmRuntimeError::try_from_error(result.unwrap_err()).unwrap();
+        assert_matches!(err.kind(), WasmRuntimeErrorKind::OutOfBounds);
+        assert_eq!(err.position(), position);
+        // low memory is neither read nor written through a wrapped address
+        let memory = instance.memory(0).unwrap().try_borrow().unwrap();
+        assert_eq!(&memory.as_slice()[..16], &[0x55; 16]);
+    };
 
-    let slice = [0, 0x43, 0, 0, 0x80, 0x7f, 0x0B];
-    let result_types = [WasmValType::F32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+    for fusion in [true, false] {
+        // base + offset = 0x1_0000_0004 must not alias address 4
+        for (signature, opcode) in [
+            ("ii", 0x28),
+            ("li", 0x29),
+            ("fi", 0x2A),
+            ("di", 0x2B),
+            ("ii", 0x2D),
+            ("li", 0x35),
+        ] {
+            let instance = load(signature, opcode, &[8], fusion);
+            assert_trap(&instance, 0xFFFF_FFFC, 3);
+        }
+        // offset=0xFFFF_FFFF
+        let instance = load("ii", 0x28, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F], fusion);
+        assert_trap(&instance, 5, 3);
+        assert_trap(&instance, 0, 3);
+
+        // i32.const 1 i32.store offset=8
+        let instance = store(&[0x41, 1], 0x36, &[8], fusion);
+        assert_trap(&instance, 0xFFFF_FFFC, 5);
+        // i64.const 1 i64.store8 offset=8
+        let instance = store(&[0x42, 1], 0x3C, &[8], fusion);
+        assert_trap(&instance, 0xFFFF_FFF8, 5);
+
+        // (func (param i32) (result i32)
+        //   (i32.load offset=12 (i32.add (local.get 0) (i32.const 0x7FFF_FFFC))))
+        let instance = build_instance(
+            WasmModule::builder()
+                .options(CompileOptions::new().with_fusion(fusion))
+                .function(
+                    WasmType::from_signature("ii").unwrap(),
+                    &[
+                        0, 0x20, 0, 0x41, 0xFC, 0xFF, 0xFF, 0xFF, 0x07, 0x6A, 0x28, 2, 12, 0x0B,
+                    ],
+                )
+                .memory(WasmLimit::new(1, None)),
+        );
+        assert_trap(&instance, 0x7FFF_FFFC, 10);
+
+        // the last bytes of the memory are still accessible
+        let instance = load("ii", 0x28, &[8], fusion);
+        let result = instance
+            .function_by_index(0)
+            .unwrap()
+            .invoke(&[0xFFF4.into()])
+            .unwrap();
+        assert_eq!(result.unwrap().get_i32().unwrap(), 0);
+        assert_trap(&instance, 0xFFF5, 3);
+    }
+}
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_f32()
-        .unwrap();
-    assert!(result.is_infinite());
+#[test]
+fn multi_memory_load_store() {
+    use crate::WasmLimit;
+
+    let instance = build_instance(
+        WasmModule::builder()
+            // (func (param i32 i32) (i32.store 1 (local.get 0) (local.get 1)))
+            .function(
+                WasmType::from_signature("vii").unwrap(),
+                &[0, 0x20, 0, 0x20, 1, 0x36, 0x42, 1, 0, 0x0B],
+            )
+            // (func (param i32) (result i32) (i32.load 1 offset=4 (local.get 0)))
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[0, 0x20, 0, 0x28, 0x42, 1, 4, 0x0B],
+            )
+            // (func (param i32) (result i32) (i32.load (local.get 0)))
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[0, 0x20, 0, 0x28, 2, 0, 0x0B],
+            )
+            .memory(WasmLimit::new(1, None))
+            .memory(WasmLimit::new(1, None)),
+    );
 
-    let slice = [0, 0x43, 0xdb, 0x0f, 0x49, 0x40, 0x0B];
-    let result_types = [WasmValType::F32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+    let store = instance.function_by_index(0).unwrap();
+    store.invoke(&[8.into(), 0x1234_5678.into()]).unwrap();
+
+    let load1 = instance.function_by_index(1).unwrap();
+    let result = load1.invoke(&[4.into()]).unwrap().unwrap();
+    assert_eq!(result.get_i32().unwrap(), 0x1234_5678);
+
+    // memory 0 is not affected
+    let load0 = instance.function_by_index(2).unwrap();
+    let result = load0.invoke(&[8.into()]).unwrap().unwrap();
+    assert_eq!(result.get_i32().unwrap(), 0);
+    let mem1 = instance.memory(1).unwrap().try_borrow().unwrap();
+    assert_eq!(mem1.read_u32(8), 0x1234_5678);
+    drop(mem1);
+
+    let err = invoke(&load1, &[0xFFFC.into()]).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::OutOfBounds);
+
+    // the memory index must refer to a declared memory
+    let err = WasmModule::builder()
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x20, 0, 0x28, 0x42, 2, 0, 0x0B],
+        )
+        .memory(WasmLimit::new(1, None))
+        .memory(WasmLimit::new(1, None))
+        .build()
+        .unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::OutOfMemory);
+}
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_f32()
-        .unwrap();
-    assert_eq!(result, 3.1415927);
+#[test]
+fn wasm_ptr_arithmetic() {
+    use crate::WasmLimit;
+
+    let instance = build_instance(WasmModule::builder().memory(WasmLimit::new(1, None)));
+    let memory = instance.memory(0).unwrap();
+
+    let array = [10u32, 20, 30, 40, 50];
+    let bytes = array
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<_>>();
+    memory.write_slice(0x102, &bytes).unwrap();
+
+    let base = WasmPtr::<u32>::from_u32(0x102);
+    assert_eq!(base.read(memory).unwrap(), 10);
+    assert_eq!(base.add(3).unwrap().as_u32(), 0x10E);
+    assert_eq!(base.add(3).unwrap().read(memory).unwrap(), 40);
+    assert_eq!(base.offset(4).unwrap().read(memory).unwrap(), 20);
+
+    let dest = WasmPtrMut::<u32>::from_u32(0x102).add(4).unwrap();
+    dest.write(memory, 0x1234_5678).unwrap();
+    assert_eq!(dest.read(memory).unwrap(), 0x1234_5678);
+    assert_eq!(base.add(4).unwrap().read(memory).unwrap(), 0x1234_5678);
+
+    // the last element of the memory
+    let last = WasmPtr::<u32>::from_u32(0xFFFC);
+    assert_eq!(last.read(memory).unwrap(), 0);
+    assert_matches!(
+        last.add(1).unwrap().read(memory),
+        Err(WasmRuntimeErrorKind::OutOfBounds)
+    );
+    assert_matches!(
+        last.offset(1).unwrap().read(memory),
+        Err(WasmRuntimeErrorKind::OutOfBounds)
+    );
+    assert_matches!(
+        WasmPtrMut::<u32>::from_u32(0xFFFE).write(memory, 0),
+        Err(WasmRuntimeErrorKind::OutOfBounds)
+    );
+
+    // the address arithmetic does not wrap around
+    let high = WasmPtr::<u64>::from_u32(u32::MAX - 7);
+    assert_matches!(high.add(1), Err(WasmRuntimeErrorKind::OutOfBounds));
+    assert_matches!(high.offset(8), Err(WasmRuntimeErrorKind::OutOfBounds));
+    assert_matches!(base.add(u32::MAX), Err(WasmRuntimeErrorKind::OutOfBounds));
 }
 
 #[test]
-fn float64_const() {
-    let slice = [0, 0x44, 0, 0, 0, 0, 0, 0, 0, 0, 0x0B];
-    let result_types = [WasmValType::F64];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn memory_index_bounds() {
+    use crate::WasmLimit;
+
+    // (func (param i32 i32) (result i32) (call $str_len (local.get 0) (local.get 1)))
+    let builder = || {
+        WasmModule::builder()
+            .import_function("env", "str_len", WasmType::from_signature("iii").unwrap())
+            .function(
+                WasmType::from_signature("iii").unwrap(),
+                &[0, 0x20, 0, 0x20, 1, 0x10, 0, 0x0B],
+            )
+    };
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
+    let mut instance = build_instance(builder().memory(WasmLimit::new(1, None)));
+    assert!(instance.memory(0).is_ok());
+    assert!(instance.memory(1).is_err());
+    assert_matches!(
+        instance.memory(5),
+        Err(WasmRuntimeErrorKind::NoMemoryDeclared)
+    );
+    assert_matches!(
+        instance.memory_mut(5),
+        Err(WasmRuntimeErrorKind::NoMemoryDeclared)
+    );
+
+    instance
+        .memory(0)
         .unwrap()
-        .get_f64()
+        .write_slice(16, b"hello")
         .unwrap();
-    assert_eq!(result, 0.0);
+    let func = instance.function_by_index(1).unwrap();
+    let result = func.invoke(&[16.into(), 5.into()]).unwrap().unwrap();
+    assert_eq!(result.get_i32().unwrap(), 5);
+
+    let err = invoke(&func, &[0xFFFE.into(), 5.into()]).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::OutOfBounds);
+
+    let instance = build_instance(builder());
+    assert!(instance.memory(0).is_err());
+    let err = invoke(
+        &instance.function_by_index(1).unwrap(),
+        &[0.into(), 0.into()],
+    )
+    .unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::NoMemoryDeclared);
+}
 
-    let slice = [0, 0x44, 0, 0, 0, 0, 0, 0, 0xf8, 0x7f, 0x0B];
-    let result_types = [WasmValType::F64];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+#[test]
+fn import_export_detail() {
+    use crate::{ExportKind, ImportExportKind, ImportKind, WasmRefType};
+
+    // (module
+    //   (import "env" "f" (func (param i32)))
+    //   (import "env" "mem" (memory 1 2))
+    //   (import "env" "g" (global (mut i32)))
+    //   (import "env" "tab" (table 3 funcref))
+    //   (func (export "run") (param i32))
+    //   (export "g" (global 0))
+    //   (export "m" (memory 0))
+    //   (export "t" (table 0)))
+    let bytes = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x05, 0x01, 0x60, 0x01, 0x7F, 0x00, // type
+        0x02, 0x2A, 0x04, // import
+        0x03, b'e', b'n', b'v', 0x01, b'f', 0x00, 0x00, //
+        0x03, b'e', b'n', b'v', 0x03, b'm', b'e', b'm', 0x02, 0x01, 0x01, 0x02, //
+        0x03, b'e', b'n', b'v', 0x01, b'g', 0x03, 0x7F, 0x01, //
+        0x03, b'e', b'n', b'v', 0x03, b't', b'a', b'b', 0x01, 0x70, 0x00, 0x03, //
+        0x03, 0x02, 0x01, 0x00, // func
+        0x07, 0x13, 0x04, // export
+        0x03, b'r', b'u', b'n', 0x00, 0x01, //
+        0x01, b'g', 0x03, 0x00, //
+        0x01, b'm', 0x02, 0x00, //
+        0x01, b't', 0x01, 0x00, //
+        0x0A, 0x04, 0x01, 0x02, 0x00, 0x0B, // code
+    ];
+    let module = WebAssembly::compile(&bytes).unwrap();
+
+    let imports = module.imports().collect::<Vec<_>>();
+    assert_eq!(imports.len(), 4);
+    assert!(imports.iter().all(|v| v.module == "env"));
+    assert_matches!(imports[0].desc, ImportKind::Function(func_type) if func_type.signature() == "vi");
+    assert_matches!(imports[1].desc, ImportKind::Memory(limit) if limit.min() == 1 && limit.max() == Some(2));
+    assert_matches!(imports[2].desc, ImportKind::Global(WasmValType::I32, true));
+    assert_matches!(
+        imports[3].desc,
+        ImportKind::Table(limit, WasmRefType::FuncRef) if limit.min() == 3 && limit.max().is_none()
+    );
+    assert_eq!(imports[3].kind, ImportExportKind::Table);
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_f64()
-        .unwrap();
-    assert!(result.is_nan());
+    let exports = module.exports().collect::<Vec<_>>();
+    assert_eq!(exports.len(), 4);
+    assert_matches!(exports[0].desc, ExportKind::Function(1, func_type) if func_type.signature() == "vi");
+    assert_matches!(
+        exports[1].desc,
+        ExportKind::Global(0, WasmValType::I32, true)
+    );
+    assert_matches!(exports[2].desc, ExportKind::Memory(0));
+    assert_matches!(
+        exports[3].desc,
+        ExportKind::Table(0, limit, WasmRefType::FuncRef) if limit.min() == 3
+    );
 
-    let slice = [0, 0x44, 0, 0, 0, 0, 0, 0, 0xf0, 0x7f, 0x0B];
-    let result_types = [WasmValType::F64];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+    // the global export refers to a global that does not exist
+    let mut bytes = bytes;
+    bytes[bytes.len() - 15] = 0x01;
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(&WebAssembly::compile(&bytes).unwrap_err()),
+        Some(WasmCompileErrorKind::InvalidGlobal)
+    );
+}
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_f64()
-        .unwrap();
-    assert!(result.is_infinite());
+#[test]
+fn exports_by_kind() {
+    use crate::{ImportExportKind, WasmLimit};
+
+    let instance = build_instance(
+        WasmModule::builder()
+            // (func (result i32) i32.const 1)
+            .function(WasmType::from_signature("iv").unwrap(), &[0, 0x41, 1, 0x0B])
+            // (func (result i32) i32.const 2)
+            .function(WasmType::from_signature("iv").unwrap(), &[0, 0x41, 2, 0x0B])
+            .memory(WasmLimit::new(1, None))
+            .global(WasmValue::from(123i32), false)
+            .export("one", ImportExportKind::Function, 0)
+            .export("memory", ImportExportKind::Memory, 0)
+            .export("two", ImportExportKind::Function, 1)
+            .export("value", ImportExportKind::Global, 0),
+    );
+    let exports = instance.exports();
+
+    let functions = exports
+        .functions()
+        .map(|(name, func)| {
+            let result = func.invoke(&[]).unwrap().unwrap().get_i32().unwrap();
+            (name, result)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(functions, [("one", 1), ("two", 2)]);
+
+    let memories = exports.memories().collect::<Vec<_>>();
+    assert_eq!(memories.len(), 1);
+    assert_eq!(memories[0].0, "memory");
+    assert_eq!(memories[0].1.size(), 1);
+
+    let globals = exports.globals().collect::<Vec<_>>();
+    assert_eq!(globals.len(), 1);
+    assert_eq!(globals[0].0, "value");
+    assert_eq!(globals[0].1.value().get_i32().unwrap(), 123);
+}
 
-    let slice = [
-        0, 0x44, 0x18, 0x2d, 0x44, 0x54, 0xfb, 0x21, 0x09, 0x40, 0x0b,
-    ];
-    let result_types = [WasmValType::F64];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info =
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+#[test]
+fn instance_call_checks_args() {
+    use crate::ImportExportKind;
+
+    // (func (param i32 f64) (result i32) local.get 0)
+    let instance = build_instance(
+        WasmModule::builder()
+            .function(
+                WasmType::from_signature("iid").unwrap(),
+                &[0, 0x20, 0, 0x0B],
+            )
+            .export("first", ImportExportKind::Function, 0),
+    );
 
-    let result = interp
-        .invoke(0, &info, &mut [], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_f64()
-        .unwrap();
-    assert_eq!(result, PI);
+    let result = instance.call("first", &[7.into(), 1.5.into()]).unwrap();
+    assert_eq!(result.unwrap().get_i32().unwrap(), 7);
+
+    let err = instance
+        .call("first", &[1.5.into(), 2.5.into()])
+        .unwrap_err();
+    assert_matches!(
+        WasmRuntimeError::try_from_error(err).unwrap().kind(),
+        WasmRuntimeErrorKind::ArgTypeMismatch {
+            index: 0,
+            expected: WasmValType::I32,
+            got: WasmValType::F64,
+        }
+    );
+    let err = instance.call("first", &[7.into(), 2.into()]).unwrap_err();
+    assert_matches!(
+        WasmRuntimeError::try_from_error(err).unwrap().kind(),
+        WasmRuntimeErrorKind::ArgTypeMismatch {
+            index: 1,
+            expected: WasmValType::F64,
+            got: WasmValType::I32,
+        }
+    );
+    let err = instance.call("first", &[7.into()]).unwrap_err();
+    assert_matches!(
+        WasmRuntimeError::try_from_error(err).unwrap().kind(),
+        WasmRuntimeErrorKind::ArgCountMismatch {
+            expected: 2,
+            got: 1
+        }
+    );
+    let err = instance.call("second", &[]).unwrap_err();
+    assert_matches!(
+        WasmRuntimeError::try_from_error(err).unwrap().kind(),
+        WasmRuntimeErrorKind::NoMethod
+    );
 }
 
 #[test]
-fn const_local() {
-    let slice = [
-        1, 1, 0x7F, 0x41, 0xFB, 0x00, 0x21, 0, 0x41, 0x12, 0x1A, 0x20, 0, 0x0B,
-    ];
-    let param_types = [];
-    let result_types = [WasmValType::I32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &param_types,
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn exports_run_tests() {
+    use crate::{ImportExportKind, WasmLimit};
+
+    let instance = build_instance(
+        WasmModule::builder()
+            // (func (result i32) i32.const 1)
+            .function(WasmType::from_signature("iv").unwrap(), &[0, 0x41, 1, 0x0B])
+            // (func (param i32) (result i32) local.get 0 i32.eqz)
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[0, 0x20, 0, 0x45, 0x0B],
+            )
+            // (func (param i64 f64) (result i32) i32.const 1)
+            .function(
+                WasmType::from_signature("ild").unwrap(),
+                &[0, 0x41, 1, 0x0B],
+            )
+            // (func (result i32) unreachable)
+            .function(WasmType::from_signature("iv").unwrap(), &[0, 0x00, 0x0B])
+            .memory(WasmLimit::new(1, None))
+            .export("test_const", ImportExportKind::Function, 0)
+            .export("memory", ImportExportKind::Memory, 0)
+            .export("test_eqz", ImportExportKind::Function, 1)
+            .export("test_params", ImportExportKind::Function, 2)
+            .export("helper", ImportExportKind::Function, 3),
+    );
 
-    let result = interp
-        .invoke(0, &info, &[0.into()], &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i32()
+    let mut passed = Vec::new();
+    for (name, func) in instance.exports().functions() {
+        if !name.starts_with("test_") {
+            continue;
+        }
+        let args = func
+            .func_type()
+            .param_types()
+            .iter()
+            .map(|v| WasmValue::default_for(*v))
+            .collect::<Vec<_>>();
+        let result = func.invoke(&args).unwrap().unwrap();
+        assert_eq!(result.get_i32().unwrap(), 1, "{name}");
+        passed.push(name);
+    }
+    assert_eq!(passed, ["test_const", "test_eqz", "test_params"]);
+
+    // the handle knows the type of its function
+    let (_, func) = instance
+        .exports()
+        .functions()
+        .find(|(name, _)| *name == "test_params")
         .unwrap();
-    assert_eq!(result, 123);
+    assert_eq!(
+        func.func_type().param_types(),
+        [WasmValType::I64, WasmValType::F64]
+    );
+    assert_eq!(func.func_type().result_types(), [WasmValType::I32]);
 }
 
 #[test]
-fn div32_s() {
-    let slice = [0, 0x20, 0, 0x20, 1, 0x6D, 0x0B];
-    let param_types = [WasmValType::I32, WasmValType::I32];
-    let result_types = [WasmValType::I32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &param_types,
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn exports_string_result() {
+    use crate::{ImportExportKind, WasmLimit};
 
-    let mut locals = [7006652.into(), 5678.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 1234);
+    #[wasm_exports]
+    trait Render {
+        fn render() -> String;
+        fn render_long() -> String;
+    }
 
-    let mut locals = [42.into(), (-6).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
+    let instance = build_instance(
+        WasmModule::builder()
+            // (func (param i32) (result i32) global.get 0 global.get 0 local.get 0 i32.add global.set 0)
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[0, 0x23, 0, 0x23, 0, 0x20, 0, 0x6A, 0x24, 0, 0x0B],
+            )
+            // (func (param i32 i32) (result i32)
+            //   local.get 0 i32.const "hell" i32.store local.get 0 i32.const 'o' i32.store8 offset=4
+            //   i32.const 5)
+            .function(
+                WasmType::from_signature("iii").unwrap(),
+                &[
+                    0, 0x20, 0, 0x41, 0xE8, 0xCA, 0xB1, 0xE3, 0x06, 0x36, 2, 0, 0x20, 0, 0x41,
+                    0xEF, 0x00, 0x3A, 0, 4, 0x41, 5, 0x0B,
+                ],
+            )
+            // (func (param i32 i32) (result i32)
+            //   (block
+            //     (br_if 0 (i32.lt_u (local.get 1) (i32.const 300)))
+            //     (memory.fill (local.get 0) (i32.const 'a') (i32.const 300)))
+            //   i32.const 300)
+            .function(
+                WasmType::from_signature("iii").unwrap(),
+                &[
+                    0, 0x02, 0x40, 0x20, 1, 0x41, 0xAC, 0x02, 0x49, 0x0D, 0, 0x20, 0, 0x41, 0xE1,
+                    0x00, 0x41, 0xAC, 0x02, 0xFC, 0x0B, 0, 0x0B, 0x41, 0xAC, 0x02, 0x0B,
+                ],
+            )
+            // (func (param i32 i32) global.get 1 i32.const 1 i32.add global.set 1)
+            .function(
+                WasmType::from_signature("vii").unwrap(),
+                &[0, 0x23, 1, 0x41, 1, 0x6A, 0x24, 1, 0x0B],
+            )
+            .memory(WasmLimit::new(1, None))
+            .global(WasmValue::from(1024i32), true)
+            .global(WasmValue::from(0i32), true)
+            .export("malloc", ImportExportKind::Function, 0)
+            .export("free", ImportExportKind::Function, 3)
+            .export("render", ImportExportKind::Function, 1)
+            .export("render_long", ImportExportKind::Function, 2),
+    );
+
+    assert_eq!(instance.exports().render().unwrap(), "hello");
+    assert_eq!(instance.exports().render_long().unwrap(), "a".repeat(300));
+    // every buffer is released, including the one that was too small
+    assert_eq!(instance.global_get(1).unwrap().get_i32().unwrap(), 3);
+}
+
+#[test]
+fn exports_view() {
+    use crate::{ImportExportKind, WasmLimit};
+
+    let instance = build_instance(
+        WasmModule::builder()
+            // (func (result i32) i32.const 1)
+            .function(WasmType::from_signature("iv").unwrap(), &[0, 0x41, 1, 0x0B])
+            // (func (result i32) i32.const 2)
+            .function(WasmType::from_signature("iv").unwrap(), &[0, 0x41, 2, 0x0B])
+            .memory(WasmLimit::new(1, None))
+            .export("main", ImportExportKind::Function, 0)
+            .export("helper", ImportExportKind::Function, 1)
+            .export("memory", ImportExportKind::Memory, 0),
+    );
+
+    let view = instance
+        .exports()
+        .view(&[("main", "run"), ("memory", "heap"), ("missing", "gone")]);
+    let result = view.get("run").unwrap().invoke(&[]).unwrap().unwrap();
+    assert_eq!(result.get_i32().unwrap(), 1);
+    assert!(view.get("main").is_none());
+    assert!(view.get("helper").is_none());
+    assert!(view.get("gone").is_none());
+    assert!(view.get("heap").is_none());
+
+    let functions = view.functions().map(|(name, _)| name).collect::<Vec<_>>();
+    assert_eq!(functions, ["run"]);
+    let memories = view.memories().map(|(name, _)| name).collect::<Vec<_>>();
+    assert_eq!(memories, ["heap"]);
+    assert_eq!(view.globals().count(), 0);
+
+    // the original names are still available
+    assert!(instance.exports().get("main").is_some());
+}
+
+#[test]
+fn host_function_reentrancy() {
+    use crate::ImportExportKind;
+
+    struct ReenterEnv;
+
+    impl WasmEnv for ReenterEnv {
+        fn resolve_imports(
+            &self,
+            _mod_name: &str,
+            name: &str,
+            _type: &WasmType,
+        ) -> WasmImportResult {
+            match name {
+                "reenter" => WasmImportResult::Ok(reenter),
+                _ => WasmImportResult::NoMethod,
+            }
+        }
+    }
+
+    // calls back into the instance while the caller still has operands on its stack
+    fn reenter(instance: &WasmInstance, mut args: WasmArgs) -> WasmDynResult {
+        let value = args.next::<i32>()?;
+        let (name, arg) = if value >= 10 {
+            ("outer", value - 10)
+        } else {
+            ("inner", value)
+        };
+        instance.function(name)?.invoke(&[arg.into()])
+    }
+
+    let instance = WasmModule::builder()
+        .import_function("env", "reenter", WasmType::from_signature("ii").unwrap())
+        // (func (param i32) (result i32)
+        //   local.get 0 i32.const 100 i32.mul local.get 0 call 0 i32.add)
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[
+                0, 0x20, 0, 0x41, 0xE4, 0x00, 0x6C, 0x20, 0, 0x10, 0, 0x6A, 0x0B,
+            ],
+        )
+        // (func (param i32) (result i32) local.get 0 local.get 0 i32.add i32.const 1 i32.add)
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x20, 0, 0x20, 0, 0x6A, 0x41, 1, 0x6A, 0x0B],
+        )
+        .export("outer", ImportExportKind::Function, 1)
+        .export("inner", ImportExportKind::Function, 2)
+        .build()
         .unwrap()
-        .get_i32()
+        .instantiate(&ReenterEnv)
         .unwrap();
-    assert_eq!(result, -7);
 
-    let mut locals = [(-42).into(), (6).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
+    // outer(5) = 500 + inner(5)
+    let result = instance.function("outer").unwrap().invoke(&[5.into()]);
+    assert_eq!(result.unwrap().unwrap().get_i32().unwrap(), 511);
+
+    // outer(15) = 1500 + outer(5), reentering twice
+    let mut interp = WasmInterpreter::new(&instance);
+    let result = interp.invoke_function(1, &[15.into()]).unwrap();
+    assert_eq!(result.unwrap().get_i32().unwrap(), 2011);
+    // the interpreter is still usable after the nested invocations
+    let result = interp.invoke_function(2, &[20.into()]).unwrap();
+    assert_eq!(result.unwrap().get_i32().unwrap(), 41);
+    let result = interp.invoke_function(1, &[25.into()]).unwrap();
+    assert_eq!(result.unwrap().get_i32().unwrap(), 2500 + 1500 + 511);
+}
+
+#[test]
+fn nested_host_calls_keep_frames() {
+    use crate::ImportExportKind;
+
+    struct NestEnv;
+
+    impl WasmEnv for NestEnv {
+        fn resolve_imports(
+            &self,
+            _mod_name: &str,
+            name: &str,
+            _type: &WasmType,
+        ) -> WasmImportResult {
+            match name {
+                "down" => WasmImportResult::Ok(down),
+                _ => WasmImportResult::NoMethod,
+            }
+        }
+    }
+
+    // down(0) = 7, down(n) = step(n - 1)
+    fn down(instance: &WasmInstance, mut args: WasmArgs) -> WasmDynResult {
+        let n = args.next::<i32>()?;
+        if n == 0 {
+            return Ok(Some(WasmValue::I64(7)));
+        }
+        instance.function("step")?.invoke(&[(n - 1).into()])
+    }
+
+    let instance = WasmModule::builder()
+        .import_function("env", "down", WasmType::from_signature("li").unwrap())
+        // (func (param i32) (result i64) (local i64)
+        //   local.get 0 i64.extend_i32_s i64.const 1000 i64.mul local.set 1
+        //   local.get 1 local.get 0 call 0 i64.const 10 i64.mul i64.add local.get 1 i64.add)
+        .function(
+            WasmType::from_signature("li").unwrap(),
+            &[
+                1, 1, 0x7E, 0x20, 0, 0xAC, 0x42, 0xE8, 0x07, 0x7E, 0x21, 1, 0x20, 1, 0x20, 0, 0x10,
+                0, 0x42, 10, 0x7E, 0x7C, 0x20, 1, 0x7C, 0x0B,
+            ],
+        )
+        .export("step", ImportExportKind::Function, 1)
+        .build()
         .unwrap()
-        .get_i32()
+        .instantiate(&NestEnv)
         .unwrap();
-    assert_eq!(result, -7);
 
-    let mut locals = [(-42).into(), (-6).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
+    // step(n) = 2000 * n + 10 * down(n), where the local and the operand pushed before
+    // the call must survive every nested invocation below it
+    let step = instance.function("step").unwrap();
+    for (n, expected) in [(0, 70), (1, 2_700), (2, 31_000), (3, 316_000)] {
+        let result = step.invoke(&[n.into()]).unwrap().unwrap();
+        assert_eq!(result.get_i64().unwrap(), expected);
+    }
+}
+
+#[test]
+fn variadic_host_function() {
+    struct VarEnv;
+
+    impl WasmEnv for VarEnv {
+        fn resolve_imports(
+            &self,
+            _mod_name: &str,
+            name: &str,
+            _type: &WasmType,
+        ) -> WasmImportResult {
+            match name {
+                "sum" => WasmImportResult::Ok(sum),
+                _ => WasmImportResult::NoMethod,
+            }
+        }
+    }
+
+    // sum(count, values...)
+    fn sum(_: &WasmInstance, mut args: WasmArgs) -> WasmDynResult {
+        let count = args.peek::<u32>()? as usize;
+        if count >= args.remaining() {
+            return Err(WasmRuntimeErrorKind::InvalidParameter.into());
+        }
+        args.next::<u32>()?;
+        let mut result = 0i32;
+        for _ in 0..count {
+            result += args.next::<i32>()?;
+        }
+        Ok(Some(result.into()))
+    }
+
+    let instance = WasmModule::builder()
+        .import_function("env", "sum", WasmType::from_signature("iiiii").unwrap())
+        // (func (param i32 i32 i32 i32) (result i32)
+        //   local.get 0 local.get 1 local.get 2 local.get 3 call 0)
+        .function(
+            WasmType::from_signature("iiiii").unwrap(),
+            &[0, 0x20, 0, 0x20, 1, 0x20, 2, 0x20, 3, 0x10, 0, 0x0B],
+        )
+        .build()
         .unwrap()
-        .get_i32()
+        .instantiate(&VarEnv)
         .unwrap();
-    assert_eq!(result, 7);
+    let call = |args: [i32; 4]| {
+        let args = args.map(WasmValue::from);
+        instance.function_by_index(1).unwrap().invoke(&args)
+    };
 
-    let mut locals = [1234.into(), 0.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap_err();
-    let err: Box<WasmRuntimeError> = result.downcast().unwrap();
-    assert_matches!(*err.kind(), WasmRuntimeErrorKind::DivideByZero);
-    assert_eq!(err.mnemonic(), WasmMnemonic::I32DivS);
-    assert_eq!(err.position(), 5);
+    assert_eq!(
+        call([3, 10, 20, 30]).unwrap().unwrap().get_i32().unwrap(),
+        60
+    );
+    assert_eq!(
+        call([2, 10, 20, 30]).unwrap().unwrap().get_i32().unwrap(),
+        30
+    );
+    assert_eq!(
+        call([0, 10, 20, 30]).unwrap().unwrap().get_i32().unwrap(),
+        0
+    );
+    assert!(call([4, 10, 20, 30]).is_err());
+
+    // reading past the end is an error, peeking does not consume
+    let values = [crate::WasmUnionValue::from(1i32)];
+    let mut args = WasmArgs::new(&values);
+    assert_eq!(args.remaining(), 1);
+    assert_eq!(args.peek::<i32>().unwrap(), 1);
+    assert_eq!(args.remaining(), 1);
+    assert_eq!(args.next::<i32>().unwrap(), 1);
+    assert_eq!(args.remaining(), 0);
+    assert!(args.peek::<i32>().is_err());
+    assert!(args.next::<i32>().is_err());
 }
 
 #[test]
-fn div32_u() {
-    let slice = [0, 0x20, 0, 0x20, 1, 0x6E, 0x0B];
-    let param_types = [WasmValType::I32, WasmValType::I32];
-    let result_types = [WasmValType::I32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &param_types,
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn alloc_bytes() {
+    use crate::{ImportExportKind, WasmLimit};
+
+    struct AllocEnv;
+
+    impl WasmEnv for AllocEnv {
+        fn resolve_imports(
+            &self,
+            _mod_name: &str,
+            name: &str,
+            _type: &WasmType,
+        ) -> WasmImportResult {
+            match name {
+                "shout" => WasmImportResult::Ok(shout),
+                _ => WasmImportResult::NoMethod,
+            }
+        }
+    }
 
-    let mut locals = [7006652.into(), 5678.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
+    // shout(ptr, len) returns a copy of the string in upper case, allocated by the module
+    fn shout(instance: &WasmInstance, mut args: WasmArgs) -> WasmDynResult {
+        let ptr = args.next::<WasmPtr<u8>>()?;
+        let len = args.next::<u32>()? as usize;
+        let memory = instance.memory(0).unwrap().try_borrow()?;
+        let text = memory.slice(ptr, len)?.to_ascii_uppercase();
+        drop(memory);
+        Ok(Some(instance.alloc_bytes(&text)?.into()))
+    }
+
+    let mut instance = WasmModule::builder()
+        .import_function("env", "shout", WasmType::from_signature("iii").unwrap())
+        // (func (param i32) (result i32) global.get 0 global.get 0 local.get 0 i32.add global.set 0)
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x23, 0, 0x23, 0, 0x20, 0, 0x6A, 0x24, 0, 0x0B],
+        )
+        // (func (param i32 i32))
+        .function(WasmType::from_signature("vii").unwrap(), &[0, 0x0B])
+        // (func (param i32 i32) (result i32) local.get 0 local.get 1 call 0)
+        .function(
+            WasmType::from_signature("iii").unwrap(),
+            &[0, 0x20, 0, 0x20, 1, 0x10, 0, 0x0B],
+        )
+        .memory(WasmLimit::new(1, None))
+        .global(WasmValue::from(1024i32), true)
+        .export("alloc", ImportExportKind::Function, 1)
+        .export("dealloc", ImportExportKind::Function, 2)
+        .export("run", ImportExportKind::Function, 3)
+        .build()
         .unwrap()
-        .get_i32()
+        .instantiate(&AllocEnv)
         .unwrap();
-    assert_eq!(result, 1234);
 
-    let mut locals = [42.into(), (-6).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+    let err = instance.alloc_bytes(b"hello").unwrap_err();
+    assert_matches!(
+        WasmRuntimeError::try_from_error(err).unwrap().kind(),
+        WasmRuntimeErrorKind::NoMethod
+    );
+    instance.set_allocator("alloc", "dealloc");
+
+    let ptr = instance.alloc_bytes(b"hello").unwrap();
+    assert_eq!(ptr.as_usize(), 1024);
+    let result = instance
+        .exports()
+        .get("run")
         .unwrap()
+        .invoke(&[ptr.into(), WasmValue::from(5u32)])
         .unwrap()
-        .get_i32()
+        .unwrap()
+        .get_u32()
         .unwrap();
-    assert_eq!(result, 0);
+    assert_eq!(result, 1029);
 
-    let mut locals = [(-42).into(), (6).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 715827875);
+    let memory = instance.memory(0).unwrap().try_borrow().unwrap();
+    assert_eq!(&memory[1024..1034], b"helloHELLO");
+    drop(memory);
 
-    let mut locals = [1234.into(), 0.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap_err();
-    let err: Box<WasmRuntimeError> = result.downcast().unwrap();
-    assert_matches!(*err.kind(), WasmRuntimeErrorKind::DivideByZero);
-    assert_eq!(err.mnemonic(), WasmMnemonic::I32DivU);
-    assert_eq!(err.position(), 5);
+    instance.dealloc(ptr, 5).unwrap();
 }
 
 #[test]
-fn div64_s() {
-    let slice = [0, 0x20, 0, 0x20, 1, 0x7F, 0x0B];
-    let param_types = [WasmValType::I64, WasmValType::I64];
-    let result_types = [WasmValType::I64];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &param_types,
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn arg_coercion() {
+    let instance = build_instance(
+        WasmModule::builder()
+            // (func (param i32 f32) (result i32) local.get 0)
+            .function(
+                WasmType::from_signature("iif").unwrap(),
+                &[0, 0x20, 0, 0x0B],
+            ),
+    );
+    let runnable = || instance.function_by_index(0).unwrap();
+    let is_type_mismatch = |err: Box<dyn core::error::Error>| {
+        matches!(
+            WasmRuntimeError::try_from_error(err).unwrap().kind(),
+            WasmRuntimeErrorKind::TypeMismatch
+        )
+    };
 
-    let mut locals = [7006652i64.into(), 5678i64.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i64()
-        .unwrap();
-    assert_eq!(result, 1234);
+    // strict by default
+    let args = [WasmValue::from(0x1_0000_0002i64), WasmValue::from(1.5f64)];
+    assert!(is_type_mismatch(runnable().invoke(&args).unwrap_err()));
+    assert!(is_type_mismatch(
+        runnable().invoke(&[1.into(), 1.5f64.into()]).unwrap_err()
+    ));
+    assert_eq!(
+        runnable()
+            .invoke(&[7.into(), 1.5f32.into()])
+            .unwrap()
+            .unwrap()
+            .get_i32()
+            .unwrap(),
+        7
+    );
 
-    let mut locals = [42i64.into(), (-6i64).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i64()
-        .unwrap();
-    assert_eq!(result, -7);
+    // wider values are truncated only when coercion is enabled
+    let coerced = runnable().with_arg_coercion();
+    assert!(coerced.coerces_args());
+    assert_eq!(
+        coerced.invoke(&args).unwrap().unwrap().get_i32().unwrap(),
+        2
+    );
+    // narrower values are still rejected
+    assert!(is_type_mismatch(
+        coerced
+            .invoke(&[WasmValue::from(1.5f32), WasmValue::from(1.5f32)])
+            .unwrap_err()
+    ));
+}
 
-    let mut locals = [(-42i64).into(), (6i64).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i64()
-        .unwrap();
-    assert_eq!(result, -7);
+#[test]
+fn compile_limits() {
+    // (memory 1 1000000000)
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x05, 0x08, 0x01, 0x01, 0x01, 0x80, 0x94,
+        0xEB, 0xDC, 0x03,
+    ];
+    WebAssembly::compile(&data).unwrap();
+    let limits = CompileLimits {
+        max_memory_pages: 0x100,
+        ..Default::default()
+    };
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(
+            &WebAssembly::compile_with_limits(&data, &limits).unwrap_err()
+        )
+        .unwrap(),
+        WasmCompileErrorKind::LimitsExceeded(CompileLimitKind::MemoryPages)
+    );
 
-    let mut locals = [(-42i64).into(), (-6i64).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i64()
-        .unwrap();
-    assert_eq!(result, 7);
+    // (func (local i32 i32 i32 i32 i32 i32 i32 i32 i32 i32))
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03,
+        0x02, 0x01, 0x00, 0x0A, 0x06, 0x01, 0x04, 0x01, 0x0A, 0x7F, 0x0B,
+    ];
+    let limits = CompileLimits {
+        max_locals: 10,
+        ..Default::default()
+    };
+    WebAssembly::compile_with_limits(&data, &limits).unwrap();
+    let limits = CompileLimits {
+        max_locals: 9,
+        ..Default::default()
+    };
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(
+            &WebAssembly::compile_with_limits(&data, &limits).unwrap_err()
+        )
+        .unwrap(),
+        WasmCompileErrorKind::LimitsExceeded(CompileLimitKind::Locals)
+    );
+    let limits = CompileLimits {
+        max_code_size: 3,
+        ..Default::default()
+    };
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(
+            &WebAssembly::compile_with_limits(&data, &limits).unwrap_err()
+        )
+        .unwrap(),
+        WasmCompileErrorKind::LimitsExceeded(CompileLimitKind::CodeSize)
+    );
 
-    let mut locals = [1234i64.into(), 0i64.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap_err();
-    let err: Box<WasmRuntimeError> = result.downcast().unwrap();
-    assert_matches!(*err.kind(), WasmRuntimeErrorKind::DivideByZero);
-    assert_eq!(err.mnemonic(), WasmMnemonic::I64DivS);
-    assert_eq!(err.position(), 5);
+    let data = include_bytes!("../test/tester.wasm");
+    WebAssembly::compile_with_limits(data, &CompileLimits::default()).unwrap();
+    let limits = CompileLimits {
+        max_functions: 1,
+        ..Default::default()
+    };
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(
+            &WebAssembly::compile_with_limits(data, &limits).unwrap_err()
+        )
+        .unwrap(),
+        WasmCompileErrorKind::LimitsExceeded(CompileLimitKind::Functions)
+    );
+    let limits = CompileLimits {
+        max_types: 1,
+        ..Default::default()
+    };
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(
+            &WebAssembly::compile_with_limits(data, &limits).unwrap_err()
+        )
+        .unwrap(),
+        WasmCompileErrorKind::LimitsExceeded(CompileLimitKind::Types)
+    );
 }
 
 #[test]
-fn div64_u() {
-    let slice = [0, 0x20, 0, 0x20, 1, 0x80, 0x0B];
-    let param_types = [WasmValType::I64, WasmValType::I64];
-    let result_types = [WasmValType::I64];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &param_types,
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn fused_load_store_at() {
+    use crate::WasmLimit;
+
+    let build = |fusion: bool| {
+        WasmModule::builder()
+            .options(CompileOptions::new().with_fusion(fusion))
+            // (func (param $p i32) (param $n i32) (local $i i32)
+            //   (i32.store (i32.add (local.get $p) (i32.const -4)) (i32.const 0x55AA))
+            //   (loop
+            //     (i32.store offset=4 (i32.add (local.get $p) (i32.const 8)) (local.get $i))
+            //     (local.set $p (i32.add (local.get $p) (i32.const 4)))
+            //     (br_if 0 (i32.lt_u (local.tee $i (i32.add (local.get $i) (i32.const 1)))
+            //                        (local.get $n)))))
+            .function(
+                WasmType::from_signature("vii").unwrap(),
+                &[
+                    1, 1, 0x7F, 0x20, 0, 0x41, 0x7C, 0x6A, 0x41, 0xAA, 0xAB, 1, 0x36, 2, 0, 0x03,
+                    0x40, 0x20, 0, 0x41, 8, 0x6A, 0x20, 2, 0x36, 2, 4, 0x20, 0, 0x41, 4, 0x6A,
+                    0x21, 0, 0x20, 2, 0x41, 1, 0x6A, 0x22, 2, 0x20, 1, 0x49, 0x0D, 0, 0x0B, 0x0B,
+                ],
+            )
+            // (func (param $p i32) (param $n i32) (result i32) (local $s i32)
+            //   (loop
+            //     (local.set $s (i32.add (i32.load offset=4 (i32.add (local.get $p)
+            //                                                        (i32.const 8)))
+            //                            (local.get $s)))
+            //     (local.set $p (i32.add (local.get $p) (i32.const 4)))
+            //     (br_if 0 (local.tee $n (i32.add (local.get $n) (i32.const -1)))))
+            //   (local.get $s))
+            .function(
+                WasmType::from_signature("iii").unwrap(),
+                &[
+                    1, 1, 0x7F, 0x03, 0x40, 0x20, 0, 0x41, 8, 0x6A, 0x28, 2, 4, 0x20, 2, 0x6A,
+                    0x21, 2, 0x20, 0, 0x41, 4, 0x6A, 0x21, 0, 0x20, 1, 0x41, 0x7F, 0x6A, 0x22, 1,
+                    0x0D, 0, 0x0B, 0x20, 2, 0x0B,
+                ],
+            )
+            .memory(WasmLimit::new(1, None))
+            .build()
+            .unwrap()
+    };
+    let count_codes = |module: &WasmModule, index: usize, fused: bool| match module.functions()
+        [index]
+        .content()
+    {
+        WasmFunctionContent::CodeBlock(code_block) => code_block
+            .intermediate_codes()
+            .iter()
+            .filter(|code| !fused || code.instruction().is_fused())
+            .count(),
+        _ => unreachable!(),
+    };
 
-    let mut locals = [7006652i64.into(), 5678i64.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i64()
-        .unwrap();
-    assert_eq!(result, 1234);
+    let fused = build(true);
+    let plain = build(false);
+    for index in 0..2 {
+        assert!(count_codes(&fused, index, false) < count_codes(&plain, index, false));
+        assert_eq!(count_codes(&plain, index, true), 0);
+    }
+    let has_code =
+        |module: &WasmModule, index: usize, f: fn(&WasmImInstruction) -> bool| match module
+            .functions()[index]
+            .content()
+        {
+            WasmFunctionContent::CodeBlock(code_block) => code_block
+                .intermediate_codes()
+                .iter()
+                .any(|code| f(code.instruction())),
+            _ => unreachable!(),
+        };
+    assert!(has_code(&fused, 0, |v| matches!(
+        v,
+        WasmImInstruction::FusedI32StoreAt(_, 0xFFFF_FFFC, 0, _)
+    )));
+    assert!(has_code(&fused, 0, |v| matches!(
+        v,
+        WasmImInstruction::FusedI32StoreAt(_, 8, 4, _)
+    )));
+    assert!(has_code(&fused, 1, |v| matches!(
+        v,
+        WasmImInstruction::FusedI32LoadAt(_, 8, 4, _)
+    )));
+
+    let fused = fused.instantiate(&Env {}).unwrap();
+    let plain = plain.instantiate(&Env {}).unwrap();
+    for instance in [&fused, &plain] {
+        let fill = instance.function_by_index(0).unwrap();
+        let sum = instance.function_by_index(1).unwrap();
+        fill.invoke(&[0x100.into(), 1000.into()]).unwrap();
+
+        let result = sum.invoke(&[0x100.into(), 1000.into()]).unwrap().unwrap();
+        assert_eq!(result.get_i32().unwrap(), 499500);
+
+        // the address wraps around before the memarg offset is added
+        let result = sum.invoke(&[(-8).into(), 1.into()]).unwrap().unwrap();
+        assert_eq!(result.get_i32().unwrap(), 0);
+
+        let err = invoke(&sum, &[0xFFF8.into(), 1.into()]).unwrap_err();
+        assert_matches!(err.kind(), WasmRuntimeErrorKind::OutOfBounds);
+        assert_eq!(err.mnemonic(), WasmMnemonic::I32Load);
+
+        let err = invoke(&fill, &[0xFFF4.into(), 1.into()]).unwrap_err();
+        assert_matches!(err.kind(), WasmRuntimeErrorKind::OutOfBounds);
+        assert_eq!(err.mnemonic(), WasmMnemonic::I32Store);
+    }
 
-    let mut locals = [42i64.into(), (-6i64).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i64()
-        .unwrap();
-    assert_eq!(result, 0);
+    let fused = fused.memory(0).unwrap().try_borrow().unwrap();
+    let plain = plain.memory(0).unwrap().try_borrow().unwrap();
+    assert_eq!(fused.read_u32(0xFC), 0x55AA);
+    assert_eq!(fused.read_u32(0x10C + 4 * 999), 999);
+    assert_eq!(fused.as_slice(), plain.as_slice());
+}
 
-    let mut locals = [(-42i64).into(), (6i64).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i64()
-        .unwrap();
-    assert_eq!(result, 3074457345618258595);
+#[test]
+fn fusion_equivalence() {
+    use crate::WasmLimit;
+
+    let build = |fusion: bool| {
+        WasmModule::builder()
+            .options(CompileOptions::new().with_fusion(fusion))
+            // (func (param $n i32) (result i32) (local $i i32) (local $acc i32)
+            //   (loop
+            //     (local.set $acc (i32.add (i32.add (i32.xor (i32.shl (local.get $acc) (i32.const 3))
+            //                                                (i32.shr_u (local.get $acc) (i32.const 5)))
+            //                                       (i32.and (local.get $i) (i32.const 7)))
+            //                              (i32.or (local.get $i) (i32.const 1))))
+            //     (i32.store (i32.add (local.get $i) (i32.const 16)) (local.get $acc))
+            //     (local.set $acc (i32.add (i32.load (i32.add (local.get $i) (i32.const 14)))
+            //                              (local.get $acc)))
+            //     (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            //     (br_if 0 (i32.lt_u (local.get $i) (local.get $n))))
+            //   (local.get $acc))
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[
+                    1, 2, 0x7F, 0x03, 0x40, 0x20, 2, 0x41, 3, 0x74, 0x20, 2, 0x41, 5, 0x76, 0x73,
+                    0x20, 1, 0x41, 7, 0x71, 0x6A, 0x20, 1, 0x41, 1, 0x72, 0x6A, 0x21, 2, 0x20, 1,
+                    0x41, 16, 0x6A, 0x20, 2, 0x36, 2, 0, 0x20, 1, 0x41, 14, 0x6A, 0x28, 2, 0, 0x20,
+                    2, 0x6A, 0x21, 2, 0x20, 1, 0x41, 1, 0x6A, 0x21, 1, 0x20, 1, 0x20, 0, 0x49,
+                    0x0D, 0, 0x0B, 0x20, 2, 0x0B,
+                ],
+            )
+            .memory(WasmLimit::new(1, None))
+            .build()
+            .unwrap()
+    };
+    let count_fused = |module: &WasmModule| match module.functions()[0].content() {
+        WasmFunctionContent::CodeBlock(code_block) => code_block
+            .intermediate_codes()
+            .iter()
+            .filter(|code| code.instruction().is_fused())
+            .count(),
+        _ => unreachable!(),
+    };
 
-    let mut locals = [1234i64.into(), 0i64.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap_err();
-    let err: Box<WasmRuntimeError> = result.downcast().unwrap();
-    assert_matches!(*err.kind(), WasmRuntimeErrorKind::DivideByZero);
-    assert_eq!(err.mnemonic(), WasmMnemonic::I64DivU);
-    assert_eq!(err.position(), 5);
+    let fused = build(true);
+    let plain = build(false);
+    assert!(count_fused(&fused) > 0);
+    assert_eq!(count_fused(&plain), 0);
+
+    let fused = fused.instantiate(&Env {}).unwrap();
+    let plain = plain.instantiate(&Env {}).unwrap();
+    for n in [1, 2, 3, 10, 100, 1000, 10000] {
+        let results = [&fused, &plain].map(|instance| {
+            instance
+                .function_by_index(0)
+                .unwrap()
+                .invoke(&[n.into()])
+                .unwrap()
+                .unwrap()
+                .get_i32()
+                .unwrap()
+        });
+        assert_eq!(results[0], results[1], "n = {n}");
+
+        let fused = fused.memory(0).unwrap().try_borrow().unwrap();
+        let plain = plain.memory(0).unwrap().try_borrow().unwrap();
+        assert_eq!(fused.as_slice(), plain.as_slice(), "n = {n}");
+    }
 }
 
 #[test]
-fn select_int() {
-    let slice = [0, 0x20, 0, 0x20, 1, 0x20, 2, 0x1B, 0x0B];
-    let param_types = [WasmValType::I32, WasmValType::I32, WasmValType::I32];
-    let result_types = [WasmValType::I32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &param_types,
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn fused_trap_mnemonic() {
+    use crate::WasmLimit;
+
+    let build = |fusion: bool| {
+        WasmModule::builder()
+            .options(CompileOptions::new().with_fusion(fusion))
+            // (func (param $p i32) (result i32) (local $i i32)
+            //   (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            //   (i32.add (i32.load (i32.add (local.get $p) (i32.const 4))) (local.get $i)))
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[
+                    1, 1, 0x7F, 0x20, 1, 0x41, 1, 0x6A, 0x21, 1, 0x20, 0, 0x41, 4, 0x6A, 0x28, 2,
+                    0, 0x20, 1, 0x6A, 0x0B,
+                ],
+            )
+            .memory(WasmLimit::new(1, None))
+            .build()
+            .unwrap()
+    };
 
-    let mut locals = [123.into(), 456.into(), 789.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 123);
+    let fused = build(true);
+    let WasmFunctionContent::CodeBlock(code_block) = fused.functions()[0].content() else {
+        unreachable!()
+    };
+    let fused_codes = code_block
+        .intermediate_codes()
+        .iter()
+        .filter(|code| code.instruction().is_fused())
+        .collect::<Vec<_>>();
+    assert!(fused_codes.len() >= 2);
+    for code in fused_codes {
+        assert_ne!(code.instruction().mnemonic(), WasmMnemonic::Nop);
+    }
 
-    let mut locals = [123.into(), 456.into(), 0.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 456);
+    let trap = |module: WasmModule| {
+        let instance = module.instantiate(&Env {}).unwrap();
+        let result = instance
+            .function_by_index(0)
+            .unwrap()
+            .invoke(&[0xFFFE.into()]);
+        WasmRuntimeError::try_from_error(result.unwrap_err()).unwrap()
+    };
+    let fused = trap(fused);
+    let plain = trap(build(false));
+    assert_matches!(fused.kind(), WasmRuntimeErrorKind::OutOfBounds);
+    assert_eq!(fused.mnemonic(), WasmMnemonic::I32Load);
+    // the trap points to the load instruction itself
+    assert_eq!(fused.position(), 15);
+    assert_eq!(fused.mnemonic(), plain.mnemonic());
+    assert_eq!(fused.position(), plain.position());
+    assert_eq!(fused.file_position(), plain.file_position());
 }
 
 #[test]
-fn select_float() {
-    let slice = [0, 0x20, 0, 0x20, 1, 0x20, 2, 0x1B, 0x0B];
-    let param_types = [WasmValType::F64, WasmValType::F64, WasmValType::I32];
-    let result_types = [WasmValType::F64];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &param_types,
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+fn compile_options() {
+    // (func (param i32) (result i32) local.get 0 i32.const 1 i32.add i32.extend8_s)
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x06, 0x01, 0x60, 0x01, 0x7F, 0x01,
+        0x7F, 0x03, 0x02, 0x01, 0x00, 0x0A, 0x0A, 0x01, 0x08, 0x00, 0x20, 0x00, 0x41, 0x01, 0x6A,
+        0xC0, 0x0B,
+    ];
+    let is_fused = |module: &WasmModule| match module.functions()[0].content() {
+        WasmFunctionContent::CodeBlock(code_block) => code_block
+            .intermediate_codes()
+            .iter()
+            .any(|code| matches!(code.instruction(), WasmImInstruction::FusedI32AddI(1))),
+        _ => unreachable!(),
+    };
 
-    let mut locals = [0.0.into(), PI.into(), 789.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_f64()
-        .unwrap();
-    assert_eq!(result, 0.0);
+    let module = WebAssembly::compile(&data).unwrap();
+    assert!(is_fused(&module));
 
-    let mut locals = [0.0.into(), PI.into(), 0.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+    let options = CompileOptions::new()
+        .with_fusion(false)
+        .with_proposals(&[WasmProposal::SignExtension]);
+    let module = WebAssembly::compile_with(&data, &options).unwrap();
+    assert!(!is_fused(&module));
+    let instance = module.instantiate(&Env {}).unwrap();
+    let result = instance
+        .function_by_index(0)
         .unwrap()
+        .invoke(&[0x7F.into()])
         .unwrap()
-        .get_f64()
         .unwrap();
-    assert_eq!(result, PI);
+    assert_eq!(result.get_i32().unwrap(), -128);
+
+    let options = CompileOptions::new()
+        .with_fusion(false)
+        .with_proposals(&[WasmProposal::BulkMemoryOperations]);
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(
+            &WebAssembly::compile_with(&data, &options).unwrap_err()
+        )
+        .unwrap(),
+        WasmCompileErrorKind::ProposalNotAllowed(WasmProposal::SignExtension)
+    );
 }
 
 #[test]
-fn br_if() {
-    let slice = [
-        0, 0x02, 0x40, 0x20, 0, 0x20, 1, 0x4C, 0x0d, 0, 0x41, 1, 0x0f, 0x0b, 0x41, 2, 0x0B,
-    ];
-    let param_types = [WasmValType::I32, WasmValType::I32];
+fn module_types() {
+    use crate::ImportExportKind;
+
+    let module = WasmModule::builder()
+        .import_function("env", "f", WasmType::from_signature("vl").unwrap())
+        // (func (param i32) (result i32) local.get 0)
+        .function(WasmType::from_signature("ii").unwrap(), &[0, 0x20, 0, 0x0B])
+        // (func (param i32) (result i32) local.get 0 i32.eqz)
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x20, 0, 0x45, 0x0B],
+        )
+        .export("eqz", ImportExportKind::Function, 2)
+        .build()
+        .unwrap();
+
+    assert_eq!(module.type_count(), 3);
+    let types = module
+        .types()
+        .map(|(index, func_type)| {
+            (
+                index.as_usize(),
+                func_type.param_types().to_vec(),
+                func_type.result_types().to_vec(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        [
+            (0, vec![WasmValType::I64], vec![]),
+            (1, vec![WasmValType::I32], vec![WasmValType::I32]),
+            (2, vec![WasmValType::I32], vec![WasmValType::I32]),
+        ]
+    );
+
+    // identical types are still distinct entries referred to by their own functions
+    let (index1, _) = module.types().nth(1).unwrap();
+    let (index2, _) = module.types().nth(2).unwrap();
+    assert_ne!(index1, index2);
+    assert_eq!(module.functions()[1].type_index(), index1);
+    assert_eq!(module.functions()[2].type_index(), index2);
+
+    assert_eq!(WasmModule::builder().build().unwrap().type_count(), 0);
+}
+
+#[test]
+fn module_needs_features() {
+    use crate::{ProposalSet, WasmLimit};
+
+    let module = WasmModule::builder()
+        // (func (param i32) (result i32) local.get 0 i32.extend8_s)
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x20, 0, 0xC0, 0x0B],
+        )
+        // (func (param i32 i32 i32) local.get 0 local.get 1 local.get 2 memory.copy)
+        .function(
+            WasmType::from_signature("viii").unwrap(),
+            &[0, 0x20, 0, 0x20, 1, 0x20, 2, 0xFC, 0x0A, 0, 0, 0x0B],
+        )
+        .memory(WasmLimit::new(1, None))
+        .build()
+        .unwrap();
+    let features = module.needs_features();
+    assert!(features.contains(WasmProposal::Mvp));
+    assert!(features.contains(WasmProposal::SignExtension));
+    assert!(features.contains(WasmProposal::BulkMemoryOperations));
+    assert!(!features.contains(WasmProposal::NonTrappingFloatToIntConversion));
+    assert!(!features.contains(WasmProposal::ReferenceTypes));
+
+    let accepted = [
+        WasmProposal::Mvp,
+        WasmProposal::SignExtension,
+        WasmProposal::BulkMemoryOperations,
+    ]
+    .into_iter()
+    .collect::<ProposalSet>();
+    assert!(features.is_subset(&accepted));
+    assert!(!features.is_subset(&[WasmProposal::Mvp].into_iter().collect()));
+
+    // (func (result i32) i32.const 1)
+    let module = WasmModule::builder()
+        .function(WasmType::from_signature("iv").unwrap(), &[0, 0x41, 1, 0x0B])
+        .build()
+        .unwrap();
+    assert_eq!(
+        module.needs_features(),
+        [WasmProposal::Mvp].into_iter().collect()
+    );
+    assert!(WasmModule::builder()
+        .build()
+        .unwrap()
+        .needs_features()
+        .is_empty());
+}
+
+#[test]
+fn opcode_encode() {
+    let data = [
+        0x00, // unreachable
+        0x02, 0x40, // block
+        0x03, 0x7F, // loop (result i32)
+        0x0E, 0x03, 0x00, 0x01, 0x02, 0x80, 0x01, // br_table 0 1 2 128
+        0x10, 0xB4, 0x24, // call 0x1234
+        0x11, 0x02, 0x00, // call_indirect 2 0
+        0x20, 0x80, 0x01, // local.get 128
+        0x28, 0x02, 0x10, // i32.load offset=16 align=2
+        0x37, 0x03, 0x80, 0x80, 0x04, // i64.store offset=0x10000 align=3
+        0x3F, 0x00, // memory.size 0
+        0x41, 0x7F, // i32.const -1
+        0x42, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7F, // i64.const MIN
+        0x43, 0x00, 0x00, 0xC0, 0x7F, // f32.const nan
+        0x44, 0x18, 0x2D, 0x44, 0x54, 0xFB, 0x21, 0x09, 0x40, // f64.const pi
+        0x6A, // i32.add
+        0xFC, 0x00, // i32.trunc_sat_f32_s
+        0xFC, 0x09, 0x01, // data.drop 1
+        0x0B, // end
+    ];
+    let mut reader = Leb128Reader::from_slice(&data);
+    let mut output = Vec::new();
+    while !reader.is_eof() {
+        let position = reader.position();
+        let opcode = WasmOpcode::fetch(&mut reader).unwrap();
+        let mut bytes = Vec::new();
+        opcode.encode(&mut bytes).unwrap();
+        assert_eq!(
+            bytes.as_slice(),
+            &data[position..reader.position()],
+            "{}",
+            opcode
+        );
+        output.extend_from_slice(&bytes);
+    }
+    assert_eq!(output.as_slice(), &data);
+}
+
+#[test]
+fn value_le_bytes() {
+    let values = [
+        WasmValue::I32(-2),
+        WasmValue::I64(0x0123_4567_89AB_CDEF),
+        WasmValue::F32(f32::from_bits(0x7FA0_0001)),
+        WasmValue::F64(f64::from_bits(0xFFF8_0000_0000_0123)),
+        WasmValue::F64(-0.0),
+        WasmValue::FuncRef(Some(5)),
+        WasmValue::FuncRef(None),
+    ];
+    for value in values {
+        let (bytes, val_type) = value.to_le_bytes();
+        assert_eq!(val_type, value.val_type());
+        let len = match val_type {
+            WasmValType::I32 | WasmValType::F32 => 4,
+            _ => 8,
+        };
+        let decoded = WasmValue::from_le_bytes(val_type, &bytes[..len]).unwrap();
+        assert_eq!(decoded.to_le_bytes(), (bytes, val_type));
+    }
+    assert_eq!(
+        WasmValue::I32(-2).to_le_bytes().0,
+        [0xFE, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]
+    );
+
+    for (val_type, len) in [
+        (WasmValType::I32, 8),
+        (WasmValType::F32, 3),
+        (WasmValType::I64, 4),
+        (WasmValType::F64, 0),
+    ] {
+        let err = WasmValue::from_le_bytes(val_type, &[0; 8][..len]).unwrap_err();
+        assert_matches!(
+            WasmRuntimeError::try_from_error(err).unwrap().kind(),
+            WasmRuntimeErrorKind::InvalidParameter
+        );
+    }
+}
+
+#[test]
+fn opcode_type_transition() {
+    use WasmValType::*;
+
+    assert_eq!(
+        WasmOpcode::I32Add.type_transition(&[I32, I32]),
+        Ok(vec![I32])
+    );
+    assert_eq!(WasmOpcode::I64Eqz.type_transition(&[I64]), Ok(vec![I32]));
+    // values below the operands are left as they are
+    assert_eq!(
+        WasmOpcode::F64PromoteF32.type_transition(&[I64, F32]),
+        Ok(vec![I64, F64])
+    );
+    assert_eq!(
+        WasmOpcode::I32Const(1).type_transition(&[F32]),
+        Ok(vec![F32, I32])
+    );
+    assert_eq!(
+        WasmOpcode::I64Store32(WasmMemArg::new(0, 2)).type_transition(&[I32, I64]),
+        Ok(vec![])
+    );
+
+    assert_eq!(
+        WasmOpcode::I32Add.type_transition(&[I32, I64]),
+        Err(WasmCompileErrorKind::TypeMismatch)
+    );
+    assert_eq!(
+        WasmOpcode::I32Add.type_transition(&[I32]),
+        Err(WasmCompileErrorKind::OutOfStack)
+    );
+    assert_eq!(
+        WasmOpcode::Br(0).type_transition(&[]),
+        Err(WasmCompileErrorKind::UnsupportedBytecode(WasmMnemonic::Br))
+    );
+    assert_eq!(
+        WasmOpcode::LocalGet(0).type_transition(&[]),
+        Err(WasmCompileErrorKind::UnsupportedBytecode(
+            WasmMnemonic::LocalGet
+        ))
+    );
+}
+
+#[test]
+fn leb128_sub_reader() {
+    let data = [0x03, 0xE5, 0x8E, 0x26, 0x7F];
+    let mut reader = Leb128Reader::from_slice(&data);
+    let len = reader.read_unsigned().unwrap() as usize;
+    let mut sub = reader.sub_reader(len).unwrap();
+    assert_eq!(reader.position(), 4);
+    assert_eq!(sub.read_unsigned().unwrap(), 624485);
+    assert!(sub.is_eof());
+    // the parent still has a byte, but the sub reader must not see it
+    assert_matches!(sub.read_byte(), Err(ReadError::UnexpectedEof));
+    assert_matches!(reader.sub_reader(2).err(), Some(ReadError::UnexpectedEof));
+    assert_eq!(reader.read_byte().unwrap(), 0x7F);
+
+    // (func) whose declared body length covers a trailing garbage byte after `end`
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03,
+        0x02, 0x01, 0x00, 0x0A, 0x05, 0x01, 0x03, 0x00, 0x0B, 0xFF,
+    ];
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(&WebAssembly::compile(&data).unwrap_err()).unwrap(),
+        WasmCompileErrorKind::InvalidData
+    );
+    // a section that declares more bytes than the module has
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(
+            &WebAssembly::compile(&data[..data.len() - 1]).unwrap_err()
+        )
+        .unwrap(),
+        WasmCompileErrorKind::UnexpectedEof
+    );
+}
+
+#[test]
+fn section_order() {
+    let data = [
+        0, 97, 115, 109, 1, 0, 0, 0, 1, 1, 0, 2, 1, 0, 2, 1, 0, 3, 1, 0, 4, 1, 0, 5, 1, 0,
+    ];
+    WebAssembly::instantiate(&data, &Env {}).unwrap();
+
+    let data = [
+        0, 97, 115, 109, 1, 0, 0, 0, 1, 1, 0, 2, 1, 0, 2, 1, 0, 3, 1, 0, 4, 1, 0, 3, 1, 0,
+    ];
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(&WebAssembly::instantiate(&data, &Env {}).unwrap_err())
+            .unwrap(),
+        WasmCompileErrorKind::InvalidSectionOrder(WasmSectionId::Function)
+    );
+}
+
+#[test]
+fn check_section_order() {
+    use WasmSectionId::*;
+
+    assert_eq!(WasmModule::check_section_order(Type, Function), Ok(()));
+    assert_eq!(WasmModule::check_section_order(Element, DataCount), Ok(()));
+    assert_eq!(WasmModule::check_section_order(DataCount, Code), Ok(()));
+    assert_eq!(WasmModule::check_section_order(Code, Custom), Ok(()));
+    assert_eq!(WasmModule::check_section_order(Custom, Type), Ok(()));
+    assert_eq!(
+        WasmModule::check_section_order(Code, Function),
+        Err(WasmCompileErrorKind::InvalidSectionOrder(Function))
+    );
+    assert_eq!(
+        WasmModule::check_section_order(Code, DataCount),
+        Err(WasmCompileErrorKind::InvalidSectionOrder(DataCount))
+    );
+}
+
+#[test]
+fn wasm_signature() {
+    use WasmValType::*;
+
+    let signature = WasmSignature::new(&[I32, I64, F32, F64, FuncRef], &[]);
+    assert_eq!(signature.to_string(), "vilfd_");
+    assert_eq!(WasmSignature::from_signature("vilfd_").unwrap(), signature);
+
+    let signature = WasmSignature::default();
+    assert_eq!(signature.to_string(), "vv");
+    assert_eq!(WasmSignature::from_signature("vv").unwrap(), signature);
+
+    let signature = WasmSignature::default().param(F64).result(I32);
+    assert_eq!(signature.to_string(), "id");
+    assert_eq!(signature.param_types(), &[F64]);
+    assert_eq!(signature.result_types(), &[I32]);
+
+    for (c, val_type) in [
+        ('i', I32),
+        ('l', I64),
+        ('f', F32),
+        ('d', F64),
+        ('_', FuncRef),
+    ] {
+        assert_eq!(val_type.mnemonic(), c);
+        assert_eq!(WasmValType::from_mnemonic(c), Some(val_type));
+
+        let signature = format!("{c}v");
+        let ty = WasmType::from_signature(&signature).unwrap();
+        assert_eq!(ty.param_types(), &[]);
+        assert_eq!(ty.result_types(), &[val_type]);
+        assert_eq!(ty.signature(), signature);
+
+        let signature = format!("v{c}{c}");
+        let ty = WasmType::from_signature(&signature).unwrap();
+        assert_eq!(ty.param_types(), &[val_type, val_type]);
+        assert_eq!(ty.result_types(), &[]);
+        assert_eq!(ty.signature(), signature);
+    }
+
+    for signature in ["", "v", "i", "x", "vx", "viv", "vvi", "iiv"] {
+        assert!(
+            WasmSignature::from_signature(signature).is_none(),
+            "{signature}"
+        );
+        assert!(WasmType::from_signature(signature).is_none(), "{signature}");
+    }
+}
+
+#[test]
+fn i32_const() {
+    let slice = [0, 0x41, 0xf8, 0xac, 0xd1, 0x91, 0x01, 0x0B];
     let result_types = [WasmValType::I32];
     let mut stream = Leb128Reader::from_slice(&slice);
     let instance = WasmInstance::empty();
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &param_types,
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
     let mut interp = WasmInterpreter::new(&instance);
 
-    let mut locals = [123.into(), 456.into()];
     let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
         .get_i32()
         .unwrap();
-    assert_eq!(result, 2);
+    assert_eq!(result, 0x12345678);
+}
 
-    let mut locals = [123.into(), 123.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 2);
+#[test]
+fn i32_const_type_mismatch() {
+    let slice = [0, 0x41, 0x00, 0x01, 0x0B];
+    let result_types = [WasmValType::I64];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module())
+            .unwrap_err()
+            .kind(),
+        WasmCompileErrorKind::TypeMismatch
+    );
 
-    let mut locals = [456.into(), 123.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
-        .unwrap()
-        .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 1);
+    let slice = [0, 0x41, 0x00, 0x01, 0x0B];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-    let mut locals = [123.into(), (-456).into()];
     let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
         .get_i32()
         .unwrap();
-    assert_eq!(result, 1);
+    assert_eq!(result, 0);
 
-    let mut locals = [456.into(), (-123).into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+    let result2 = interp
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 1);
+        .get_i64();
+    assert_matches!(result2, Err(WasmRuntimeErrorKind::TypeMismatch));
 }
 
 #[test]
-fn br_table() {
+fn i64_const() {
     let slice = [
-        0, 0x02, 0x40, 0x02, 0x40, 0x0b, 0x0b, 0x02, 0x40, 0x02, 0x40, 0x02, 0x40, 0x20, 0x00,
-        0x0e, 0x02, 0x00, 0x01, 0x02, 0x0b, 0x41, 0xfb, 0x00, 0x0f, 0x0b, 0x41, 0xc8, 0x03, 0x0f,
-        0x0b, 0x41, 0x95, 0x06, 0x0b,
+        0, 0x42, 0xef, 0x9b, 0xaf, 0xcd, 0xf8, 0xac, 0xd1, 0x91, 0x01, 0x0B,
     ];
-    let param_types = [WasmValType::I32];
-    let result_types = [WasmValType::I32];
+    let result_types = [WasmValType::I64];
     let mut stream = Leb128Reader::from_slice(&slice);
     let instance = WasmInstance::empty();
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &param_types,
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
     let mut interp = WasmInterpreter::new(&instance);
 
-    let mut locals = [0.into()];
     let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
-        .get_i32()
+        .get_i64()
         .unwrap();
-    assert_eq!(result, 123);
+    assert_eq!(result, 0x123456789abcdef);
+}
+
+#[test]
+fn const_max_length() {
+    let instance = WasmInstance::empty();
+    let run = |opcode: u8, leb: &[u8], result_type: WasmValType| {
+        let mut slice = vec![0, opcode];
+        slice.extend_from_slice(leb);
+        slice.push(0x0B);
+        let result_types = [result_type];
+        let mut stream = Leb128Reader::from_slice(&slice);
+        let info =
+            WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module())
+                .map_err(|err| err.kind().clone())?;
+        let mut interp = WasmInterpreter::new(&instance);
+        Ok::<_, WasmCompileErrorKind>(
+            interp
+                .invoke(0, &info, &mut [], &result_types)
+                .unwrap()
+                .unwrap(),
+        )
+    };
+    let i32_const = |leb: &[u8]| run(0x41, leb, WasmValType::I32).map(|v| v.get_i32().unwrap());
+    let i64_const = |leb: &[u8]| run(0x42, leb, WasmValType::I64).map(|v| v.get_i64().unwrap());
+
+    // 5 bytes is the longest valid encoding for i32
+    assert_eq!(i32_const(&[0xFF, 0xFF, 0xFF, 0xFF, 0x07]), Ok(i32::MAX));
+    assert_eq!(i32_const(&[0x80, 0x80, 0x80, 0x80, 0x78]), Ok(i32::MIN));
+    assert_eq!(i32_const(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F]), Ok(-1));
+    assert_eq!(i32_const(&[0x80, 0x80, 0x80, 0x80, 0x00]), Ok(0));
+    assert_eq!(
+        i32_const(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]),
+        Err(WasmCompileErrorKind::InvalidData)
+    );
+
+    // 10 bytes is the longest valid encoding for i64
+    let mut leb = [0xFF; 10];
+    leb[9] = 0x00;
+    assert_eq!(i64_const(&leb), Ok(i64::MAX));
+    let mut leb = [0x80; 10];
+    leb[9] = 0x7F;
+    assert_eq!(i64_const(&leb), Ok(i64::MIN));
+    let mut leb = [0x80; 11];
+    leb[10] = 0x00;
+    assert_eq!(i64_const(&leb), Err(WasmCompileErrorKind::InvalidData));
+}
+
+#[test]
+fn i64_const_type_mismatch() {
+    let slice = [0, 0x42, 0x00, 0x01, 0x0B];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module())
+            .unwrap_err()
+            .kind(),
+        WasmCompileErrorKind::TypeMismatch
+    );
+}
+
+#[test]
+fn float_const() {
+    let slice = [0, 0x43, 0, 0, 0, 0, 0x0B];
+    let result_types = [WasmValType::F32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-    let mut locals = [1.into()];
     let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
-        .get_i32()
+        .get_f32()
         .unwrap();
-    assert_eq!(result, 456);
+    assert_eq!(result, 0.0);
+
+    let slice = [0, 0x43, 0, 0, 0xc0, 0x7f, 0x0B];
+    let result_types = [WasmValType::F32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-    let mut locals = [2.into()];
     let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
-        .get_i32()
+        .get_f32()
         .unwrap();
-    assert_eq!(result, 789);
+    assert!(result.is_nan());
 
-    let mut locals = [3.into()];
-    let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+    let slice = [0, 0x43, 0, 0, 0x80, 0x7f, 0x0B];
+    let result_types = [WasmValType::F32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
+
+    let result = interp
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
-        .get_i32()
+        .get_f32()
         .unwrap();
-    assert_eq!(result, 789);
+    assert!(result.is_infinite());
+
+    let slice = [0, 0x43, 0xdb, 0x0f, 0x49, 0x40, 0x0B];
+    let result_types = [WasmValType::F32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-    let mut locals = [4.into()];
     let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
-        .get_i32()
+        .get_f32()
         .unwrap();
-    assert_eq!(result, 789);
+    assert_eq!(result, 3.1415927);
+}
+
+#[test]
+fn float64_const() {
+    let slice = [0, 0x44, 0, 0, 0, 0, 0, 0, 0, 0, 0x0B];
+    let result_types = [WasmValType::F64];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-    let mut locals = [5.into()];
     let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
-        .get_i32()
+        .get_f64()
         .unwrap();
-    assert_eq!(result, 789);
+    assert_eq!(result, 0.0);
+
+    let slice = [0, 0x44, 0, 0, 0, 0, 0, 0, 0xf8, 0x7f, 0x0B];
+    let result_types = [WasmValType::F64];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-    let mut locals = [(-1).into()];
     let result = interp
-        .invoke(0, &info, &mut locals, &result_types)
+        .invoke(0, &info, &mut [], &result_types)
         .unwrap()
         .unwrap()
-        .get_i32()
+        .get_f64()
         .unwrap();
-    assert_eq!(result, 789);
-}
+    assert!(result.is_nan());
 
-#[test]
-fn app_fact() {
-    let instance =
-        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+    let slice = [0, 0x44, 0, 0, 0, 0, 0, 0, 0xf0, 0x7f, 0x0B];
+    let result_types = [WasmValType::F64];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-    let result = instance.exports().fact(7).unwrap();
-    assert_eq!(result, 5040);
+    let result = interp
+        .invoke(0, &info, &mut [], &result_types)
+        .unwrap()
+        .unwrap()
+        .get_f64()
+        .unwrap();
+    assert!(result.is_infinite());
 
-    let result = instance.exports().fact(10).unwrap();
-    assert_eq!(result, 3628800);
+    let slice = [
+        0, 0x44, 0x18, 0x2d, 0x44, 0x54, 0xfb, 0x21, 0x09, 0x40, 0x0b,
+    ];
+    let result_types = [WasmValType::F64];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
+
+    let result = interp
+        .invoke(0, &info, &mut [], &result_types)
+        .unwrap()
+        .unwrap()
+        .get_f64()
+        .unwrap();
+    assert_eq!(result, PI);
 }
 
 #[test]
-fn app_fib() {
-    let instance =
-        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
-
-    let result = instance.exports().fib(5).unwrap();
-    assert_eq!(result, 5);
-
-    let result = instance.exports().fib(10).unwrap();
-    assert_eq!(result, 55);
+fn const_local() {
+    let slice = [
+        1, 1, 0x7F, 0x41, 0xFB, 0x00, 0x21, 0, 0x41, 0x12, 0x1A, 0x20, 0, 0x0B,
+    ];
+    let param_types = [];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-    let result = instance.exports().fib(20).unwrap();
-    assert_eq!(result, 6765);
+    let result = interp
+        .invoke(0, &info, &[0.into()], &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 123);
 }
 
 #[test]
-fn opr_test_i32() {
-    let instance =
-        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+fn div32_s() {
+    let slice = [0, 0x20, 0, 0x20, 1, 0x6D, 0x0B];
+    let param_types = [WasmValType::I32, WasmValType::I32];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-    let memory = instance.memory(0).unwrap().try_borrow().unwrap();
+    let mut locals = [7006652.into(), 5678.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 1234);
 
-    for val in [
-        0i32,
-        1,
-        -1,
-        0x1234_5678,
-        0x5555_5555,
-        0xAAAA_AAAAu32 as i32,
-        0x0000_FFFF,
-        0xFFFF_0000u32 as i32,
-    ] {
-        memory.fill(0xCC);
-        let result = instance.exports().test_unary_i32(val).unwrap();
-        assert_eq!(result, 0x24);
+    let mut locals = [42.into(), (-6).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, -7);
 
-        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+    let mut locals = [(-42).into(), (6).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, -7);
 
-        assert_eq!(memory.read_u32(0x10), (val == 0) as u32);
-        assert_eq!(memory.read_u32(0x14), val.trailing_zeros());
-        assert_eq!(memory.read_u32(0x18), val.leading_zeros());
-        assert_eq!(memory.read_u32(0x1C), val.count_ones());
-        assert_eq!(memory.read_u32(0x20), ((val as i8) as i32) as u32);
-        assert_eq!(memory.read_u32(0x24), ((val as i16) as i32) as u32);
+    let mut locals = [(-42).into(), (-6).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 7);
 
-        assert_eq!(memory.read_u64(0x28), 0xCCCC_CCCC_CCCC_CCCC);
+    let mut locals = [1234.into(), 0.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap_err();
+    let err: Box<WasmRuntimeError> = result.downcast().unwrap();
+    assert_matches!(*err.kind(), WasmRuntimeErrorKind::DivideByZero);
+    assert_eq!(err.mnemonic(), WasmMnemonic::I32DivS);
+    assert_eq!(err.position(), 5);
+}
 
-        memory.fill(0xCC);
-        let result = instance.exports().test_fusion_unary_i32(val).unwrap();
-        assert_eq!(result, 0x50);
+#[test]
+fn div32_u() {
+    let slice = [0, 0x20, 0, 0x20, 1, 0x6E, 0x0B];
+    let param_types = [WasmValType::I32, WasmValType::I32];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+    let mut locals = [7006652.into(), 5678.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 1234);
 
-        let valu = val as u32;
-        assert_eq!(memory.read_i32(0x10), 0x12345678);
-        assert_eq!(memory.read_i32(0x14), val.wrapping_add(1234));
-        assert_eq!(memory.read_i32(0x18), val.wrapping_sub(1234));
-        assert_eq!(memory.read_i32(0x1C), val.wrapping_add(5678));
+    let mut locals = [42.into(), (-6).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 0);
 
-        assert_eq!(memory.read_u32(0x20), valu & 0x5555_5555);
-        assert_eq!(memory.read_u32(0x24), valu & 0xaaaa_aaaa);
-        assert_eq!(memory.read_u32(0x28), valu | 0x5555_5555);
-        assert_eq!(memory.read_u32(0x2C), valu | 0xaaaa_aaaa);
-        assert_eq!(memory.read_u32(0x30), valu ^ 0x5555_5555);
-        assert_eq!(memory.read_u32(0x34), valu ^ 0xaaaa_aaaa);
+    let mut locals = [(-42).into(), (6).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 715827875);
 
-        assert_eq!(memory.read_u32(0x38), valu.wrapping_shl(7));
-        assert_eq!(memory.read_u32(0x3C), valu.wrapping_shl(19));
-        assert_eq!(memory.read_i32(0x40), val.wrapping_shr(5));
-        assert_eq!(memory.read_i32(0x44), val.wrapping_shr(17));
-        assert_eq!(memory.read_u32(0x48), valu.wrapping_shr(3));
-        assert_eq!(memory.read_u32(0x4C), valu.wrapping_shr(13));
+    let mut locals = [1234.into(), 0.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap_err();
+    let err: Box<WasmRuntimeError> = result.downcast().unwrap();
+    assert_matches!(*err.kind(), WasmRuntimeErrorKind::DivideByZero);
+    assert_eq!(err.mnemonic(), WasmMnemonic::I32DivU);
+    assert_eq!(err.position(), 5);
+}
 
-        assert_eq!(memory.read_i32(0x50), if val == 0 { 1234 } else { 5678 });
+#[test]
+fn div64_s() {
+    let slice = [0, 0x20, 0, 0x20, 1, 0x7F, 0x0B];
+    let param_types = [WasmValType::I64, WasmValType::I64];
+    let result_types = [WasmValType::I64];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-        assert_eq!(memory.read_u32(0x54), 0xCCCC_CCCC);
-        assert_eq!(memory.read_u64(0x58), 0xCCCC_CCCC_CCCC_CCCC);
-    }
+    let mut locals = [7006652i64.into(), 5678i64.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i64()
+        .unwrap();
+    assert_eq!(result, 1234);
 
-    for (lhs, rhs) in [
-        (1i32, 1i32),
-        (-1, -1),
-        (1234, 1234),
-        (-5678, -5678),
-        (1234, 5678),
-        (5678, 1234),
-        (1234, -1234),
-        (-1234, 1234),
-        (0x1234_5678, 0x1234_5678),
-        (0x7FFF_FFFF, 0x8000_0000u32 as i32),
-        (0x8000_0000u32 as i32, 0x7FFF_FFFF),
-        (0x1234_5678, 0xFEDC_BA98u32 as i32),
-        (0x5555_5555, 0xAAAA_AAAAu32 as i32),
-    ] {
-        let lhsu = lhs as u32;
-        let rhsu = rhs as u32;
+    let mut locals = [42i64.into(), (-6i64).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i64()
+        .unwrap();
+    assert_eq!(result, -7);
 
-        memory.fill(0xCC);
-        let result = instance.exports().test_bin_i32(lhs, rhs).unwrap();
-        assert_eq!(result, 112);
+    let mut locals = [(-42i64).into(), (6i64).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i64()
+        .unwrap();
+    assert_eq!(result, -7);
 
-        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+    let mut locals = [(-42i64).into(), (-6i64).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i64()
+        .unwrap();
+    assert_eq!(result, 7);
 
-        assert_eq!(memory.read_u32(0x10), (lhs == rhs) as u32);
-        assert_eq!(memory.read_u32(0x14), (lhs != rhs) as u32);
-        assert_eq!(memory.read_u32(0x18), (lhs < rhs) as u32);
-        assert_eq!(memory.read_u32(0x1c), (lhsu < rhsu) as u32);
-        assert_eq!(memory.read_u32(0x20), (lhs > rhs) as u32);
-        assert_eq!(memory.read_u32(0x24), (lhsu > rhsu) as u32);
-        assert_eq!(memory.read_u32(0x28), (lhs <= rhs) as u32);
-        assert_eq!(memory.read_u32(0x2c), (lhsu <= rhsu) as u32);
-        assert_eq!(memory.read_u32(0x30), (lhs >= rhs) as u32);
-        assert_eq!(memory.read_u32(0x34), (lhsu >= rhsu) as u32);
+    let mut locals = [1234i64.into(), 0i64.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap_err();
+    let err: Box<WasmRuntimeError> = result.downcast().unwrap();
+    assert_matches!(*err.kind(), WasmRuntimeErrorKind::DivideByZero);
+    assert_eq!(err.mnemonic(), WasmMnemonic::I64DivS);
+    assert_eq!(err.position(), 5);
+}
 
-        assert_eq!(memory.read_u32(0x38) as i32, lhs.wrapping_add(rhs));
-        assert_eq!(memory.read_u32(0x3c) as i32, lhs.wrapping_sub(rhs));
-        assert_eq!(memory.read_u32(0x40) as i32, lhs.wrapping_mul(rhs));
-        assert_eq!(memory.read_u32(0x44) as i32, lhs.wrapping_div(rhs));
-        assert_eq!(memory.read_u32(0x48), lhsu.wrapping_div(rhsu));
-        assert_eq!(memory.read_u32(0x4c) as i32, lhs.wrapping_rem(rhs));
-        assert_eq!(memory.read_u32(0x50), lhsu.wrapping_rem(rhsu));
+#[test]
+fn div64_u() {
+    let slice = [0, 0x20, 0, 0x20, 1, 0x80, 0x0B];
+    let param_types = [WasmValType::I64, WasmValType::I64];
+    let result_types = [WasmValType::I64];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-        assert_eq!(memory.read_u32(0x54) as i32, lhs & rhs);
-        assert_eq!(memory.read_u32(0x58) as i32, lhs | rhs);
-        assert_eq!(memory.read_u32(0x5c) as i32, lhs ^ rhs);
+    let mut locals = [7006652i64.into(), 5678i64.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i64()
+        .unwrap();
+    assert_eq!(result, 1234);
 
-        assert_eq!(memory.read_u32(0x60) as i32, lhs.wrapping_shl(rhsu));
-        assert_eq!(memory.read_u32(0x64) as i32, lhs.wrapping_shr(rhsu));
-        assert_eq!(memory.read_u32(0x68), lhsu.wrapping_shr(rhsu));
-        assert_eq!(memory.read_u32(0x6c), lhsu.rotate_left(rhsu));
-        assert_eq!(memory.read_u32(0x70), lhsu.rotate_right(rhsu));
+    let mut locals = [42i64.into(), (-6i64).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i64()
+        .unwrap();
+    assert_eq!(result, 0);
 
-        assert_eq!(memory.read_u32(0x74), 0xCCCCCCCC);
-        assert_eq!(memory.read_u64(0x78), 0xCCCC_CCCC_CCCC_CCCC);
+    let mut locals = [(-42i64).into(), (6i64).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i64()
+        .unwrap();
+    assert_eq!(result, 3074457345618258595);
 
-        memory.fill(0xCC);
-        let result = instance.exports().test_fusion_binary_i32(lhs, rhs).unwrap();
-        assert_eq!(result, 0x34);
+    let mut locals = [1234i64.into(), 0i64.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap_err();
+    let err: Box<WasmRuntimeError> = result.downcast().unwrap();
+    assert_matches!(*err.kind(), WasmRuntimeErrorKind::DivideByZero);
+    assert_eq!(err.mnemonic(), WasmMnemonic::I64DivU);
+    assert_eq!(err.position(), 5);
+}
 
-        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+#[test]
+fn select_int() {
+    let slice = [0, 0x20, 0, 0x20, 1, 0x20, 2, 0x1B, 0x0B];
+    let param_types = [WasmValType::I32, WasmValType::I32, WasmValType::I32];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-        assert_eq!(memory.read_u32(0x10), if lhs == rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x14), if lhs != rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x18), if lhs < rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x1c), if lhsu < rhsu { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x20), if lhs > rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x24), if lhsu > rhsu { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x28), if lhs <= rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x2c), if lhsu <= rhsu { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x30), if lhs >= rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x34), if lhsu >= rhsu { 123 } else { 456 });
+    let mut locals = [123.into(), 456.into(), 789.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 123);
 
-        assert_eq!(memory.read_u64(0x38), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(0x40), 0xCCCC_CCCC_CCCC_CCCC);
-    }
+    let mut locals = [123.into(), 456.into(), 0.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 456);
 }
 
 #[test]
-fn opr_test_i64() {
-    let instance =
-        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
-
-    let memory = instance.memory(0).unwrap().try_borrow().unwrap();
-
-    for val in [
-        0i64,
-        1,
-        -1,
-        0x1234_5678_ABCD_DEF0,
-        0x5555_5555_5555_5555,
-        0xAAAA_AAAA_AAAA_AAAAu64 as i64,
-        0x0000_0000_FFFF_FFFF,
-        0xFFFF_FFFF_0000_0000u64 as i64,
-    ] {
-        memory.fill(0xCC);
-        let result = instance.exports().test_unary_i64(val).unwrap();
-        assert_eq!(result, 0x48);
-
-        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+fn select_float() {
+    let slice = [0, 0x20, 0, 0x20, 1, 0x20, 2, 0x1B, 0x0B];
+    let param_types = [WasmValType::F64, WasmValType::F64, WasmValType::I32];
+    let result_types = [WasmValType::F64];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-        assert_eq!(memory.read_u32(0x10), (val == 0) as u32);
-        assert_eq!(memory.read_u64(0x18), val.trailing_zeros() as u64);
-        assert_eq!(memory.read_u64(0x20), val.leading_zeros() as u64);
-        assert_eq!(memory.read_u64(0x28), val.count_ones() as u64);
-        assert_eq!(memory.read_u64(0x30), ((val as i8) as i64) as u64);
-        assert_eq!(memory.read_u64(0x38), ((val as i16) as i64) as u64);
-        assert_eq!(memory.read_u64(0x40), ((val as i32) as i64) as u64);
+    let mut locals = [0.0.into(), PI.into(), 789.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_f64()
+        .unwrap();
+    assert_eq!(result, 0.0);
 
-        assert_eq!(memory.read_u32(0x48), (val as u32));
-        assert_eq!(memory.read_u32(0x4C), 0xCCCC_CCCC);
+    let mut locals = [0.0.into(), PI.into(), 0.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_f64()
+        .unwrap();
+    assert_eq!(result, PI);
+}
 
-        assert_eq!(memory.read_u64(0x50), 0xCCCC_CCCC_CCCC_CCCC);
+#[test]
+fn select_typed() {
+    let param_types = [WasmValType::I32, WasmValType::I32, WasmValType::I32];
+    let result_types = [WasmValType::I32];
+    let instance = WasmInstance::empty();
+    let generate = |slice: &[u8]| {
+        let mut stream = Leb128Reader::from_slice(slice);
+        WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &param_types,
+            &result_types,
+            instance.module(),
+        )
+    };
 
-        memory.fill(0xCC);
-        let result = instance.exports().test_fused_i64(val).unwrap();
-        assert_eq!(result, 0x90);
+    // (select (result i32) (local.get 0) (local.get 1) (local.get 2))
+    let info = generate(&[0, 0x20, 0, 0x20, 1, 0x20, 2, 0x1C, 0x01, 0x7F, 0x0B]).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
+    for (cc, expected) in [(789, 123), (0, 456)] {
+        let locals = [123.into(), 456.into(), cc.into()];
+        let result = interp
+            .invoke(0, &info, &locals, &result_types)
+            .unwrap()
+            .unwrap()
+            .get_i32()
+            .unwrap();
+        assert_eq!(result, expected);
+    }
 
-        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+    // (select (result i64) ...) with i32 operands
+    let err = generate(&[0, 0x20, 0, 0x20, 1, 0x20, 2, 0x1C, 0x01, 0x7E, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
 
-        let valu = val as u64;
-        assert_eq!(memory.read_i64(0x10), 0x12345678);
-        assert_eq!(memory.read_i64(0x18), val.wrapping_add(12345678));
-        assert_eq!(memory.read_i64(0x20), val.wrapping_sub(12345678));
-        assert_eq!(memory.read_i64(0x28), val.wrapping_add(987654321));
+    // (select (result i32 i32) ...)
+    let err = generate(&[0, 0x20, 0, 0x20, 1, 0x20, 2, 0x1C, 0x02, 0x7F, 0x7F, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::InvalidData);
+}
 
-        assert_eq!(memory.read_u64(0x30), valu & 0x5555_5555_5555_5555);
-        assert_eq!(memory.read_u64(0x38), valu & 0xaaaa_aaaa_aaaa_aaaa);
-        assert_eq!(memory.read_u64(0x40), valu | 0x5555_5555_5555_5555);
-        assert_eq!(memory.read_u64(0x48), valu | 0xaaaa_aaaa_aaaa_aaaa);
-        assert_eq!(memory.read_u64(0x50), valu ^ 0x5555_5555_5555_5555);
-        assert_eq!(memory.read_u64(0x58), valu ^ 0xaaaa_aaaa_aaaa_aaaa);
+#[test]
+fn br_if() {
+    let slice = [
+        0, 0x02, 0x40, 0x20, 0, 0x20, 1, 0x4C, 0x0d, 0, 0x41, 1, 0x0f, 0x0b, 0x41, 2, 0x0B,
+    ];
+    let param_types = [WasmValType::I32, WasmValType::I32];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-        assert_eq!(memory.read_u64(0x60), valu.wrapping_shl(7));
-        assert_eq!(memory.read_u64(0x68), valu.wrapping_shl(19));
-        assert_eq!(memory.read_i64(0x70), val.wrapping_shr(5));
-        assert_eq!(memory.read_i64(0x78), val.wrapping_shr(17));
-        assert_eq!(memory.read_u64(0x80), valu.wrapping_shr(3));
-        assert_eq!(memory.read_u64(0x88), valu.wrapping_shr(13));
+    let mut locals = [123.into(), 456.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 2);
 
-        assert_eq!(memory.read_i32(0x90), if val == 0 { 1234 } else { 5678 });
+    let mut locals = [123.into(), 123.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 2);
 
-        assert_eq!(memory.read_u32(0x94), 0xCCCC_CCCC);
-        assert_eq!(memory.read_u64(0x98), 0xCCCC_CCCC_CCCC_CCCC);
-    }
+    let mut locals = [456.into(), 123.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 1);
 
-    for (lhs, rhs) in [
-        (1i64, 1i64),
-        (-1, -1),
-        (1234, 1234),
-        (-5678, -5678),
-        (1234, 5678),
-        (5678, 1234),
-        (1234, -1234),
-        (-1234, 1234),
-        (0x1111_1111_1234_5678, 0x0000_0000_1234_5678),
-        (0x1234_5678_9ABC_DEF0, 0x1234_5678_9ABC_DEF0),
-        (0x7FFF_FFFF_FFFF_FFFF, 0x8000_0000_0000_0000u64 as i64),
-        (0x8000_0000_0000_0000u64 as i64, 0x7FFF_FFFF_FFFF_FFFF),
-        (0x1234_5678_9ABC_DEF0, 0xFEDC_BA98_7654_3210u64 as i64),
-        (0x5555_5555_5555_5555, 0xAAAA_AAAA_AAAA_AAAAu64 as i64),
-    ] {
-        let lhsu = lhs as u64;
-        let rhsu = rhs as u64;
+    let mut locals = [123.into(), (-456).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 1);
 
-        memory.fill(0xCC);
-        let result = instance.exports().test_bin_i64(lhs, rhs).unwrap();
-        assert_eq!(result, 168);
+    let mut locals = [456.into(), (-123).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 1);
+}
 
-        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+#[test]
+fn br_table() {
+    let slice = [
+        0, 0x02, 0x40, 0x02, 0x40, 0x0b, 0x0b, 0x02, 0x40, 0x02, 0x40, 0x02, 0x40, 0x20, 0x00,
+        0x0e, 0x02, 0x00, 0x01, 0x02, 0x0b, 0x41, 0xfb, 0x00, 0x0f, 0x0b, 0x41, 0xc8, 0x03, 0x0f,
+        0x0b, 0x41, 0x95, 0x06, 0x0b,
+    ];
+    let param_types = [WasmValType::I32];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
 
-        assert_eq!(memory.read_u32(0x10), (lhs == rhs) as u32);
-        assert_eq!(memory.read_u32(0x14), (lhs != rhs) as u32);
-        assert_eq!(memory.read_u32(0x18), (lhs < rhs) as u32);
-        assert_eq!(memory.read_u32(0x1c), (lhsu < rhsu) as u32);
-        assert_eq!(memory.read_u32(0x20), (lhs > rhs) as u32);
-        assert_eq!(memory.read_u32(0x24), (lhsu > rhsu) as u32);
-        assert_eq!(memory.read_u32(0x28), (lhs <= rhs) as u32);
-        assert_eq!(memory.read_u32(0x2c), (lhsu <= rhsu) as u32);
-        assert_eq!(memory.read_u32(0x30), (lhs >= rhs) as u32);
-        assert_eq!(memory.read_u32(0x34), (lhsu >= rhsu) as u32);
+    let mut locals = [0.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 123);
 
-        assert_eq!(memory.read_u64(0x38) as i64, lhs.wrapping_add(rhs));
-        assert_eq!(memory.read_u64(0x40) as i64, lhs.wrapping_sub(rhs));
-        assert_eq!(memory.read_u64(0x48) as i64, lhs.wrapping_mul(rhs));
-        assert_eq!(memory.read_u64(0x50) as i64, lhs.wrapping_div(rhs));
-        assert_eq!(memory.read_u64(0x58), lhsu.wrapping_div(rhsu));
-        assert_eq!(memory.read_u64(0x60) as i64, lhs.wrapping_rem(rhs));
-        assert_eq!(memory.read_u64(0x68), lhsu.wrapping_rem(rhsu));
+    let mut locals = [1.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 456);
 
-        assert_eq!(memory.read_u64(0x70) as i64, lhs & rhs);
-        assert_eq!(memory.read_u64(0x78) as i64, lhs | rhs);
-        assert_eq!(memory.read_u64(0x80) as i64, lhs ^ rhs);
+    let mut locals = [2.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 789);
 
-        assert_eq!(memory.read_u64(0x88) as i64, lhs.wrapping_shl(rhs as u32));
-        assert_eq!(memory.read_u64(0x90) as i64, lhs.wrapping_shr(rhs as u32));
-        assert_eq!(memory.read_u64(0x98), lhsu.wrapping_shr(rhs as u32));
-        assert_eq!(memory.read_u64(0xA0), lhsu.rotate_left(rhs as u32));
-        assert_eq!(memory.read_u64(0xA8), lhsu.rotate_right(rhs as u32));
+    let mut locals = [3.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 789);
 
-        assert_eq!(memory.read_u64(0xB0), 0xCCCC_CCCC_CCCC_CCCC);
+    let mut locals = [4.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 789);
 
-        memory.fill(0xCC);
-        let result = instance.exports().test_fusion_binary_i64(lhs, rhs).unwrap();
-        assert_eq!(result, 0x34);
+    let mut locals = [5.into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 789);
 
-        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+    let mut locals = [(-1).into()];
+    let result = interp
+        .invoke(0, &info, &mut locals, &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 789);
+}
 
-        assert_eq!(memory.read_u32(0x10), if lhs == rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x14), if lhs != rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x18), if lhs < rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x1c), if lhsu < rhsu { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x20), if lhs > rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x24), if lhsu > rhsu { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x28), if lhs <= rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x2c), if lhsu <= rhsu { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x30), if lhs >= rhs { 123 } else { 456 });
-        assert_eq!(memory.read_u32(0x34), if lhsu >= rhsu { 123 } else { 456 });
+#[test]
+fn app_fact() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
 
-        assert_eq!(memory.read_u64(0x38), 0xCCCC_CCCC_CCCC_CCCC);
-        assert_eq!(memory.read_u64(0x40), 0xCCCC_CCCC_CCCC_CCCC);
-    }
+    let result = instance.exports().fact(7).unwrap();
+    assert_eq!(result, 5040);
+
+    let result = instance.exports().fact(10).unwrap();
+    assert_eq!(result, 3628800);
 }
 
 #[test]
-fn call_test() {
+fn app_fib() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+
+    let result = instance.exports().fib(5).unwrap();
+    assert_eq!(result, 5);
+
+    let result = instance.exports().fib(10).unwrap();
+    assert_eq!(result, 55);
+
+    let result = instance.exports().fib(20).unwrap();
+    assert_eq!(result, 6765);
+}
+
+#[test]
+fn interp_invoke_function() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+    let fib = instance
+        .module()
+        .function_descriptors()
+        .find(|v| v.export == Some("fib"))
+        .unwrap()
+        .index;
+
+    let mut interp = WasmInterpreter::new(&instance);
+    let result = interp
+        .invoke_function(fib, &[WasmValue::from(10i32)])
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 55);
+
+    let err = interp
+        .invoke_function(fib, &[WasmValue::from(10i64)])
+        .unwrap_err();
+    assert_matches!(
+        WasmRuntimeError::try_from_error(err).unwrap().kind(),
+        WasmRuntimeErrorKind::TypeMismatch
+    );
+
+    let err = interp.invoke_function(0, &[]).unwrap_err();
+    assert_matches!(
+        WasmRuntimeError::try_from_error(err).unwrap().kind(),
+        WasmRuntimeErrorKind::ImportedFunction
+    );
+}
+
+#[test]
+fn opr_test_i32() {
     let instance =
         WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
 
     let memory = instance.memory(0).unwrap().try_borrow().unwrap();
 
-    for (a1, a2, a3, a4) in [
-        (1u32, 2u32, 3u64, 4u64),
-        (
-            0x1234_5678,
-            0xFEDC_BA98,
-            0x1234_5678_9ABC_DEF0,
-            0xFEDC_BA98_7654_3210,
-        ),
+    for val in [
+        0i32,
+        1,
+        -1,
+        0x1234_5678,
+        0x5555_5555,
+        0xAAAA_AAAAu32 as i32,
+        0x0000_FFFF,
+        0xFFFF_0000u32 as i32,
     ] {
         memory.fill(0xCC);
-        let result = instance.exports().call_test1(a1, a2, a3, a4).unwrap();
-        assert_eq!(result, a1);
+        let result = instance.exports().test_unary_i32(val).unwrap();
+        assert_eq!(result, 0x24);
 
         assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
         assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
 
-        assert_eq!(memory.read_u32(0x10), a1);
-        assert_eq!(memory.read_u32(0x14), a2);
-        assert_eq!(memory.read_u64(0x18), a3);
-        assert_eq!(memory.read_u64(0x20), a4);
+        assert_eq!(memory.read_u32(0x10), (val == 0) as u32);
+        assert_eq!(memory.read_u32(0x14), val.trailing_zeros());
+        assert_eq!(memory.read_u32(0x18), val.leading_zeros());
+        assert_eq!(memory.read_u32(0x1C), val.count_ones());
+        assert_eq!(memory.read_u32(0x20), ((val as i8) as i32) as u32);
+        assert_eq!(memory.read_u32(0x24), ((val as i16) as i32) as u32);
 
         assert_eq!(memory.read_u64(0x28), 0xCCCC_CCCC_CCCC_CCCC);
 
         memory.fill(0xCC);
-        let result = instance.exports().call_test2(a1, a2, a3, a4).unwrap();
-        assert_eq!(result, a1.wrapping_add(a2));
+        let result = instance.exports().test_fusion_unary_i32(val).unwrap();
+        assert_eq!(result, 0x50);
 
         assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
         assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
 
-        assert_eq!(memory.read_u32(0x10), a2);
-        assert_eq!(memory.read_u32(0x14), a1);
-        assert_eq!(memory.read_u64(0x18), a4);
-        assert_eq!(memory.read_u64(0x20), a3);
+        let valu = val as u32;
+        assert_eq!(memory.read_i32(0x10), 0x12345678);
+        assert_eq!(memory.read_i32(0x14), val.wrapping_add(1234));
+        assert_eq!(memory.read_i32(0x18), val.wrapping_sub(1234));
+        assert_eq!(memory.read_i32(0x1C), val.wrapping_add(5678));
 
-        assert_eq!(memory.read_u64(0x28), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u32(0x20), valu & 0x5555_5555);
+        assert_eq!(memory.read_u32(0x24), valu & 0xaaaa_aaaa);
+        assert_eq!(memory.read_u32(0x28), valu | 0x5555_5555);
+        assert_eq!(memory.read_u32(0x2C), valu | 0xaaaa_aaaa);
+        assert_eq!(memory.read_u32(0x30), valu ^ 0x5555_5555);
+        assert_eq!(memory.read_u32(0x34), valu ^ 0xaaaa_aaaa);
+
+        assert_eq!(memory.read_u32(0x38), valu.wrapping_shl(7));
+        assert_eq!(memory.read_u32(0x3C), valu.wrapping_shl(19));
+        assert_eq!(memory.read_i32(0x40), val.wrapping_shr(5));
+        assert_eq!(memory.read_i32(0x44), val.wrapping_shr(17));
+        assert_eq!(memory.read_u32(0x48), valu.wrapping_shr(3));
+        assert_eq!(memory.read_u32(0x4C), valu.wrapping_shr(13));
+
+        assert_eq!(memory.read_i32(0x50), if val == 0 { 1234 } else { 5678 });
+
+        assert_eq!(memory.read_u32(0x54), 0xCCCC_CCCC);
+        assert_eq!(memory.read_u64(0x58), 0xCCCC_CCCC_CCCC_CCCC);
+    }
+
+    for (lhs, rhs) in [
+        (1i32, 1i32),
+        (-1, -1),
+        (1234, 1234),
+        (-5678, -5678),
+        (1234, 5678),
+        (5678, 1234),
+        (1234, -1234),
+        (-1234, 1234),
+        (0x1234_5678, 0x1234_5678),
+        (0x7FFF_FFFF, 0x8000_0000u32 as i32),
+        (0x8000_0000u32 as i32, 0x7FFF_FFFF),
+        (0x1234_5678, 0xFEDC_BA98u32 as i32),
+        (0x5555_5555, 0xAAAA_AAAAu32 as i32),
+    ] {
+        let lhsu = lhs as u32;
+        let rhsu = rhs as u32;
 
         memory.fill(0xCC);
-        let result = instance.exports().call_test3(a1, a2, a3, a4).unwrap();
-        assert_eq!(result, a3);
+        let result = instance.exports().test_bin_i32(lhs, rhs).unwrap();
+        assert_eq!(result, 112);
 
         assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
         assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
 
-        assert_eq!(memory.read_u32(0x10), a1);
-        assert_eq!(memory.read_u32(0x14), a2);
-        assert_eq!(memory.read_u64(0x18), a3);
-        assert_eq!(memory.read_u64(0x20), a4);
+        assert_eq!(memory.read_u32(0x10), (lhs == rhs) as u32);
+        assert_eq!(memory.read_u32(0x14), (lhs != rhs) as u32);
+        assert_eq!(memory.read_u32(0x18), (lhs < rhs) as u32);
+        assert_eq!(memory.read_u32(0x1c), (lhsu < rhsu) as u32);
+        assert_eq!(memory.read_u32(0x20), (lhs > rhs) as u32);
+        assert_eq!(memory.read_u32(0x24), (lhsu > rhsu) as u32);
+        assert_eq!(memory.read_u32(0x28), (lhs <= rhs) as u32);
+        assert_eq!(memory.read_u32(0x2c), (lhsu <= rhsu) as u32);
+        assert_eq!(memory.read_u32(0x30), (lhs >= rhs) as u32);
+        assert_eq!(memory.read_u32(0x34), (lhsu >= rhsu) as u32);
 
-        assert_eq!(memory.read_u64(0x28), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u32(0x38) as i32, lhs.wrapping_add(rhs));
+        assert_eq!(memory.read_u32(0x3c) as i32, lhs.wrapping_sub(rhs));
+        assert_eq!(memory.read_u32(0x40) as i32, lhs.wrapping_mul(rhs));
+        assert_eq!(memory.read_u32(0x44) as i32, lhs.wrapping_div(rhs));
+        assert_eq!(memory.read_u32(0x48), lhsu.wrapping_div(rhsu));
+        assert_eq!(memory.read_u32(0x4c) as i32, lhs.wrapping_rem(rhs));
+        assert_eq!(memory.read_u32(0x50), lhsu.wrapping_rem(rhsu));
+
+        assert_eq!(memory.read_u32(0x54) as i32, lhs & rhs);
+        assert_eq!(memory.read_u32(0x58) as i32, lhs | rhs);
+        assert_eq!(memory.read_u32(0x5c) as i32, lhs ^ rhs);
+
+        assert_eq!(memory.read_u32(0x60) as i32, lhs.wrapping_shl(rhsu));
+        assert_eq!(memory.read_u32(0x64) as i32, lhs.wrapping_shr(rhsu));
+        assert_eq!(memory.read_u32(0x68), lhsu.wrapping_shr(rhsu));
+        assert_eq!(memory.read_u32(0x6c), lhsu.rotate_left(rhsu));
+        assert_eq!(memory.read_u32(0x70), lhsu.rotate_right(rhsu));
+
+        assert_eq!(memory.read_u32(0x74), 0xCCCCCCCC);
+        assert_eq!(memory.read_u64(0x78), 0xCCCC_CCCC_CCCC_CCCC);
 
         memory.fill(0xCC);
-        let result = instance.exports().call_test4(a1, a2, a3, a4).unwrap();
-        assert_eq!(result, a4);
+        let result = instance.exports().test_fusion_binary_i32(lhs, rhs).unwrap();
+        assert_eq!(result, 0x34);
 
         assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
         assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
 
-        assert_eq!(memory.read_u32(0x10), a1.wrapping_add(a2));
-        assert_eq!(memory.read_u32(0x14), a1.wrapping_sub(a2));
-        assert_eq!(memory.read_u64(0x18), a3.wrapping_add(a4));
-        assert_eq!(memory.read_u64(0x20), a3.wrapping_sub(a4));
+        assert_eq!(memory.read_u32(0x10), if lhs == rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x14), if lhs != rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x18), if lhs < rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x1c), if lhsu < rhsu { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x20), if lhs > rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x24), if lhsu > rhsu { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x28), if lhs <= rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x2c), if lhsu <= rhsu { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x30), if lhs >= rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x34), if lhsu >= rhsu { 123 } else { 456 });
 
-        assert_eq!(memory.read_u64(0x28), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(0x38), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(0x40), 0xCCCC_CCCC_CCCC_CCCC);
     }
 }
 
 #[test]
-fn call_indirect_test() {
+fn opr_test_i64() {
     let instance =
         WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
 
-    // let memory = instance.memory(0).unwrap().try_borrow().unwrap();
+    let memory = instance.memory(0).unwrap().try_borrow().unwrap();
 
-    for a1 in [0i32, 0x12345678, 0x55555555, 0xAAAAAAAAu32 as i32] {
-        for i in 1..=3 {
-            let base = [0i32, 123, -456, 789][i as usize];
-            let result = instance
-                .exports()
-                .get("call_indirect_test")
-                .unwrap()
-                .invoke(&[(i as i32).into(), a1.into()])
-                .unwrap()
-                .unwrap()
-                .get_i32()
+    for val in [
+        0i64,
+        1,
+        -1,
+        0x1234_5678_ABCD_DEF0,
+        0x5555_5555_5555_5555,
+        0xAAAA_AAAA_AAAA_AAAAu64 as i64,
+        0x0000_0000_FFFF_FFFF,
+        0xFFFF_FFFF_0000_0000u64 as i64,
+    ] {
+        memory.fill(0xCC);
+        let result = instance.exports().test_unary_i64(val).unwrap();
+        assert_eq!(result, 0x48);
+
+        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+
+        assert_eq!(memory.read_u32(0x10), (val == 0) as u32);
+        assert_eq!(memory.read_u64(0x18), val.trailing_zeros() as u64);
+        assert_eq!(memory.read_u64(0x20), val.leading_zeros() as u64);
+        assert_eq!(memory.read_u64(0x28), val.count_ones() as u64);
+        assert_eq!(memory.read_u64(0x30), ((val as i8) as i64) as u64);
+        assert_eq!(memory.read_u64(0x38), ((val as i16) as i64) as u64);
+        assert_eq!(memory.read_u64(0x40), ((val as i32) as i64) as u64);
+
+        assert_eq!(memory.read_u32(0x48), (val as u32));
+        assert_eq!(memory.read_u32(0x4C), 0xCCCC_CCCC);
+
+        assert_eq!(memory.read_u64(0x50), 0xCCCC_CCCC_CCCC_CCCC);
+
+        memory.fill(0xCC);
+        let result = instance.exports().test_fused_i64(val).unwrap();
+        assert_eq!(result, 0x90);
+
+        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+
+        let valu = val as u64;
+        assert_eq!(memory.read_i64(0x10), 0x12345678);
+        assert_eq!(memory.read_i64(0x18), val.wrapping_add(12345678));
+        assert_eq!(memory.read_i64(0x20), val.wrapping_sub(12345678));
+        assert_eq!(memory.read_i64(0x28), val.wrapping_add(987654321));
+
+        assert_eq!(memory.read_u64(0x30), valu & 0x5555_5555_5555_5555);
+        assert_eq!(memory.read_u64(0x38), valu & 0xaaaa_aaaa_aaaa_aaaa);
+        assert_eq!(memory.read_u64(0x40), valu | 0x5555_5555_5555_5555);
+        assert_eq!(memory.read_u64(0x48), valu | 0xaaaa_aaaa_aaaa_aaaa);
+        assert_eq!(memory.read_u64(0x50), valu ^ 0x5555_5555_5555_5555);
+        assert_eq!(memory.read_u64(0x58), valu ^ 0xaaaa_aaaa_aaaa_aaaa);
+
+        assert_eq!(memory.read_u64(0x60), valu.wrapping_shl(7));
+        assert_eq!(memory.read_u64(0x68), valu.wrapping_shl(19));
+        assert_eq!(memory.read_i64(0x70), val.wrapping_shr(5));
+        assert_eq!(memory.read_i64(0x78), val.wrapping_shr(17));
+        assert_eq!(memory.read_u64(0x80), valu.wrapping_shr(3));
+        assert_eq!(memory.read_u64(0x88), valu.wrapping_shr(13));
+
+        assert_eq!(memory.read_i32(0x90), if val == 0 { 1234 } else { 5678 });
+
+        assert_eq!(memory.read_u32(0x94), 0xCCCC_CCCC);
+        assert_eq!(memory.read_u64(0x98), 0xCCCC_CCCC_CCCC_CCCC);
+    }
+
+    for (lhs, rhs) in [
+        (1i64, 1i64),
+        (-1, -1),
+        (1234, 1234),
+        (-5678, -5678),
+        (1234, 5678),
+        (5678, 1234),
+        (1234, -1234),
+        (-1234, 1234),
+        (0x1111_1111_1234_5678, 0x0000_0000_1234_5678),
+        (0x1234_5678_9ABC_DEF0, 0x1234_5678_9ABC_DEF0),
+        (0x7FFF_FFFF_FFFF_FFFF, 0x8000_0000_0000_0000u64 as i64),
+        (0x8000_0000_0000_0000u64 as i64, 0x7FFF_FFFF_FFFF_FFFF),
+        (0x1234_5678_9ABC_DEF0, 0xFEDC_BA98_7654_3210u64 as i64),
+        (0x5555_5555_5555_5555, 0xAAAA_AAAA_AAAA_AAAAu64 as i64),
+    ] {
+        let lhsu = lhs as u64;
+        let rhsu = rhs as u64;
+
+        memory.fill(0xCC);
+        let result = instance.exports().test_bin_i64(lhs, rhs).unwrap();
+        assert_eq!(result, 168);
+
+        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+
+        assert_eq!(memory.read_u32(0x10), (lhs == rhs) as u32);
+        assert_eq!(memory.read_u32(0x14), (lhs != rhs) as u32);
+        assert_eq!(memory.read_u32(0x18), (lhs < rhs) as u32);
+        assert_eq!(memory.read_u32(0x1c), (lhsu < rhsu) as u32);
+        assert_eq!(memory.read_u32(0x20), (lhs > rhs) as u32);
+        assert_eq!(memory.read_u32(0x24), (lhsu > rhsu) as u32);
+        assert_eq!(memory.read_u32(0x28), (lhs <= rhs) as u32);
+        assert_eq!(memory.read_u32(0x2c), (lhsu <= rhsu) as u32);
+        assert_eq!(memory.read_u32(0x30), (lhs >= rhs) as u32);
+        assert_eq!(memory.read_u32(0x34), (lhsu >= rhsu) as u32);
+
+        assert_eq!(memory.read_u64(0x38) as i64, lhs.wrapping_add(rhs));
+        assert_eq!(memory.read_u64(0x40) as i64, lhs.wrapping_sub(rhs));
+        assert_eq!(memory.read_u64(0x48) as i64, lhs.wrapping_mul(rhs));
+        assert_eq!(memory.read_u64(0x50) as i64, lhs.wrapping_div(rhs));
+        assert_eq!(memory.read_u64(0x58), lhsu.wrapping_div(rhsu));
+        assert_eq!(memory.read_u64(0x60) as i64, lhs.wrapping_rem(rhs));
+        assert_eq!(memory.read_u64(0x68), lhsu.wrapping_rem(rhsu));
+
+        assert_eq!(memory.read_u64(0x70) as i64, lhs & rhs);
+        assert_eq!(memory.read_u64(0x78) as i64, lhs | rhs);
+        assert_eq!(memory.read_u64(0x80) as i64, lhs ^ rhs);
+
+        assert_eq!(memory.read_u64(0x88) as i64, lhs.wrapping_shl(rhs as u32));
+        assert_eq!(memory.read_u64(0x90) as i64, lhs.wrapping_shr(rhs as u32));
+        assert_eq!(memory.read_u64(0x98), lhsu.wrapping_shr(rhs as u32));
+        assert_eq!(memory.read_u64(0xA0), lhsu.rotate_left(rhs as u32));
+        assert_eq!(memory.read_u64(0xA8), lhsu.rotate_right(rhs as u32));
+
+        assert_eq!(memory.read_u64(0xB0), 0xCCCC_CCCC_CCCC_CCCC);
+
+        memory.fill(0xCC);
+        let result = instance.exports().test_fusion_binary_i64(lhs, rhs).unwrap();
+        assert_eq!(result, 0x34);
+
+        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+
+        assert_eq!(memory.read_u32(0x10), if lhs == rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x14), if lhs != rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x18), if lhs < rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x1c), if lhsu < rhsu { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x20), if lhs > rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x24), if lhsu > rhsu { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x28), if lhs <= rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x2c), if lhsu <= rhsu { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x30), if lhs >= rhs { 123 } else { 456 });
+        assert_eq!(memory.read_u32(0x34), if lhsu >= rhsu { 123 } else { 456 });
+
+        assert_eq!(memory.read_u64(0x38), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(0x40), 0xCCCC_CCCC_CCCC_CCCC);
+    }
+}
+
+#[test]
+fn call_test() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+
+    let memory = instance.memory(0).unwrap().try_borrow().unwrap();
+
+    for (a1, a2, a3, a4) in [
+        (1u32, 2u32, 3u64, 4u64),
+        (
+            0x1234_5678,
+            0xFEDC_BA98,
+            0x1234_5678_9ABC_DEF0,
+            0xFEDC_BA98_7654_3210,
+        ),
+    ] {
+        memory.fill(0xCC);
+        let result = instance.exports().call_test1(a1, a2, a3, a4).unwrap();
+        assert_eq!(result, a1);
+
+        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+
+        assert_eq!(memory.read_u32(0x10), a1);
+        assert_eq!(memory.read_u32(0x14), a2);
+        assert_eq!(memory.read_u64(0x18), a3);
+        assert_eq!(memory.read_u64(0x20), a4);
+
+        assert_eq!(memory.read_u64(0x28), 0xCCCC_CCCC_CCCC_CCCC);
+
+        memory.fill(0xCC);
+        let result = instance.exports().call_test2(a1, a2, a3, a4).unwrap();
+        assert_eq!(result, a1.wrapping_add(a2));
+
+        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+
+        assert_eq!(memory.read_u32(0x10), a2);
+        assert_eq!(memory.read_u32(0x14), a1);
+        assert_eq!(memory.read_u64(0x18), a4);
+        assert_eq!(memory.read_u64(0x20), a3);
+
+        assert_eq!(memory.read_u64(0x28), 0xCCCC_CCCC_CCCC_CCCC);
+
+        memory.fill(0xCC);
+        let result = instance.exports().call_test3(a1, a2, a3, a4).unwrap();
+        assert_eq!(result, a3);
+
+        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+
+        assert_eq!(memory.read_u32(0x10), a1);
+        assert_eq!(memory.read_u32(0x14), a2);
+        assert_eq!(memory.read_u64(0x18), a3);
+        assert_eq!(memory.read_u64(0x20), a4);
+
+        assert_eq!(memory.read_u64(0x28), 0xCCCC_CCCC_CCCC_CCCC);
+
+        memory.fill(0xCC);
+        let result = instance.exports().call_test4(a1, a2, a3, a4).unwrap();
+        assert_eq!(result, a4);
+
+        assert_eq!(memory.read_u64(0), 0xCCCC_CCCC_CCCC_CCCC);
+        assert_eq!(memory.read_u64(8), 0xCCCC_CCCC_CCCC_CCCC);
+
+        assert_eq!(memory.read_u32(0x10), a1.wrapping_add(a2));
+        assert_eq!(memory.read_u32(0x14), a1.wrapping_sub(a2));
+        assert_eq!(memory.read_u64(0x18), a3.wrapping_add(a4));
+        assert_eq!(memory.read_u64(0x20), a3.wrapping_sub(a4));
+
+        assert_eq!(memory.read_u64(0x28), 0xCCCC_CCCC_CCCC_CCCC);
+    }
+}
+
+#[test]
+fn call_indirect_table_index() {
+    // (type $t0 (func (result i32))) (type $t1 (func (param i32) (result i32)))
+    // (table $a 1 funcref) (table $b 1 funcref) (elem (table $a) (i32.const 0) func 0)
+    // (func (type $t0) i32.const 10) (func (type $t0) i32.const 20)
+    // (func (export "a") (type $t1) local.get 0 call_indirect $a (type $t0))
+    // (func (export "b") (type $t1) local.get 0 call_indirect $b (type $t0))
+    let bytes = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x0A, 0x02, 0x60, 0x00, 0x01, 0x7F, 0x60, 0x01, 0x7F, 0x01, 0x7F, // type
+        0x03, 0x05, 0x04, 0x00, 0x00, 0x01, 0x01, // func
+        0x04, 0x07, 0x02, 0x70, 0x00, 0x01, 0x70, 0x00, 0x01, // table
+        0x07, 0x09, 0x02, 0x01, b'a', 0x00, 0x02, 0x01, b'b', 0x00, 0x03, // export
+        0x09, 0x07, 0x01, 0x00, 0x41, 0x00, 0x0B, 0x01, 0x00, // elem
+        0x0A, 0x1B, 0x04, // code
+        0x04, 0x00, 0x41, 0x0A, 0x0B, //
+        0x04, 0x00, 0x41, 0x14, 0x0B, //
+        0x07, 0x00, 0x20, 0x00, 0x11, 0x00, 0x00, 0x0B, //
+        0x07, 0x00, 0x20, 0x00, 0x11, 0x00, 0x01, 0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&bytes, &Env {}).unwrap();
+    instance.module().tables()[1].set(0, Some(1)).unwrap();
+
+    let call = |name: &str| {
+        instance
+            .function(name)
+            .unwrap()
+            .invoke(&[0.into()])
+            .map(|v| v.unwrap().get_i32().unwrap())
+    };
+    assert_eq!(call("a").unwrap(), 10);
+    assert_eq!(call("b").unwrap(), 20);
+
+    // each table traps on its own elements
+    instance.module().tables()[1].set(0, None).unwrap();
+    assert_eq!(call("a").unwrap(), 10);
+    let err = WasmRuntimeError::try_from_error(call("b").unwrap_err()).unwrap();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::UninitializedElement);
+}
+
+#[test]
+fn call_indirect_test() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+
+    // let memory = instance.memory(0).unwrap().try_borrow().unwrap();
+
+    for a1 in [0i32, 0x12345678, 0x55555555, 0xAAAAAAAAu32 as i32] {
+        for i in 1..=3 {
+            let base = [0i32, 123, -456, 789][i as usize];
+            let result = instance
+                .exports()
+                .get("call_indirect_test")
+                .unwrap()
+                .invoke(&[(i as i32).into(), a1.into()])
+                .unwrap()
+                .unwrap()
+                .get_i32()
                 .unwrap();
             assert_eq!(result, a1.wrapping_add(base));
         }
@@ -1287,7 +3344,7 @@ fn call_indirect_test() {
             .unwrap_err()
             .downcast()
             .unwrap();
-        assert_matches!(err.kind(), WasmRuntimeErrorKind::TypeMismatch);
+        assert_matches!(err.kind(), WasmRuntimeErrorKind::IndirectCallTypeMismatch);
 
         let err: Box<WasmRuntimeError> = instance
             .exports()
@@ -1297,7 +3354,7 @@ fn call_indirect_test() {
             .unwrap_err()
             .downcast()
             .unwrap();
-        assert_matches!(err.kind(), WasmRuntimeErrorKind::NoMethod);
+        assert_matches!(err.kind(), WasmRuntimeErrorKind::UndefinedElement);
     }
 }
 
@@ -1661,18 +3718,250 @@ fn memory() {
 }
 
 #[test]
-fn global() {
+fn memory_borrow() {
     let instance =
         WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+    let memory = instance.memory(0).unwrap();
+
+    {
+        let mut guard = memory.borrow_mut().unwrap();
+        assert_eq!(guard.len(), WebAssembly::PAGE_SIZE);
+        guard[16..20].copy_from_slice(&[1, 2, 3, 4]);
+
+        assert_matches!(
+            memory.borrow(),
+            Err(WasmRuntimeErrorKind::MemoryBorrowError)
+        );
+        assert_matches!(memory.grow(1), Err(WasmRuntimeErrorKind::WouldBlock));
+    }
 
-    assert_eq!(
-        instance
-            .global("global1")
-            .unwrap()
-            .value()
-            .get_i32()
-            .unwrap(),
-        123
+    let guard = memory.borrow().unwrap();
+    assert_eq!(&guard[16..20], &[1, 2, 3, 4]);
+    let guard2 = memory.borrow().unwrap();
+    assert_eq!(guard2.len(), guard.len());
+
+    assert_matches!(
+        memory.borrow_mut(),
+        Err(WasmRuntimeErrorKind::MemoryBorrowError)
+    );
+    assert_matches!(memory.grow(1), Err(WasmRuntimeErrorKind::WouldBlock));
+    drop(guard);
+    drop(guard2);
+
+    assert_eq!(memory.grow(1).unwrap(), 1);
+    let guard = memory.borrow().unwrap();
+    assert_eq!(guard.len(), WebAssembly::PAGE_SIZE * 2);
+    assert_eq!(&guard[16..20], &[1, 2, 3, 4]);
+}
+
+#[test]
+fn memory_policy() {
+    use crate::WasmLimit;
+
+    const PAGE_SIZE: usize = WebAssembly::PAGE_SIZE;
+    let instantiate = |policy: MemoryPolicy| {
+        build_instance(
+            WasmModule::builder()
+                .options(CompileOptions::new().with_memory_policy(policy))
+                // (func (param i32 i32) local.get 0 local.get 1 i32.store)
+                .function(
+                    WasmType::from_signature("vii").unwrap(),
+                    &[0, 0x20, 0, 0x20, 1, 0x36, 0x02, 0x00, 0x0B],
+                )
+                // (func (param i32) (result i32) local.get 0 i32.load)
+                .function(
+                    WasmType::from_signature("ii").unwrap(),
+                    &[0, 0x20, 0, 0x28, 0x02, 0x00, 0x0B],
+                )
+                // (func (result i32) memory.size)
+                .function(
+                    WasmType::from_signature("iv").unwrap(),
+                    &[0, 0x3F, 0x00, 0x0B],
+                )
+                // (func (param i32 i32 i32) local.get 0 local.get 1 local.get 2 memory.fill)
+                .function(
+                    WasmType::from_signature("viii").unwrap(),
+                    &[0, 0x20, 0, 0x20, 1, 0x20, 2, 0xFC, 0x0B, 0x00, 0x0B],
+                )
+                .memory(WasmLimit::new(100, None)),
+        )
+    };
+    let call = |instance: &WasmInstance, index: usize, args: &[WasmValue]| {
+        instance
+            .function_by_index(index)
+            .unwrap()
+            .invoke(args)
+            .map(|v| v.map(|v| v.get_i32().unwrap()))
+    };
+
+    let instance = instantiate(MemoryPolicy::Eager);
+    let memory = instance.memory(0).unwrap();
+    assert_eq!(memory.committed_len().unwrap(), PAGE_SIZE * 100);
+
+    let instance = instantiate(MemoryPolicy::Lazy);
+    let memory = instance.memory(0).unwrap();
+    assert_eq!(memory.policy(), MemoryPolicy::Lazy);
+    assert_eq!(memory.size(), 100);
+    assert_eq!(memory.committed_len().unwrap(), 0);
+
+    // only the first page is allocated by the write
+    call(&instance, 0, &[16.into(), 42.into()]).unwrap();
+    assert_eq!(memory.committed_len().unwrap(), PAGE_SIZE);
+    assert_eq!(call(&instance, 1, &[16.into()]).unwrap(), Some(42));
+    assert_eq!(
+        call(&instance, 1, &[(PAGE_SIZE as i32 * 99).into()]).unwrap(),
+        Some(0)
+    );
+    assert_eq!(call(&instance, 2, &[]).unwrap(), Some(100));
+    assert_eq!(memory.committed_len().unwrap(), PAGE_SIZE);
+
+    // a load across the end of the allocated pages reads the allocated bytes
+    call(
+        &instance,
+        0,
+        &[(PAGE_SIZE as i32 - 4).into(), 0x2211_0000.into()],
+    )
+    .unwrap();
+    assert_eq!(
+        call(&instance, 1, &[(PAGE_SIZE as i32 - 2).into()]).unwrap(),
+        Some(0x2211)
+    );
+    assert_eq!(memory.committed_len().unwrap(), PAGE_SIZE);
+
+    // bounds are still checked against the logical size
+    let err = call(&instance, 1, &[(PAGE_SIZE as i32 * 100).into()]).unwrap_err();
+    let err = WasmRuntimeError::try_from_error(err).unwrap();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::OutOfBounds);
+
+    // memory.fill allocates the pages it touches
+    call(
+        &instance,
+        3,
+        &[(PAGE_SIZE as i32 * 2).into(), 7.into(), 16.into()],
+    )
+    .unwrap();
+    assert_eq!(memory.committed_len().unwrap(), PAGE_SIZE * 3);
+    assert_eq!(
+        call(&instance, 1, &[(PAGE_SIZE as i32 * 2).into()]).unwrap(),
+        Some(0x07070707)
+    );
+
+    // growing does not allocate until the host borrows the memory
+    assert_eq!(memory.grow(1).unwrap(), 100);
+    assert_eq!(memory.committed_len().unwrap(), PAGE_SIZE * 3);
+    let guard = memory.borrow().unwrap();
+    assert_eq!(guard.len(), PAGE_SIZE * 101);
+    assert_eq!(&guard[16..20], &[42, 0, 0, 0]);
+}
+
+#[test]
+fn memory_mut_grow() {
+    use crate::WasmLimit;
+
+    const PAGE_SIZE: usize = WebAssembly::PAGE_SIZE;
+    let mut instance = build_instance(
+        WasmModule::builder()
+            // (func (param i32) (result i32) local.get 0 i32.load)
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[0, 0x20, 0, 0x28, 0x02, 0x00, 0x0B],
+            )
+            // (func (result i32) memory.size)
+            .function(
+                WasmType::from_signature("iv").unwrap(),
+                &[0, 0x3F, 0x00, 0x0B],
+            )
+            .memory(WasmLimit::new(1, Some(3))),
+    );
+
+    let memory = instance.memory_mut(0).unwrap();
+    assert_eq!(memory.grow(2).unwrap(), 1);
+    assert_matches!(memory.grow(1), Err(WasmRuntimeErrorKind::InvalidParameter));
+    assert_eq!(memory.size(), 3);
+    let slice = memory.as_mut_slice().unwrap();
+    assert_eq!(slice.len(), PAGE_SIZE * 3);
+    slice[PAGE_SIZE * 2 + 4..PAGE_SIZE * 2 + 8].copy_from_slice(&1234i32.to_le_bytes());
+    assert!(instance.memory_mut(1).is_err());
+
+    let call = |index: usize, args: &[WasmValue]| {
+        instance
+            .function_by_index(index)
+            .unwrap()
+            .invoke(args)
+            .unwrap()
+            .unwrap()
+            .get_i32()
+            .unwrap()
+    };
+    assert_eq!(call(0, &[(PAGE_SIZE as i32 * 2 + 4).into()]), 1234);
+    assert_eq!(call(1, &[]), 3);
+}
+
+#[test]
+fn instance_reset() {
+    const PAGE_SIZE: usize = WebAssembly::PAGE_SIZE;
+    // (memory 1 3) (global (export "g") (mut i32) (i32.const 7)) (data (i32.const 4) "\AA\BB")
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x05, 0x04, 0x01, 0x01, 0x01, 0x03, 0x06,
+        0x06, 0x01, 0x7F, 0x01, 0x41, 0x07, 0x0B, 0x07, 0x05, 0x01, 0x01, 0x67, 0x03, 0x00, 0x0B,
+        0x08, 0x01, 0x00, 0x41, 0x04, 0x0B, 0x02, 0xAA, 0xBB,
+    ];
+    let mut instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+
+    let memory = instance.memory_mut(0).unwrap();
+    assert_eq!(memory.grow(2).unwrap(), 1);
+    let slice = memory.as_mut_slice().unwrap();
+    slice[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    slice[PAGE_SIZE * 2] = 0xCC;
+    let global = instance.global("g").unwrap();
+    global.set_raw_value(WasmValue::from(123i32).into());
+    assert_eq!(global.value().get_i32().unwrap(), 123);
+
+    instance.reset().unwrap();
+
+    let memory = instance.memory(0).unwrap();
+    assert_eq!(memory.size(), 1);
+    let slice = memory.try_borrow().unwrap();
+    assert_eq!(slice.len(), PAGE_SIZE);
+    assert_eq!(&slice[..8], &[0, 0, 0, 0, 0xAA, 0xBB, 0, 0]);
+    drop(slice);
+    assert_eq!(instance.global("g").unwrap().value().get_i32().unwrap(), 7);
+
+    // the memory can grow again after the reset
+    let memory = instance.memory_mut(0).unwrap();
+    assert_eq!(memory.grow(1).unwrap(), 1);
+    assert_eq!(memory.as_mut_slice().unwrap()[PAGE_SIZE * 2 - 1], 0);
+}
+
+#[test]
+fn memory_as_mut_slice_poisoned() {
+    use crate::WasmLimit;
+
+    let mut memory = WasmMemory::new(WasmLimit::new(1, None)).unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _guard = memory.borrow_mut().unwrap();
+        panic!("poisons the memory");
+    }));
+    assert!(result.is_err());
+    assert_matches!(
+        memory.as_mut_slice(),
+        Err(WasmRuntimeErrorKind::MemoryBorrowError)
+    );
+}
+
+#[test]
+fn global() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+
+    assert_eq!(
+        instance
+            .global("global1")
+            .unwrap()
+            .value()
+            .get_i32()
+            .unwrap(),
+        123
     );
 
     let result = instance.exports().global_add(456).unwrap();
@@ -1702,6 +3991,161 @@ fn global() {
     );
 }
 
+#[test]
+fn value_unsigned() {
+    let value = WasmValue::from(-1i32);
+    assert_eq!(value.get_i32().unwrap(), -1);
+    assert_eq!(value.get_u32().unwrap(), 0xFFFF_FFFF);
+    assert_eq!(value.as_usize().unwrap(), 0xFFFF_FFFF);
+    assert_matches!(value.get_u64(), Err(WasmRuntimeErrorKind::TypeMismatch));
+
+    let value = WasmValue::from(-2i64);
+    assert_eq!(value.get_u64().unwrap(), 0xFFFF_FFFF_FFFF_FFFE);
+    assert_matches!(value.get_u32(), Err(WasmRuntimeErrorKind::TypeMismatch));
+    assert_matches!(value.as_usize(), Err(WasmRuntimeErrorKind::TypeMismatch));
+
+    assert_eq!(WasmValue::from(0x1234u32).as_usize().unwrap(), 0x1234);
+}
+
+#[test]
+fn value_raw_pair() {
+    let value = WasmValue::from(0x1122334455667788i64);
+    let (low, high) = value.into_raw_pair().unwrap();
+    assert_eq!(low, 0x55667788);
+    assert_eq!(high, 0x11223344);
+    assert_eq!(
+        WasmValue::from_raw_pair(low, high).get_i64().unwrap(),
+        0x1122334455667788
+    );
+
+    // the low half must not be sign extended
+    let value = WasmValue::from(-0x7EDC_BA98_7654_3211i64);
+    let (low, high) = value.into_raw_pair().unwrap();
+    assert!(low < 0);
+    assert_eq!(
+        WasmValue::from_raw_pair(low, high).get_i64().unwrap(),
+        -0x7EDC_BA98_7654_3211
+    );
+
+    assert_matches!(
+        WasmValue::from(1i32).into_raw_pair(),
+        Err(WasmRuntimeErrorKind::TypeMismatch)
+    );
+}
+
+#[test]
+fn value_format() {
+    assert_eq!(WasmValue::from(42i32).to_string(), "i32:42");
+    assert_eq!(WasmValue::from(-1i32).to_string(), "i32:-1");
+    assert_eq!(
+        WasmValue::from(i64::MIN).to_string(),
+        "i64:-9223372036854775808"
+    );
+    assert_eq!(WasmValue::from(1.5f32).to_string(), "f32:1.5");
+    assert_eq!(WasmValue::from(0.1f32).to_string(), "f32:0.1");
+    assert_eq!(WasmValue::from(PI).to_string(), "f64:3.141592653589793");
+    assert_eq!(WasmValue::from(0.0f32).to_string(), "f32:0");
+    assert_eq!(WasmValue::from(-0.0f32).to_string(), "f32:-0");
+    assert_eq!(WasmValue::from(0.0f64).to_string(), "f64:0");
+    assert_eq!(WasmValue::from(-0.0f64).to_string(), "f64:-0");
+    assert_eq!(WasmValue::from(f32::INFINITY).to_string(), "f32:inf");
+    assert_eq!(WasmValue::from(f64::NEG_INFINITY).to_string(), "f64:-inf");
+    assert_eq!(
+        WasmValue::from(f32::from_bits(0x7FC0_0000)).to_string(),
+        "f32:nan"
+    );
+    assert_eq!(
+        WasmValue::from(f32::from_bits(0xFFC0_0000)).to_string(),
+        "f32:-nan"
+    );
+    assert_eq!(
+        WasmValue::from(f32::from_bits(0x7F80_0001)).to_string(),
+        "f32:nan:0x1"
+    );
+    assert_eq!(
+        WasmValue::from(f64::from_bits(0x7FF8_0000_0000_0000)).to_string(),
+        "f64:nan"
+    );
+    assert_eq!(
+        WasmValue::from(f64::from_bits(0xFFF4_0000_0000_0000)).to_string(),
+        "f64:-nan:0x4000000000000"
+    );
+
+    assert_eq!(format!("{:#}", WasmValue::from(42i32)), "i32:42");
+    assert_eq!(format!("{:#}", WasmValue::from(1.5f32)), "f32:0x3fc00000");
+    assert_eq!(
+        format!("{:#}", WasmValue::from(-0.0f64)),
+        "f64:0x8000000000000000"
+    );
+
+    assert_eq!(format!("{:?}", WasmValue::from(42i32)), "I32(42)");
+    assert_eq!(format!("{:?}", WasmValue::from(-7i64)), "I64(-7)");
+    assert_eq!(
+        format!("{:?}", WasmValue::from(-0.0f32)),
+        "F32(-0.0 [0x80000000])"
+    );
+    assert_eq!(
+        format!("{:?}", WasmValue::from(1.0f64)),
+        "F64(1.0 [0x3ff0000000000000])"
+    );
+}
+
+#[test]
+fn module_globals() {
+    // (global i32 (i32.const 42))
+    // (global (mut f64) (f64.const 1.5))
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x06, 0x12, 0x02, 0x7F, 0x00, 0x41, 0x2A,
+        0x0B, 0x7C, 0x01, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF8, 0x3F, 0x0B,
+    ];
+    let module = WebAssembly::compile(&data).unwrap();
+    let globals = module.globals();
+    assert_eq!(globals.len(), 2);
+
+    assert_eq!(globals[0].val_type(), WasmValType::I32);
+    assert!(!globals[0].is_mutable());
+    assert_eq!(globals[0].initial_value().get_i32().unwrap(), 42);
+
+    assert_eq!(globals[1].val_type(), WasmValType::F64);
+    assert!(globals[1].is_mutable());
+    assert_eq!(globals[1].initial_value().get_f64().unwrap(), 1.5);
+
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+    instance.exports().global_add(1).unwrap();
+    let global = instance.global("global1").unwrap();
+    assert_eq!(global.value().get_i32().unwrap(), 124);
+    assert_eq!(global.initial_value().get_i32().unwrap(), 123);
+}
+
+#[test]
+fn stack_pointer() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+
+    assert_eq!(instance.stack_pointer(), Some(123));
+
+    instance.set_stack_pointer(0x1_0000).unwrap();
+    assert_eq!(instance.stack_pointer(), Some(0x1_0000));
+    assert_eq!(
+        instance
+            .global("__stack_pointer")
+            .unwrap()
+            .value()
+            .get_i32()
+            .unwrap(),
+        0x1_0000
+    );
+
+    let data = [0, 97, 115, 109, 1, 0, 0, 0];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+    assert_eq!(instance.stack_pointer(), None);
+    assert_matches!(
+        instance.set_stack_pointer(0),
+        Err(WasmRuntimeErrorKind::NoMethod)
+    );
+}
+
 #[test]
 fn name() {
     let slice = [
@@ -1885,7 +4329,6 @@ fn float32_opr() {
         } else if val >= i32::MAX as f64 {
             assert_eq!(memory.read_i32(0x18), i32::MAX);
         } else {
-            assert_eq!(memory.read_i32(0x10), i32val);
             assert_eq!(memory.read_i32(0x18), i32val);
         }
 
@@ -1895,7 +4338,6 @@ fn float32_opr() {
         } else if val >= u32::MAX as f64 {
             assert_eq!(memory.read_u32(0x1C), u32::MAX);
         } else {
-            assert_eq!(memory.read_u32(0x14), u32val);
             assert_eq!(memory.read_u32(0x1C), u32val);
         }
 
@@ -1905,7 +4347,6 @@ fn float32_opr() {
         } else if val >= i64::MAX as f64 {
             assert_eq!(memory.read_i64(0x30), i64::MAX);
         } else {
-            assert_eq!(memory.read_i64(0x20), i64val);
             assert_eq!(memory.read_i64(0x30), i64val);
         }
 
@@ -1915,7 +4356,6 @@ fn float32_opr() {
         } else if val >= u64::MAX as f64 {
             assert_eq!(memory.read_u64(0x38), u64::MAX);
         } else {
-            assert_eq!(memory.read_u64(0x28), u64val);
             assert_eq!(memory.read_u64(0x38), u64val);
         }
 
@@ -2566,7 +5006,6 @@ fn float64_opr() {
         } else if val >= i32::MAX as f64 {
             assert_eq!(memory.read_i32(0x18), i32::MAX);
         } else {
-            assert_eq!(memory.read_i32(0x10), i32val);
             assert_eq!(memory.read_i32(0x18), i32val);
         }
 
@@ -2576,7 +5015,6 @@ fn float64_opr() {
         } else if val >= u32::MAX as f64 {
             assert_eq!(memory.read_u32(0x1C), u32::MAX);
         } else {
-            assert_eq!(memory.read_u32(0x14), u32val);
             assert_eq!(memory.read_u32(0x1C), u32val);
         }
 
@@ -2586,7 +5024,6 @@ fn float64_opr() {
         } else if val >= i64::MAX as f64 {
             assert_eq!(memory.read_i64(0x30), i64::MAX);
         } else {
-            assert_eq!(memory.read_i64(0x20), i64val);
             assert_eq!(memory.read_i64(0x30), i64val);
         }
 
@@ -2596,7 +5033,6 @@ fn float64_opr() {
         } else if val >= u64::MAX as f64 {
             assert_eq!(memory.read_u64(0x38), u64::MAX);
         } else {
-            assert_eq!(memory.read_u64(0x28), u64val);
             assert_eq!(memory.read_u64(0x38), u64val);
         }
 
@@ -3083,332 +5519,3437 @@ fn float64_opr() {
                 assert_eq!(test, rhs);
             }
 
-            assert_eq!(memory.read_u64(0x60), 0xCCCC_CCCC_CCCC_CCCC);
-            assert_eq!(memory.read_u64(0x68), 0xCCCC_CCCC_CCCC_CCCC);
+            assert_eq!(memory.read_u64(0x60), 0xCCCC_CCCC_CCCC_CCCC);
+            assert_eq!(memory.read_u64(0x68), 0xCCCC_CCCC_CCCC_CCCC);
+        }
+    }
+}
+
+#[test]
+fn sqrt_special() {
+    let instance = WasmInstance::empty();
+    let run = |opcode: u8, arg: WasmValue| {
+        // local.get 0 (sqrt) end
+        let slice = [0, 0x20, 0, opcode, 0x0B];
+        let param_types = [arg.val_type()];
+        let result_types = [arg.val_type()];
+        let mut stream = Leb128Reader::from_slice(&slice);
+        let info = WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &param_types,
+            &result_types,
+            instance.module(),
+        )
+        .unwrap();
+        let mut interp = WasmInterpreter::new(&instance);
+        let mut locals = [arg.into()];
+        interp
+            .invoke(0, &info, &mut locals, &result_types)
+            .unwrap()
+            .unwrap()
+    };
+    let sqrt_f32 = |v: f32| run(0x91, v.into()).get_f32().unwrap();
+    let sqrt_f64 = |v: f64| run(0x9F, v.into()).get_f64().unwrap();
+
+    assert!(sqrt_f32(-1.0).is_nan());
+    assert!(sqrt_f32(f32::NEG_INFINITY).is_nan());
+    assert!(sqrt_f32(f32::NAN).is_nan());
+    assert_eq!(sqrt_f32(-0.0).to_bits(), (-0.0f32).to_bits());
+    assert_eq!(sqrt_f32(0.0).to_bits(), 0.0f32.to_bits());
+    assert_eq!(sqrt_f32(f32::INFINITY), f32::INFINITY);
+    assert_eq!(sqrt_f32(4.0), 2.0);
+
+    assert!(sqrt_f64(-1.0).is_nan());
+    assert!(sqrt_f64(f64::NEG_INFINITY).is_nan());
+    assert!(sqrt_f64(f64::NAN).is_nan());
+    assert_eq!(sqrt_f64(-0.0).to_bits(), (-0.0f64).to_bits());
+    assert_eq!(sqrt_f64(0.0).to_bits(), 0.0f64.to_bits());
+    assert_eq!(sqrt_f64(f64::INFINITY), f64::INFINITY);
+    assert_eq!(sqrt_f64(4.0), 2.0);
+}
+
+#[test]
+fn block_nest() {
+    let slice = [0, 0x02, 0x40, 0x02, 0x40, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+
+    let slice = [0, 0x02, 0x40, 0x02, 0x40, 0x01, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
+            .unwrap_err()
+            .kind(),
+        WasmCompileErrorKind::UnexpectedEof
+    );
+
+    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &[],
+        &[WasmValType::I32],
+        instance.module(),
+    )
+    .unwrap();
+
+    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
+            .unwrap_err()
+            .kind(),
+        WasmCompileErrorKind::OutOfStack
+    );
+
+    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
+            .unwrap_err()
+            .kind(),
+        WasmCompileErrorKind::InvalidStackLevel
+    );
+
+    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &[],
+            &[WasmValType::I64],
+            instance.module()
+        )
+        .unwrap_err()
+        .kind(),
+        WasmCompileErrorKind::TypeMismatch
+    );
+
+    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x42, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &[],
+            &[WasmValType::I32],
+            instance.module()
+        )
+        .unwrap_err()
+        .kind(),
+        WasmCompileErrorKind::TypeMismatch
+    );
+
+    let slice = [0, 0x02, 0x7F, 0x02, 0x7E, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &[],
+            &[WasmValType::I32],
+            instance.module()
+        )
+        .unwrap_err()
+        .kind(),
+        WasmCompileErrorKind::TypeMismatch
+    );
+
+    let slice = [0, 0x02, 0x7E, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &[],
+            &[WasmValType::I32],
+            instance.module()
+        )
+        .unwrap_err()
+        .kind(),
+        WasmCompileErrorKind::TypeMismatch
+    );
+
+    let slice = [0, 0x02, 0x7E, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &[],
+            &[WasmValType::I64],
+            instance.module()
+        )
+        .unwrap_err()
+        .kind(),
+        WasmCompileErrorKind::TypeMismatch
+    );
+
+    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x20, 0x00, 0x0B, 0x0B, 0x0B];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &[WasmValType::I32],
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
+
+    let result = interp
+        .invoke(0, &info, &mut [123.into()], &result_types)
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 123);
+}
+
+#[test]
+fn block_test() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+
+    let result = instance.exports().block_test(1, 123, 456, 789).unwrap();
+    assert_eq!(result, 456);
+
+    let result = instance.exports().block_test(2, 123, 456, 789).unwrap();
+    assert_eq!(result, 789);
+}
+
+#[test]
+fn loop_nest() {
+    let slice = [0, 0x02, 0x40, 0x03, 0x40, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+
+    let slice = [0, 0x02, 0x40, 0x03, 0x40, 0x01, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
+            .unwrap_err()
+            .kind(),
+        WasmCompileErrorKind::UnexpectedEof
+    );
+
+    let slice = [0, 0x02, 0x7F, 0x03, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &[],
+        &[WasmValType::I32],
+        instance.module(),
+    )
+    .unwrap();
+
+    let slice = [0, 0x02, 0x7F, 0x03, 0x7F, 0x01, 0x0B, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
+            .unwrap_err()
+            .kind(),
+        WasmCompileErrorKind::OutOfStack
+    );
+}
+
+#[test]
+fn loop_test() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+
+    let result = instance.exports().loop_test(10).unwrap();
+    assert_eq!(result, 55);
+
+    let result = instance.exports().loop_test(100).unwrap();
+    assert_eq!(result, 5050);
+}
+
+#[test]
+fn if_nest() {
+    let slice = [0, 0x41, 0x01, 0x04, 0x40, 0x01, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+
+    let slice = [0, 0x41, 0x01, 0x04, 0x40, 0x01, 0x05, 0x01, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+
+    let slice = [
+        0, 0x41, 0x01, 0x04, 0x7F, 0x41, 0x01, 0x05, 0x41, 0x01, 0x0B, 0x1A, 0x0B,
+    ];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+
+    let slice = [0, 0x41, 0x01, 0x04, 0x7F, 0x41, 0x01, 0x0B, 0x1A, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
+            .unwrap_err()
+            .kind(),
+        WasmCompileErrorKind::ElseNotExists
+    );
+
+    let slice = [0, 0x05, 0x01, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    assert_matches!(
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
+            .unwrap_err()
+            .kind(),
+        WasmCompileErrorKind::ElseWithoutIf
+    );
+}
+
+#[test]
+fn if_test() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+
+    let result = instance.exports().if_test1(123, 456, true).unwrap();
+    assert_eq!(result, 123);
+
+    let result = instance
+        .exports()
+        .get("if_test1")
+        .unwrap()
+        .invoke(&[123.into(), 456.into(), 2.into()])
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 123);
+
+    let result = instance.exports().if_test1(123, 456, false).unwrap();
+    assert_eq!(result, 456);
+
+    let result = instance.exports().if_test2(123, 456, true).unwrap();
+    assert_eq!(result, 123);
+
+    let result = instance
+        .exports()
+        .get("if_test2")
+        .unwrap()
+        .invoke(&[123.into(), 456.into(), 2.into()])
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 123);
+
+    let result = instance.exports().if_test1(123, 456, false).unwrap();
+    assert_eq!(result, 456);
+}
+
+#[test]
+fn import_test() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+
+    let result = instance.exports().import_test1(123, 456).unwrap();
+    assert_eq!(result, 123 + 456);
+
+    let result = instance.exports().import_test2(987, 654).unwrap();
+    assert_eq!(result, 987 - 654);
+}
+
+#[test]
+fn function_by_index() {
+    // (import "env" "add" (func (param i32 i32) (result i32)))
+    // (func (param i32 i32) (result i32) local.get 0 local.get 1 i32.mul)
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7F, 0x7F,
+        0x01, 0x7F, 0x02, 0x0B, 0x01, 0x03, 0x65, 0x6E, 0x76, 0x03, 0x61, 0x64, 0x64, 0x00, 0x00,
+        0x03, 0x02, 0x01, 0x00, 0x0A, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6C, 0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+
+    let result = instance
+        .function_by_index(1)
+        .unwrap()
+        .invoke(&[6.into(), 7.into()])
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.get_i32().unwrap(), 42);
+
+    assert_matches!(
+        instance.function_by_index(0),
+        Err(WasmRuntimeErrorKind::ImportedFunction)
+    );
+    assert_matches!(
+        instance.function_by_index(2),
+        Err(WasmRuntimeErrorKind::NoMethod)
+    );
+}
+
+#[test]
+fn no_memory_declared() {
+    // (import "env" "str_len" (func $str_len (param i32 i32) (result i32)))
+    // (func (export "test") (param i32 i32) (result i32) local.get 0 local.get 1 call $str_len)
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7F, 0x7F,
+        0x01, 0x7F, 0x02, 0x0F, 0x01, 0x03, 0x65, 0x6E, 0x76, 0x07, 0x73, 0x74, 0x72, 0x5F, 0x6C,
+        0x65, 0x6E, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0x07, 0x08, 0x01, 0x04, 0x74, 0x65, 0x73,
+        0x74, 0x00, 0x01, 0x0A, 0x0A, 0x01, 0x08, 0x00, 0x20, 0x00, 0x20, 0x01, 0x10, 0x00, 0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+    assert_matches!(
+        instance.memory(0),
+        Err(WasmRuntimeErrorKind::NoMemoryDeclared)
+    );
+
+    let err = invoke(
+        &instance.exports().get("test").unwrap(),
+        &[0.into(), 0.into()],
+    )
+    .unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::NoMemoryDeclared);
+}
+
+#[test]
+fn exit_test() {
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+    let err = instance.exports().exit_test().unwrap_err();
+    let err = WasmRuntimeError::try_from_error(err).unwrap();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::Exit)
+}
+
+#[test]
+fn invoke_multi_default() {
+    struct Answer;
+
+    impl WasmInvocation for Answer {
+        fn invoke(&self, _params: &[WasmValue]) -> WasmDynResult {
+            Ok(Some(42.into()))
+        }
+    }
+
+    let results = Answer.invoke_multi(&[]).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].get_i32().unwrap(), 42);
+}
+
+#[test]
+fn multi_value_results() {
+    // (func $pair (export "pair") (result i32 f64) i32.const 7 f64.const 2.5)
+    // (func $pair2 (export "pair2") (result i32 f64) (local i32 i32 i32)
+    //   i32.const 8 f64.const 1.5 return)
+    // (func $sum (export "sum") (result f64) (local f64)
+    //   call $pair local.set 0 f64.convert_i32_s local.get 0 f64.add
+    //   call $pair2 local.set 0 f64.convert_i32_s f64.add local.get 0 f64.add)
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x0A, 0x02, 0x60, 0x00, 0x02, 0x7F,
+        0x7C, 0x60, 0x00, 0x01, 0x7C, 0x03, 0x04, 0x03, 0x00, 0x00, 0x01, 0x07, 0x16, 0x03, 0x04,
+        0x70, 0x61, 0x69, 0x72, 0x00, 0x00, 0x05, 0x70, 0x61, 0x69, 0x72, 0x32, 0x00, 0x01, 0x03,
+        0x73, 0x75, 0x6D, 0x00, 0x02, 0x0A, 0x36, 0x03, 0x0D, 0x00, 0x41, 0x07, 0x44, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x04, 0x40, 0x0B, 0x10, 0x01, 0x03, 0x7F, 0x41, 0x08, 0x44, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0xF8, 0x3F, 0x0F, 0x0B, 0x15, 0x01, 0x01, 0x7C, 0x10, 0x00,
+        0x21, 0x00, 0xB7, 0x20, 0x00, 0xA0, 0x10, 0x01, 0x21, 0x00, 0xB7, 0xA0, 0x20, 0x00, 0xA0,
+        0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+
+    let results = instance
+        .exports()
+        .get("pair")
+        .unwrap()
+        .invoke_multi(&[])
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].get_i32().unwrap(), 7);
+    assert_eq!(results[1].get_f64().unwrap(), 2.5);
+
+    let results = instance
+        .exports()
+        .get("pair2")
+        .unwrap()
+        .invoke_multi(&[])
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].get_i32().unwrap(), 8);
+    assert_eq!(results[1].get_f64().unwrap(), 1.5);
+
+    // invoke returns the first result
+    let result = instance.exports().get("pair").unwrap().invoke(&[]).unwrap();
+    assert_eq!(result.unwrap().get_i32().unwrap(), 7);
+
+    let results = instance
+        .exports()
+        .get("sum")
+        .unwrap()
+        .invoke_multi(&[])
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].get_f64().unwrap(), 19.0);
+}
+
+#[test]
+fn loop_result() {
+    let cases: &[(&[u8], [i32; 4])] = &[
+        // (local i32)
+        // loop (result i32)
+        //   local.get 1 local.get 0 i32.add local.set 1
+        //   local.get 0 i32.const 1 i32.sub local.tee 0
+        //   br_if 0
+        //   local.get 1
+        // end
+        (
+            &[
+                1, 1, 0x7F, 0x03, 0x7F, 0x20, 1, 0x20, 0, 0x6A, 0x21, 1, 0x20, 0, 0x41, 1, 0x6B,
+                0x22, 0, 0x0D, 0, 0x20, 1, 0x0B, 0x0B,
+            ],
+            [1, 3, 55, 5050],
+        ),
+        // loop (result i32)
+        //   local.get 0 i32.const 10 i32.mul
+        //   local.get 0 i32.const 1 i32.sub local.tee 0
+        //   br_if 0
+        // end
+        (
+            &[
+                0, 0x03, 0x7F, 0x20, 0, 0x41, 10, 0x6C, 0x20, 0, 0x41, 1, 0x6B, 0x22, 0, 0x0D, 0,
+                0x0B, 0x0B,
+            ],
+            [10, 10, 10, 10],
+        ),
+        // i32.const 100
+        // loop (result i32)
+        //   local.get 0 i32.const 1 i32.sub local.tee 0
+        //   br_if 0
+        //   local.get 0
+        // end
+        // i32.add
+        (
+            &[
+                0, 0x41, 0xE4, 0, 0x03, 0x7F, 0x20, 0, 0x41, 1, 0x6B, 0x22, 0, 0x0D, 0, 0x20, 0,
+                0x0B, 0x6A, 0x0B,
+            ],
+            [100, 100, 100, 100],
+        ),
+    ];
+    let param_types = [WasmValType::I32];
+    let result_types = [WasmValType::I32];
+    let instance = WasmInstance::empty();
+    for (slice, expected) in cases {
+        let mut stream = Leb128Reader::from_slice(slice);
+        let info = WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &param_types,
+            &result_types,
+            instance.module(),
+        )
+        .unwrap();
+        let mut interp = WasmInterpreter::new(&instance);
+        for (arg, expected) in [1, 2, 10, 100].into_iter().zip(expected) {
+            let result = interp
+                .invoke(
+                    0,
+                    &info,
+                    &[arg.into(), crate::WasmUnionValue::zero()],
+                    &result_types,
+                )
+                .unwrap()
+                .unwrap()
+                .get_i32()
+                .unwrap();
+            assert_eq!(result, *expected, "{arg}");
+        }
+    }
+}
+
+#[test]
+fn return_float() {
+    let cases: &[&[u8]] = &[
+        // f64.const 3.14
+        &[
+            0, 0x44, 0x1F, 0x85, 0xEB, 0x51, 0xB8, 0x1E, 0x09, 0x40, 0x0B,
+        ],
+        // block (result f64) f64.const 3.14 end
+        &[
+            0, 0x02, 0x7C, 0x44, 0x1F, 0x85, 0xEB, 0x51, 0xB8, 0x1E, 0x09, 0x40, 0x0B, 0x0B,
+        ],
+        // loop (result f64) f64.const 3.14 end
+        &[
+            0, 0x03, 0x7C, 0x44, 0x1F, 0x85, 0xEB, 0x51, 0xB8, 0x1E, 0x09, 0x40, 0x0B, 0x0B,
+        ],
+        // block (result f64) f64.const 3.14 br 0 end
+        &[
+            0, 0x02, 0x7C, 0x44, 0x1F, 0x85, 0xEB, 0x51, 0xB8, 0x1E, 0x09, 0x40, 0x0C, 0x00, 0x0B,
+            0x0B,
+        ],
+        // block (result f64) f64.const 3.14 return end
+        &[
+            0, 0x02, 0x7C, 0x44, 0x1F, 0x85, 0xEB, 0x51, 0xB8, 0x1E, 0x09, 0x40, 0x0F, 0x0B, 0x0B,
+        ],
+        // i32.const 1 if (result f64) f64.const 3.14 else f64.const 0 end
+        &[
+            0, 0x41, 0x01, 0x04, 0x7C, 0x44, 0x1F, 0x85, 0xEB, 0x51, 0xB8, 0x1E, 0x09, 0x40, 0x05,
+            0x44, 0, 0, 0, 0, 0, 0, 0, 0, 0x0B, 0x0B,
+        ],
+        // (local f64) block (result f64) f64.const 3.14 local.tee 0 end
+        &[
+            1, 1, 0x7C, 0x02, 0x7C, 0x44, 0x1F, 0x85, 0xEB, 0x51, 0xB8, 0x1E, 0x09, 0x40, 0x22,
+            0x00, 0x0B, 0x0B,
+        ],
+    ];
+    for slice in cases {
+        let result_types = [WasmValType::F64];
+        let mut stream = Leb128Reader::from_slice(slice);
+        let instance = WasmInstance::empty();
+        let info =
+            WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module())
+                .unwrap();
+        let mut interp = WasmInterpreter::new(&instance);
+        let mut locals = [crate::WasmUnionValue::zero(); 1];
+        let result = interp
+            .invoke(
+                0,
+                &info,
+                &mut locals[..info.local_types().len()],
+                &result_types,
+            )
+            .unwrap()
+            .unwrap()
+            .get_f64()
+            .unwrap();
+        assert_eq!(result.to_bits(), 0x4009_1EB8_51EB_851F);
+    }
+
+    // block (result f32) f32.const 3.14 return end
+    let slice = [
+        0, 0x02, 0x7D, 0x43, 0xC3, 0xF5, 0x48, 0x40, 0x0F, 0x0B, 0x0B,
+    ];
+    let result_types = [WasmValType::F32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
+    let result = interp
+        .invoke(0, &info, &mut [], &result_types)
+        .unwrap()
+        .unwrap()
+        .get_f32()
+        .unwrap();
+    assert_eq!(result.to_bits(), 0x4048_F5C3);
+}
+
+#[test]
+fn frame_layout() {
+    // (func (param i32) (result i32) (local i64) local.get 0 i32.const 1 i32.add)
+    let slice = [1, 1, 0x7E, 0x20, 0x00, 0x41, 0x01, 0x6A, 0x0B];
+    let param_types = [WasmValType::I32];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+
+    let layout = info.frame_layout();
+    assert_eq!(layout.locals_size(), 2);
+    assert_eq!(layout.value_stack_size(), 2);
+    assert_eq!(layout.slots(), 4);
+    assert_eq!(
+        layout.size_in_bytes(),
+        4 * size_of::<crate::WasmUnionValue>()
+    );
+
+    let mut interp = WasmInterpreter::new(&instance);
+    let result = interp
+        .invoke(
+            0,
+            &info,
+            &[41.into(), crate::WasmUnionValue::zero()],
+            &result_types,
+        )
+        .unwrap()
+        .unwrap()
+        .get_i32()
+        .unwrap();
+    assert_eq!(result, 42);
+    assert_matches!(
+        WasmRuntimeError::try_from_error(
+            interp
+                .invoke(0, &info, &[41.into()], &result_types)
+                .unwrap_err()
+        )
+        .unwrap()
+        .kind(),
+        WasmRuntimeErrorKind::InvalidParameter
+    );
+}
+
+#[test]
+fn generate_with_scratch() {
+    use crate::cg::WasmCodeScratch;
+
+    let instance = WasmInstance::empty();
+    let functions: [(&[u8], &[WasmValType], i32); 4] = [
+        // i32.const 42
+        (&[0, 0x41, 0x2A, 0x0B], &[], 42),
+        // (if (result i32) (local.get 0) (then i32.const 1) (else i32.const 2))
+        (
+            &[0, 0x20, 0, 0x04, 0x7F, 0x41, 1, 0x05, 0x41, 2, 0x0B, 0x0B],
+            &[WasmValType::I32],
+            1,
+        ),
+        // i64.const 0 (type mismatch)
+        (&[0, 0x42, 0, 0x0B], &[], 0),
+        // (block (result i32) (i32.const 7) (br 0)) (local i32) local.set 0 local.get 0
+        (
+            &[
+                1, 1, 0x7F, 0x02, 0x7F, 0x41, 7, 0x0C, 0, 0x0B, 0x21, 0, 0x20, 0, 0x0B,
+            ],
+            &[],
+            7,
+        ),
+    ];
+    let result_types = [WasmValType::I32];
+
+    let mut scratch = WasmCodeScratch::new();
+    for (slice, param_types, expected) in functions {
+        let fresh = WasmCodeBlock::generate(
+            0,
+            0,
+            &mut Leb128Reader::from_slice(slice),
+            param_types,
+            &result_types,
+            instance.module(),
+        );
+        let reused = WasmCodeBlock::generate_with_scratch(
+            0,
+            0,
+            &mut Leb128Reader::from_slice(slice),
+            param_types,
+            &result_types,
+            instance.module(),
+            &mut scratch,
+        );
+        let (fresh, reused) = match (fresh, reused) {
+            (Ok(fresh), Ok(reused)) => (fresh, reused),
+            (Err(fresh), Err(reused)) => {
+                assert_matches!(fresh.kind(), WasmCompileErrorKind::TypeMismatch);
+                assert_matches!(reused.kind(), WasmCompileErrorKind::TypeMismatch);
+                continue;
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            format!("{:?}", fresh.intermediate_codes()),
+            format!("{:?}", reused.intermediate_codes())
+        );
+        assert_eq!(fresh.frame_layout(), reused.frame_layout());
+
+        let mut interp = WasmInterpreter::new(&instance);
+        let mut locals = [crate::WasmUnionValue::from(1)];
+        let result = interp
+            .invoke(0, &reused, &mut locals, &result_types)
+            .unwrap()
+            .unwrap()
+            .get_i32()
+            .unwrap();
+        assert_eq!(result, expected);
+    }
+
+    let instance =
+        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+    assert_eq!(instance.exports().fib(10).unwrap(), 55);
+}
+
+#[test]
+fn funcref_local_default() {
+    use crate::ImportExportKind;
+
+    let instance = build_instance(
+        WasmModule::builder()
+            // (func (result i32) (local funcref) local.get 0 ref.is_null)
+            .function(
+                WasmType::from_signature("iv").unwrap(),
+                &[1, 1, 0x70, 0x20, 0, 0xD1, 0x0B],
+            )
+            // (func (result i32) i32.const 0 i32.const 0 i32.add drop call 0)
+            .function(
+                WasmType::from_signature("iv").unwrap(),
+                &[0, 0x41, 0, 0x41, 0, 0x6A, 0x1A, 0x10, 0, 0x0B],
+            )
+            .export("direct", ImportExportKind::Function, 0)
+            .export("nested", ImportExportKind::Function, 1),
+    );
+
+    for name in ["direct", "nested"] {
+        let result = instance.function(name).unwrap().invoke(&[]).unwrap();
+        assert_eq!(result.unwrap().get_i32().unwrap(), 1, "{name}");
+    }
+}
+
+#[test]
+fn table_set_call_indirect() {
+    // (type $t (func (result i32)))
+    // (table 1 funcref)
+    // (func $answer (type $t) i32.const 42)
+    // (func $set (type $t) (table.set (i32.const 0) (ref.func $answer)) (call_indirect (type $t) (i32.const 0)))
+    // (func $call (type $t) (call_indirect (type $t) (i32.const 0)))
+    // (func $is_null (type $t) (ref.is_null (table.get (i32.const 0))))
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7F,
+        0x03, 0x05, 0x04, 0x00, 0x00, 0x00, 0x00, 0x04, 0x04, 0x01, 0x70, 0x00, 0x01, 0x0A, 0x24,
+        0x04, 0x04, 0x00, 0x41, 0x2A, 0x0B, 0x0D, 0x00, 0x41, 0x00, 0xD2, 0x00, 0x26, 0x00, 0x41,
+        0x00, 0x11, 0x00, 0x00, 0x0B, 0x07, 0x00, 0x41, 0x00, 0x11, 0x00, 0x00, 0x0B, 0x07, 0x00,
+        0x41, 0x00, 0x25, 0x00, 0xD1, 0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+    let call = |index: usize| instance.function_by_index(index).unwrap().invoke(&[]);
+
+    assert_eq!(call(3).unwrap().unwrap().get_i32().unwrap(), 1);
+    let err = WasmRuntimeError::try_from_error(call(2).unwrap_err()).unwrap();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::UninitializedElement);
+
+    assert_eq!(call(1).unwrap().unwrap().get_i32().unwrap(), 42);
+    assert_eq!(call(3).unwrap().unwrap().get_i32().unwrap(), 0);
+    assert_eq!(call(2).unwrap().unwrap().get_i32().unwrap(), 42);
+    assert_eq!(instance.module().tables()[0].get(0).unwrap(), Some(0));
+    assert_matches!(
+        instance.module().tables()[0].get(1),
+        Err(WasmRuntimeErrorKind::OutOfBounds)
+    );
+}
+
+#[test]
+fn call_indirect_table_type() {
+    // (type $t (func)) (table 1 <elem_type>) (func (call_indirect (type $t) (i32.const 0)))
+    let data = |elem_type: u8| {
+        [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x04, 0x04, 0x01, elem_type, 0x00, 0x01, 0x0A, 0x09, 0x01,
+            0x07, 0x00, 0x41, 0x00, 0x11, 0x00, 0x00, 0x0B,
+        ]
+    };
+
+    let module = WebAssembly::compile(&data(0x70)).unwrap();
+    assert_eq!(module.tables()[0].elem_type(), WasmRefType::FuncRef);
+
+    // externref
+    let err = WebAssembly::compile(&data(0x6F)).unwrap_err();
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(&err).unwrap(),
+        WasmCompileErrorKind::TypeMismatch
+    );
+}
+
+#[test]
+fn trap_kinds() {
+    use crate::WasmLimit;
+
+    let trap = |body: &[u8], params: &[WasmValue], result: WasmValType| {
+        let param_types = params.iter().map(|v| v.val_type()).collect::<Vec<_>>();
+        let err = WebAssembly::eval_expr(body, &param_types, &[result], params).unwrap_err();
+        let err = WasmRuntimeError::try_from_error(err).unwrap();
+        err.kind().trap_message()
+    };
+    let i32x2 = |a: i32, b: i32| [WasmValue::from(a), WasmValue::from(b)];
+    let i64x2 = |a: i64, b: i64| [WasmValue::from(a), WasmValue::from(b)];
+    let i32 = WasmValType::I32;
+    let i64 = WasmValType::I64;
+
+    // unreachable
+    assert_eq!(trap(&[0, 0x00, 0x0B], &[], i32), Some("unreachable"));
+
+    // local.get 0 local.get 1 (op)
+    let bin_op = |opcode: u8| [0, 0x20, 0, 0x20, 1, opcode, 0x0B];
+    for opcode in [0x6D, 0x6E, 0x6F, 0x70] {
+        assert_eq!(
+            trap(&bin_op(opcode), &i32x2(1, 0), i32),
+            Some("integer divide by zero"),
+            "{opcode:02x}"
+        );
+    }
+    for opcode in [0x7F, 0x80, 0x81, 0x82] {
+        assert_eq!(
+            trap(&bin_op(opcode), &i64x2(1, 0), i64),
+            Some("integer divide by zero"),
+            "{opcode:02x}"
+        );
+    }
+    assert_eq!(
+        trap(&bin_op(0x6D), &i32x2(i32::MIN, -1), i32),
+        Some("integer overflow")
+    );
+    assert_eq!(
+        trap(&bin_op(0x7F), &i64x2(i64::MIN, -1), i64),
+        Some("integer overflow")
+    );
+    // the remainder of MIN / -1 is defined
+    let result = WebAssembly::eval_expr(&bin_op(0x6F), &[i32, i32], &[i32], &i32x2(i32::MIN, -1));
+    assert_eq!(result.unwrap()[0].get_i32().unwrap(), 0);
+
+    // local.get 0 (op)
+    let unary = |opcode: u8| [0, 0x20, 0, opcode, 0x0B];
+    assert_eq!(
+        trap(&unary(0xA8), &[f32::NAN.into()], i32),
+        Some("invalid conversion to integer")
+    );
+    assert_eq!(
+        trap(&unary(0xA8), &[3e9f32.into()], i32),
+        Some("integer overflow")
+    );
+    assert_eq!(
+        trap(&unary(0xB1), &[(-1.0f64).into()], i64),
+        Some("integer overflow")
+    );
+
+    // (func (param i32) (result i32) local.get 0 i32.load)
+    let instance = build_instance(
+        WasmModule::builder()
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[0, 0x20, 0, 0x28, 2, 0, 0x0B],
+            )
+            .memory(WasmLimit::new(1, None)),
+    );
+    let err = invoke(&instance.function_by_index(0).unwrap(), &[0x10000.into()]).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::OutOfBounds);
+    assert_eq!(
+        err.kind().trap_message(),
+        Some("out of bounds memory access")
+    );
+
+    // (type $t0 (func (param i32) (result i32))) (type $t1 (func (result i32)))
+    // (table 3 funcref) (elem (i32.const 0) 1 2)
+    // (func (export "call") (type $t0) local.get 0 call_indirect (type $t1))
+    // (func (type $t1) i32.const 7)
+    // (func (type $t0) local.get 0)
+    let bytes = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x0A, 0x02, 0x60, 0x01, 0x7F, 0x01,
+        0x7F, 0x60, 0x00, 0x01, 0x7F, 0x03, 0x04, 0x03, 0x00, 0x01, 0x00, 0x04, 0x04, 0x01, 0x70,
+        0x00, 0x03, 0x07, 0x08, 0x01, 0x04, 0x63, 0x61, 0x6C, 0x6C, 0x00, 0x00, 0x09, 0x08, 0x01,
+        0x00, 0x41, 0x00, 0x0B, 0x02, 0x01, 0x02, 0x0A, 0x13, 0x03, 0x07, 0x00, 0x20, 0x00, 0x11,
+        0x01, 0x00, 0x0B, 0x04, 0x00, 0x41, 0x07, 0x0B, 0x04, 0x00, 0x20, 0x00, 0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&bytes, &Env {}).unwrap();
+    let call = |index: i32| {
+        invoke(&instance.function("call").unwrap(), &[index.into()])
+            .map(|v| v.unwrap().get_i32().unwrap())
+    };
+    assert_eq!(call(0).unwrap(), 7);
+
+    let err = call(1).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::IndirectCallTypeMismatch);
+    assert_eq!(
+        err.kind().trap_message(),
+        Some("indirect call type mismatch")
+    );
+    assert_eq!(err.mnemonic(), WasmMnemonic::CallIndirect);
+
+    let err = call(2).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::UninitializedElement);
+    assert_eq!(err.kind().trap_message(), Some("uninitialized element"));
+
+    for index in [3, -1] {
+        let err = call(index).unwrap_err();
+        assert_matches!(err.kind(), WasmRuntimeErrorKind::UndefinedElement);
+        assert_eq!(err.kind().trap_message(), Some("undefined element"));
+    }
+
+    // (table 1 funcref)
+    // (func (export "get") (param i32) (result i32) local.get 0 table.get 0 ref.is_null)
+    // (func (export "set") (param i32) local.get 0 ref.null func table.set 0)
+    let bytes = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x0A, 0x02, 0x60, 0x01, 0x7F, 0x01,
+        0x7F, 0x60, 0x01, 0x7F, 0x00, 0x03, 0x03, 0x02, 0x00, 0x01, 0x04, 0x04, 0x01, 0x70, 0x00,
+        0x01, 0x07, 0x0D, 0x02, 0x03, 0x67, 0x65, 0x74, 0x00, 0x00, 0x03, 0x73, 0x65, 0x74, 0x00,
+        0x01, 0x0A, 0x12, 0x02, 0x07, 0x00, 0x20, 0x00, 0x25, 0x00, 0xD1, 0x0B, 0x08, 0x00, 0x20,
+        0x00, 0xD0, 0x70, 0x26, 0x00, 0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&bytes, &Env {}).unwrap();
+    for name in ["get", "set"] {
+        let function = instance.function(name).unwrap();
+        invoke(&function, &[0.into()]).unwrap();
+        let err = invoke(&function, &[1.into()]).unwrap_err();
+        assert_matches!(err.kind(), WasmRuntimeErrorKind::TableOutOfBounds);
+        assert_eq!(
+            err.kind().trap_message(),
+            Some("out of bounds table access")
+        );
+    }
+
+    // errors that are not traps have no message
+    assert_eq!(WasmRuntimeErrorKind::NoMethod.trap_message(), None);
+    assert_eq!(WasmRuntimeErrorKind::Exit.trap_message(), None);
+}
+
+#[test]
+fn host_call_indirect() {
+    // (type $t (func (param i32) (result i32))) (table 3 funcref) (elem (i32.const 0) 0 1)
+    // (func (type $t) local.get 0 i32.const 1 i32.add)
+    // (func (type $t) local.get 0 i32.const 2 i32.mul)
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x06, 0x01, 0x60, 0x01, 0x7F, 0x01,
+        0x7F, 0x03, 0x03, 0x02, 0x00, 0x00, 0x04, 0x04, 0x01, 0x70, 0x00, 0x03, 0x09, 0x08, 0x01,
+        0x00, 0x41, 0x00, 0x0B, 0x02, 0x00, 0x01, 0x0A, 0x11, 0x02, 0x07, 0x00, 0x20, 0x00, 0x41,
+        0x01, 0x6A, 0x0B, 0x07, 0x00, 0x20, 0x00, 0x41, 0x02, 0x6C, 0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+    let call = |table_index: usize, elem_index: usize, arg: WasmValue| {
+        instance
+            .call_indirect(table_index, elem_index, &[arg])
+            .map(|v| v.unwrap().get_i32().unwrap())
+            .map_err(|e| WasmRuntimeError::try_from_error(e).unwrap())
+    };
+
+    assert_eq!(call(0, 0, 41.into()).unwrap(), 42);
+    assert_eq!(call(0, 1, 21.into()).unwrap(), 42);
+
+    // null and out of bounds elements, and a missing table
+    assert_matches!(
+        call(0, 2, 1.into()).unwrap_err().kind(),
+        WasmRuntimeErrorKind::UninitializedElement
+    );
+    assert_matches!(
+        call(0, 3, 1.into()).unwrap_err().kind(),
+        WasmRuntimeErrorKind::UndefinedElement
+    );
+    assert_matches!(
+        call(1, 0, 1.into()).unwrap_err().kind(),
+        WasmRuntimeErrorKind::UndefinedElement
+    );
+    // the arguments must match the signature of the handler
+    assert_matches!(
+        call(0, 0, 1i64.into()).unwrap_err().kind(),
+        WasmRuntimeErrorKind::IndirectCallTypeMismatch
+    );
+    assert_matches!(
+        instance
+            .call_indirect(0, 0, &[])
+            .map_err(|e| WasmRuntimeError::try_from_error(e).unwrap())
+            .unwrap_err()
+            .kind(),
+        WasmRuntimeErrorKind::IndirectCallTypeMismatch
+    );
+}
+
+#[test]
+fn segment_bounds() {
+    use crate::WasmLimit;
+
+    const PAGE_SIZE: usize = WebAssembly::PAGE_SIZE;
+    let compile_err = |data: &[u8]| {
+        WasmCompileErrorKind::downcast_ref(&WebAssembly::compile(data).unwrap_err())
+            .unwrap()
+            .clone()
+    };
+
+    // (memory 1) (data (i32.const <offset>) <data>)
+    let data_module = |offset: &[u8], data: &[u8]| {
+        let mut bytes = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01,
+        ];
+        bytes.extend_from_slice(&[
+            0x0B,
+            (offset.len() + data.len() + 5) as u8,
+            0x01,
+            0x00,
+            0x41,
+        ]);
+        bytes.extend_from_slice(offset);
+        bytes.extend_from_slice(&[0x0B, data.len() as u8]);
+        bytes.extend_from_slice(data);
+        bytes
+    };
+    // 65534, 65535 and 65536
+    let (before_end, last, end) = ([0xFE, 0xFF, 0x03], [0xFF, 0xFF, 0x03], [0x80, 0x80, 0x04]);
+
+    WebAssembly::compile(&data_module(&before_end, &[0xAA, 0xBB])).unwrap();
+    assert_matches!(
+        compile_err(&data_module(&last, &[0xAA, 0xBB])),
+        WasmCompileErrorKind::InvalidData
+    );
+    // a zero-length segment at the end of the memory is valid
+    WebAssembly::compile(&data_module(&end, &[])).unwrap();
+    assert_matches!(
+        compile_err(&data_module(&end, &[0xAA])),
+        WasmCompileErrorKind::InvalidData
+    );
+
+    // (table 2 funcref) (func) (elem (i32.const <offset>) <elements>)
+    let elem_module = |offset: u8, elements: &[u8]| {
+        let mut bytes = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x04, 0x04, 0x01, 0x70, 0x00, 0x02,
+        ];
+        bytes.extend_from_slice(&[0x09, elements.len() as u8 + 6, 0x01, 0x00, 0x41, offset]);
+        bytes.extend_from_slice(&[0x0B, elements.len() as u8]);
+        bytes.extend_from_slice(elements);
+        bytes.extend_from_slice(&[0x0A, 0x04, 0x01, 0x02, 0x00, 0x0B]);
+        bytes
+    };
+
+    let module = WebAssembly::compile(&elem_module(0, &[0, 0])).unwrap();
+    assert_eq!(module.tables()[0].get(1).unwrap(), Some(0));
+    WebAssembly::compile(&elem_module(2, &[])).unwrap();
+    assert_matches!(
+        compile_err(&elem_module(1, &[0, 0])),
+        WasmCompileErrorKind::InvalidData
+    );
+    assert_matches!(
+        compile_err(&elem_module(3, &[])),
+        WasmCompileErrorKind::InvalidData
+    );
+
+    // nothing is written by an out of bounds write
+    let memory = WasmMemory::new(WasmLimit::new(1, None)).unwrap();
+    assert_matches!(
+        memory.write_slice(PAGE_SIZE - 1, &[0xAA, 0xBB]),
+        Err(WasmRuntimeErrorKind::OutOfBounds)
+    );
+    assert_eq!(memory.try_borrow().unwrap()[PAGE_SIZE - 1], 0);
+    memory.write_slice(PAGE_SIZE, &[]).unwrap();
+}
+
+#[test]
+fn data_count() {
+    // (memory 1) (func <code>) (data (i32.const 0) "\AA") with an optional data count section
+    let module = |data_count: Option<u8>, code: &[u8], has_data: bool| {
+        let mut data = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01,
+        ];
+        if let Some(count) = data_count {
+            data.extend_from_slice(&[0x0C, 0x01, count]);
+        }
+        data.extend_from_slice(&[0x0A, code.len() as u8 + 4, 0x01, code.len() as u8 + 2, 0x00]);
+        data.extend_from_slice(code);
+        data.push(0x0B);
+        if has_data {
+            data.extend_from_slice(&[0x0B, 0x07, 0x01, 0x00, 0x41, 0x00, 0x0B, 0x01, 0xAA]);
+        }
+        data
+    };
+    let compile_err = |data: &[u8]| {
+        WasmCompileErrorKind::downcast_ref(&WebAssembly::compile(data).unwrap_err())
+            .unwrap()
+            .clone()
+    };
+
+    WebAssembly::compile(&module(None, &[], true)).unwrap();
+    WebAssembly::compile(&module(Some(1), &[], true)).unwrap();
+    WebAssembly::compile(&module(Some(0), &[], false)).unwrap();
+
+    // the count does not match the data section
+    assert_matches!(
+        compile_err(&module(Some(2), &[], true)),
+        WasmCompileErrorKind::InvalidData
+    );
+    assert_matches!(
+        compile_err(&module(Some(0), &[], true)),
+        WasmCompileErrorKind::InvalidData
+    );
+    assert_matches!(
+        compile_err(&module(Some(1), &[], false)),
+        WasmCompileErrorKind::InvalidData
+    );
+
+    // memory.init and data.drop require the data count section
+    // i32.const 0 i32.const 0 i32.const 1 memory.init 0
+    let memory_init = [0x41, 0x00, 0x41, 0x00, 0x41, 0x01, 0xFC, 0x08, 0x00, 0x00];
+    // data.drop 0
+    let data_drop = [0xFC, 0x09, 0x00];
+    for code in [memory_init.as_slice(), data_drop.as_slice()] {
+        assert_matches!(
+            compile_err(&module(None, code, true)),
+            WasmCompileErrorKind::InvalidData
+        );
+        WebAssembly::compile(&module(Some(1), code, true)).unwrap();
+    }
+}
+
+#[test]
+fn memory_init_memory_index() {
+    // (memory 1) (memory 1) (data "\AA\BB\CC")
+    // (func (export "init") (param i32 i32 i32) local.get 0 local.get 1 local.get 2 memory.init 0 1)
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x03, 0x7F, 0x7F,
+        0x7F, 0x00, 0x03, 0x02, 0x01, 0x00, 0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x01, 0x07, 0x08,
+        0x01, 0x04, 0x69, 0x6E, 0x69, 0x74, 0x00, 0x00, 0x0C, 0x01, 0x01, 0x0A, 0x0E, 0x01, 0x0C,
+        0x00, 0x20, 0x00, 0x20, 0x01, 0x20, 0x02, 0xFC, 0x08, 0x00, 0x01, 0x0B, 0x0B, 0x06, 0x01,
+        0x01, 0x03, 0xAA, 0xBB, 0xCC,
+    ];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+    let init = |dest: i32, src: i32, count: i32| {
+        invoke(
+            &instance.exports().get("init").unwrap(),
+            &[dest.into(), src.into(), count.into()],
+        )
+    };
+    let memory = |index: usize, range: core::ops::Range<usize>| {
+        instance.memory(index).unwrap().try_borrow().unwrap()[range].to_vec()
+    };
+
+    init(4, 0, 3).unwrap();
+    assert_eq!(memory(1, 4..7), [0xAA, 0xBB, 0xCC]);
+    // memory 0 is not affected
+    assert_eq!(memory(0, 4..7), [0, 0, 0]);
+
+    // the bounds are those of memory 1
+    assert_matches!(
+        init(WebAssembly::PAGE_SIZE as i32 - 1, 0, 2)
+            .unwrap_err()
+            .kind(),
+        WasmRuntimeErrorKind::OutOfBounds
+    );
+}
+
+#[test]
+fn memory_ops_memory_index() {
+    // (memory 1) (memory 3)
+    // (func (export "size1") (result i32) memory.size 1)
+    // (func (export "grow1") (param i32) (result i32) local.get 0 memory.grow 1)
+    // (func (export "fill1") (param i32 i32 i32) local.get 0 local.get 1 local.get 2 memory.fill 1)
+    // (func (export "copy01") (param i32 i32 i32) local.get 0 local.get 1 local.get 2 memory.copy 0 1)
+    // (func (export "copy10") (param i32 i32 i32) local.get 0 local.get 1 local.get 2 memory.copy 1 0)
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x10, 0x03, 0x60, 0x00, 0x01, 0x7F,
+        0x60, 0x01, 0x7F, 0x01, 0x7F, 0x60, 0x03, 0x7F, 0x7F, 0x7F, 0x00, 0x03, 0x06, 0x05, 0x00,
+        0x01, 0x02, 0x02, 0x02, 0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x03, 0x07, 0x2B, 0x05, 0x05,
+        0x73, 0x69, 0x7A, 0x65, 0x31, 0x00, 0x00, 0x05, 0x67, 0x72, 0x6F, 0x77, 0x31, 0x00, 0x01,
+        0x05, 0x66, 0x69, 0x6C, 0x6C, 0x31, 0x00, 0x02, 0x06, 0x63, 0x6F, 0x70, 0x79, 0x30, 0x31,
+        0x00, 0x03, 0x06, 0x63, 0x6F, 0x70, 0x79, 0x31, 0x30, 0x00, 0x04, 0x0A, 0x33, 0x05, 0x04,
+        0x00, 0x3F, 0x01, 0x0B, 0x06, 0x00, 0x20, 0x00, 0x40, 0x01, 0x0B, 0x0B, 0x00, 0x20, 0x00,
+        0x20, 0x01, 0x20, 0x02, 0xFC, 0x0B, 0x01, 0x0B, 0x0C, 0x00, 0x20, 0x00, 0x20, 0x01, 0x20,
+        0x02, 0xFC, 0x0A, 0x00, 0x01, 0x0B, 0x0C, 0x00, 0x20, 0x00, 0x20, 0x01, 0x20, 0x02, 0xFC,
+        0x0A, 0x01, 0x00, 0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+    let call =
+        |name: &str, args: &[WasmValue]| invoke(&instance.exports().get(name).unwrap(), args);
+    let op3 = |name: &str, a: i32, b: i32, c: i32| call(name, &[a.into(), b.into(), c.into()]);
+    let memory = |index: usize, range: core::ops::Range<usize>| {
+        instance.memory(index).unwrap().try_borrow().unwrap()[range].to_vec()
+    };
+
+    assert_eq!(call("size1", &[]).unwrap().unwrap().get_i32().unwrap(), 3);
+    assert_eq!(
+        call("grow1", &[1.into()])
+            .unwrap()
+            .unwrap()
+            .get_i32()
+            .unwrap(),
+        3
+    );
+    assert_eq!(call("size1", &[]).unwrap().unwrap().get_i32().unwrap(), 4);
+    assert_eq!(instance.memory(0).unwrap().size(), 1);
+    assert_eq!(instance.memory(1).unwrap().size(), 4);
+
+    // the bounds are those of memory 1
+    let last_page = 3 * WebAssembly::PAGE_SIZE as i32;
+    op3("fill1", last_page + 4, 0xAB, 4).unwrap();
+    assert_eq!(
+        memory(1, last_page as usize + 4..last_page as usize + 8),
+        [0xAB; 4]
+    );
+    op3("fill1", 4, 0xCD, 4).unwrap();
+    assert_eq!(memory(1, 4..8), [0xCD; 4]);
+    assert_eq!(memory(0, 4..8), [0; 4]);
+
+    // memory 1 -> memory 0
+    op3("copy01", 16, 4, 4).unwrap();
+    assert_eq!(memory(0, 16..20), [0xCD; 4]);
+    assert_eq!(memory(1, 16..20), [0; 4]);
+    assert_matches!(
+        op3("copy01", WebAssembly::PAGE_SIZE as i32, last_page + 4, 4)
+            .unwrap_err()
+            .kind(),
+        WasmRuntimeErrorKind::OutOfBounds
+    );
+
+    // memory 0 -> memory 1
+    op3("copy10", last_page + 32, 16, 4).unwrap();
+    assert_eq!(
+        memory(1, last_page as usize + 32..last_page as usize + 36),
+        [0xCD; 4]
+    );
+    op3("copy10", 2 * WebAssembly::PAGE_SIZE as i32, 0, 4).unwrap();
+    assert_matches!(
+        op3("copy10", 0, WebAssembly::PAGE_SIZE as i32 - 2, 4)
+            .unwrap_err()
+            .kind(),
+        WasmRuntimeErrorKind::OutOfBounds
+    );
+}
+
+#[test]
+fn memory_init_dropped() {
+    // (memory 1) (data "\AA\BB\CC")
+    // (func (export "init") (param i32 i32 i32) local.get 0 local.get 1 local.get 2 memory.init 0)
+    // (func (export "drop") data.drop 0)
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x0A, 0x02, 0x60, 0x03, 0x7F, 0x7F,
+        0x7F, 0x00, 0x60, 0x00, 0x00, 0x03, 0x03, 0x02, 0x00, 0x01, 0x05, 0x03, 0x01, 0x00, 0x01,
+        0x07, 0x0F, 0x02, 0x04, 0x69, 0x6E, 0x69, 0x74, 0x00, 0x00, 0x04, 0x64, 0x72, 0x6F, 0x70,
+        0x00, 0x01, 0x0C, 0x01, 0x01, 0x0A, 0x14, 0x02, 0x0C, 0x00, 0x20, 0x00, 0x20, 0x01, 0x20,
+        0x02, 0xFC, 0x08, 0x00, 0x00, 0x0B, 0x05, 0x00, 0xFC, 0x09, 0x00, 0x0B, 0x0B, 0x06, 0x01,
+        0x01, 0x03, 0xAA, 0xBB, 0xCC,
+    ];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+    let init = |dest: i32, src: i32, count: i32| {
+        invoke(
+            &instance.exports().get("init").unwrap(),
+            &[dest.into(), src.into(), count.into()],
+        )
+    };
+    let memory = |range: core::ops::Range<usize>| {
+        instance.memory(0).unwrap().try_borrow().unwrap()[range].to_vec()
+    };
+
+    // passive segments are not applied at instantiation
+    assert_eq!(memory(0..4), [0, 0, 0, 0]);
+    init(1, 1, 2).unwrap();
+    assert_eq!(memory(0..4), [0, 0xBB, 0xCC, 0]);
+    assert_matches!(
+        init(0, 2, 2).unwrap_err().kind(),
+        WasmRuntimeErrorKind::OutOfBounds
+    );
+    assert_matches!(
+        init(WebAssembly::PAGE_SIZE as i32 - 1, 0, 2)
+            .unwrap_err()
+            .kind(),
+        WasmRuntimeErrorKind::OutOfBounds
+    );
+
+    instance.exports().get("drop").unwrap().invoke(&[]).unwrap();
+    // a dropped segment traps unless nothing is copied
+    assert_matches!(
+        init(8, 0, 1).unwrap_err().kind(),
+        WasmRuntimeErrorKind::OutOfBounds
+    );
+    init(8, 0, 0).unwrap();
+    assert_eq!(memory(8..9), [0]);
+    // dropping again is allowed
+    instance.exports().get("drop").unwrap().invoke(&[]).unwrap();
+
+    // reset makes the passive segment available again
+    instance.reset().unwrap();
+    init(8, 0, 3).unwrap();
+    assert_eq!(memory(0..12), [0, 0, 0, 0, 0, 0, 0, 0, 0xAA, 0xBB, 0xCC, 0]);
+
+    // unless the dropped segments are kept dropped
+    instance.exports().get("drop").unwrap().invoke(&[]).unwrap();
+    instance.reset_with(true).unwrap();
+    assert_matches!(
+        init(8, 0, 1).unwrap_err().kind(),
+        WasmRuntimeErrorKind::OutOfBounds
+    );
+    assert_eq!(memory(8..12), [0, 0, 0, 0]);
+}
+
+#[test]
+fn instance_reset_imported_global() {
+    struct GlobalEnv;
+
+    impl WasmEnv for GlobalEnv {
+        fn resolve_imports(
+            &self,
+            _mod_name: &str,
+            _name: &str,
+            _type: &WasmType,
+        ) -> WasmImportResult {
+            WasmImportResult::NoMethod
+        }
+
+        fn resolve_global(
+            &self,
+            _mod_name: &str,
+            _name: &str,
+            _val_type: WasmValType,
+            _is_mutable: bool,
+        ) -> Option<WasmValue> {
+            Some(WasmValue::I32(5))
+        }
+    }
+
+    // (import "env" "g" (global (mut i32))) (global (mut i32) (i32.const 7))
+    // (export "g" (global 0)) (export "own" (global 1))
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x02, 0x0A, 0x01, 0x03, 0x65, 0x6E, 0x76,
+        0x01, 0x67, 0x03, 0x7F, 0x01, 0x06, 0x06, 0x01, 0x7F, 0x01, 0x41, 0x07, 0x0B, 0x07, 0x0B,
+        0x02, 0x01, 0x67, 0x03, 0x00, 0x03, 0x6F, 0x77, 0x6E, 0x03, 0x01,
+    ];
+    let instance = WebAssembly::instantiate(&data, &GlobalEnv).unwrap();
+    let value = |name: &str| instance.global(name).unwrap().value().get_i32().unwrap();
+    assert_eq!(value("g"), 5);
+
+    instance
+        .global("g")
+        .unwrap()
+        .set_raw_value(WasmValue::from(99i32).into());
+    instance
+        .global("own")
+        .unwrap()
+        .set_raw_value(WasmValue::from(123i32).into());
+    instance.reset().unwrap();
+
+    // the imported global belongs to the host
+    assert_eq!(value("g"), 99);
+    assert_eq!(value("own"), 7);
+}
+
+#[test]
+fn scan_opcodes() {
+    use crate::opcode::WasmMnemonic;
+
+    // (memory 1) (func i32.const 0 i32.const 0 i32.const 0 memory.copy f64.const 0 f64.sqrt drop)
+    let code = [
+        0x00, 0x41, 0x00, 0x41, 0x00, 0x41, 0x00, 0xFC, 0x0A, 0x00, 0x00, 0x44, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x9F, 0x1A, 0x0B,
+    ];
+    let module = |code: &[u8]| {
+        let mut data = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01,
+        ];
+        data.extend_from_slice(&[0x0A, code.len() as u8 + 2, 0x01, code.len() as u8]);
+        data.extend_from_slice(code);
+        data
+    };
+
+    let data = module(&code);
+    WebAssembly::compile(&data).unwrap();
+    let opcodes = WebAssembly::scan_opcodes(&data).unwrap();
+    assert_eq!(
+        opcodes.into_iter().collect::<Vec<_>>(),
+        [
+            WasmMnemonic::End,
+            WasmMnemonic::Drop,
+            WasmMnemonic::I32Const,
+            WasmMnemonic::F64Const,
+            WasmMnemonic::F64Sqrt,
+            WasmMnemonic::MemoryCopy,
+        ]
+    );
+
+    // i32.const 1 i32.const 2 i32.const 0 select (result i32) drop
+    let select = [
+        0x00, 0x41, 0x01, 0x41, 0x02, 0x41, 0x00, 0x1C, 0x01, 0x7F, 0x1A, 0x0B,
+    ];
+    let opcodes = WebAssembly::scan_opcodes(&module(&select)).unwrap();
+    assert_eq!(
+        opcodes.into_iter().collect::<Vec<_>>(),
+        [
+            WasmMnemonic::End,
+            WasmMnemonic::Drop,
+            WasmMnemonic::SelectT,
+            WasmMnemonic::I32Const,
+        ]
+    );
+
+    // unsupported opcodes are reported without compiling the module
+    let simd = [0x00, 0xFD, 0x0C, 0x0B];
+    assert_matches!(
+        WebAssembly::scan_opcodes(&module(&simd)),
+        Err(WasmCompileErrorKind::InvalidBytecode(0xFD))
+    );
+    assert_matches!(
+        WebAssembly::scan_opcodes(b"\0asm"),
+        Err(WasmCompileErrorKind::BadExecutable)
+    );
+}
+
+#[test]
+fn compile_error_range() {
+    // (func <code>)
+    let module = |code: &[u8]| {
+        let mut data = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0A,
+        ];
+        data.extend_from_slice(&[code.len() as u8 + 4, 0x01, code.len() as u8 + 2, 0x00]);
+        data.extend_from_slice(code);
+        data.push(0x0B);
+        data
+    };
+    let compile_err = |data: &[u8]| {
+        WasmCompileError::downcast_clone(&WebAssembly::compile(data).unwrap_err()).unwrap()
+    };
+    // the code starts after the header, the sections and the body header
+    const CODE: usize = 23;
+
+    // nop, i32.const with a 6-byte operand, drop
+    let err = compile_err(&module(&[
+        0x01, 0x41, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00, 0x1A,
+    ]));
+    assert_eq!(err.kind(), &WasmCompileErrorKind::InvalidData);
+    assert_eq!(err.section(), Some(WasmSectionId::Code));
+    assert_eq!(err.byte_range(), CODE + 1..CODE + 8);
+
+    // i64.const stopping in the middle of an operand that is too long to decode
+    let mut code = vec![0x42];
+    code.extend_from_slice(&[0x80; 11]);
+    code.extend_from_slice(&[0x00, 0x1A]);
+    let err = compile_err(&module(&code));
+    assert_eq!(err.kind(), &WasmCompileErrorKind::InvalidData);
+    assert_eq!(err.byte_range(), CODE..CODE + 13);
+
+    // i32.const 1, i64.const 1, i32.add
+    let err = compile_err(&module(&[0x41, 0x01, 0x42, 0x01, 0x6A, 0x1A]));
+    assert_eq!(err.kind(), &WasmCompileErrorKind::TypeMismatch);
+    assert_eq!(err.byte_range(), CODE + 4..CODE + 5);
+
+    // (global i32 (i32.const <6-byte operand>))
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x06, 0x0B, 0x01, 0x7F, 0x00, 0x41, 0xFF,
+        0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 0x0B,
+    ];
+    let err = compile_err(&data);
+    assert_eq!(err.section(), Some(WasmSectionId::Global));
+    assert_eq!(err.byte_range(), 13..20);
+
+    // errors without a position cover the whole section
+    // (memory 1) (data count 2) (data (i32.const 0) "\AA")
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x0C, 0x01,
+        0x02, 0x0B, 0x07, 0x01, 0x00, 0x41, 0x00, 0x0B, 0x01, 0xAA,
+    ];
+    let err = compile_err(&data);
+    assert_eq!(err.section(), Some(WasmSectionId::Data));
+    assert_eq!(err.byte_range(), 18..25);
+}
+
+#[test]
+fn code_section_mismatch() {
+    // (type (func)) (func)
+    let module = |code: &[u8]| {
+        let mut data = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00,
+        ];
+        data.extend_from_slice(code);
+        data
+    };
+    let compile_err = |data: &[u8]| {
+        WasmCompileError::downcast_clone(&WebAssembly::compile(data).unwrap_err()).unwrap()
+    };
+
+    assert!(WebAssembly::compile(&module(&[0x0A, 0x04, 0x01, 0x02, 0x00, 0x0B])).is_ok());
+
+    // a nop after the final end, within the body size
+    let err = compile_err(&module(&[0x0A, 0x05, 0x01, 0x03, 0x00, 0x0B, 0x01]));
+    assert_eq!(err.kind(), &WasmCompileErrorKind::InvalidData);
+    assert_eq!(err.section(), Some(WasmSectionId::Code));
+    assert_eq!(err.byte_range(), 24..25);
+
+    // the body size exceeds the section
+    let err = compile_err(&module(&[0x0A, 0x04, 0x01, 0x05, 0x00, 0x0B]));
+    assert_eq!(err.kind(), &WasmCompileErrorKind::UnexpectedEof);
+
+    // the body size stops before the final end
+    let err = compile_err(&module(&[0x0A, 0x04, 0x01, 0x01, 0x00, 0x0B]));
+    assert_eq!(err.kind(), &WasmCompileErrorKind::UnexpectedEof);
+
+    // two bodies for one function
+    let err = compile_err(&module(&[
+        0x0A, 0x07, 0x02, 0x02, 0x00, 0x0B, 0x02, 0x00, 0x0B,
+    ]));
+    assert_eq!(
+        err.kind(),
+        &WasmCompileErrorKind::FunctionAndCodeSectionMismatch
+    );
+
+    // no code section
+    let err = WebAssembly::compile(&module(&[])).unwrap_err();
+    assert_eq!(
+        WasmCompileErrorKind::downcast_ref(&err),
+        Some(&WasmCompileErrorKind::FunctionAndCodeSectionMismatch)
+    );
+}
+
+#[test]
+fn locals_error_position() {
+    use crate::CompileErrorSource;
+
+    // (func <body>)
+    let module = |body: &[u8]| {
+        let mut data = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0A,
+        ];
+        data.extend_from_slice(&[body.len() as u8 + 2, 0x01, body.len() as u8]);
+        data.extend_from_slice(body);
+        data
+    };
+    let compile_err = |data: &[u8]| {
+        WasmCompileError::downcast_clone(&WebAssembly::compile(data).unwrap_err()).unwrap()
+    };
+    // the body starts after the header, the sections and the body size
+    const BODY: usize = 22;
+
+    // (local i32) (local <invalid type>)
+    let err = compile_err(&module(&[0x02, 0x01, 0x7F, 0x01, 0x55, 0x0B]));
+    assert_eq!(err.file_position().position(), BODY + 3);
+    assert_eq!(err.byte_range(), BODY + 3..BODY + 5);
+    assert_matches!(
+        err.source(),
+        CompileErrorSource::Function(0, None, position, None) if position.position() == 3
+    );
+
+    // the number of declarations is truncated
+    let err = compile_err(&module(&[0x80, 0x80]));
+    assert_eq!(err.kind(), &WasmCompileErrorKind::UnexpectedEof);
+    assert_eq!(err.file_position().position(), BODY);
+    assert_eq!(err.byte_range(), BODY..BODY + 2);
+
+    // errors in instructions are not affected, i64.const 0 i32.eqz
+    let err = compile_err(&module(&[0x01, 0x01, 0x7F, 0x42, 0x00, 0x45, 0x0B]));
+    assert_eq!(err.kind(), &WasmCompileErrorKind::TypeMismatch);
+    assert_eq!(err.byte_range(), BODY + 5..BODY + 6);
+    assert_matches!(
+        err.source(),
+        CompileErrorSource::Function(0, None, _, Some(WasmOpcode::I32Eqz))
+    );
+}
+
+#[test]
+fn memarg_alignment() {
+    // (memory 1) (func <code>)
+    let module = |code: &[u8]| {
+        let mut data = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x0A,
+        ];
+        data.extend_from_slice(&[code.len() as u8 + 4, 0x01, code.len() as u8 + 2, 0x00]);
+        data.extend_from_slice(code);
+        data.push(0x0B);
+        data
+    };
+    let compile_err = |code: &[u8]| {
+        WasmCompileErrorKind::downcast_ref(&WebAssembly::compile(&module(code)).unwrap_err())
+            .unwrap()
+            .clone()
+    };
+
+    // i32.const 0 i64.load align=8 drop
+    WebAssembly::compile(&module(&[0x41, 0x00, 0x29, 0x03, 0x00, 0x1A])).unwrap();
+    // i32.const 0 i64.load align=16 drop
+    assert_matches!(
+        compile_err(&[0x41, 0x00, 0x29, 0x04, 0x00, 0x1A]),
+        WasmCompileErrorKind::InvalidAlignment
+    );
+
+    // i32.const 0 i32.load8_u align=1 drop
+    WebAssembly::compile(&module(&[0x41, 0x00, 0x2D, 0x00, 0x00, 0x1A])).unwrap();
+    // i32.const 0 i32.load8_u align=2 drop
+    assert_matches!(
+        compile_err(&[0x41, 0x00, 0x2D, 0x01, 0x00, 0x1A]),
+        WasmCompileErrorKind::InvalidAlignment
+    );
+
+    // i32.const 0 i32.const 0 i32.store16 align=2
+    WebAssembly::compile(&module(&[0x41, 0x00, 0x41, 0x00, 0x3B, 0x01, 0x00])).unwrap();
+    // i32.const 0 i32.const 0 i32.store16 align=4
+    assert_matches!(
+        compile_err(&[0x41, 0x00, 0x41, 0x00, 0x3B, 0x02, 0x00]),
+        WasmCompileErrorKind::InvalidAlignment
+    );
+
+    // The alignment is only a hint, so unaligned accesses still work.
+    // i32.const 1 i32.load align=4 drop
+    let instance =
+        WebAssembly::instantiate(&module(&[0x41, 0x01, 0x28, 0x02, 0x00, 0x1A]), &Env {}).unwrap();
+    instance.function_by_index(0).unwrap().invoke(&[]).unwrap();
+}
+
+#[test]
+fn store_type_mismatch() {
+    // (memory 1) (func <code>)
+    let module = |code: &[u8]| {
+        let mut data = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x0A,
+        ];
+        data.extend_from_slice(&[code.len() as u8 + 4, 0x01, code.len() as u8 + 2, 0x00]);
+        data.extend_from_slice(code);
+        data.push(0x0B);
+        data
+    };
+    let compile_err = |code: &[u8]| {
+        WasmCompileErrorKind::downcast_ref(&WebAssembly::compile(&module(code)).unwrap_err())
+            .unwrap()
+            .clone()
+    };
+
+    // i32.const 0 i32.const 0 i32.store
+    WebAssembly::compile(&module(&[0x41, 0x00, 0x41, 0x00, 0x36, 0x02, 0x00])).unwrap();
+    // i32.const 0 f32.const 0 i32.store
+    assert_matches!(
+        compile_err(&[0x41, 0x00, 0x43, 0x00, 0x00, 0x00, 0x00, 0x36, 0x02, 0x00]),
+        WasmCompileErrorKind::TypeMismatch
+    );
+    // i64.const 0 i32.const 0 i32.store
+    assert_matches!(
+        compile_err(&[0x42, 0x00, 0x41, 0x00, 0x36, 0x02, 0x00]),
+        WasmCompileErrorKind::TypeMismatch
+    );
+    // i32.const 0 i32.const 0 i64.store
+    assert_matches!(
+        compile_err(&[0x41, 0x00, 0x41, 0x00, 0x37, 0x03, 0x00]),
+        WasmCompileErrorKind::TypeMismatch
+    );
+    // i64.const 0 i64.const 0 i64.store
+    assert_matches!(
+        compile_err(&[0x42, 0x00, 0x42, 0x00, 0x37, 0x03, 0x00]),
+        WasmCompileErrorKind::TypeMismatch
+    );
+    // i32.const 0 f64.const 0 f32.store
+    assert_matches!(
+        compile_err(&[
+            0x41, 0x00, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x38, 0x02, 0x00
+        ]),
+        WasmCompileErrorKind::TypeMismatch
+    );
+}
+
+#[test]
+fn trap_hook() {
+    // i32.const 1 i32.const 0 i32.div_s
+    let slice = [0, 0x41, 0x01, 0x41, 0x00, 0x6D, 0x0B];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let instance = WasmInstance::empty();
+    let info =
+        WasmCodeBlock::generate(0, 0, &mut stream, &[], &result_types, instance.module()).unwrap();
+
+    let traps = std::cell::RefCell::new(Vec::new());
+    let mut interp = WasmInterpreter::new(&instance);
+    interp.on_trap(Box::new(|err: &WasmRuntimeError| {
+        traps.borrow_mut().push((
+            matches!(err.kind(), WasmRuntimeErrorKind::DivideByZero),
+            err.mnemonic(),
+            err.position(),
+        ))
+    }));
+    let err = interp.invoke(0, &info, &[], &result_types).unwrap_err();
+    drop(interp);
+
+    let err = WasmRuntimeError::try_from_error(err).unwrap();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::DivideByZero);
+    let traps = traps.into_inner();
+    assert_eq!(traps.len(), 1);
+    assert_matches!(traps[0], (true, WasmMnemonic::I32DivS, 5));
+}
+
+#[test]
+fn loop_watchdog() {
+    let instance = WasmInstance::empty();
+
+    // loop br 0 end
+    let slice = [0, 0x03, 0x40, 0x0C, 0, 0x0B, 0x0B];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let info = WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
+    interp.set_loop_watchdog(100);
+    let err = interp.invoke(0, &info, &[], &[]).unwrap_err();
+    let err = WasmRuntimeError::try_from_error(err).unwrap();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::PossibleInfiniteLoop);
+
+    // loop local.get 0 i32.const 1 i32.sub local.tee 0 br_if 0 end local.get 0
+    let slice = [
+        0, 0x03, 0x40, 0x20, 0, 0x41, 1, 0x6B, 0x22, 0, 0x0D, 0, 0x0B, 0x20, 0, 0x0B,
+    ];
+    let param_types = [WasmValType::I32];
+    let result_types = [WasmValType::I32];
+    let mut stream = Leb128Reader::from_slice(&slice);
+    let info = WasmCodeBlock::generate(
+        0,
+        0,
+        &mut stream,
+        &param_types,
+        &result_types,
+        instance.module(),
+    )
+    .unwrap();
+    let mut interp = WasmInterpreter::new(&instance);
+    interp.set_loop_watchdog(2);
+    let result = interp
+        .invoke(0, &info, &[WasmValue::from(1000i32).into()], &result_types)
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.get_i32().unwrap(), 0);
+
+    // set on the instance, the watchdog also applies to the public invoke path
+    let mut instance = build_instance(
+        WasmModule::builder()
+            // (func (result i32)
+            //   i32.const 0
+            //   (loop (param i32) (result i32)
+            //     i32.const 1 i32.add
+            //     (global.set 0 (i32.sub (global.get 0) (i32.const 1)))
+            //     (br_if 0 (global.get 0))))
+            .function(
+                WasmType::from_signature("iv").unwrap(),
+                &[
+                    0, 0x41, 0, 0x03, 2, 0x41, 1, 0x6A, 0x23, 0, 0x41, 1, 0x6B, 0x24, 0, 0x23, 0,
+                    0x0D, 0, 0x0B, 0x0B,
+                ],
+            )
+            // (func (result i32) i32.const 7 (loop (param i32) (result i32) br 0))
+            .function(
+                WasmType::from_signature("iv").unwrap(),
+                &[0, 0x41, 7, 0x03, 2, 0x0C, 0, 0x0B, 0x0B],
+            )
+            // declares the type of the loops above
+            .function(WasmType::from_signature("ii").unwrap(), &[0, 0x20, 0, 0x0B])
+            .global(WasmValue::from(1000i32), true),
+    );
+    instance.set_loop_watchdog(2);
+
+    // only the loop parameter changes, which is progress
+    let result = instance.function_by_index(0).unwrap().invoke(&[]).unwrap();
+    assert_eq!(result.unwrap().get_i32().unwrap(), 1000);
+
+    let err = invoke(&instance.function_by_index(1).unwrap(), &[]).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::PossibleInfiniteLoop);
+}
+
+#[test]
+fn fused_branch_trap_position() {
+    for fusion in [true, false] {
+        // (block
+        //   (br_if 0 (i32.lt_s (local.get 0) (local.get 1)))
+        //   (drop (i32.div_s (i32.const 1) (i32.const 0))))
+        // (i32.rem_u (i32.const 1) (i32.const 0))
+        let module = WasmModule::builder()
+            .options(CompileOptions::new().with_fusion(fusion))
+            .function(
+                WasmType::from_signature("iii").unwrap(),
+                &[
+                    0, 0x02, 0x40, 0x20, 0, 0x20, 1, 0x48, 0x0D, 0, 0x41, 1, 0x41, 0, 0x6D, 0x1A,
+                    0x0B, 0x41, 1, 0x41, 0, 0x70, 0x0B,
+                ],
+            )
+            .build()
+            .unwrap();
+        assert_eq!(
+            module.disassemble(0).unwrap().contains("FusedI32BrLtS"),
+            fusion
+        );
+        let instance = module.instantiate(&Env {}).unwrap();
+        let run = |lhs: i32, rhs: i32| {
+            let err = invoke(
+                &instance.function_by_index(0).unwrap(),
+                &[lhs.into(), rhs.into()],
+            )
+            .unwrap_err();
+            assert_matches!(err.kind(), WasmRuntimeErrorKind::DivideByZero);
+            (err.mnemonic(), err.position())
+        };
+
+        // not taken
+        assert_eq!(run(1, 0), (WasmMnemonic::I32DivS, 14));
+        // taken
+        assert_eq!(run(-1, 0), (WasmMnemonic::I32RemU, 21));
+    }
+}
+
+#[test]
+fn trunc_trap() {
+    let instance = WasmInstance::empty();
+    let run = |opcode: u8, arg: WasmValue, result_type: WasmValType| {
+        // local.get 0 (trunc) end
+        let slice = [0, 0x20, 0, opcode, 0x0B];
+        let param_types = [arg.val_type()];
+        let result_types = [result_type];
+        let mut stream = Leb128Reader::from_slice(&slice);
+        let info = WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &param_types,
+            &result_types,
+            instance.module(),
+        )
+        .unwrap();
+        let mut interp = WasmInterpreter::new(&instance);
+        let mut locals = [arg.into()];
+        interp
+            .invoke(0, &info, &mut locals, &result_types)
+            .map(|v| v.unwrap())
+            .map_err(|err| {
+                let err = WasmRuntimeError::try_from_error(err).unwrap();
+                assert_eq!(err.position(), 3);
+                let kind = match err.kind() {
+                    WasmRuntimeErrorKind::InvalidConversionToInteger => "nan",
+                    WasmRuntimeErrorKind::IntegerOverflow => "overflow",
+                    _ => "other",
+                };
+                (err.mnemonic(), kind)
+            })
+    };
+    // i32.trunc_f32_s
+    assert_eq!(
+        run(0xA8, (-2147483648.0f32).into(), WasmValType::I32)
+            .unwrap()
+            .get_i32()
+            .unwrap(),
+        i32::MIN
+    );
+    assert_eq!(
+        run(0xA8, (-1.9f32).into(), WasmValType::I32)
+            .unwrap()
+            .get_i32()
+            .unwrap(),
+        -1
+    );
+    for (arg, kind) in [
+        (f32::NAN, "nan"),
+        (f32::INFINITY, "overflow"),
+        (f32::NEG_INFINITY, "overflow"),
+        (2147483648.0, "overflow"),
+        (-2147483904.0, "overflow"),
+    ] {
+        assert_eq!(
+            run(0xA8, arg.into(), WasmValType::I32).unwrap_err(),
+            (WasmMnemonic::I32TruncF32S, kind)
+        );
+    }
+
+    // i32.trunc_f32_u
+    assert_eq!(
+        run(0xA9, (-0.9f32).into(), WasmValType::I32)
+            .unwrap()
+            .get_u32()
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        run(0xA9, (4294967040.0f32).into(), WasmValType::I32)
+            .unwrap()
+            .get_u32()
+            .unwrap(),
+        4294967040
+    );
+    for (arg, kind) in [
+        (f32::NAN, "nan"),
+        (f32::INFINITY, "overflow"),
+        (-1.0, "overflow"),
+        (4294967296.0, "overflow"),
+    ] {
+        assert_eq!(
+            run(0xA9, arg.into(), WasmValType::I32).unwrap_err(),
+            (WasmMnemonic::I32TruncF32U, kind)
+        );
+    }
+
+    // i32.trunc_f64_s
+    assert_eq!(
+        run(0xAA, (2147483647.9f64).into(), WasmValType::I32)
+            .unwrap()
+            .get_i32()
+            .unwrap(),
+        i32::MAX
+    );
+    assert_eq!(
+        run(0xAA, (-2147483648.9f64).into(), WasmValType::I32)
+            .unwrap()
+            .get_i32()
+            .unwrap(),
+        i32::MIN
+    );
+    for (arg, kind) in [
+        (f64::NAN, "nan"),
+        (f64::INFINITY, "overflow"),
+        (2147483648.0, "overflow"),
+        (-2147483649.0, "overflow"),
+    ] {
+        assert_eq!(
+            run(0xAA, arg.into(), WasmValType::I32).unwrap_err(),
+            (WasmMnemonic::I32TruncF64S, kind)
+        );
+    }
+
+    // i32.trunc_f64_u
+    assert_eq!(
+        run(0xAB, (4294967295.9f64).into(), WasmValType::I32)
+            .unwrap()
+            .get_u32()
+            .unwrap(),
+        u32::MAX
+    );
+    for (arg, kind) in [
+        (f64::NAN, "nan"),
+        (-1.0, "overflow"),
+        (4294967296.0, "overflow"),
+    ] {
+        assert_eq!(
+            run(0xAB, arg.into(), WasmValType::I32).unwrap_err(),
+            (WasmMnemonic::I32TruncF64U, kind)
+        );
+    }
+
+    // i64.trunc_f32_s
+    assert_eq!(
+        run(0xAE, (-9223372036854775808.0f32).into(), WasmValType::I64)
+            .unwrap()
+            .get_i64()
+            .unwrap(),
+        i64::MIN
+    );
+    for (arg, kind) in [
+        (f32::NAN, "nan"),
+        (9223372036854775808.0, "overflow"),
+        (-9223373136366403584.0, "overflow"),
+    ] {
+        assert_eq!(
+            run(0xAE, arg.into(), WasmValType::I64).unwrap_err(),
+            (WasmMnemonic::I64TruncF32S, kind)
+        );
+    }
+
+    // i64.trunc_f32_u
+    for (arg, kind) in [
+        (f32::NAN, "nan"),
+        (-1.0, "overflow"),
+        (18446744073709551616.0, "overflow"),
+    ] {
+        assert_eq!(
+            run(0xAF, arg.into(), WasmValType::I64).unwrap_err(),
+            (WasmMnemonic::I64TruncF32U, kind)
+        );
+    }
+
+    // i64.trunc_f64_s
+    assert_eq!(
+        run(0xB0, (-9223372036854775808.0f64).into(), WasmValType::I64)
+            .unwrap()
+            .get_i64()
+            .unwrap(),
+        i64::MIN
+    );
+    for (arg, kind) in [
+        (f64::NAN, "nan"),
+        (9223372036854775808.0, "overflow"),
+        (-9223372036854777856.0, "overflow"),
+    ] {
+        assert_eq!(
+            run(0xB0, arg.into(), WasmValType::I64).unwrap_err(),
+            (WasmMnemonic::I64TruncF64S, kind)
+        );
+    }
+
+    // i64.trunc_f64_u
+    assert_eq!(
+        run(0xB1, (18446744073709549568.0f64).into(), WasmValType::I64)
+            .unwrap()
+            .get_u64()
+            .unwrap(),
+        18446744073709549568
+    );
+    for (arg, kind) in [
+        (f64::NAN, "nan"),
+        (f64::NEG_INFINITY, "overflow"),
+        (18446744073709551616.0, "overflow"),
+    ] {
+        assert_eq!(
+            run(0xB1, arg.into(), WasmValType::I64).unwrap_err(),
+            (WasmMnemonic::I64TruncF64U, kind)
+        );
+    }
+
+    for val in [0.0f64, -0.0, 0.75, -0.75, 1.5, -1.5, 123456.75, -123456.75] {
+        let fval = val as f32;
+        let run_i32 = |opcode, arg: WasmValue| {
+            run(opcode, arg, WasmValType::I32)
+                .unwrap()
+                .get_i32()
+                .unwrap()
+        };
+        let run_i64 = |opcode, arg: WasmValue| {
+            run(opcode, arg, WasmValType::I64)
+                .unwrap()
+                .get_i64()
+                .unwrap()
+        };
+        assert_eq!(run_i32(0xA8, fval.into()), fval as i32);
+        assert_eq!(run_i32(0xAA, val.into()), val as i32);
+        assert_eq!(run_i64(0xAE, fval.into()), fval as i64);
+        assert_eq!(run_i64(0xB0, val.into()), val as i64);
+        if val > -1.0 {
+            assert_eq!(run_i32(0xA9, fval.into()) as u32, fval as u32);
+            assert_eq!(run_i32(0xAB, val.into()) as u32, val as u32);
+            assert_eq!(run_i64(0xAF, fval.into()) as u64, fval as u64);
+            assert_eq!(run_i64(0xB1, val.into()) as u64, val as u64);
         }
     }
 }
 
 #[test]
-fn block_nest() {
-    let slice = [0, 0x02, 0x40, 0x02, 0x40, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
+fn integer_comparisons() {
     let instance = WasmInstance::empty();
-    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+    let run = |slice: &[u8], lhs: WasmValue, rhs: WasmValue| {
+        let param_types = [lhs.val_type(), rhs.val_type()];
+        let result_types = [WasmValType::I32];
+        let mut stream = Leb128Reader::from_slice(slice);
+        let info = WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &param_types,
+            &result_types,
+            instance.module(),
+        )
+        .unwrap();
+        let mut interp = WasmInterpreter::new(&instance);
+        let mut locals = [lhs.into(), rhs.into()];
+        interp
+            .invoke(0, &info, &mut locals, &result_types)
+            .unwrap()
+            .unwrap()
+            .get_i32()
+            .unwrap()
+    };
+    // local.get 0 local.get 1 (op)
+    let compare = |opcode: u8, lhs: WasmValue, rhs: WasmValue| {
+        run(&[0, 0x20, 0, 0x20, 1, opcode, 0x0B], lhs, rhs)
+    };
+    // (block (br_if 0 (op (local.get 0) (local.get 1))) (return (i32.const 0))) i32.const 1
+    let branch = |opcode: u8, lhs: WasmValue, rhs: WasmValue| {
+        run(
+            &[
+                0, 0x02, 0x40, 0x20, 0, 0x20, 1, opcode, 0x0D, 0, 0x41, 0, 0x0F, 0x0B, 0x41, 1,
+                0x0B,
+            ],
+            lhs,
+            rhs,
+        )
+    };
 
-    let slice = [0, 0x02, 0x40, 0x02, 0x40, 0x01, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
+    let i32_ops: [(u8, &str, fn(i32, i32) -> bool); 10] = [
+        (0x46, "i32.eq", |a, b| a == b),
+        (0x47, "i32.ne", |a, b| a != b),
+        (0x48, "i32.lt_s", |a, b| a < b),
+        (0x49, "i32.lt_u", |a, b| (a as u32) < (b as u32)),
+        (0x4A, "i32.gt_s", |a, b| a > b),
+        (0x4B, "i32.gt_u", |a, b| (a as u32) > (b as u32)),
+        (0x4C, "i32.le_s", |a, b| a <= b),
+        (0x4D, "i32.le_u", |a, b| (a as u32) <= (b as u32)),
+        (0x4E, "i32.ge_s", |a, b| a >= b),
+        (0x4F, "i32.ge_u", |a, b| (a as u32) >= (b as u32)),
+    ];
+    let i32_pairs = [
+        (0, 0),
+        (1, -1),
+        (-1, 1),
+        (-1, -1),
+        (i32::MIN, 1),
+        (1, i32::MIN),
+        (i32::MIN, i32::MAX),
+        (i32::MAX, i32::MIN),
+        (i32::MIN, i32::MIN),
+        (0, -1),
+    ];
+    for (opcode, name, expected) in i32_ops {
+        for (lhs, rhs) in i32_pairs {
+            let expected = expected(lhs, rhs) as i32;
+            assert_eq!(
+                compare(opcode, lhs.into(), rhs.into()),
+                expected,
+                "{name} {lhs} {rhs}"
+            );
+            assert_eq!(
+                branch(opcode, lhs.into(), rhs.into()),
+                expected,
+                "br_if {name} {lhs} {rhs}"
+            );
+        }
+    }
+
+    let i64_ops: [(u8, &str, fn(i64, i64) -> bool); 10] = [
+        (0x51, "i64.eq", |a, b| a == b),
+        (0x52, "i64.ne", |a, b| a != b),
+        (0x53, "i64.lt_s", |a, b| a < b),
+        (0x54, "i64.lt_u", |a, b| (a as u64) < (b as u64)),
+        (0x55, "i64.gt_s", |a, b| a > b),
+        (0x56, "i64.gt_u", |a, b| (a as u64) > (b as u64)),
+        (0x57, "i64.le_s", |a, b| a <= b),
+        (0x58, "i64.le_u", |a, b| (a as u64) <= (b as u64)),
+        (0x59, "i64.ge_s", |a, b| a >= b),
+        (0x5A, "i64.ge_u", |a, b| (a as u64) >= (b as u64)),
+    ];
+    let i64_pairs = [
+        (0, 0),
+        (1, -1),
+        (-1, 1),
+        (-1, -1),
+        (i64::MIN, 1),
+        (1, i64::MIN),
+        (i64::MIN, i64::MAX),
+        (i64::MAX, i64::MIN),
+        (i64::MIN, i64::MIN),
+        (0x8000_0000, 1),
+        (0x1_0000_0000, 1),
+        (1, 0x1_0000_0000),
+    ];
+    for (opcode, name, expected) in i64_ops {
+        for (lhs, rhs) in i64_pairs {
+            let expected = expected(lhs, rhs) as i32;
+            assert_eq!(
+                compare(opcode, lhs.into(), rhs.into()),
+                expected,
+                "{name} {lhs} {rhs}"
+            );
+            assert_eq!(
+                branch(opcode, lhs.into(), rhs.into()),
+                expected,
+                "br_if {name} {lhs} {rhs}"
+            );
+        }
+    }
+}
+
+#[test]
+fn div_s_overflow() {
     let instance = WasmInstance::empty();
-    assert_matches!(
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
-            .unwrap_err()
-            .kind(),
-        WasmCompileErrorKind::UnexpectedEof
+    let run = |opcode: u8, lhs: WasmValue, rhs: WasmValue| {
+        // local.get 0 local.get 1 (div/rem) end
+        let slice = [0, 0x20, 0, 0x20, 1, opcode, 0x0B];
+        let param_types = [lhs.val_type(), rhs.val_type()];
+        let result_types = [lhs.val_type()];
+        let mut stream = Leb128Reader::from_slice(&slice);
+        let info = WasmCodeBlock::generate(
+            0,
+            0,
+            &mut stream,
+            &param_types,
+            &result_types,
+            instance.module(),
+        )
+        .unwrap();
+        let mut interp = WasmInterpreter::new(&instance);
+        let mut locals = [lhs.into(), rhs.into()];
+        interp
+            .invoke(0, &info, &mut locals, &result_types)
+            .map(|v| v.unwrap())
+            .map_err(|err| {
+                let err = WasmRuntimeError::try_from_error(err).unwrap();
+                assert_eq!(err.position(), 5);
+                assert_matches!(err.kind(), WasmRuntimeErrorKind::IntegerOverflow);
+                err.mnemonic()
+            })
+    };
+
+    // i32.div_s / i64.div_s
+    assert_eq!(
+        run(0x6D, i32::MIN.into(), (-1).into()).unwrap_err(),
+        WasmMnemonic::I32DivS
+    );
+    assert_eq!(
+        run(0x7F, i64::MIN.into(), (-1i64).into()).unwrap_err(),
+        WasmMnemonic::I64DivS
+    );
+    assert_eq!(
+        run(0x6D, i32::MIN.into(), 1.into())
+            .unwrap()
+            .get_i32()
+            .unwrap(),
+        i32::MIN
+    );
+    assert_eq!(
+        run(0x6D, (i32::MIN + 1).into(), (-1).into())
+            .unwrap()
+            .get_i32()
+            .unwrap(),
+        i32::MAX
     );
 
-    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &[],
-        &[WasmValType::I32],
-        instance.module(),
+    // i32.rem_s / i64.rem_s
+    assert_eq!(
+        run(0x6F, i32::MIN.into(), (-1).into())
+            .unwrap()
+            .get_i32()
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        run(0x81, i64::MIN.into(), (-1i64).into())
+            .unwrap()
+            .get_i64()
+            .unwrap(),
+        0
+    );
+}
+#[test]
+fn unknown_custom_sections() {
+    // (type (func (result i32)))
+    // (@custom "vendor" "\ff\ff\00\01")
+    // (@custom "\ff" "\fe")
+    // (func (type 0))
+    // (@custom "name" "\ff")
+    // (code (i32.const 42))
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7F,
+        0x00, 0x0B, 0x06, 0x76, 0x65, 0x6E, 0x64, 0x6F, 0x72, 0xFF, 0xFF, 0x00, 0x01, 0x00, 0x03,
+        0x01, 0xFF, 0xFE, 0x03, 0x02, 0x01, 0x00, 0x00, 0x06, 0x04, 0x6E, 0x61, 0x6D, 0x65, 0xFF,
+        0x0A, 0x06, 0x01, 0x04, 0x00, 0x41, 0x2A, 0x0B,
+    ];
+    let instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+    assert_eq!(
+        instance
+            .module()
+            .custom_sections("vendor")
+            .unwrap()
+            .as_ref(),
+        &[0xFF, 0xFF, 0x00, 0x01]
+    );
+    let result = instance
+        .function_by_index(0)
+        .unwrap()
+        .invoke(&[])
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.get_i32().unwrap(), 42);
+}
+
+#[test]
+fn memory_protect() {
+    // (memory 1)
+    // (func (param i32) (i32.store (local.get 0) (i32.const 1)))
+    let data = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x05, 0x01, 0x60, 0x01, 0x7F, 0x00,
+        0x03, 0x02, 0x01, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x0A, 0x0B, 0x01, 0x09, 0x00, 0x20,
+        0x00, 0x41, 0x01, 0x36, 0x02, 0x00, 0x0B,
+    ];
+    let mut instance = WebAssembly::instantiate(&data, &Env {}).unwrap();
+    instance.protect(16, 16);
+
+    let store = |instance: &WasmInstance, address: i32| {
+        invoke(&instance.function_by_index(0).unwrap(), &[address.into()]).map(|_| ())
+    };
+
+    store(&instance, 12).unwrap();
+    store(&instance, 32).unwrap();
+    for address in [13, 16, 28] {
+        let err = store(&instance, address).unwrap_err();
+        assert_matches!(err.kind(), WasmRuntimeErrorKind::ProtectionViolation);
+        assert_eq!(err.mnemonic(), WasmMnemonic::I32Store);
+    }
+    assert_eq!(instance.memory(0).unwrap().borrow().unwrap()[16], 0);
+
+    instance.unprotect(20, 4);
+    store(&instance, 20).unwrap();
+    let err = store(&instance, 16).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::ProtectionViolation);
+    let err = store(&instance, 24).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::ProtectionViolation);
+
+    instance.unprotect(0, 0x10000);
+    store(&instance, 16).unwrap();
+}
+
+#[test]
+fn loop_params() {
+    for fusion in [true, false] {
+        // (i32.const 100)
+        // (i32.const 0)
+        // (loop (type 0) (param i32) (result i32)
+        //   (i32.add (local.get 0))
+        //   (br_if 0 (local.tee 0 (i32.sub (local.get 0) (i32.const 1)))))
+        // (i32.add)
+        let module = WasmModule::builder()
+            .options(CompileOptions::new().with_fusion(fusion))
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[
+                    0, 0x41, 0xE4, 0x00, 0x41, 0, 0x03, 0, 0x20, 0, 0x6A, 0x20, 0, 0x41, 1, 0x6B,
+                    0x22, 0, 0x0D, 0, 0x0B, 0x6A, 0x0B,
+                ],
+            )
+            .build()
+            .unwrap();
+        let instance = module.instantiate(&Env {}).unwrap();
+        let func = instance.function_by_index(0).unwrap();
+        for (n, expected) in [(1, 101), (10, 155), (100, 5150)] {
+            let result = func.invoke(&[WasmValue::from(n)]).unwrap().unwrap();
+            assert_eq!(result.get_i32().unwrap(), expected);
+        }
+    }
+
+    // (i32.const 0) (loop (type 0) (br 0 (i64.const 1)))
+    let err = WasmModule::builder()
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x41, 0, 0x03, 0, 0x42, 1, 0x0C, 0, 0x0B, 0x0B],
+        )
+        .build()
+        .unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+
+    // (loop (type 0)) without the parameter on the stack
+    let err = WasmModule::builder()
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x42, 0, 0x03, 0, 0x0B, 0x0B],
+        )
+        .build()
+        .unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+}
+
+#[test]
+fn unreachable_stack_polymorphism() {
+    let build = |signature: &str, body: &[u8]| {
+        WasmModule::builder()
+            .function(WasmType::from_signature(signature).unwrap(), body)
+            .build()
+    };
+
+    // (func (result i32) unreachable i32.add)
+    assert!(build("iv", &[0, 0x00, 0x6A, 0x0B]).is_ok());
+    // (func (param i32) (result i32) local.get 0 return select)
+    assert!(build("ii", &[0, 0x20, 0, 0x0F, 0x1B, 0x0B]).is_ok());
+    // (func i32.const 0 return)
+    assert!(build("vv", &[0, 0x41, 0, 0x0F, 0x0B]).is_ok());
+    // (func (result i32) i32.const 7 (block unreachable drop drop))
+    // the dead code does not pop the operands outside the block
+    assert!(build(
+        "iv",
+        &[0, 0x41, 7, 0x02, 0x40, 0x00, 0x1A, 0x1A, 0x0B, 0x0B],
+    )
+    .is_ok());
+
+    // (func (result i32)
+    //   (block (result i32) i32.const 1 br 0 drop drop i64.const 0 i32.wrap_i64))
+    let module = build(
+        "iv",
+        &[
+            0, 0x02, 0x7F, 0x41, 1, 0x0C, 0, 0x1A, 0x1A, 0x42, 0, 0xA7, 0x0B, 0x0B,
+        ],
     )
     .unwrap();
+    let instance = module.instantiate(&Env {}).unwrap();
+    let result = instance.function_by_index(0).unwrap().invoke(&[]).unwrap();
+    assert_eq!(result.unwrap().get_i32().unwrap(), 1);
+
+    // the operands pushed by dead code are still checked
+    // (func (result i32) unreachable i64.const 0 i32.add)
+    let err = build("iv", &[0, 0x00, 0x42, 0, 0x6A, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+    // (func (result i32) unreachable i64.const 0)
+    let err = build("iv", &[0, 0x00, 0x42, 0, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+    // (func (result i32) unreachable i32.add i64.const 0 i64.add)
+    let err = build("iv", &[0, 0x00, 0x6A, 0x42, 0, 0x7C, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+    // (func (result i32) (block (result i32) unreachable i32.const 1 i32.const 2))
+    let err = build("iv", &[0, 0x02, 0x7F, 0x00, 0x41, 1, 0x41, 2, 0x0B, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::InvalidStackLevel);
+    // (func (result i32) unreachable i32.const 0 i32.const 0)
+    let err = build("iv", &[0, 0x00, 0x41, 0, 0x41, 0, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::InvalidStackLevel);
+
+    // live code is checked as before
+    // (func (result i32) i64.const 0 i32.eqz)
+    let err = build("iv", &[0, 0x42, 0, 0x45, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+    // (func (result i32) i32.add)
+    let err = build("iv", &[0, 0x6A, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::OutOfStack);
+    // (func (result i32) i32.const 0 i32.const 0)
+    let err = build("iv", &[0, 0x41, 0, 0x41, 0, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::InvalidStackLevel);
+    // (func i32.const 0)
+    let err = build("vv", &[0, 0x41, 0, 0x0B]).unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::InvalidStackLevel);
+}
 
-    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    assert_matches!(
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
-            .unwrap_err()
-            .kind(),
-        WasmCompileErrorKind::OutOfStack
-    );
+#[test]
+fn br_table_block_params() {
+    // type 0: (param i32) (result i32), type 1: (param i32) (result i64)
+    let builder = |body: &[u8]| {
+        WasmModule::builder()
+            .function(WasmType::from_signature("ii").unwrap(), &[0, 0x20, 0, 0x0B])
+            .function(
+                WasmType::from_signature("li").unwrap(),
+                &[0, 0x20, 0, 0xAD, 0x0B],
+            )
+            .function(WasmType::from_signature("ii").unwrap(), body)
+            .build()
+    };
 
-    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    assert_matches!(
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
-            .unwrap_err()
-            .kind(),
-        WasmCompileErrorKind::InvalidStackLevel
-    );
+    // (block (type 0) (loop (type 0) (br_table 0 1 (local.get 0))))
+    let module = builder(&[
+        0, 0x20, 0, 0x02, 0, 0x03, 0, 0x20, 0, 0x0E, 1, 0, 1, 0x0B, 0x0B, 0x0B,
+    ]);
+    assert!(module.is_ok());
+
+    // (block (type 1) (loop (type 0) (br_table 0 1 (local.get 0))))
+    let err = builder(&[
+        0, 0x20, 0, 0x02, 1, 0x03, 0, 0x20, 0, 0x0E, 1, 0, 1, 0x0B, 0x0B, 0xA7, 0x0B,
+    ])
+    .unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+
+    // (block (type 1) (block (type 1) (br_table 0 1 (local.get 0)) (drop) (i64.const 0)))
+    // with the i32 parameter on the stack where the labels take i64
+    let err = builder(&[
+        0, 0x20, 0, 0x02, 1, 0x02, 1, 0x20, 0, 0x0E, 1, 0, 1, 0x1A, 0x42, 0, 0x0B, 0x0B, 0xA7, 0x0B,
+    ])
+    .unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+
+    // (block (type 1) (block (type 1) (br_table 0 1 (i64.extend_i32_u) (local.get 0))))
+    let module = builder(&[
+        0, 0x20, 0, 0x02, 1, 0x02, 1, 0xAD, 0x20, 0, 0x0E, 1, 0, 1, 0x0B, 0x0B, 0xA7, 0x0B,
+    ]);
+    assert!(module.is_ok());
+}
 
-    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
+#[test]
+fn float_copysign() {
     let instance = WasmInstance::empty();
-    assert_matches!(
-        WasmCodeBlock::generate(
+    let run = |opcode: u8, lhs: WasmValue, rhs: WasmValue| {
+        let param_types = [lhs.val_type(), rhs.val_type()];
+        let result_types = [lhs.val_type()];
+        // local.get 0 local.get 1 (op)
+        let slice = [0, 0x20, 0, 0x20, 1, opcode, 0x0B];
+        let mut stream = Leb128Reader::from_slice(&slice);
+        let info = WasmCodeBlock::generate(
             0,
             0,
             &mut stream,
-            &[],
-            &[WasmValType::I64],
-            instance.module()
+            &param_types,
+            &result_types,
+            instance.module(),
         )
-        .unwrap_err()
-        .kind(),
-        WasmCompileErrorKind::TypeMismatch
-    );
+        .unwrap();
+        let mut interp = WasmInterpreter::new(&instance);
+        let mut locals = [lhs.into(), rhs.into()];
+        interp
+            .invoke(0, &info, &mut locals, &result_types)
+            .unwrap()
+            .unwrap()
+    };
 
-    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x42, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
+    let nan = f32::from_bits(0x7FC0_1234);
+    let f32_cases = [
+        (nan, -1.0, 0xFFC0_1234),
+        (-nan, 1.0, 0x7FC0_1234),
+        (nan, -0.0, 0xFFC0_1234),
+        (3.0, -0.0, (-3.0f32).to_bits()),
+        (-3.0, 0.0, 3.0f32.to_bits()),
+        (0.0, -1.0, (-0.0f32).to_bits()),
+        (f32::INFINITY, -nan, f32::NEG_INFINITY.to_bits()),
+    ];
+    for (lhs, rhs, expected) in f32_cases {
+        let result = run(0x98, lhs.into(), rhs.into()).get_f32().unwrap();
+        assert_eq!(result.to_bits(), expected, "f32.copysign {lhs} {rhs}");
+    }
+
+    let nan = f64::from_bits(0x7FF8_0000_0000_1234);
+    let f64_cases = [
+        (nan, -1.0, 0xFFF8_0000_0000_1234),
+        (-nan, 1.0, 0x7FF8_0000_0000_1234),
+        (nan, -0.0, 0xFFF8_0000_0000_1234),
+        (3.0, -0.0, (-3.0f64).to_bits()),
+        (-3.0, 0.0, 3.0f64.to_bits()),
+        (0.0, -1.0, (-0.0f64).to_bits()),
+        (f64::INFINITY, -nan, f64::NEG_INFINITY.to_bits()),
+    ];
+    for (lhs, rhs, expected) in f64_cases {
+        let result = run(0xA6, lhs.into(), rhs.into()).get_f64().unwrap();
+        assert_eq!(result.to_bits(), expected, "f64.copysign {lhs} {rhs}");
+    }
+}
+
+#[test]
+fn float_min_max() {
     let instance = WasmInstance::empty();
-    assert_matches!(
-        WasmCodeBlock::generate(
+    let run = |opcode: u8, lhs: WasmValue, rhs: WasmValue| {
+        let param_types = [lhs.val_type(), rhs.val_type()];
+        let result_types = [lhs.val_type()];
+        // local.get 0 local.get 1 (op)
+        let slice = [0, 0x20, 0, 0x20, 1, opcode, 0x0B];
+        let mut stream = Leb128Reader::from_slice(&slice);
+        let info = WasmCodeBlock::generate(
             0,
             0,
             &mut stream,
-            &[],
-            &[WasmValType::I32],
-            instance.module()
+            &param_types,
+            &result_types,
+            instance.module(),
         )
-        .unwrap_err()
-        .kind(),
-        WasmCompileErrorKind::TypeMismatch
-    );
+        .unwrap();
+        let mut interp = WasmInterpreter::new(&instance);
+        let mut locals = [lhs.into(), rhs.into()];
+        interp
+            .invoke(0, &info, &mut locals, &result_types)
+            .unwrap()
+            .unwrap()
+    };
 
-    let slice = [0, 0x02, 0x7F, 0x02, 0x7E, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
+    // (opcode, lhs, rhs, expected)
+    let f32_cases = [
+        (0x96, 1.0, 2.0, 1.0f32),
+        (0x97, 1.0, 2.0, 2.0),
+        (0x96, -0.0, 0.0, -0.0),
+        (0x96, 0.0, -0.0, -0.0),
+        (0x97, -0.0, 0.0, 0.0),
+        (0x97, 0.0, -0.0, 0.0),
+        (0x96, f32::NEG_INFINITY, f32::MIN, f32::NEG_INFINITY),
+        (0x97, f32::INFINITY, f32::MAX, f32::INFINITY),
+    ];
+    for (opcode, lhs, rhs, expected) in f32_cases {
+        let result = run(opcode, lhs.into(), rhs.into()).get_f32().unwrap();
+        assert_eq!(
+            result.to_bits(),
+            expected.to_bits(),
+            "{opcode:02x} {lhs} {rhs}"
+        );
+    }
+
+    let f64_cases = [
+        (0xA4, 1.0, 2.0, 1.0f64),
+        (0xA5, 1.0, 2.0, 2.0),
+        (0xA4, -0.0, 0.0, -0.0),
+        (0xA4, 0.0, -0.0, -0.0),
+        (0xA5, -0.0, 0.0, 0.0),
+        (0xA5, 0.0, -0.0, 0.0),
+        (0xA4, f64::NEG_INFINITY, f64::MIN, f64::NEG_INFINITY),
+        (0xA5, f64::INFINITY, f64::MAX, f64::INFINITY),
+    ];
+    for (opcode, lhs, rhs, expected) in f64_cases {
+        let result = run(opcode, lhs.into(), rhs.into()).get_f64().unwrap();
+        assert_eq!(
+            result.to_bits(),
+            expected.to_bits(),
+            "{opcode:02x} {lhs} {rhs}"
+        );
+    }
+
+    // a NaN operand on either side gives a quiet NaN, and a canonical one stays canonical
+    for opcode in [0x96, 0x97] {
+        for (lhs, rhs) in [(f32::NAN, 1.0), (1.0, f32::NAN), (f32::NAN, f32::NAN)] {
+            let result = run(opcode, lhs.into(), rhs.into()).get_f32().unwrap();
+            assert_eq!(result.to_bits() & 0x7FFF_FFFF, 0x7FC0_0000, "{opcode:02x}");
+        }
+        let nan = f32::from_bits(0x7FA0_0000);
+        let result = run(opcode, nan.into(), 0.0f32.into()).get_f32().unwrap();
+        assert!(result.is_nan());
+        assert_ne!(result.to_bits() & 0x0040_0000, 0);
+    }
+    for opcode in [0xA4, 0xA5] {
+        for (lhs, rhs) in [(f64::NAN, 1.0), (1.0, f64::NAN), (f64::NAN, f64::NAN)] {
+            let result = run(opcode, lhs.into(), rhs.into()).get_f64().unwrap();
+            assert_eq!(
+                result.to_bits() & 0x7FFF_FFFF_FFFF_FFFF,
+                0x7FF8_0000_0000_0000,
+                "{opcode:02x}"
+            );
+        }
+        let nan = f64::from_bits(0x7FF4_0000_0000_0000);
+        let result = run(opcode, nan.into(), 0.0f64.into()).get_f64().unwrap();
+        assert!(result.is_nan());
+        assert_ne!(result.to_bits() & 0x0008_0000_0000_0000, 0);
+    }
+}
+
+#[test]
+fn float_nearest() {
     let instance = WasmInstance::empty();
-    assert_matches!(
-        WasmCodeBlock::generate(
+    let run = |opcode: u8, arg: WasmValue| {
+        let param_types = [arg.val_type()];
+        let result_types = [arg.val_type()];
+        // local.get 0 (op)
+        let slice = [0, 0x20, 0, opcode, 0x0B];
+        let mut stream = Leb128Reader::from_slice(&slice);
+        let info = WasmCodeBlock::generate(
             0,
             0,
             &mut stream,
-            &[],
-            &[WasmValType::I32],
-            instance.module()
+            &param_types,
+            &result_types,
+            instance.module(),
         )
-        .unwrap_err()
-        .kind(),
-        WasmCompileErrorKind::TypeMismatch
-    );
+        .unwrap();
+        let mut interp = WasmInterpreter::new(&instance);
+        let mut locals = [arg.into()];
+        interp
+            .invoke(0, &info, &mut locals, &result_types)
+            .unwrap()
+            .unwrap()
+    };
+
+    let f64_cases = [
+        (2.5, 2.0),
+        (3.5, 4.0),
+        (-2.5, -2.0),
+        (-3.5, -4.0),
+        (0.5, 0.0),
+        (1.5, 2.0),
+        (-0.5, -0.0),
+        (-0.4, -0.0),
+        (0.49999999999999994, 0.0),
+        (4503599627370497.0, 4503599627370497.0),
+        (-4503599627370497.0, -4503599627370497.0),
+        (0.0, 0.0),
+        (-0.0, -0.0),
+        (f64::INFINITY, f64::INFINITY),
+        (f64::NEG_INFINITY, f64::NEG_INFINITY),
+    ];
+    for (arg, expected) in f64_cases {
+        let result = run(0x9E, WasmValue::from(arg)).get_f64().unwrap();
+        assert_eq!(result.to_bits(), expected.to_bits(), "f64.nearest {arg}");
+
+        let (arg, expected) = (arg as f32, expected as f32);
+        let result = run(0x90, WasmValue::from(arg)).get_f32().unwrap();
+        assert_eq!(result.to_bits(), expected.to_bits(), "f32.nearest {arg}");
+    }
+    for (arg, expected) in [
+        (8388609.0f32, 8388609.0f32),
+        (0.49999997, 0.0),
+        (-1.5, -2.0),
+    ] {
+        let result = run(0x90, WasmValue::from(arg)).get_f32().unwrap();
+        assert_eq!(result.to_bits(), expected.to_bits(), "f32.nearest {arg}");
+    }
+
+    assert!(run(0x9E, WasmValue::from(f64::NAN))
+        .get_f64()
+        .unwrap()
+        .is_nan());
+    assert!(run(0x90, WasmValue::from(f32::NAN))
+        .get_f32()
+        .unwrap()
+        .is_nan());
+}
+
+#[test]
+fn eval_expr() {
+    // (i32.add (local.get 0) (local.get 1))
+    let body = [0, 0x20, 0, 0x20, 1, 0x6A, 0x0B];
+    let i32x2 = [WasmValType::I32, WasmValType::I32];
+    let results = WebAssembly::eval_expr(
+        &body,
+        &i32x2,
+        &[WasmValType::I32],
+        &[i32::MAX.into(), 1.into()],
+    )
+    .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].get_i32().unwrap(), i32::MIN);
+
+    // (i32.trunc_sat_f32_s (local.get 0)) (local.get 0)
+    let body = [0, 0x20, 0, 0xFC, 0x00, 0x20, 0, 0x0B];
+    let results = WebAssembly::eval_expr(
+        &body,
+        &[WasmValType::F32],
+        &[WasmValType::I32, WasmValType::F32],
+        &[WasmValue::from(1e10f32)],
+    )
+    .unwrap();
+    assert_eq!(results[0].get_i32().unwrap(), i32::MAX);
+    assert_eq!(results[1].get_f32().unwrap(), 1e10);
+
+    // arguments must match the parameter types
+    assert!(WebAssembly::eval_expr(&body, &[WasmValType::F32], &[], &[1.into()]).is_err());
+    // the body must be valid for the given types
+    assert!(WebAssembly::eval_expr(&body, &[WasmValType::I64], &[], &[1i64.into()]).is_err());
+}
+
+#[test]
+fn block_result_as_function_result() {
+    for fusion in [true, false] {
+        let options = CompileOptions::new().with_fusion(fusion);
+        let run = |body: &[u8], result_type: &str, params: &[WasmValue]| {
+            let signature = format!("{result_type}{}", if params.is_empty() { "v" } else { "i" });
+            build_instance(
+                WasmModule::builder()
+                    .options(options)
+                    .function(WasmType::from_signature(&signature).unwrap(), body),
+            )
+            .function_by_index(0)
+            .unwrap()
+            .invoke_multi(params)
+            .unwrap()
+        };
 
-    let slice = [0, 0x02, 0x7E, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    assert_matches!(
-        WasmCodeBlock::generate(
-            0,
-            0,
-            &mut stream,
+        // (block (result i32) i32.const 5)
+        let results = run(&[0, 0x02, 0x7F, 0x41, 5, 0x0B, 0x0B], "i", &[]);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].get_i32().unwrap(), 5);
+
+        // (block (result f64) (block (result f64) f64.const 2.5))
+        let results = run(
+            &[
+                0, 0x02, 0x7C, 0x02, 0x7C, 0x44, 0, 0, 0, 0, 0, 0, 0x04, 0x40, 0x0B, 0x0B, 0x0B,
+            ],
+            "d",
             &[],
-            &[WasmValType::I32],
-            instance.module()
-        )
-        .unwrap_err()
-        .kind(),
-        WasmCompileErrorKind::TypeMismatch
-    );
+        );
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].get_f64().unwrap(), 2.5);
+
+        // (block (result i32) (br_if 0 (i32.const 7) (local.get 0)) drop i32.const 9)
+        let body = [
+            0, 0x02, 0x7F, 0x41, 7, 0x20, 0, 0x0D, 0, 0x1A, 0x41, 9, 0x0B, 0x0B,
+        ];
+        let results = run(&body, "i", &[1.into()]);
+        assert_eq!(results[0].get_i32().unwrap(), 7);
+        let results = run(&body, "i", &[0.into()]);
+        assert_eq!(results[0].get_i32().unwrap(), 9);
+
+        // (block (result i32) i32.const 3 br 0) with an unreachable tail
+        let results = run(&[0, 0x02, 0x7F, 0x41, 3, 0x0C, 0, 0x0B, 0x0B], "i", &[]);
+        assert_eq!(results[0].get_i32().unwrap(), 3);
+    }
 
-    let slice = [0, 0x02, 0x7E, 0x02, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    assert_matches!(
-        WasmCodeBlock::generate(
-            0,
-            0,
-            &mut stream,
-            &[],
-            &[WasmValType::I64],
-            instance.module()
+    // the block result does not match the function result
+    let err = WasmModule::builder()
+        .function(
+            WasmType::from_signature("lv").unwrap(),
+            &[0, 0x02, 0x7F, 0x41, 5, 0x0B, 0x0B],
         )
-        .unwrap_err()
-        .kind(),
-        WasmCompileErrorKind::TypeMismatch
-    );
+        .build()
+        .unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::TypeMismatch);
+}
 
-    let slice = [0, 0x02, 0x7F, 0x02, 0x7F, 0x20, 0x00, 0x0B, 0x0B, 0x0B];
-    let result_types = [WasmValType::I32];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let info = WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &[WasmValType::I32],
-        &result_types,
-        instance.module(),
-    )
-    .unwrap();
-    let mut interp = WasmInterpreter::new(&instance);
+#[test]
+fn host_memory_grow() {
+    use crate::WasmLimit;
+
+    struct GrowEnv;
+
+    impl WasmEnv for GrowEnv {
+        fn resolve_imports(
+            &self,
+            _mod_name: &str,
+            name: &str,
+            _type: &WasmType,
+        ) -> WasmImportResult {
+            match name {
+                "grow" => WasmImportResult::Ok(grow),
+                _ => WasmImportResult::NoMethod,
+            }
+        }
+    }
 
-    let result = interp
-        .invoke(0, &info, &mut [123.into()], &result_types)
-        .unwrap()
+    fn grow(instance: &WasmInstance, mut args: WasmArgs) -> WasmDynResult {
+        let delta = args.next::<u32>()?;
+        Ok(Some(instance.memory_grow(0, delta).into()))
+    }
+
+    let instance = WasmModule::builder()
+        .import_function("env", "grow", WasmType::from_signature("ii").unwrap())
+        // (func (param i32) (result i32) (drop (call 0 (local.get 0))) (memory.size))
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x20, 0, 0x10, 0, 0x1A, 0x3F, 0, 0x0B],
+        )
+        // (func (param i32) (result i32) (call 0 (local.get 0)))
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x20, 0, 0x10, 0, 0x0B],
+        )
+        .memory(WasmLimit::new(1, Some(4)))
+        .build()
         .unwrap()
-        .get_i32()
+        .instantiate(&GrowEnv)
         .unwrap();
-    assert_eq!(result, 123);
-}
 
-#[test]
-fn block_test() {
-    let instance =
-        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+    let grow_and_size = instance.function_by_index(1).unwrap();
+    let grow = instance.function_by_index(2).unwrap();
 
-    let result = instance.exports().block_test(1, 123, 456, 789).unwrap();
-    assert_eq!(result, 456);
+    let result = grow_and_size.invoke(&[2.into()]).unwrap().unwrap();
+    assert_eq!(result.get_i32().unwrap(), 3);
+    assert_eq!(instance.memory(0).unwrap().size(), 3);
 
-    let result = instance.exports().block_test(2, 123, 456, 789).unwrap();
-    assert_eq!(result, 789);
+    // the limit is checked as the opcode does
+    let result = grow.invoke(&[2.into()]).unwrap().unwrap();
+    assert_eq!(result.get_i32().unwrap(), -1);
+    let result = grow.invoke(&[1.into()]).unwrap().unwrap();
+    assert_eq!(result.get_i32().unwrap(), 3);
+    let result = grow_and_size.invoke(&[0.into()]).unwrap().unwrap();
+    assert_eq!(result.get_i32().unwrap(), 4);
+
+    assert_eq!(instance.memory_grow(1, 1), -1);
+    assert_eq!(instance.memory_grow(0, 1), -1);
+    assert_eq!(instance.memory_grow(0, 0), 4);
 }
 
 #[test]
-fn loop_nest() {
-    let slice = [0, 0x02, 0x40, 0x03, 0x40, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+fn host_table_import() {
+    use crate::{WasmDynFunc, WasmLimit};
+
+    struct TableEnv(Vec<Option<WasmHostFunction>>);
+
+    impl WasmEnv for TableEnv {
+        fn resolve_imports(
+            &self,
+            _mod_name: &str,
+            _name: &str,
+            _type: &WasmType,
+        ) -> WasmImportResult {
+            WasmImportResult::NoMethod
+        }
 
-    let slice = [0, 0x02, 0x40, 0x03, 0x40, 0x01, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
+        fn resolve_table(
+            &self,
+            _mod_name: &str,
+            name: &str,
+            limit: &WasmLimit,
+        ) -> Option<Vec<Option<WasmHostFunction>>> {
+            assert_eq!(name, "table");
+            assert_eq!(limit.min(), 2);
+            Some(self.0.clone())
+        }
+    }
+
+    fn add(_: &WasmInstance, mut args: WasmArgs) -> WasmDynResult {
+        Ok(Some((args.next::<i32>()? + args.next::<i32>()?).into()))
+    }
+
+    fn mul(_: &WasmInstance, mut args: WasmArgs) -> WasmDynResult {
+        Ok(Some((args.next::<i32>()? * args.next::<i32>()?).into()))
+    }
+
+    fn sum3(_: &WasmInstance, mut args: WasmArgs) -> WasmDynResult {
+        Ok(Some(
+            (args.next::<i32>()? + args.next::<i32>()? + args.next::<i32>()?).into(),
+        ))
+    }
+
+    // (type $t0 (func (param i32 i32) (result i32)))
+    // (type $t1 (func (param i32 i32) (result i32)))
+    // (type $t2 (func (param i32 i32 i32) (result i32)))
+    // (import "env" "table" (table 2 3 funcref))
+    // (func (export "call") (type $t2)
+    //   local.get 1 local.get 2 local.get 0 call_indirect (type $t0))
+    // (func $sub (type $t1) local.get 0 local.get 1 i32.sub)
+    // (elem (i32.const 1) $sub)
+    let bytes = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x14, 0x03, 0x60, 0x02, 0x7F, 0x7F,
+        0x01, 0x7F, 0x60, 0x02, 0x7F, 0x7F, 0x01, 0x7F, 0x60, 0x03, 0x7F, 0x7F, 0x7F, 0x01, 0x7F,
+        0x02, 0x10, 0x01, 0x03, 0x65, 0x6E, 0x76, 0x05, 0x74, 0x61, 0x62, 0x6C, 0x65, 0x01, 0x70,
+        0x01, 0x02, 0x03, 0x03, 0x03, 0x02, 0x02, 0x01, 0x07, 0x08, 0x01, 0x04, 0x63, 0x61, 0x6C,
+        0x6C, 0x00, 0x00, 0x09, 0x07, 0x01, 0x00, 0x41, 0x01, 0x0B, 0x01, 0x01, 0x0A, 0x15, 0x02,
+        0x0B, 0x00, 0x20, 0x01, 0x20, 0x02, 0x20, 0x00, 0x11, 0x00, 0x00, 0x0B, 0x07, 0x00, 0x20,
+        0x00, 0x20, 0x01, 0x6B, 0x0B,
+    ];
+    let host = |signature: &str, func: WasmDynFunc| {
+        Some(WasmHostFunction::new(
+            WasmType::from_signature(signature).unwrap(),
+            func,
+        ))
+    };
+
+    let env = TableEnv(vec![host("iii", add), None, host("iiii", sum3)]);
+    let mut instance = WebAssembly::instantiate(&bytes, &env).unwrap();
+    let call = |instance: &WasmInstance, args: [i32; 3]| {
+        invoke(
+            &instance.function("call").unwrap(),
+            &args.map(WasmValue::from),
+        )
+        .map(|v| v.unwrap().get_i32().unwrap())
+    };
+
+    // the host function supplied at index 0
+    assert_eq!(call(&instance, [0, 2, 3]).unwrap(), 5);
+    // the element segment overrides the host's null at index 1,
+    // and $t1 matches $t0 as they are structurally the same
+    assert_eq!(call(&instance, [1, 5, 3]).unwrap(), 2);
+    // the host function at index 2 has a different type
+    let err = call(&instance, [2, 1, 2]).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::IndirectCallTypeMismatch);
+    assert_eq!(err.mnemonic(), WasmMnemonic::CallIndirect);
+    assert_eq!(instance.module().tables()[0].size().unwrap(), 3);
+
+    // the host installs another function after instantiation
+    let index = instance
+        .add_host_function(WasmHostFunction::new(
+            WasmType::from_signature("iii").unwrap(),
+            mul,
+        ))
+        .unwrap();
+    instance.module().tables()[0].set(2, Some(index)).unwrap();
+    assert_eq!(call(&instance, [2, 4, 5]).unwrap(), 20);
     assert_matches!(
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
-            .unwrap_err()
-            .kind(),
-        WasmCompileErrorKind::UnexpectedEof
+        instance.add_host_function(WasmHostFunction::new(
+            WasmType::from_signature("vv").unwrap(),
+            mul,
+        )),
+        Err(WasmRuntimeErrorKind::TypeMismatch)
     );
 
-    let slice = [0, 0x02, 0x7F, 0x03, 0x7F, 0x41, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    WasmCodeBlock::generate(
-        0,
-        0,
-        &mut stream,
-        &[],
-        &[WasmValType::I32],
-        instance.module(),
-    )
-    .unwrap();
+    // the imported table belongs to the host and survives a reset
+    instance.reset().unwrap();
+    assert_eq!(call(&instance, [2, 4, 5]).unwrap(), 20);
 
-    let slice = [0, 0x02, 0x7F, 0x03, 0x7F, 0x01, 0x0B, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
+    // a host function of a type the module does not declare
+    let env = TableEnv(vec![host("ll", add)]);
+    let err = WebAssembly::instantiate(&bytes, &env).unwrap_err();
     assert_matches!(
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
-            .unwrap_err()
-            .kind(),
-        WasmCompileErrorKind::OutOfStack
+        err.downcast_ref::<WasmLinkError>(),
+        Some(WasmLinkError::InvalidTable(name)) if name == "table"
+    );
+    // more elements than the maximum size of the table
+    let env = TableEnv(vec![None; 4]);
+    let err = WebAssembly::instantiate(&bytes, &env).unwrap_err();
+    assert_matches!(
+        err.downcast_ref::<WasmLinkError>(),
+        Some(WasmLinkError::InvalidTable(_))
     );
+
+    // without the host's elements the table keeps only the module's own
+    let instance = WebAssembly::instantiate(&bytes, &Env {}).unwrap();
+    assert_eq!(call(&instance, [1, 5, 3]).unwrap(), 2);
+    let err = call(&instance, [0, 5, 3]).unwrap_err();
+    assert_matches!(err.kind(), WasmRuntimeErrorKind::UninitializedElement);
 }
 
 #[test]
-fn loop_test() {
-    let instance =
-        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+fn global_init_from_import() {
+    struct GlobalEnv(WasmValue);
+
+    impl WasmEnv for GlobalEnv {
+        fn resolve_imports(
+            &self,
+            _mod_name: &str,
+            _name: &str,
+            _type: &WasmType,
+        ) -> WasmImportResult {
+            WasmImportResult::NoMethod
+        }
 
-    let result = instance.exports().loop_test(10).unwrap();
-    assert_eq!(result, 55);
+        fn resolve_global(
+            &self,
+            _mod_name: &str,
+            name: &str,
+            val_type: WasmValType,
+            is_mutable: bool,
+        ) -> Option<WasmValue> {
+            assert_eq!(name, "base");
+            assert_eq!(val_type, WasmValType::I32);
+            assert!(!is_mutable);
+            Some(self.0)
+        }
+    }
 
-    let result = instance.exports().loop_test(100).unwrap();
-    assert_eq!(result, 5050);
-}
+    fn module(mutable_import: u8, g1_init: u8) -> Vec<u8> {
+        vec![
+            0x00,
+            0x61,
+            0x73,
+            0x6D,
+            0x01,
+            0x00,
+            0x00,
+            0x00, // header
+            // (import "env" "base" (global i32))
+            0x02,
+            0x0D,
+            0x01,
+            0x03,
+            b'e',
+            b'n',
+            b'v',
+            0x04,
+            b'b',
+            b'a',
+            b's',
+            b'e',
+            0x03,
+            0x7F,
+            mutable_import,
+            // (global $g1 i32 (global.get g1_init)) (global $g2 i32 (global.get $g1))
+            0x06,
+            0x0B,
+            0x02,
+            0x7F,
+            0x00,
+            0x23,
+            g1_init,
+            0x0B,
+            0x7F,
+            0x00,
+            0x23,
+            0x01,
+            0x0B,
+            // (export "g1" (global $g1)) (export "g2" (global $g2))
+            0x07,
+            0x0B,
+            0x02,
+            0x02,
+            b'g',
+            b'1',
+            0x03,
+            0x01,
+            0x02,
+            b'g',
+            b'2',
+            0x03,
+            0x02,
+        ]
+    }
 
-#[test]
-fn if_nest() {
-    let slice = [0, 0x41, 0x01, 0x04, 0x40, 0x01, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+    let exported = |instance: &WasmInstance| {
+        instance
+            .exports()
+            .globals()
+            .map(|(_, global)| global.value().get_i32().unwrap())
+            .collect::<Vec<_>>()
+    };
 
-    let slice = [0, 0x41, 0x01, 0x04, 0x40, 0x01, 0x05, 0x01, 0x0B, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+    // g2 reads g1, which reads the imported global
+    let bytes = module(0, 0);
+    let instance = WebAssembly::instantiate(&bytes, &GlobalEnv(1234.into())).unwrap();
+    assert_eq!(
+        instance.module().globals()[0].value().get_i32().unwrap(),
+        1234
+    );
+    assert_eq!(exported(&instance), [1234, 1234]);
+    assert_eq!(
+        instance.module().globals()[2]
+            .initial_value()
+            .get_i32()
+            .unwrap(),
+        1234
+    );
 
-    let slice = [
-        0, 0x41, 0x01, 0x04, 0x7F, 0x41, 0x01, 0x05, 0x41, 0x01, 0x0B, 0x1A, 0x0B,
-    ];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
-    WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module()).unwrap();
+    // an import the host doesn't provide stays zero
+    let instance = WebAssembly::instantiate(&bytes, &Env {}).unwrap();
+    assert_eq!(exported(&instance), [0, 0]);
 
-    let slice = [0, 0x41, 0x01, 0x04, 0x7F, 0x41, 0x01, 0x0B, 0x1A, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
+    // a value of another type fails to link
+    let err = WebAssembly::instantiate(&bytes, &GlobalEnv(1234i64.into())).unwrap_err();
     assert_matches!(
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
-            .unwrap_err()
-            .kind(),
-        WasmCompileErrorKind::ElseNotExists
+        err.downcast_ref::<WasmLinkError>(),
+        Some(WasmLinkError::InvalidGlobal(name)) if name == "base"
     );
 
-    let slice = [0, 0x05, 0x01, 0x0B];
-    let mut stream = Leb128Reader::from_slice(&slice);
-    let instance = WasmInstance::empty();
+    // initializers can't read the global itself or a later one
+    for g1_init in [1, 2] {
+        let err = WebAssembly::compile(&module(0, g1_init)).unwrap_err();
+        assert_matches!(
+            WasmCompileErrorKind::downcast_ref(&err).unwrap(),
+            WasmCompileErrorKind::InvalidGlobal
+        );
+    }
+    // nor a mutable global
+    let err = WebAssembly::compile(&module(1, 0)).unwrap_err();
     assert_matches!(
-        WasmCodeBlock::generate(0, 0, &mut stream, &[], &[], instance.module())
-            .unwrap_err()
-            .kind(),
-        WasmCompileErrorKind::ElseWithoutIf
+        WasmCompileErrorKind::downcast_ref(&err).unwrap(),
+        WasmCompileErrorKind::InvalidGlobal
     );
+
+    // (import "env" "base" (global i32)) (global i32 <init>)
+    // an initializer must leave exactly one value
+    for init in [[0x23, 0x00, 0x41, 0x07], [0x41, 0x07, 0x23, 0x00]] {
+        let mut bytes = module(0, 0)[..23].to_vec();
+        bytes.extend_from_slice(&[0x06, 0x08, 0x01, 0x7F, 0x00]);
+        bytes.extend_from_slice(&init);
+        bytes.push(0x0B);
+        let err = WebAssembly::compile(&bytes).unwrap_err();
+        assert_matches!(
+            WasmCompileErrorKind::downcast_ref(&err).unwrap(),
+            WasmCompileErrorKind::InvalidData
+        );
+    }
 }
 
 #[test]
-fn if_test() {
-    let instance =
-        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+fn host_global_access() {
+    struct GlobalEnv;
+
+    impl WasmEnv for GlobalEnv {
+        fn resolve_imports(
+            &self,
+            _mod_name: &str,
+            name: &str,
+            _type: &WasmType,
+        ) -> WasmImportResult {
+            match name {
+                "bump" => WasmImportResult::Ok(bump),
+                _ => WasmImportResult::NoMethod,
+            }
+        }
+    }
 
-    let result = instance.exports().if_test1(123, 456, true).unwrap();
-    assert_eq!(result, 123);
+    fn bump(instance: &WasmInstance, _: WasmArgs) -> WasmDynResult {
+        let value = instance.global_get(0)?.get_i32()?;
+        instance.global_set(0, (value + 1).into())?;
+        Ok(None)
+    }
 
-    let result = instance
-        .exports()
-        .get("if_test1")
-        .unwrap()
-        .invoke(&[123.into(), 456.into(), 2.into()])
-        .unwrap()
+    let instance = WasmModule::builder()
+        .import_function("env", "bump", WasmType::from_signature("vv").unwrap())
+        // (func (result i32) call 0 call 0 global.get 0)
+        .function(
+            WasmType::from_signature("iv").unwrap(),
+            &[0, 0x10, 0, 0x10, 0, 0x23, 0, 0x0B],
+        )
+        .global(WasmValue::from(40i32), true)
+        .global(WasmValue::from(1i64), false)
+        .build()
         .unwrap()
-        .get_i32()
+        .instantiate(&GlobalEnv)
         .unwrap();
-    assert_eq!(result, 123);
 
-    let result = instance.exports().if_test1(123, 456, false).unwrap();
-    assert_eq!(result, 456);
+    let func = instance.function_by_index(1).unwrap();
+    assert_eq!(func.invoke(&[]).unwrap().unwrap().get_i32().unwrap(), 42);
+    assert_eq!(func.invoke(&[]).unwrap().unwrap().get_i32().unwrap(), 44);
+    assert_eq!(instance.global_get(0).unwrap().get_i32().unwrap(), 44);
 
-    let result = instance.exports().if_test2(123, 456, true).unwrap();
-    assert_eq!(result, 123);
+    assert_eq!(instance.global_get(1).unwrap().get_i64().unwrap(), 1);
+    assert_matches!(
+        instance.global_set(1, 2i64.into()),
+        Err(WasmRuntimeErrorKind::InvalidParameter)
+    );
+    assert_matches!(
+        instance.global_set(0, 2i64.into()),
+        Err(WasmRuntimeErrorKind::TypeMismatch)
+    );
+    assert_matches!(
+        instance.global_get(2),
+        Err(WasmRuntimeErrorKind::InvalidParameter)
+    );
+    assert_eq!(instance.global_get(0).unwrap().get_i32().unwrap(), 44);
+}
 
-    let result = instance
-        .exports()
-        .get("if_test2")
-        .unwrap()
-        .invoke(&[123.into(), 456.into(), 2.into()])
-        .unwrap()
-        .unwrap()
-        .get_i32()
-        .unwrap();
-    assert_eq!(result, 123);
+#[test]
+fn shared_memory_flag() {
+    use crate::ImportKind;
+
+    let module = |import_limits: &[u8], memory_limits: &[u8]| {
+        let mut bytes = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+        // (import "env" "mem" (memory ...))
+        bytes.extend([0x02, 10 + import_limits.len() as u8, 0x01]);
+        bytes.extend([0x03, b'e', b'n', b'v', 0x03, b'm', b'e', b'm', 0x02]);
+        bytes.extend(import_limits);
+        // (memory ...)
+        bytes.extend([0x05, 1 + memory_limits.len() as u8, 0x01]);
+        bytes.extend(memory_limits);
+        WebAssembly::compile(&bytes)
+    };
 
-    let result = instance.exports().if_test1(123, 456, false).unwrap();
-    assert_eq!(result, 456);
+    // (memory 1 2 shared) and (memory 1 3)
+    let module1 = module(&[0x03, 1, 2], &[0x01, 1, 3]).unwrap();
+    let ImportKind::Memory(limit) = module1.imports().next().unwrap().desc else {
+        unreachable!()
+    };
+    assert!(limit.is_shared());
+    assert_eq!(limit.max(), Some(2));
+    assert!(module1.memories()[0].is_shared());
+    assert!(!module1.memories()[1].is_shared());
+
+    // (memory 1 3) and (memory 1 2 shared)
+    let module2 = module(&[0x01, 1, 3], &[0x03, 1, 2]).unwrap();
+    assert!(!module2.memories()[0].is_shared());
+    assert!(module2.memories()[1].is_shared());
+    assert_eq!(module2.memories()[1].size(), 1);
+
+    // (memory 0 0 shared) declares a maximum size of zero
+    let module3 = module(&[0x03, 0, 0], &[0x03, 0, 0]).unwrap();
+    assert!(module3.memories()[0].is_shared());
+    assert!(module3.memories()[1].is_shared());
+    assert_eq!(module3.memories()[1].size(), 0);
+
+    // a shared memory without the maximum size
+    let err = module(&[0x01, 1, 3], &[0x02, 1]).unwrap_err();
+    assert_matches!(
+        WasmCompileErrorKind::downcast_ref(&err),
+        Some(WasmCompileErrorKind::InvalidData)
+    );
 }
 
 #[test]
-fn import_test() {
-    let instance =
-        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
+fn if_without_else() {
+    for fusion in [true, false] {
+        let build = |body: &[u8]| {
+            build_instance(
+                WasmModule::builder()
+                    .options(CompileOptions::new().with_fusion(fusion))
+                    .function(WasmType::from_signature("ii").unwrap(), body),
+            )
+        };
 
-    let result = instance.exports().import_test1(123, 456).unwrap();
-    assert_eq!(result, 123 + 456);
+        // (local i32)
+        // (if (local.get 0) (then (local.set 1 (i32.const 10))))
+        // (i32.add (local.get 1) (i32.const 1))
+        let instance = build(&[
+            1, 1, 0x7F, 0x20, 0, 0x04, 0x40, 0x41, 10, 0x21, 1, 0x0B, 0x20, 1, 0x41, 1, 0x6A, 0x0B,
+        ]);
+        let func = instance.function_by_index(0).unwrap();
+        let result = func.invoke(&[1.into()]).unwrap().unwrap();
+        assert_eq!(result.get_i32().unwrap(), 11);
+        let result = func.invoke(&[0.into()]).unwrap().unwrap();
+        assert_eq!(result.get_i32().unwrap(), 1);
+
+        // (if (local.get 0) (then unreachable)) (i32.const 7)
+        let instance = build(&[0, 0x20, 0, 0x04, 0x40, 0x00, 0x0B, 0x41, 7, 0x0B]);
+        let func = instance.function_by_index(0).unwrap();
+        let result = func.invoke(&[0.into()]).unwrap().unwrap();
+        assert_eq!(result.get_i32().unwrap(), 7);
+        let err = invoke(&func, &[1.into()]).unwrap_err();
+        assert_matches!(err.kind(), WasmRuntimeErrorKind::Unreachable);
+
+        // (if (local.get 0) (then (if (local.get 0) (then (return (i32.const 3)))))) (i32.const 4)
+        let instance = build(&[
+            0, 0x20, 0, 0x04, 0x40, 0x20, 0, 0x04, 0x40, 0x41, 3, 0x0F, 0x0B, 0x0B, 0x41, 4, 0x0B,
+        ]);
+        let func = instance.function_by_index(0).unwrap();
+        let result = func.invoke(&[1.into()]).unwrap().unwrap();
+        assert_eq!(result.get_i32().unwrap(), 3);
+        let result = func.invoke(&[0.into()]).unwrap().unwrap();
+        assert_eq!(result.get_i32().unwrap(), 4);
+    }
 
-    let result = instance.exports().import_test2(987, 654).unwrap();
-    assert_eq!(result, 987 - 654);
+    // (if (result i32) (local.get 0) (then (i32.const 1)))
+    let err = WasmModule::builder()
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[0, 0x20, 0, 0x04, 0x7F, 0x41, 1, 0x0B, 0x0B],
+        )
+        .build()
+        .unwrap_err();
+    assert_matches!(err.kind(), WasmCompileErrorKind::ElseNotExists);
 }
 
 #[test]
-fn exit_test() {
-    let instance =
-        WebAssembly::instantiate(include_bytes!("../test/tester.wasm"), &Env {}).unwrap();
-    let err = instance.exports().exit_test().unwrap_err();
-    let err = WasmRuntimeError::try_from_error(err).unwrap();
-    assert_matches!(err.kind(), WasmRuntimeErrorKind::Exit)
+fn instruction_histogram() {
+    let module = WebAssembly::compile(include_bytes!("../test/tester.wasm")).unwrap();
+    let histogram = module.instruction_histogram();
+    assert!(histogram[&WasmMnemonic::I32Add] > 0);
+    assert!(histogram[&WasmMnemonic::Return] > 0);
+    assert!(!histogram.contains_key(&WasmMnemonic::Nop));
+
+    for fusion in [true, false] {
+        // (func (param i32) (result i32) (i32.add (local.get 0) (i32.const 1)))
+        let module = WasmModule::builder()
+            .options(CompileOptions::new().with_fusion(fusion))
+            .function(
+                WasmType::from_signature("ii").unwrap(),
+                &[0, 0x20, 0, 0x41, 1, 0x6A, 0x0B],
+            )
+            .build()
+            .unwrap();
+        let histogram = module.instruction_histogram();
+        assert_eq!(histogram[&WasmMnemonic::I32Add], 1);
+        assert_eq!(histogram[&WasmMnemonic::Return], 1);
+        if !fusion {
+            assert_eq!(histogram[&WasmMnemonic::LocalGet], 1);
+            assert_eq!(histogram[&WasmMnemonic::I32Const], 1);
+        }
+    }
+}
+
+#[test]
+fn wasi_fd_write() {
+    use crate::wasi::{WasiEnv, ERRNO_BADF, ERRNO_SUCCESS, MODULE_NAME};
+    use crate::WasmLimit;
+    use core::cell::RefCell;
+    use std::sync::Arc;
+
+    // (func (param $fd i32) (result i32)
+    //   (call $fd_write (local.get $fd) (i32.const 0) (i32.const 2) (i32.const 64)))
+    let mut instance = WasmModule::builder()
+        .import_function(
+            MODULE_NAME,
+            "fd_write",
+            WasmType::from_signature("iiiii").unwrap(),
+        )
+        .function(
+            WasmType::from_signature("ii").unwrap(),
+            &[
+                0, 0x20, 0, 0x41, 0, 0x41, 2, 0x41, 0xC0, 0x00, 0x10, 0, 0x0B,
+            ],
+        )
+        .memory(WasmLimit::new(1, None))
+        .build()
+        .unwrap()
+        .instantiate(&WasiEnv)
+        .unwrap();
+
+    let stdout = Arc::new(RefCell::new(String::new()));
+    instance.set_stdout(stdout.clone());
+
+    // iovs = [{ "hel", 3 }, { "lo\n", 3 }]
+    let memory = instance.memory(0).unwrap();
+    memory.write_slice(16, b"hello\n").unwrap();
+    memory
+        .write_slice(0, &[16, 0, 0, 0, 3, 0, 0, 0, 19, 0, 0, 0, 3, 0, 0, 0])
+        .unwrap();
+
+    let func = instance.function_by_index(1).unwrap();
+    let read_nwritten = || {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&instance.memory(0).unwrap().borrow().unwrap()[64..68]);
+        u32::from_le_bytes(bytes)
+    };
+
+    let errno = func.invoke(&[1.into()]).unwrap().unwrap();
+    assert_eq!(errno.get_u32().unwrap(), ERRNO_SUCCESS);
+    assert_eq!(read_nwritten(), 6);
+    let errno = func.invoke(&[1.into()]).unwrap().unwrap();
+    assert_eq!(errno.get_u32().unwrap(), ERRNO_SUCCESS);
+    assert_eq!(stdout.borrow().as_str(), "hello\nhello\n");
+
+    // the standard error is discarded without a sink
+    let errno = func.invoke(&[2.into()]).unwrap().unwrap();
+    assert_eq!(errno.get_u32().unwrap(), ERRNO_SUCCESS);
+    assert_eq!(read_nwritten(), 6);
+    assert_eq!(stdout.borrow().len(), 12);
+
+    let errno = func.invoke(&[3.into()]).unwrap().unwrap();
+    assert_eq!(errno.get_u32().unwrap(), ERRNO_BADF);
+
+    // other modules are not resolved by WasiEnv
+    let result = WasmModule::builder()
+        .import_function(
+            "env",
+            "fd_write",
+            WasmType::from_signature("iiiii").unwrap(),
+        )
+        .build()
+        .unwrap()
+        .instantiate(&WasiEnv);
+    assert!(result.is_err());
 }
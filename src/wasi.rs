@@ -0,0 +1,121 @@
+//! A minimal subset of WASI
+//!
+//! Only `fd_write` to the standard output and the standard error is provided.
+//! The output goes to the sinks set by [`WasmInstance::set_stdout`] and
+//! [`WasmInstance::set_stderr`], and is discarded if none is set.
+
+use crate::memory::WasmPtr;
+use crate::*;
+use core::cell::RefCell;
+use core::fmt;
+
+/// The module name of the WASI imports
+pub const MODULE_NAME: &str = "wasi_snapshot_preview1";
+
+/// No error
+pub const ERRNO_SUCCESS: u32 = 0;
+/// Bad file descriptor
+pub const ERRNO_BADF: u32 = 8;
+
+/// A destination of the output written by `fd_write`
+pub trait WasiOutput {
+    fn write(&self, bytes: &[u8]);
+}
+
+/// Collects the output as text, replacing invalid UTF-8 sequences.
+impl<W: fmt::Write> WasiOutput for RefCell<W> {
+    fn write(&self, bytes: &[u8]) {
+        let mut writer = self.borrow_mut();
+        for chunk in bytes.utf8_chunks() {
+            let _ = writer.write_str(chunk.valid());
+            if !chunk.invalid().is_empty() {
+                let _ = writer.write_char(char::REPLACEMENT_CHARACTER);
+            }
+        }
+    }
+}
+
+/// The standard output and the standard error of an instance
+#[derive(Default, Clone)]
+pub struct WasiStdio {
+    stdout: Option<Arc<dyn WasiOutput>>,
+    stderr: Option<Arc<dyn WasiOutput>>,
+}
+
+impl WasiStdio {
+    #[inline]
+    pub fn set_stdout(&mut self, output: Arc<dyn WasiOutput>) {
+        self.stdout = Some(output);
+    }
+
+    #[inline]
+    pub fn set_stderr(&mut self, output: Arc<dyn WasiOutput>) {
+        self.stderr = Some(output);
+    }
+
+    /// Returns the sink of the standard output (1) or the standard error (2),
+    /// or `None` if the output is discarded.
+    fn output(&self, fd: u32) -> Option<&dyn WasiOutput> {
+        match fd {
+            1 => self.stdout.as_deref(),
+            2 => self.stderr.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for WasiStdio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasiStdio")
+            .field("stdout", &self.stdout.is_some())
+            .field("stderr", &self.stderr.is_some())
+            .finish()
+    }
+}
+
+/// Resolves the WASI imports
+pub struct WasiEnv;
+
+impl WasmEnv for WasiEnv {
+    fn resolve_imports(&self, mod_name: &str, name: &str, _type: &WasmType) -> WasmImportResult {
+        if mod_name != MODULE_NAME {
+            return WasmImportResult::NoModule;
+        }
+        match name {
+            "fd_write" => WasmImportResult::Ok(fd_write),
+            _ => WasmImportResult::NoMethod,
+        }
+    }
+}
+
+/// `fd_write(fd, iovs, iovs_len, nwritten) -> errno`
+fn fd_write(instance: &WasmInstance, mut args: WasmArgs) -> WasmDynResult {
+    let fd = args.next::<u32>()?;
+    let iovs = args.next::<u32>()?;
+    let iovs_len = args.next::<u32>()? as usize;
+    let nwritten = args.next::<u32>()?;
+
+    if !matches!(fd, 1 | 2) {
+        return Ok(Some(WasmValue::from(ERRNO_BADF)));
+    }
+    let output = instance.stdio().output(fd);
+
+    let memory = instance.memory(0)?;
+    let mut total = 0u32;
+    {
+        let guard = memory.try_borrow()?;
+        let iovs = guard.slice(WasmPtr::<u8>::from_u32(iovs), iovs_len * 8)?;
+        for iov in iovs.chunks_exact(8) {
+            let base = u32::from_le_bytes(iov[0..4].try_into().unwrap());
+            let len = u32::from_le_bytes(iov[4..8].try_into().unwrap());
+            let bytes = guard.slice(WasmPtr::<u8>::from_u32(base), len as usize)?;
+            if let Some(output) = output {
+                output.write(bytes);
+            }
+            total = total.wrapping_add(len);
+        }
+    }
+    memory.write_slice(nwritten as usize, &total.to_le_bytes())?;
+
+    Ok(Some(WasmValue::from(ERRNO_SUCCESS)))
+}
@@ -1,9 +1,12 @@
 //! WebAssembly Interpreter
-use crate::cg::WasmCodeBlock;
+use crate::cg::{WasmCodeBlock, WasmCodeScratch};
 use crate::leb128::*;
-use crate::memory::WasmMemory;
-use crate::opcode::{WasmMnemonic, WasmOpcode};
+use crate::memory::{MemoryPolicy, WasmMemory};
+use crate::opcode::{WasmMnemonic, WasmOpcode, WasmProposal};
+use crate::sync::rwlock_nb::RwLockNb;
+use crate::wasi::{WasiOutput, WasiStdio};
 use crate::*;
+use alloc::collections::BTreeSet;
 use alloc::format;
 use alloc::string::*;
 use core::error::Error;
@@ -14,7 +17,7 @@ use core::ops::*;
 use core::ptr::slice_from_raw_parts_mut;
 use core::slice;
 use core::str;
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use smallvec::SmallVec;
 
 use self::memory::{WasmPtr, WasmPtrMut};
@@ -52,7 +55,25 @@ impl WebAssembly {
     /// Compile wasm module
     #[inline]
     pub fn compile(bytes: &[u8]) -> Result<WasmModule, Box<dyn Error>> {
-        WasmModule::compile(bytes)
+        Self::compile_with(bytes, &CompileOptions::DEFAULT)
+    }
+
+    /// Compile wasm module, rejecting it as soon as any of the limits is exceeded
+    #[inline]
+    pub fn compile_with_limits(
+        bytes: &[u8],
+        limits: &CompileLimits,
+    ) -> Result<WasmModule, Box<dyn Error>> {
+        Self::compile_with(bytes, &CompileOptions::new().with_limits(*limits))
+    }
+
+    /// Compile wasm module with the specified options
+    #[inline]
+    pub fn compile_with(
+        bytes: &[u8],
+        options: &CompileOptions,
+    ) -> Result<WasmModule, Box<dyn Error>> {
+        WasmModule::compile(bytes, options)
     }
 
     #[inline]
@@ -60,6 +81,307 @@ impl WebAssembly {
     pub fn validate(bytes: &[u8]) -> bool {
         Self::compile(bytes).is_ok()
     }
+
+    /// Compiles a single function body and evaluates it with the given parameters
+    ///
+    /// `bytes` is a function body as it appears in the code section, i.e. the local declarations
+    /// followed by the instructions and the final `end`. The function runs in a module of its own
+    /// with no imports and no memory, and all of its results are returned in order.
+    ///
+    /// ```
+    /// use wami::prelude::*;
+    /// use wami::WasmValType;
+    ///
+    /// // (i32.add (i32.const 2) (i32.const 3))
+    /// let body = [0x00, 0x41, 0x02, 0x41, 0x03, 0x6A, 0x0B];
+    /// let results = WebAssembly::eval_expr(&body, &[], &[WasmValType::I32], &[]).unwrap();
+    /// assert_eq!(results[0].get_i32().unwrap(), 5);
+    /// ```
+    pub fn eval_expr(
+        bytes: &[u8],
+        param_types: &[WasmValType],
+        result_types: &[WasmValType],
+        params: &[WasmValue],
+    ) -> WasmResult<Vec<WasmValue>> {
+        let module = WasmModule::builder()
+            .function(WasmSignature::new(param_types, result_types).into(), bytes)
+            .build()?;
+        WasmInstance::new(module)
+            .function_by_index(0)?
+            .invoke_multi(params)
+    }
+
+    /// Collects the opcodes used in all function bodies without compiling them
+    ///
+    /// Only the code section is decoded, so this is a cheap way to find out which instructions
+    /// a module needs before compiling it. Opcodes unknown to this crate (e.g. SIMD) are
+    /// reported as [`WasmCompileErrorKind::InvalidBytecode`].
+    pub fn scan_opcodes(bytes: &[u8]) -> Result<BTreeSet<WasmMnemonic>, WasmCompileErrorKind> {
+        if !Self::identify(bytes) {
+            return Err(WasmCompileErrorKind::BadExecutable);
+        }
+        let mut result = BTreeSet::new();
+        let mut reader = Leb128Reader::from_slice(&bytes[8..]);
+        while let Some(mut section) = WasmSection::from_reader(&mut reader)? {
+            if section.section_id != WasmSectionId::Code {
+                continue;
+            }
+            let n_items: usize = section.reader.read()?;
+            for _ in 0..n_items {
+                let length: usize = section.reader.read()?;
+                let mut code = section.reader.sub_reader(length)?;
+                let n_local_var_types: usize = code.read()?;
+                for _ in 0..n_local_var_types {
+                    let _repeat = code.read_unsigned()?;
+                    let _val_type = code.read_byte()?;
+                }
+                while !code.is_eof() {
+                    let opcode = WasmOpcode::fetch(&mut code)?;
+                    // immediates of these opcodes are not decoded by `fetch`
+                    let n_immediates = match opcode {
+                        WasmOpcode::SelectT(n_types) => n_types as usize,
+                        WasmOpcode::MemoryCopy | WasmOpcode::ReturnCallIndirect => 2,
+                        WasmOpcode::MemoryFill
+                        | WasmOpcode::MemoryInit(_)
+                        | WasmOpcode::ReturnCall
+                        | WasmOpcode::CallRef
+                        | WasmOpcode::ReturnCallRef
+                        | WasmOpcode::BrOnNull => 1,
+                        _ => 0,
+                    };
+                    for _ in 0..n_immediates {
+                        code.read_unsigned()?;
+                    }
+                    result.insert(opcode.mnemonic());
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Upper bounds of the resources a module may declare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompileLimits {
+    /// Number of types in the type section
+    pub max_types: usize,
+    /// Number of functions, including imported functions
+    pub max_functions: usize,
+    /// Total size of all function bodies in bytes
+    pub max_code_size: usize,
+    /// Number of local variables per function, including parameters
+    pub max_locals: usize,
+    /// Initial or maximum size of each table
+    pub max_table_size: u32,
+    /// Initial or maximum number of pages of each memory
+    pub max_memory_pages: u32,
+}
+
+impl CompileLimits {
+    pub const UNLIMITED: Self = Self {
+        max_types: usize::MAX,
+        max_functions: usize::MAX,
+        max_code_size: usize::MAX,
+        max_locals: usize::MAX,
+        max_table_size: u32::MAX,
+        max_memory_pages: u32::MAX,
+    };
+
+    #[inline]
+    fn check(
+        value: usize,
+        limit: usize,
+        kind: CompileLimitKind,
+    ) -> Result<(), WasmCompileErrorKind> {
+        if value > limit {
+            Err(WasmCompileErrorKind::LimitsExceeded(kind))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    fn check_limit(
+        limit: &WasmLimit,
+        max: u32,
+        kind: CompileLimitKind,
+    ) -> Result<(), WasmCompileErrorKind> {
+        let size = limit.max().unwrap_or(limit.min()).max(limit.min());
+        Self::check(size as usize, max as usize, kind)
+    }
+
+    /// Checks the length of a code section, which holds at most `max_code_size` bytes of bodies
+    /// besides the count and the length prefix of each body.
+    #[inline]
+    fn check_code_section(&self, length: usize) -> Result<(), WasmCompileErrorKind> {
+        let prefixes = self.max_functions.saturating_add(1).saturating_mul(5);
+        Self::check(
+            length,
+            self.max_code_size.saturating_add(prefixes),
+            CompileLimitKind::CodeSize,
+        )
+    }
+}
+
+impl Default for CompileLimits {
+    #[inline]
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+/// Options that control how a module is compiled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompileOptions {
+    limits: CompileLimits,
+    fusion: bool,
+    proposals: Option<ProposalSet>,
+    memory_policy: MemoryPolicy,
+}
+
+impl CompileOptions {
+    /// No limits, instruction fusion enabled and all supported proposals allowed
+    pub const DEFAULT: Self = Self {
+        limits: CompileLimits::UNLIMITED,
+        fusion: true,
+        proposals: None,
+        memory_policy: MemoryPolicy::Eager,
+    };
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self::DEFAULT
+    }
+
+    /// Sets the upper bounds of the resources a module may declare.
+    #[inline]
+    pub const fn with_limits(mut self, limits: CompileLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Enables or disables fusing adjacent instructions into superinstructions.
+    ///
+    /// The unfused code runs with the same results, so turning this off helps to find out
+    /// whether a wrong result comes from the fusion pass.
+    #[inline]
+    pub const fn with_fusion(mut self, fusion: bool) -> Self {
+        self.fusion = fusion;
+        self
+    }
+
+    /// Allows only the MVP and the specified proposals.
+    ///
+    /// Instructions belonging to any other proposal are rejected with
+    /// [`WasmCompileErrorKind::ProposalNotAllowed`].
+    #[inline]
+    pub fn with_proposals(mut self, proposals: &[WasmProposal]) -> Self {
+        self.proposals = Some(proposals.iter().copied().collect());
+        self
+    }
+
+    /// Sets how the pages of the linear memories are allocated.
+    #[inline]
+    pub const fn with_memory_policy(mut self, memory_policy: MemoryPolicy) -> Self {
+        self.memory_policy = memory_policy;
+        self
+    }
+
+    #[inline]
+    pub const fn limits(&self) -> &CompileLimits {
+        &self.limits
+    }
+
+    #[inline]
+    pub const fn memory_policy(&self) -> MemoryPolicy {
+        self.memory_policy
+    }
+
+    #[inline]
+    pub const fn fusion(&self) -> bool {
+        self.fusion
+    }
+
+    #[inline]
+    pub const fn is_allowed(&self, proposal: WasmProposal) -> bool {
+        match self.proposals {
+            Some(_) if matches!(proposal, WasmProposal::Mvp) => true,
+            Some(proposals) => proposals.contains(proposal),
+            None => true,
+        }
+    }
+}
+
+impl Default for CompileOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A set of [`WasmProposal`]s
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProposalSet(u32);
+
+impl ProposalSet {
+    pub const EMPTY: Self = Self(0);
+
+    #[inline]
+    pub const fn new() -> Self {
+        Self::EMPTY
+    }
+
+    #[inline]
+    const fn bit(proposal: WasmProposal) -> u32 {
+        1 << (proposal as u32)
+    }
+
+    #[inline]
+    pub const fn contains(&self, proposal: WasmProposal) -> bool {
+        (self.0 & Self::bit(proposal)) != 0
+    }
+
+    #[inline]
+    pub const fn insert(&mut self, proposal: WasmProposal) {
+        self.0 |= Self::bit(proposal);
+    }
+
+    #[inline]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns whether all proposals in this set are also in `other`.
+    #[inline]
+    pub const fn is_subset(&self, other: &Self) -> bool {
+        (self.0 & !other.0) == 0
+    }
+}
+
+impl FromIterator<WasmProposal> for ProposalSet {
+    fn from_iter<T: IntoIterator<Item = WasmProposal>>(iter: T) -> Self {
+        let mut result = Self::EMPTY;
+        for proposal in iter {
+            result.insert(proposal);
+        }
+        result
+    }
+}
+
+/// Which of the [`CompileLimits`] was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompileLimitKind {
+    Types,
+    Functions,
+    CodeSize,
+    Locals,
+    TableSize,
+    MemoryPages,
 }
 
 pub type WasmResult<T> = Result<T, Box<dyn Error>>;
@@ -69,6 +391,36 @@ pub type WasmDynFunc = fn(&WasmInstance, WasmArgs) -> WasmDynResult;
 pub trait WasmEnv {
     fn resolve_imports(&self, mod_name: &str, name: &str, type_: &WasmType) -> WasmImportResult;
 
+    /// Returns the initial elements of an imported table, or `None` to leave them all null.
+    ///
+    /// The table grows to hold all the elements, as long as it stays within `limit`.
+    /// Active element segments of the module are applied on top of these elements.
+    #[inline]
+    fn resolve_table(
+        &self,
+        mod_name: &str,
+        name: &str,
+        limit: &WasmLimit,
+    ) -> Option<Vec<Option<WasmHostFunction>>> {
+        let _ = (mod_name, name, limit);
+        None
+    }
+
+    /// Returns the value of an imported global, or `None` to leave it zero.
+    ///
+    /// A value of another type than `val_type` fails to link.
+    #[inline]
+    fn resolve_global(
+        &self,
+        mod_name: &str,
+        name: &str,
+        val_type: WasmValType,
+        is_mutable: bool,
+    ) -> Option<WasmValue> {
+        let _ = (mod_name, name, val_type, is_mutable);
+        None
+    }
+
     /// Run the first resolver, and if none is found, run the next one.
     #[inline]
     fn chain<'a, T: WasmEnv>(&'a self, next: &'a T) -> WasmEnvChain<'a, Self, T> {
@@ -88,6 +440,48 @@ impl<A: WasmEnv, B: WasmEnv> WasmEnv for WasmEnvChain<'_, A, B> {
             _ => self.b.resolve_imports(mod_name, name, type_),
         }
     }
+
+    fn resolve_table(
+        &self,
+        mod_name: &str,
+        name: &str,
+        limit: &WasmLimit,
+    ) -> Option<Vec<Option<WasmHostFunction>>> {
+        self.a
+            .resolve_table(mod_name, name, limit)
+            .or_else(|| self.b.resolve_table(mod_name, name, limit))
+    }
+
+    fn resolve_global(
+        &self,
+        mod_name: &str,
+        name: &str,
+        val_type: WasmValType,
+        is_mutable: bool,
+    ) -> Option<WasmValue> {
+        self.a
+            .resolve_global(mod_name, name, val_type, is_mutable)
+            .or_else(|| self.b.resolve_global(mod_name, name, val_type, is_mutable))
+    }
+}
+
+/// A host function that can be stored in a table
+#[derive(Clone)]
+pub struct WasmHostFunction {
+    func_type: WasmType,
+    func: WasmDynFunc,
+}
+
+impl WasmHostFunction {
+    #[inline]
+    pub const fn new(func_type: WasmType, func: WasmDynFunc) -> Self {
+        Self { func_type, func }
+    }
+
+    #[inline]
+    pub const fn func_type(&self) -> &WasmType {
+        &self.func_type
+    }
 }
 
 pub enum WasmImportResult {
@@ -108,8 +502,12 @@ pub struct WasmModule {
     exports: Vec<WasmExport>,
     start: Option<usize>,
     data_count: Option<usize>,
+    data_segments: Vec<WasmDataSegment>,
+    elem_segments: Vec<WasmElemSegment>,
     custom_sections: BTreeMap<String, Box<[u8]>>,
     names: Option<WasmName>,
+    options: CompileOptions,
+    features: ProposalSet,
 }
 
 impl fmt::Debug for WasmModule {
@@ -131,93 +529,147 @@ impl WasmModule {
             exports: Vec::new(),
             start: None,
             data_count: None,
+            data_segments: Vec::new(),
+            elem_segments: Vec::new(),
             custom_sections: BTreeMap::new(),
             names: None,
+            options: CompileOptions::DEFAULT,
+            features: ProposalSet::EMPTY,
         }
     }
 
+    /// Returns a builder to construct a module without the binary format.
+    #[inline]
+    pub fn builder() -> WasmModuleBuilder {
+        WasmModuleBuilder::new()
+    }
+
+    /// Checks that section `next` may follow section `prev`.
+    ///
+    /// `prev` is the last section other than a custom section; custom sections may appear anywhere.
     #[inline]
-    fn compile(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+    pub const fn check_section_order(
+        prev: WasmSectionId,
+        next: WasmSectionId,
+    ) -> Result<(), WasmCompileErrorKind> {
+        if prev.depends_on_order() && next.depends_on_order() && prev as u8 > next as u8 {
+            Err(WasmCompileErrorKind::InvalidSectionOrder(next))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    fn compile(bytes: &[u8], options: &CompileOptions) -> Result<Self, Box<dyn Error>> {
         if !WebAssembly::identify(bytes) {
             return Err(WasmCompileErrorKind::BadExecutable.into());
         }
-        let mut module = Self::default();
+        let mut module = Self {
+            options: *options,
+            ..Default::default()
+        };
         let mut reader = Leb128Reader::from_slice(&bytes[8..]);
         let reader = &mut reader;
-        let mut last_section_id = WasmSectionId::Type;
-
-        while let Some(mut section) = WasmSection::from_reader(reader)? {
-            match section.section_id {
-                WasmSectionId::Custom => {
-                    match section.reader.read() {
-                        Ok(section_name) => {
-                            let mut blob = Vec::new();
-                            section
-                                .reader
-                                .read_to_end(&mut blob)
-                                .map_err(|err| WasmCompileErrorKind::from(err))?;
-
-                            match section_name {
-                                WasmName::SECTION_NAME => {
-                                    let mut reader = Leb128Reader::from_slice(blob.as_slice());
-                                    module.names = WasmName::from_reader(&mut reader).ok();
-                                }
-                                _ => (),
-                            }
 
-                            module
-                                .custom_sections
-                                .insert(section_name.to_owned(), blob.into_boxed_slice());
-                        }
-                        Err(_) => {
-                            // ignored
-                        }
-                    };
-                }
-                _ => (),
+        // custom sections are collected in a separate pass
+        while let Some(section) = WasmSection::from_reader(reader)? {
+            if section.section_id == WasmSectionId::Custom {
+                module.parse_sec_custom(section)?;
             }
         }
         reader.reset();
 
+        let mut state = WasmSectionState::default();
         while let Some(section) = WasmSection::from_reader(reader)? {
-            if section.section_id.depends_on_order() {
-                if last_section_id > section.section_id {
-                    return Err(
-                        WasmCompileErrorKind::InvalidSectionOrder(section.section_id).into(),
-                    );
-                }
-                last_section_id = section.section_id;
+            module.parse_section(section, &mut state)?;
+        }
+
+        module.finish_sections(&state)
+    }
+
+    /// Parses a section other than a custom section.
+    fn parse_section(
+        &mut self,
+        section: WasmSection,
+        state: &mut WasmSectionState,
+    ) -> Result<(), Box<dyn Error>> {
+        Self::check_section_order(state.last_section_id, section.section_id)?;
+        if section.section_id.depends_on_order() {
+            state.last_section_id = section.section_id;
+        }
+        let section_id = section.section_id;
+        let section_range =
+            section.file_position()..section.file_position() + section.content_size();
+        match section_id {
+            WasmSectionId::Custom => Ok(()),
+            WasmSectionId::Type => self.parse_sec_type(section).map_err(Into::into),
+            WasmSectionId::Import => self.parse_sec_import(section).map_err(Into::into),
+            WasmSectionId::Function => self.parse_sec_func(section).map_err(Into::into),
+            WasmSectionId::Table => self.parse_sec_table(section).map_err(Into::into),
+            WasmSectionId::Memory => self.parse_sec_memory(section).map_err(Into::into),
+            WasmSectionId::Global => self.parse_sec_global(section),
+            WasmSectionId::Export => self.parse_sec_export(section).map_err(Into::into),
+            WasmSectionId::Start => self.parse_sec_start(section).map_err(Into::into),
+            WasmSectionId::Element => self.parse_sec_elem(section),
+            WasmSectionId::Code => self.parse_sec_code(section),
+            WasmSectionId::Data => {
+                state.has_data = true;
+                self.parse_sec_data(section)
             }
-            match section.section_id {
-                WasmSectionId::Custom => (),
-                WasmSectionId::Type => module.parse_sec_type(section)?,
-                WasmSectionId::Import => module.parse_sec_import(section)?,
-                WasmSectionId::Function => module.parse_sec_func(section)?,
-                WasmSectionId::Table => module.parse_sec_table(section)?,
-                WasmSectionId::Memory => module.parse_sec_memory(section)?,
-                WasmSectionId::Global => module.parse_sec_global(section)?,
-                WasmSectionId::Export => module.parse_sec_export(section)?,
-                WasmSectionId::Start => module.parse_sec_start(section)?,
-                WasmSectionId::Element => module.parse_sec_elem(section)?,
-                WasmSectionId::Code => module.parse_sec_code(section)?,
-                WasmSectionId::Data => module.parse_sec_data(section)?,
-                WasmSectionId::DataCount => module.parse_sec_data_count(section)?,
-            };
+            WasmSectionId::DataCount => self.parse_sec_data_count(section).map_err(Into::into),
+        }
+        .map_err(|err: WasmCompileError| err.with_section(section_id, section_range))?;
+        Ok(())
+    }
+
+    /// Checks the sections as a whole once all of them are parsed.
+    fn finish_sections(mut self, state: &WasmSectionState) -> Result<Self, Box<dyn Error>> {
+        if !state.has_data && self.data_count.is_some_and(|v| v > 0) {
+            return Err(WasmCompileErrorKind::InvalidData.into());
+        }
+        // functions declared without the code section
+        if self
+            .functions
+            .iter()
+            .any(|v| !v.is_external && matches!(v.content(), WasmFunctionContent::Unresolved))
+        {
+            return Err(WasmCompileErrorKind::FunctionAndCodeSectionMismatch.into());
         }
 
-        module.types.shrink_to_fit();
-        module.imports.shrink_to_fit();
-        module.functions.shrink_to_fit();
-        module.tables.shrink_to_fit();
-        module.memories.shrink_to_fit();
-        module.globals.shrink_to_fit();
-        module.exports.shrink_to_fit();
+        self.types.shrink_to_fit();
+        self.imports.shrink_to_fit();
+        self.functions.shrink_to_fit();
+        self.tables.shrink_to_fit();
+        self.memories.shrink_to_fit();
+        self.globals.shrink_to_fit();
+        self.exports.shrink_to_fit();
 
-        Ok(module)
+        Ok(self)
+    }
+
+    /// Parse "custom" section
+    fn parse_sec_custom(&mut self, mut section: WasmSection) -> Result<(), WasmCompileErrorKind> {
+        let Ok::<&str, _>(section_name) = section.reader.read() else {
+            // ignored
+            return Ok(());
+        };
+        let mut blob = Vec::new();
+        section.reader.read_to_end(&mut blob)?;
+
+        if section_name == WasmName::SECTION_NAME {
+            let mut reader = Leb128Reader::from_slice(blob.as_slice());
+            self.names = WasmName::from_reader(&mut reader).ok();
+        }
+
+        self.custom_sections
+            .insert(section_name.to_owned(), blob.into_boxed_slice());
+        Ok(())
     }
 
     pub fn instantiate<Env: WasmEnv>(mut self, env: &Env) -> Result<WasmInstance, Box<dyn Error>> {
         let mut func_idx = 0;
+        let mut table_idx = 0;
+        let mut global_idx = 0;
         for import in &self.imports {
             match import.desc {
                 WasmImportDescriptor::Function(type_index) => {
@@ -238,17 +690,72 @@ impl WasmModule {
                     }
                     func_idx += 1;
                 }
+                WasmImportDescriptor::Table(_, limit) => {
+                    if let Some(elements) =
+                        env.resolve_table(&import.mod_name, &import.name, &limit)
+                    {
+                        let invalid = || WasmLinkError::InvalidTable(import.name.clone());
+                        let mut indices = Vec::with_capacity(elements.len());
+                        for element in elements {
+                            let index = match element {
+                                Some(host) => {
+                                    let index = self.functions.len();
+                                    let func = WasmFunction::from_host(index, &self.types, host)
+                                        .ok_or_else(invalid)?;
+                                    self.functions.push(func);
+                                    index
+                                }
+                                None => WasmTable::NULL_REF,
+                            };
+                            indices.push(index);
+                        }
+                        let table = &mut self.tables[table_idx];
+                        table.import(&indices).map_err(|_| invalid())?;
+                        // the module's own elements take precedence over the host's
+                        for segment in self.elem_segments.iter() {
+                            if segment.table_index == table_idx {
+                                let end = segment.offset + segment.elements.len();
+                                table.table()[segment.offset..end]
+                                    .copy_from_slice(&segment.elements);
+                            }
+                        }
+                    }
+                    table_idx += 1;
+                }
+                WasmImportDescriptor::Global(val_type, is_mutable) => {
+                    if let Some(value) =
+                        env.resolve_global(&import.mod_name, &import.name, val_type, is_mutable)
+                    {
+                        if !value.is_valid_type(val_type) {
+                            return Err(WasmLinkError::InvalidGlobal(import.name.clone()).into());
+                        }
+                        self.globals[global_idx].initialize(value);
+                    }
+                    global_idx += 1;
+                }
                 WasmImportDescriptor::Memory(_) => {
                     // TODO: import memory
                 }
             }
         }
+        // initializers may read imported globals, so evaluate them again in definition order
+        for index in global_idx..self.globals.len() {
+            if let Some(source) = self.globals[index].init_global {
+                let value = self.globals[source].value();
+                self.globals[index].initialize(value);
+            }
+        }
         Ok(WasmInstance::new(self))
     }
 
     /// Parse "type" section
     fn parse_sec_type(&mut self, mut section: WasmSection) -> Result<(), WasmCompileErrorKind> {
         let n_items: usize = section.reader.read()?;
+        CompileLimits::check(
+            self.types.len().saturating_add(n_items),
+            self.options.limits.max_types,
+            CompileLimitKind::Types,
+        )?;
         for _ in 0..n_items {
             let ft = WasmType::from_reader(&mut section.reader)?;
             self.types.push(ft);
@@ -264,6 +771,11 @@ impl WasmModule {
             match import.desc {
                 WasmImportDescriptor::Function(type_index) => {
                     let index = self.functions.len();
+                    CompileLimits::check(
+                        index + 1,
+                        self.options.limits.max_functions,
+                        CompileLimitKind::Functions,
+                    )?;
                     let func_type = self
                         .types
                         .get(type_index.as_usize())
@@ -275,8 +787,29 @@ impl WasmModule {
                     ));
                 }
                 WasmImportDescriptor::Memory(memtype) => {
+                    CompileLimits::check_limit(
+                        &memtype,
+                        self.options.limits.max_memory_pages,
+                        CompileLimitKind::MemoryPages,
+                    )?;
                     // TODO: import memory
-                    self.memories.push(WasmMemory::new(memtype)?);
+                    self.memories.push(WasmMemory::with_policy(
+                        memtype,
+                        self.options.memory_policy,
+                    )?);
+                }
+                WasmImportDescriptor::Table(elem_type, limit) => {
+                    self.tables.push(WasmTable::new(
+                        elem_type,
+                        limit,
+                        self.options.limits.max_table_size,
+                    )?);
+                }
+                WasmImportDescriptor::Global(val_type, is_mutable) => {
+                    self.globals.push(WasmGlobal::new(
+                        WasmValue::default_for(val_type),
+                        is_mutable,
+                    )?);
                 }
             }
             self.imports.push(import);
@@ -287,6 +820,11 @@ impl WasmModule {
     /// Parse "func" section
     fn parse_sec_func(&mut self, mut section: WasmSection) -> Result<(), WasmCompileErrorKind> {
         let n_items: usize = section.reader.read()?;
+        CompileLimits::check(
+            self.functions.len().saturating_add(n_items),
+            self.options.limits.max_functions,
+            CompileLimitKind::Functions,
+        )?;
         let base_index = self.imports.len();
         for index in 0..n_items {
             let type_index = WasmTypeIndex(section.reader.read()?);
@@ -318,7 +856,13 @@ impl WasmModule {
         let n_items: usize = section.reader.read()?;
         for _ in 0..n_items {
             let limit = WasmLimit::from_reader(&mut section.reader, true)?;
-            self.memories.push(WasmMemory::new(limit)?);
+            CompileLimits::check_limit(
+                &limit,
+                self.options.limits.max_memory_pages,
+                CompileLimitKind::MemoryPages,
+            )?;
+            self.memories
+                .push(WasmMemory::with_policy(limit, self.options.memory_policy)?);
         }
         Ok(())
     }
@@ -327,7 +871,8 @@ impl WasmModule {
     fn parse_sec_table(&mut self, mut section: WasmSection) -> Result<(), WasmCompileErrorKind> {
         let n_items: usize = section.reader.read()?;
         for _ in 0..n_items {
-            let table = WasmTable::from_reader(&mut section.reader)?;
+            let table =
+                WasmTable::from_reader(&mut section.reader, self.options.limits.max_table_size)?;
             self.tables.push(table);
         }
         Ok(())
@@ -344,25 +889,41 @@ impl WasmModule {
                 .tables
                 .get_mut(tabidx)
                 .ok_or(WasmCompileErrorKind::InvalidData)?;
-            for i in offset..offset + n_elements {
+            if table.elem_type() != WasmRefType::FuncRef {
+                return Err(WasmCompileErrorKind::TypeMismatch.into());
+            }
+            let mut elements = Vec::with_capacity(n_elements);
+            for _ in 0..n_elements {
                 let elem: usize = section.reader.read()?;
-                table.table.get_mut(i).map(|v| *v = elem);
+                elements.push(elem);
             }
+            // the segment is applied only if it fits in the table as a whole
+            let table = table.table();
+            let dest = offset
+                .checked_add(n_elements)
+                .and_then(|end| table.get_mut(offset..end))
+                .ok_or(WasmCompileErrorKind::InvalidData)?;
+            dest.copy_from_slice(&elements);
+            self.elem_segments.push(WasmElemSegment {
+                table_index: tabidx,
+                offset,
+                elements: elements.into_boxed_slice(),
+            });
         }
         Ok(())
     }
 
     /// Parse "code" section
     fn parse_sec_code(&mut self, mut section: WasmSection) -> Result<(), WasmCompileError> {
-        let base = self
-            .functions
-            .iter()
-            .enumerate()
-            .find(|(_, v)| !v.is_external && matches!(v.content(), WasmFunctionContent::Unresolved))
-            .map(|(i, _)| i)
-            .ok_or(WasmCompileErrorKind::OutOfFunction)?;
-
         let n_items: usize = section.reader.read()?;
+        let n_functions = self.functions.iter().filter(|v| !v.is_external).count();
+        if n_items != n_functions {
+            return Err(WasmCompileErrorKind::FunctionAndCodeSectionMismatch.into());
+        }
+        let base = self.functions.len() - n_functions;
+
+        let mut code_size = 0usize;
+        let mut scratch = WasmCodeScratch::new();
         for i in 0..n_items {
             let index = base + i;
 
@@ -371,17 +932,40 @@ impl WasmModule {
                 .get(index)
                 .ok_or(WasmCompileErrorKind::OutOfFunction)?;
             let length: usize = section.reader.read()?;
+            code_size = code_size.saturating_add(length);
+            CompileLimits::check(
+                code_size,
+                self.options.limits.max_code_size,
+                CompileLimitKind::CodeSize,
+            )?;
             let file_position = section.file_position() + section.reader.position();
-            let mut reader = section.reader.sub_slice(length).unwrap();
-            let code_block = WasmCodeBlock::generate(
+            let mut reader = section.reader.sub_reader(length)?;
+            let code_block = WasmCodeBlock::generate_with_scratch(
                 index,
                 file_position,
                 &mut reader,
                 func_def.param_types(),
                 func_def.result_types(),
                 self,
+                &mut scratch,
             )?;
+            if !reader.is_eof() {
+                // the body must end exactly at the final `end`
+                let position = file_position + reader.position();
+                return Err(WasmCompileError::new(
+                    WasmCompileErrorKind::InvalidData,
+                    ExceptionPosition::new(position),
+                    CompileErrorSource::Function(
+                        index,
+                        None,
+                        ExceptionPosition::new(position),
+                        None,
+                    ),
+                )
+                .with_byte_range(position..file_position + length));
+            }
 
+            self.features = self.features.union(code_block.proposals());
             self.functions
                 .get_mut(index)
                 .ok_or(WasmCompileErrorKind::OutOfFunction)
@@ -393,8 +977,24 @@ impl WasmModule {
     /// Parse "data" section
     fn parse_sec_data(&mut self, mut section: WasmSection) -> Result<(), WasmCompileError> {
         let n_items: usize = section.reader.read()?;
+        if self.data_count.is_some_and(|v| v != n_items) {
+            return Err(WasmCompileErrorKind::InvalidData.into());
+        }
         for _ in 0..n_items {
-            let memidx: usize = section.reader.read()?;
+            let flags: u32 = section.reader.read()?;
+            let memidx: usize = match flags {
+                0 => 0,
+                1 => {
+                    let src = section.reader.read_blob()?;
+                    self.data_segments.push(WasmDataSegment {
+                        active: None,
+                        data: src.into(),
+                    });
+                    continue;
+                }
+                2 => section.reader.read()?,
+                _ => return Err(WasmCompileErrorKind::InvalidData.into()),
+            };
             let offset = self.eval_offset(&mut section)?;
             let src = section.reader.read_blob()?;
             let memory = self
@@ -403,7 +1003,11 @@ impl WasmModule {
                 .ok_or(WasmCompileErrorKind::InvalidData)?;
             memory
                 .write_slice(offset, src)
-                .map_err(|_| WasmCompileErrorKind::InvalidData)?
+                .map_err(|_| WasmCompileErrorKind::InvalidData)?;
+            self.data_segments.push(WasmDataSegment {
+                active: Some((memidx, offset)),
+                data: src.into(),
+            });
         }
         Ok(())
     }
@@ -425,13 +1029,15 @@ impl WasmModule {
                 .map_err(|v| v.into())
                 .and_then(|v| WasmValType::from_u8(v))?;
             let is_mutable = section.reader.read_byte()? == 1;
-            let value = self.eval_const_expr(&mut section)?;
+            let (value, init_global) = self.eval_const_expr(&mut section)?;
 
             if !value.is_valid_type(val_type) {
                 return Err(WasmCompileErrorKind::InvalidGlobal.into());
             }
 
-            WasmGlobal::new(value, is_mutable).map(|v| self.globals.push(v))?;
+            let mut global = WasmGlobal::new(value, is_mutable)?;
+            global.init_global = init_global;
+            self.globals.push(global);
         }
         Ok(())
     }
@@ -447,25 +1053,40 @@ impl WasmModule {
     }
 
     fn eval_offset(&self, section: &mut WasmSection) -> Result<usize, WasmCompileError> {
-        self.eval_const_expr(section)
-            .and_then(|v| {
-                v.get_i32()
-                    .map_err(|_| WasmCompileErrorKind::InvalidData.into())
-            })
+        let (value, init_global) = self.eval_const_expr(section)?;
+        if init_global.is_some() {
+            // the value of an imported global is not known until instantiation
+            return Err(WasmCompileErrorKind::UnsupportedBytecode(WasmMnemonic::GlobalGet).into());
+        }
+        value
+            .get_i32()
             .map(|v| v as usize)
+            .map_err(|_| WasmCompileErrorKind::InvalidData.into())
     }
 
-    fn eval_const_expr(&self, section: &mut WasmSection) -> Result<WasmValue, WasmCompileError> {
+    /// Returns the value of the constant expression,
+    /// and the global it reads if it is a `global.get`.
+    fn eval_const_expr(
+        &self,
+        section: &mut WasmSection,
+    ) -> Result<(WasmValue, Option<usize>), WasmCompileError> {
         let base_position = section.file_position();
         let mut ex_position = ExceptionPosition::UNKNOWN;
         let reader = &mut section.reader;
         self._eval_const_expr(reader, &mut ex_position)
             .map_err(|kind| {
+                let start = ex_position.position();
+                reader.set_position(start);
+                if WasmOpcode::fetch(reader).is_err() {
+                    reader.skip_leb128();
+                }
+                let end = reader.position();
                 WasmCompileError::new(
                     kind,
-                    ExceptionPosition::new(base_position + ex_position.position()),
+                    ExceptionPosition::new(base_position + start),
                     CompileErrorSource::ConstantExpression(ex_position),
                 )
+                .with_byte_range(base_position + start..base_position + end)
             })
     }
 
@@ -473,27 +1094,53 @@ impl WasmModule {
         &self,
         reader: &mut Leb128Reader,
         ex_position: &mut ExceptionPosition,
-    ) -> Result<WasmValue, WasmCompileErrorKind> {
+    ) -> Result<(WasmValue, Option<usize>), WasmCompileErrorKind> {
+        // each value is paired with the global it was read from
         let mut vs = Vec::new();
         loop {
             *ex_position = ExceptionPosition::new(reader.position());
             let bc = WasmOpcode::fetch(reader)?;
             match bc {
-                WasmOpcode::I32Const(v) => vs.push(WasmValue::from(v)),
-                WasmOpcode::I64Const(v) => vs.push(WasmValue::from(v)),
-                WasmOpcode::F32Const(v) => vs.push(WasmValue::from(v)),
-                WasmOpcode::F64Const(v) => vs.push(WasmValue::from(v)),
-                WasmOpcode::End => match vs.last() {
-                    Some(v) => return Ok(*v),
-                    None => {
-                        return Err(WasmCompileErrorKind::InvalidData);
-                    }
+                WasmOpcode::I32Const(v) => vs.push((WasmValue::from(v), None)),
+                WasmOpcode::I64Const(v) => vs.push((WasmValue::from(v), None)),
+                WasmOpcode::F32Const(v) => vs.push((WasmValue::from(v), None)),
+                WasmOpcode::F64Const(v) => vs.push((WasmValue::from(v), None)),
+                WasmOpcode::GlobalGet(index) => {
+                    // only the immutable globals defined or imported before can be read
+                    let index = index as usize;
+                    let global = self
+                        .globals
+                        .get(index)
+                        .filter(|v| !v.is_mutable())
+                        .ok_or(WasmCompileErrorKind::InvalidGlobal)?;
+                    vs.push((global.value(), Some(index)));
+                }
+                // the expression must leave exactly one value
+                WasmOpcode::End => match vs.as_slice() {
+                    [value] => return Ok(*value),
+                    _ => return Err(WasmCompileErrorKind::InvalidData),
                 },
                 _ => return Err(WasmCompileErrorKind::UnsupportedBytecode(bc.mnemonic())),
             }
         }
     }
 
+    /// Returns the function types of the type section together with their indices.
+    ///
+    /// Structurally identical types are not merged and keep their own indices.
+    #[inline]
+    pub fn types(&self) -> impl ExactSizeIterator<Item = (WasmTypeIndex, &WasmType)> {
+        self.types
+            .iter()
+            .enumerate()
+            .map(|(index, v)| (WasmTypeIndex(index as u32), v))
+    }
+
+    #[inline]
+    pub fn type_count(&self) -> usize {
+        self.types.len()
+    }
+
     #[inline]
     pub(crate) fn type_by_index(&self, index: WasmTypeIndex) -> &WasmType {
         unsafe { self.types.get_unchecked(index.as_usize()) }
@@ -505,6 +1152,18 @@ impl WasmModule {
             module: v.mod_name.as_str(),
             name: v.name.as_str(),
             kind: ImportExportKind::from_import_desc(&v.desc),
+            desc: match v.desc {
+                WasmImportDescriptor::Function(type_index) => {
+                    ImportKind::Function(self.type_by_index(type_index))
+                }
+                WasmImportDescriptor::Table(elem_type, limit) => {
+                    ImportKind::Table(limit, elem_type)
+                }
+                WasmImportDescriptor::Memory(limit) => ImportKind::Memory(limit),
+                WasmImportDescriptor::Global(val_type, is_mutable) => {
+                    ImportKind::Global(val_type, is_mutable)
+                }
+            },
         })
     }
 
@@ -513,9 +1172,136 @@ impl WasmModule {
         self.exports.iter().map(|v| ModuleExport {
             name: v.name.as_str(),
             kind: ImportExportKind::from_export_desc(&v.desc),
+            desc: match v.desc {
+                WasmExportDesc::Function(index) => {
+                    ExportKind::Function(index, &self.functions[index].func_type)
+                }
+                WasmExportDesc::Table(index) => {
+                    let table = &self.tables[index];
+                    ExportKind::Table(index, table.limit(), table.elem_type())
+                }
+                WasmExportDesc::Memory(index) => ExportKind::Memory(index),
+                WasmExportDesc::Global(index) => {
+                    let global = self.global_get(index);
+                    ExportKind::Global(index.as_usize(), global.val_type(), global.is_mutable())
+                }
+            },
+        })
+    }
+
+    /// Returns the index and name of the exported memory.
+    ///
+    /// The export named `"memory"` is preferred if more than one memory is exported.
+    pub fn exported_memory(&self) -> Option<(usize, &str)> {
+        self.find_export("memory", |desc| match *desc {
+            WasmExportDesc::Memory(index) => Some(index),
+            _ => None,
         })
     }
 
+    #[inline]
+    pub fn exported_memory_index(&self) -> Option<usize> {
+        self.exported_memory().map(|v| v.0)
+    }
+
+    #[inline]
+    pub fn exported_memory_name(&self) -> Option<&str> {
+        self.exported_memory().map(|v| v.1)
+    }
+
+    /// Returns the index and name of the exported table.
+    ///
+    /// The export named `"__indirect_function_table"` is preferred if more than one table is exported.
+    pub fn exported_table(&self) -> Option<(usize, &str)> {
+        self.find_export("__indirect_function_table", |desc| match *desc {
+            WasmExportDesc::Table(index) => Some(index),
+            _ => None,
+        })
+    }
+
+    #[inline]
+    pub fn exported_table_index(&self) -> Option<usize> {
+        self.exported_table().map(|v| v.0)
+    }
+
+    #[inline]
+    pub fn exported_table_name(&self) -> Option<&str> {
+        self.exported_table().map(|v| v.1)
+    }
+
+    fn find_export<F>(&self, preferred_name: &str, f: F) -> Option<(usize, &str)>
+    where
+        F: Fn(&WasmExportDesc) -> Option<usize>,
+    {
+        let mut exports = self
+            .exports
+            .iter()
+            .filter_map(|v| f(&v.desc).map(|index| (index, v.name.as_str())));
+        let first = exports.next()?;
+        Some(
+            core::iter::once(first)
+                .chain(exports)
+                .find(|v| v.1 == preferred_name)
+                .unwrap_or(first),
+        )
+    }
+
+    /// Returns the disassembly of the intermediate code of the function at `index`,
+    /// or `None` if the function has no body in this module.
+    pub fn disassemble(&self, index: usize) -> Option<String> {
+        match self.functions.get(index)?.content() {
+            WasmFunctionContent::CodeBlock(code_block) => Some(code_block.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Counts the intermediate instructions of all function bodies by their opcode.
+    ///
+    /// Fused instructions are counted as the arithmetic or comparison opcode they contain.
+    pub fn instruction_histogram(&self) -> BTreeMap<WasmMnemonic, usize> {
+        let mut result = BTreeMap::new();
+        for func in self.functions.iter() {
+            if let WasmFunctionContent::CodeBlock(code_block) = func.content() {
+                for code in code_block.intermediate_codes() {
+                    *result.entry(code.instruction().mnemonic()).or_insert(0) += 1;
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns all functions in index order, together with their import and export names.
+    pub fn function_descriptors<'a>(&'a self) -> impl Iterator<Item = FunctionDescriptor<'a>> {
+        let mut imports = self
+            .imports
+            .iter()
+            .filter(|v| matches!(v.desc, WasmImportDescriptor::Function(_)));
+        self.functions.iter().map(move |func| FunctionDescriptor {
+            index: func.index(),
+            signature: &func.func_type,
+            import: func
+                .is_external
+                .then(|| imports.next())
+                .flatten()
+                .map(|v| (v.mod_name.as_str(), v.name.as_str())),
+            export: self
+                .exports
+                .iter()
+                .find(
+                    |v| matches!(v.desc, WasmExportDesc::Function(index) if index == func.index()),
+                )
+                .map(|v| v.name.as_str()),
+        })
+    }
+
+    /// Returns the proposals whose instructions appear in the function bodies.
+    ///
+    /// The set is collected while the bodies are compiled.
+    #[inline]
+    pub const fn needs_features(&self) -> ProposalSet {
+        self.features
+    }
+
     #[inline]
     pub fn custom_sections<'a>(&'a self, section_name: &str) -> Option<&Box<[u8]>> {
         self.custom_sections.get(section_name)
@@ -541,11 +1327,27 @@ impl WasmModule {
     }
 
     #[inline]
-    pub(crate) fn elem_get(&self, index: usize) -> Option<&WasmFunction> {
+    pub fn tables(&self) -> &[WasmTable] {
+        &self.tables
+    }
+
+    /// Returns the function stored in the element of the table, as `call_indirect` looks it up.
+    #[inline]
+    pub(crate) fn elem_get(
+        &self,
+        table_index: usize,
+        index: usize,
+    ) -> Result<&WasmFunction, WasmRuntimeErrorKind> {
         self.tables
-            .get(0)
-            .and_then(|v| v.table.get(index))
-            .and_then(|v| self.functions.get(*v))
+            .get(table_index)
+            .ok_or(WasmRuntimeErrorKind::UndefinedElement)?
+            .get(index)
+            .map_err(|err| match err {
+                WasmRuntimeErrorKind::OutOfBounds => WasmRuntimeErrorKind::UndefinedElement,
+                err => err,
+            })?
+            .and_then(|v| self.functions.get(v))
+            .ok_or(WasmRuntimeErrorKind::UninitializedElement)
     }
 
     #[inline]
@@ -556,8 +1358,9 @@ impl WasmModule {
         })
     }
 
+    /// Returns the global variables of this module in index order.
     #[inline]
-    pub(crate) fn globals(&self) -> &[WasmGlobal] {
+    pub fn globals(&self) -> &[WasmGlobal] {
         self.globals.as_slice()
     }
 
@@ -591,17 +1394,198 @@ impl WasmModule {
     pub(crate) fn names(&self) -> Option<&WasmName> {
         self.names.as_ref()
     }
+
+    #[inline]
+    pub(crate) fn limits(&self) -> &CompileLimits {
+        self.options.limits()
+    }
+
+    #[inline]
+    pub(crate) fn options(&self) -> &CompileOptions {
+        &self.options
+    }
 }
 
 pub struct ModuleExport<'a> {
     pub name: &'a str,
     pub kind: ImportExportKind,
+    pub desc: ExportKind<'a>,
+}
+
+/// A builder to construct a [`WasmModule`] from in-memory parts
+///
+/// Function indices start with the imported functions,
+/// followed by the functions added by [`WasmModuleBuilder::function`].
+#[derive(Default)]
+pub struct WasmModuleBuilder {
+    options: CompileOptions,
+    imports: Vec<(String, String, WasmType)>,
+    functions: Vec<(WasmType, Vec<u8>)>,
+    memories: Vec<WasmLimit>,
+    globals: Vec<(WasmValue, bool)>,
+    exports: Vec<(String, ImportExportKind, usize)>,
+}
+
+impl WasmModuleBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn options(mut self, options: CompileOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    #[inline]
+    pub fn import_function(mut self, module: &str, name: &str, func_type: WasmType) -> Self {
+        self.imports
+            .push((module.to_owned(), name.to_owned(), func_type));
+        self
+    }
+
+    /// Adds a function.
+    ///
+    /// `body` is the same as an entry of the code section without the size,
+    /// i.e. the local declarations followed by the instructions and the final `end`.
+    #[inline]
+    pub fn function(mut self, func_type: WasmType, body: &[u8]) -> Self {
+        self.functions.push((func_type, body.to_vec()));
+        self
+    }
+
+    #[inline]
+    pub fn memory(mut self, limit: WasmLimit) -> Self {
+        self.memories.push(limit);
+        self
+    }
+
+    #[inline]
+    pub fn global(mut self, value: WasmValue, is_mutable: bool) -> Self {
+        self.globals.push((value, is_mutable));
+        self
+    }
+
+    #[inline]
+    pub fn export(mut self, name: &str, kind: ImportExportKind, index: usize) -> Self {
+        self.exports.push((name.to_owned(), kind, index));
+        self
+    }
+
+    /// Verifies the function bodies and builds the module.
+    pub fn build(self) -> Result<WasmModule, WasmCompileError> {
+        let mut module = WasmModule {
+            options: self.options,
+            ..Default::default()
+        };
+        let limits = *module.limits();
+
+        CompileLimits::check(
+            self.imports.len().saturating_add(self.functions.len()),
+            limits.max_functions,
+            CompileLimitKind::Functions,
+        )?;
+        CompileLimits::check(
+            self.imports.len().saturating_add(self.functions.len()),
+            limits.max_types,
+            CompileLimitKind::Types,
+        )?;
+
+        for (mod_name, name, func_type) in self.imports {
+            let index = module.functions.len();
+            let type_index = WasmTypeIndex(module.types.len() as u32);
+            module.functions.push(WasmFunction::from_import(
+                index,
+                type_index,
+                func_type.clone(),
+            ));
+            module.types.push(func_type);
+            module.imports.push(WasmImport {
+                mod_name,
+                name,
+                desc: WasmImportDescriptor::Function(type_index),
+            });
+        }
+
+        let base_index = module.functions.len();
+        let mut bodies = Vec::with_capacity(self.functions.len());
+        for (func_type, body) in self.functions {
+            let index = module.functions.len();
+            let type_index = WasmTypeIndex(module.types.len() as u32);
+            module
+                .functions
+                .push(WasmFunction::internal(index, type_index, func_type.clone()));
+            module.types.push(func_type);
+            bodies.push(body);
+        }
+
+        for limit in self.memories {
+            CompileLimits::check_limit(
+                &limit,
+                limits.max_memory_pages,
+                CompileLimitKind::MemoryPages,
+            )?;
+            module.memories.push(WasmMemory::with_policy(
+                limit,
+                module.options.memory_policy,
+            )?);
+        }
+
+        for (value, is_mutable) in self.globals {
+            module.globals.push(WasmGlobal::new(value, is_mutable)?);
+        }
+
+        for (name, kind, index) in self.exports {
+            let desc = match kind {
+                ImportExportKind::Function if index < module.functions.len() => {
+                    WasmExportDesc::Function(index)
+                }
+                ImportExportKind::Memory if index < module.memories.len() => {
+                    WasmExportDesc::Memory(index)
+                }
+                ImportExportKind::Global if index < module.globals.len() => {
+                    WasmExportDesc::Global(unsafe { GlobalVarIndex::new(index as u32) })
+                }
+                _ => return Err(WasmCompileErrorKind::InvalidData.into()),
+            };
+            module.exports.push(WasmExport { name, desc });
+        }
+
+        let mut scratch = WasmCodeScratch::new();
+        for (index, body) in (base_index..).zip(bodies) {
+            let func = &module.functions[index];
+            let code_block = WasmCodeBlock::generate_with_scratch(
+                index,
+                0,
+                &mut Leb128Reader::from_slice(&body),
+                func.param_types(),
+                func.result_types(),
+                &module,
+                &mut scratch,
+            )?;
+            module.features = module.features.union(code_block.proposals());
+            module.functions[index].set_code_block(code_block)?;
+        }
+
+        Ok(module)
+    }
+}
+
+pub struct FunctionDescriptor<'a> {
+    pub index: usize,
+    pub signature: &'a WasmType,
+    /// Module and field name if the function is imported
+    pub import: Option<(&'a str, &'a str)>,
+    /// The first export name of the function, if any
+    pub export: Option<&'a str>,
 }
 
 pub struct ModuleImport<'a> {
     pub module: &'a str,
     pub name: &'a str,
     pub kind: ImportExportKind,
+    pub desc: ImportKind<'a>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -612,71 +1596,531 @@ pub enum ImportExportKind {
     Global,
 }
 
-impl ImportExportKind {
-    #[inline]
-    fn from_import_desc(desc: &WasmImportDescriptor) -> Self {
-        match *desc {
-            WasmImportDescriptor::Function(_) => Self::Function,
-            WasmImportDescriptor::Memory(_) => Self::Memory,
-        }
+impl ImportExportKind {
+    #[inline]
+    fn from_import_desc(desc: &WasmImportDescriptor) -> Self {
+        match *desc {
+            WasmImportDescriptor::Function(_) => Self::Function,
+            WasmImportDescriptor::Table(_, _) => Self::Table,
+            WasmImportDescriptor::Memory(_) => Self::Memory,
+            WasmImportDescriptor::Global(_, _) => Self::Global,
+        }
+    }
+
+    #[inline]
+    fn from_export_desc(desc: &WasmExportDesc) -> Self {
+        match *desc {
+            WasmExportDesc::Function(_) => Self::Function,
+            WasmExportDesc::Table(_) => Self::Table,
+            WasmExportDesc::Memory(_) => Self::Memory,
+            WasmExportDesc::Global(_) => Self::Global,
+        }
+    }
+}
+
+/// Detailed description of an import
+#[derive(Debug, Clone, Copy)]
+pub enum ImportKind<'a> {
+    Function(&'a WasmType),
+    Table(WasmLimit, WasmRefType),
+    Memory(WasmLimit),
+    /// The type of the global and whether it is mutable
+    Global(WasmValType, bool),
+}
+
+/// Detailed description of an export with the index it refers to
+#[derive(Debug, Clone, Copy)]
+pub enum ExportKind<'a> {
+    Function(usize, &'a WasmType),
+    Table(usize, WasmLimit, WasmRefType),
+    Memory(usize),
+    /// The index, the type of the global and whether it is mutable
+    Global(usize, WasmValType, bool),
+}
+
+#[derive(Debug)]
+pub struct WasmInstance {
+    module: WasmModule,
+    exports: BTreeMap<String, usize>,
+    protected: Vec<Range<usize>>,
+    dropped_data: Box<[AtomicBool]>,
+    /// Names of the exported allocator functions
+    allocator: (String, String),
+    /// Default for [`WasmInterpreter::set_loop_watchdog`](crate::cg::intr::WasmInterpreter::set_loop_watchdog)
+    loop_watchdog: Option<u32>,
+    stdio: WasiStdio,
+}
+
+impl WasmInstance {
+    /// Default name of the exported function for [`WasmInstance::alloc_bytes`]
+    pub const DEFAULT_ALLOC: &'static str = "malloc";
+    /// Default name of the exported function for [`WasmInstance::dealloc`]
+    pub const DEFAULT_DEALLOC: &'static str = "free";
+
+    #[inline]
+    fn new(module: WasmModule) -> Self {
+        let mut exports = BTreeMap::new();
+        for export in module.exports.iter() {
+            if let WasmExportDesc::Function(index) = export.desc {
+                exports.insert(export.name.clone(), index);
+            }
+        }
+
+        // active segments are dropped right after they are applied
+        let dropped_data = module
+            .data_segments
+            .iter()
+            .map(|v| AtomicBool::new(v.active.is_some()))
+            .collect();
+
+        Self {
+            module,
+            exports,
+            protected: Vec::new(),
+            dropped_data,
+            allocator: (
+                Self::DEFAULT_ALLOC.to_owned(),
+                Self::DEFAULT_DEALLOC.to_owned(),
+            ),
+            loop_watchdog: None,
+            stdio: WasiStdio::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn empty() -> Self {
+        Self {
+            module: WasmModule::empty(),
+            exports: BTreeMap::new(),
+            protected: Vec::new(),
+            dropped_data: Box::new([]),
+            allocator: (
+                Self::DEFAULT_ALLOC.to_owned(),
+                Self::DEFAULT_DEALLOC.to_owned(),
+            ),
+            loop_watchdog: None,
+            stdio: WasiStdio::default(),
+        }
+    }
+
+    #[inline]
+    pub fn module(&self) -> &WasmModule {
+        &self.module
+    }
+
+    #[inline]
+    pub fn exports<'a>(&'a self) -> WasmExports<'a> {
+        WasmExports { instance: self }
+    }
+
+    /// Returns the memory at `index`, or [`WasmRuntimeErrorKind::NoMemoryDeclared`] if it does not exist.
+    #[inline]
+    pub fn memory(&self, index: usize) -> Result<&WasmMemory, WasmRuntimeErrorKind> {
+        self.module
+            .memories()
+            .get(index)
+            .ok_or(WasmRuntimeErrorKind::NoMemoryDeclared)
+    }
+
+    /// Returns the memory at `index`, or [`WasmRuntimeErrorKind::NoMemoryDeclared`] if it does not exist.
+    #[inline]
+    pub fn memory_mut(&mut self, index: usize) -> Result<&mut WasmMemory, WasmRuntimeErrorKind> {
+        self.module
+            .memories
+            .get_mut(index)
+            .ok_or(WasmRuntimeErrorKind::NoMemoryDeclared)
+    }
+
+    /// Grows the memory at `index` by `delta` pages, as `memory.grow` does.
+    ///
+    /// Returns the previous size in pages, or -1 if the memory does not exist or cannot grow.
+    pub fn memory_grow(&self, index: usize, delta: u32) -> i32 {
+        self.memory(index)
+            .ok()
+            .and_then(|memory| memory.grow(delta).ok())
+            .map(|v| v as i32)
+            .unwrap_or(-1)
+    }
+
+    /// Adds a host function to the function index space and returns its index.
+    ///
+    /// The index can be stored into a table with [`WasmTable::set`] to be called by `call_indirect`.
+    /// The module must declare the type of the function, otherwise it fails with
+    /// [`WasmRuntimeErrorKind::TypeMismatch`].
+    pub fn add_host_function(
+        &mut self,
+        host: WasmHostFunction,
+    ) -> Result<usize, WasmRuntimeErrorKind> {
+        let module = &mut self.module;
+        let index = module.functions.len();
+        let func = WasmFunction::from_host(index, &module.types, host)
+            .ok_or(WasmRuntimeErrorKind::TypeMismatch)?;
+        module.functions.push(func);
+        Ok(index)
+    }
+
+    /// Returns the contents of the data segment, which is empty once the segment is dropped.
+    pub(crate) fn data_segment(&self, index: usize) -> &[u8] {
+        match self.dropped_data.get(index) {
+            Some(dropped) if !dropped.load(Ordering::Relaxed) => {
+                &self.module.data_segments[index].data
+            }
+            _ => &[],
+        }
+    }
+
+    /// Drops the data segment as `data.drop` does.
+    pub(crate) fn drop_data_segment(&self, index: usize) {
+        if let Some(dropped) = self.dropped_data.get(index) {
+            dropped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Restores the memories, tables and mutable globals to their state right after instantiation.
+    ///
+    /// Memories shrink back to their initial size and the active data and element segments
+    /// are applied again. Passive data segments dropped by `data.drop` become available again.
+    /// Imported memories, tables and globals are left as they are, as they belong to the host.
+    #[inline]
+    pub fn reset(&self) -> Result<(), WasmRuntimeErrorKind> {
+        self.reset_with(false)
+    }
+
+    /// Same as [`WasmInstance::reset`], but passive data segments dropped by `data.drop`
+    /// stay dropped if `keep_dropped` is `true`.
+    pub fn reset_with(&self, keep_dropped: bool) -> Result<(), WasmRuntimeErrorKind> {
+        let module = &self.module;
+        let n_imported_memories = module
+            .imports
+            .iter()
+            .filter(|v| matches!(v.desc, WasmImportDescriptor::Memory(_)))
+            .count();
+        let n_imported_tables = module
+            .imports
+            .iter()
+            .filter(|v| matches!(v.desc, WasmImportDescriptor::Table(_, _)))
+            .count();
+        let n_imported_globals = module
+            .imports
+            .iter()
+            .filter(|v| matches!(v.desc, WasmImportDescriptor::Global(_, _)))
+            .count();
+
+        for memory in module.memories.iter().skip(n_imported_memories) {
+            memory.reset()?;
+        }
+        for (segment, dropped) in module.data_segments.iter().zip(self.dropped_data.iter()) {
+            // active segments are dropped once applied, passive ones are available again
+            if segment.active.is_some() || !keep_dropped {
+                dropped.store(segment.active.is_some(), Ordering::Relaxed);
+            }
+            let Some((memory_index, offset)) = segment.active else {
+                continue;
+            };
+            if memory_index < n_imported_memories {
+                continue;
+            }
+            module.memories[memory_index].write_slice(offset, &segment.data)?;
+        }
+
+        for table in module.tables.iter().skip(n_imported_tables) {
+            table.reset()?;
+        }
+        for segment in module.elem_segments.iter() {
+            if segment.table_index < n_imported_tables {
+                continue;
+            }
+            let table = &module.tables[segment.table_index];
+            for (index, elem) in segment.elements.iter().enumerate() {
+                table.set(segment.offset + index, Some(*elem))?;
+            }
+        }
+
+        for global in module
+            .globals
+            .iter()
+            .skip(n_imported_globals)
+            .filter(|v| v.is_mutable())
+        {
+            global.set_raw_value(global.initial_value().into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns a handle to invoke the exported function `name`.
+    ///
+    /// A missing export is [`WasmRuntimeErrorKind::NoMethod`], even when the module has no functions.
+    pub fn function(&self, name: &str) -> Result<WasmRunnable<'_>, WasmRuntimeErrorKind> {
+        self.exports()
+            .get(name)
+            .ok_or(WasmRuntimeErrorKind::NoMethod)
+    }
+
+    /// Invokes the exported function `name` after checking the arguments against its type.
+    ///
+    /// The error names the first offending argument, as
+    /// [`WasmRuntimeErrorKind::ArgCountMismatch`] or [`WasmRuntimeErrorKind::ArgTypeMismatch`].
+    pub fn call(&self, name: &str, args: &[WasmValue]) -> WasmResult<Option<WasmValue>> {
+        let function = self.function(name)?;
+        let param_types = function.func_type().param_types();
+        if args.len() != param_types.len() {
+            return Err(WasmRuntimeErrorKind::ArgCountMismatch {
+                expected: param_types.len(),
+                got: args.len(),
+            }
+            .into());
+        }
+        for (index, (arg, expected)) in args.iter().zip(param_types).enumerate() {
+            if !arg.is_valid_type(*expected) {
+                return Err(WasmRuntimeErrorKind::ArgTypeMismatch {
+                    index,
+                    expected: *expected,
+                    got: arg.val_type(),
+                }
+                .into());
+            }
+        }
+        function.invoke(args)
+    }
+
+    /// Returns a handle to invoke the function at `index` in the function index space,
+    /// whether or not it is exported.
+    pub fn function_by_index(
+        &self,
+        index: usize,
+    ) -> Result<WasmRunnable<'_>, WasmRuntimeErrorKind> {
+        let function = self
+            .module
+            .functions
+            .get(index)
+            .ok_or(WasmRuntimeErrorKind::NoMethod)?;
+        if function.is_external {
+            return Err(WasmRuntimeErrorKind::ImportedFunction);
+        }
+        Ok(WasmRunnable::new(function, self))
+    }
+
+    /// Invokes the function stored at `elem_index` of the table, as `call_indirect` does.
+    ///
+    /// Reports the same trap kinds as the instruction: an element past the end of the table
+    /// or a missing table is [`WasmRuntimeErrorKind::UndefinedElement`], a null element is
+    /// [`WasmRuntimeErrorKind::UninitializedElement`], and arguments that do not match the
+    /// parameters of the function are [`WasmRuntimeErrorKind::IndirectCallTypeMismatch`].
+    pub fn call_indirect(
+        &self,
+        table_index: usize,
+        elem_index: usize,
+        args: &[WasmValue],
+    ) -> Result<Option<WasmValue>, Box<dyn Error>> {
+        let func_index = self
+            .module
+            .tables
+            .get(table_index)
+            .ok_or(WasmRuntimeErrorKind::UndefinedElement)?
+            .get(elem_index)
+            .map_err(|err| match err {
+                WasmRuntimeErrorKind::OutOfBounds => WasmRuntimeErrorKind::UndefinedElement,
+                err => err,
+            })?
+            .ok_or(WasmRuntimeErrorKind::UninitializedElement)?;
+        let function = self.function_by_index(func_index)?;
+        let param_types = function.func_type().param_types();
+        if args.len() != param_types.len()
+            || args
+                .iter()
+                .zip(param_types)
+                .any(|(arg, expected)| !arg.is_valid_type(*expected))
+        {
+            return Err(WasmRuntimeErrorKind::IndirectCallTypeMismatch.into());
+        }
+        function.invoke(args)
+    }
+
+    #[inline]
+    pub fn global(&self, name: &str) -> Result<&WasmGlobal, WasmRuntimeErrorKind> {
+        self.module.global(name)
+    }
+
+    /// Returns the value of the global at `index` in the global index space.
+    ///
+    /// Host functions can use this to read the globals of the calling module.
+    pub fn global_get(&self, index: usize) -> Result<WasmValue, WasmRuntimeErrorKind> {
+        self.module
+            .globals()
+            .get(index)
+            .map(|v| v.value())
+            .ok_or(WasmRuntimeErrorKind::InvalidParameter)
+    }
+
+    /// Sets the value of the mutable global at `index` in the global index space.
+    ///
+    /// The new value is visible to the module as soon as this returns,
+    /// so host functions can use this to update the globals of the calling module.
+    pub fn global_set(&self, index: usize, value: WasmValue) -> Result<(), WasmRuntimeErrorKind> {
+        self.module
+            .globals()
+            .get(index)
+            .ok_or(WasmRuntimeErrorKind::InvalidParameter)?
+            .set_value(value)
+    }
+
+    pub const STACK_POINTER_NAME: &'static str = "__stack_pointer";
+
+    /// Returns the shadow stack pointer global, searched by export name and then by the name section.
+    fn stack_pointer_global(&self) -> Option<&WasmGlobal> {
+        self.module
+            .global(Self::STACK_POINTER_NAME)
+            .ok()
+            .or_else(|| {
+                self.module.names().and_then(|names| {
+                    names
+                        .globals()
+                        .iter()
+                        .find(|(_, name)| name == Self::STACK_POINTER_NAME)
+                        .and_then(|(index, _)| self.module.globals().get(*index))
+                })
+            })
+            .filter(|v| v.val_type() == WasmValType::I32)
     }
 
+    /// Returns the current value of the shadow stack pointer, if the module has one.
     #[inline]
-    fn from_export_desc(desc: &WasmExportDesc) -> Self {
-        match *desc {
-            WasmExportDesc::Function(_) => Self::Function,
-            WasmExportDesc::Table(_) => Self::Table,
-            WasmExportDesc::Memory(_) => Self::Memory,
-            WasmExportDesc::Global(_) => Self::Global,
+    pub fn stack_pointer(&self) -> Option<i32> {
+        self.stack_pointer_global()
+            .and_then(|v| v.value().get_i32().ok())
+    }
+
+    /// Overwrites the shadow stack pointer.
+    pub fn set_stack_pointer(&self, value: i32) -> Result<(), WasmRuntimeErrorKind> {
+        let global = self
+            .stack_pointer_global()
+            .ok_or(WasmRuntimeErrorKind::NoMethod)?;
+        if !global.is_mutable() {
+            return Err(WasmRuntimeErrorKind::TypeMismatch);
         }
+        global.set_raw_value(value.into());
+        Ok(())
     }
-}
 
-#[derive(Debug)]
-pub struct WasmInstance {
-    module: WasmModule,
-    exports: BTreeMap<String, usize>,
-}
+    /// Makes a range of memory 0 read-only for wasm code.
+    ///
+    /// Any store into the range traps with [`WasmRuntimeErrorKind::ProtectionViolation`].
+    /// This is a debugging aid; the host can still write to the memory.
+    pub fn protect(&mut self, start: usize, len: usize) {
+        if len > 0 {
+            self.protected.push(start..start.saturating_add(len));
+        }
+    }
 
-impl WasmInstance {
-    #[inline]
-    fn new(module: WasmModule) -> Self {
-        let mut exports = BTreeMap::new();
-        for export in module.exports.iter() {
-            if let WasmExportDesc::Function(index) = export.desc {
-                exports.insert(export.name.clone(), index);
+    /// Makes a range of memory 0 writable again.
+    pub fn unprotect(&mut self, start: usize, len: usize) {
+        let end = start.saturating_add(len);
+        let mut protected = Vec::with_capacity(self.protected.len());
+        for range in self.protected.drain(..) {
+            if range.end <= start || end <= range.start {
+                protected.push(range);
+            } else {
+                if range.start < start {
+                    protected.push(range.start..start);
+                }
+                if end < range.end {
+                    protected.push(end..range.end);
+                }
             }
         }
+        self.protected = protected;
+    }
 
-        Self { module, exports }
+    /// Sets the destination of the standard output written through [`WasiEnv`](crate::wasi::WasiEnv).
+    #[inline]
+    pub fn set_stdout(&mut self, output: Arc<dyn WasiOutput>) {
+        self.stdio.set_stdout(output);
     }
 
-    #[cfg(test)]
-    pub fn empty() -> Self {
-        Self {
-            module: WasmModule::empty(),
-            exports: BTreeMap::new(),
-        }
+    /// Sets the destination of the standard error written through [`WasiEnv`](crate::wasi::WasiEnv).
+    #[inline]
+    pub fn set_stderr(&mut self, output: Arc<dyn WasiOutput>) {
+        self.stdio.set_stderr(output);
     }
 
     #[inline]
-    pub fn module(&self) -> &WasmModule {
-        &self.module
+    pub(crate) fn stdio(&self) -> &WasiStdio {
+        &self.stdio
     }
 
+    /// Sets the names of the exported functions used by [`WasmInstance::alloc`]
+    /// and [`WasmInstance::dealloc`].
+    pub fn set_allocator(&mut self, alloc: &str, dealloc: &str) {
+        self.allocator = (alloc.to_owned(), dealloc.to_owned());
+    }
+
+    /// Enables [`WasmInterpreter::set_loop_watchdog`](crate::cg::intr::WasmInterpreter::set_loop_watchdog)
+    /// for every invocation on this instance,
+    /// including those made through [`WasmRunnable::invoke`].
     #[inline]
-    pub fn exports<'a>(&'a self) -> WasmExports<'a> {
-        WasmExports { instance: self }
+    pub fn set_loop_watchdog(&mut self, iterations: u32) {
+        self.loop_watchdog = Some(iterations);
     }
 
     #[inline]
-    pub fn memory(&self, index: usize) -> Option<&WasmMemory> {
-        self.module.memories().get(index)
+    pub fn loop_watchdog(&self) -> Option<u32> {
+        self.loop_watchdog
+    }
+
+    /// Reserves `len` bytes in memory 0 with the exported allocator.
+    ///
+    /// The allocator takes the size in bytes and returns the pointer.
+    /// This can also be called from a host function while the module is running.
+    pub fn alloc(&self, len: usize) -> WasmResult<WasmPtr<u8>> {
+        let ptr = self
+            .exports()
+            .get(&self.allocator.0)
+            .ok_or(WasmRuntimeErrorKind::NoMethod)?
+            .invoke(&[WasmValue::from(len as u32)])?
+            .ok_or(WasmRuntimeErrorKind::TypeMismatch)?
+            .get_u32()?;
+        Ok(WasmPtr::from_u32(ptr))
+    }
+
+    /// Reserves space with [`WasmInstance::alloc`] and copies the bytes into memory 0.
+    pub fn alloc_bytes(&self, bytes: &[u8]) -> WasmResult<WasmPtr<u8>> {
+        let ptr = self.alloc(bytes.len())?;
+        self.memory(0)?.write_slice(ptr.as_usize(), bytes)?;
+        Ok(ptr)
+    }
+
+    /// Releases the space returned by [`WasmInstance::alloc`] or [`WasmInstance::alloc_bytes`].
+    ///
+    /// `len` is passed only if the exported function takes the size as its second parameter.
+    pub fn dealloc(&self, ptr: WasmPtr<u8>, len: usize) -> WasmResult<()> {
+        let func = self
+            .exports()
+            .get(&self.allocator.1)
+            .ok_or(WasmRuntimeErrorKind::NoMethod)?;
+        let args = [WasmValue::from(ptr), WasmValue::from(len as u32)];
+        let n_args = func.function().param_types().len().min(args.len());
+        func.invoke(&args[..n_args])?;
+        Ok(())
     }
 
+    /// Checks whether a store of `len` bytes at `offset` is allowed.
     #[inline]
-    pub fn global(&self, name: &str) -> Result<&WasmGlobal, WasmRuntimeErrorKind> {
-        self.module.global(name)
+    pub(crate) fn check_protection(
+        &self,
+        offset: usize,
+        len: usize,
+    ) -> Result<(), WasmRuntimeErrorKind> {
+        let end = offset.saturating_add(len);
+        if self
+            .protected
+            .iter()
+            .any(|range| offset < range.end && range.start < end)
+        {
+            Err(WasmRuntimeErrorKind::ProtectionViolation)
+        } else {
+            Ok(())
+        }
     }
 }
 
@@ -703,6 +2147,145 @@ impl<'a> WasmExports<'a> {
     pub fn memory(&self) -> &WasmMemory {
         self.instance.memory(0).unwrap()
     }
+
+    /// Returns the exported functions together with their export names.
+    ///
+    /// Exports of other kinds are skipped, so each handle can be invoked directly.
+    pub fn functions(&self) -> impl Iterator<Item = (&'a str, WasmRunnable<'a>)> {
+        let instance = self.instance;
+        instance
+            .module
+            .exports
+            .iter()
+            .filter_map(move |v| match v.desc {
+                WasmExportDesc::Function(index) => instance
+                    .module
+                    .functions
+                    .get(index)
+                    .map(|func| (v.name.as_str(), WasmRunnable::new(func, instance))),
+                _ => None,
+            })
+    }
+
+    /// Returns the exported memories together with their export names.
+    pub fn memories(&self) -> impl Iterator<Item = (&'a str, &'a WasmMemory)> {
+        let instance = self.instance;
+        instance
+            .module
+            .exports
+            .iter()
+            .filter_map(move |v| match v.desc {
+                WasmExportDesc::Memory(index) => instance
+                    .memory(index)
+                    .ok()
+                    .map(|memory| (v.name.as_str(), memory)),
+                _ => None,
+            })
+    }
+
+    /// Returns the exported globals together with their export names.
+    pub fn globals(&self) -> impl Iterator<Item = (&'a str, &'a WasmGlobal)> {
+        let instance = self.instance;
+        instance
+            .module
+            .exports
+            .iter()
+            .filter_map(move |v| match v.desc {
+                WasmExportDesc::Global(index) => {
+                    Some((v.name.as_str(), instance.module.global_get(index)))
+                }
+                _ => None,
+            })
+    }
+
+    /// Returns a view that exposes only the listed exports, renamed as `(old, new)`.
+    ///
+    /// Names that are not exported by the module are ignored.
+    pub fn view(&self, names: &[(&str, &str)]) -> WasmExportsView<'a> {
+        let instance = self.instance;
+        let exports = names
+            .iter()
+            .filter_map(|(old, new)| {
+                instance
+                    .module
+                    .exports
+                    .iter()
+                    .find(|v| v.name == *old)
+                    .map(|v| (new.to_string(), v))
+            })
+            .collect();
+        WasmExportsView { instance, exports }
+    }
+}
+
+/// A read-only subset of the exports of an instance under different names
+///
+/// Created by [`WasmExports::view`].
+pub struct WasmExportsView<'a> {
+    instance: &'a WasmInstance,
+    exports: BTreeMap<String, &'a WasmExport>,
+}
+
+impl<'a> WasmExportsView<'a> {
+    #[inline]
+    pub fn instance(&self) -> &'a WasmInstance {
+        self.instance
+    }
+
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<WasmRunnable<'a>> {
+        match self.exports.get(name)?.desc {
+            WasmExportDesc::Function(index) => self
+                .instance
+                .module
+                .functions
+                .get(index)
+                .map(|v| WasmRunnable::new(v, self.instance)),
+            _ => None,
+        }
+    }
+
+    /// Returns the exported functions together with their new names.
+    pub fn functions(&self) -> impl Iterator<Item = (&str, WasmRunnable<'a>)> {
+        let instance = self.instance;
+        self.exports
+            .iter()
+            .filter_map(move |(name, v)| match v.desc {
+                WasmExportDesc::Function(index) => instance
+                    .module
+                    .functions
+                    .get(index)
+                    .map(|func| (name.as_str(), WasmRunnable::new(func, instance))),
+                _ => None,
+            })
+    }
+
+    /// Returns the exported memories together with their new names.
+    pub fn memories(&self) -> impl Iterator<Item = (&str, &'a WasmMemory)> {
+        let instance = self.instance;
+        self.exports
+            .iter()
+            .filter_map(move |(name, v)| match v.desc {
+                WasmExportDesc::Memory(index) => instance
+                    .memory(index)
+                    .ok()
+                    .map(|memory| (name.as_str(), memory)),
+                _ => None,
+            })
+    }
+
+    /// Returns the exported globals together with their new names.
+    pub fn globals(&self) -> impl Iterator<Item = (&str, &'a WasmGlobal)> {
+        let instance = self.instance;
+        self.exports
+            .iter()
+            .filter_map(move |(name, v)| match v.desc {
+                WasmExportDesc::Global(index) => {
+                    Some((name.as_str(), instance.module.global_get(index)))
+                }
+                _ => None,
+            })
+    }
 }
 
 /// WebAssembly memory argument
@@ -710,20 +2293,168 @@ impl<'a> WasmExports<'a> {
 pub struct WasmMemArg {
     pub align: u32,
     pub offset: u32,
+    /// The memory to access, which is encoded only by the multi-memory proposal
+    pub memory: u32,
 }
 
 impl WasmMemArg {
+    /// The bit of the alignment field that indicates an explicit memory index
+    pub const HAS_MEMORY_INDEX: u32 = 0x40;
+
     #[inline]
     pub const fn new(offset: u32, align: u32) -> Self {
-        Self { offset, align }
+        Self {
+            offset,
+            align,
+            memory: 0,
+        }
+    }
+
+    #[inline]
+    pub const fn with_memory(mut self, memory: u32) -> Self {
+        self.memory = memory;
+        self
     }
 }
 
 impl<'a, 'b> ReadLeb128<'a, WasmMemArg> for Leb128Reader<'b> {
     fn read(&'a mut self) -> Result<WasmMemArg, ReadError> {
-        let a = self.read()?;
+        let a: u32 = self.read()?;
+        let m = if (a & WasmMemArg::HAS_MEMORY_INDEX) != 0 {
+            self.read()?
+        } else {
+            0
+        };
         let o = self.read()?;
-        Ok(WasmMemArg::new(o, a))
+        Ok(WasmMemArg::new(o, a & !WasmMemArg::HAS_MEMORY_INDEX).with_memory(m))
+    }
+}
+
+impl WriteLeb128<WasmMemArg> for Leb128Writer {
+    fn write(&mut self, value: WasmMemArg) -> Result<(), WriteError> {
+        if value.memory != 0 {
+            self.write(value.align | WasmMemArg::HAS_MEMORY_INDEX)?;
+            self.write(value.memory)?;
+        } else {
+            self.write(value.align)?;
+        }
+        self.write(value.offset)
+    }
+}
+
+/// Parse state shared by the sections of a module
+struct WasmSectionState {
+    last_section_id: WasmSectionId,
+    has_data: bool,
+}
+
+impl Default for WasmSectionState {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            last_section_id: WasmSectionId::Type,
+            has_data: false,
+        }
+    }
+}
+
+/// Compiles a module from chunks of bytes, decoding each section as soon as it is complete
+pub struct WasmStreamingLoader {
+    module: WasmModule,
+    state: WasmSectionState,
+    buffer: Vec<u8>,
+    /// File position of the first byte in the buffer
+    file_position: usize,
+}
+
+impl WasmStreamingLoader {
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_options(&CompileOptions::DEFAULT)
+    }
+
+    #[inline]
+    pub fn with_options(options: &CompileOptions) -> Self {
+        Self {
+            module: WasmModule {
+                options: *options,
+                ..Default::default()
+            },
+            state: WasmSectionState::default(),
+            buffer: Vec::new(),
+            file_position: 0,
+        }
+    }
+
+    /// Appends the bytes and decodes all sections completed by them.
+    ///
+    /// An incomplete section is kept until the next call.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.buffer.extend_from_slice(bytes);
+
+        if self.file_position == 0 {
+            let header = [WebAssembly::MAGIC, WebAssembly::VER_CURRENT].concat();
+            let len = self.buffer.len().min(header.len());
+            if self.buffer[..len] != header[..len] {
+                return Err(WasmCompileErrorKind::BadExecutable.into());
+            }
+            if len < header.len() {
+                return Ok(());
+            }
+            self.buffer.drain(..header.len());
+            self.file_position = header.len();
+        }
+
+        while let Some(len) = self.next_section_len()? {
+            let mut reader = Leb128Reader::from_slice(&self.buffer[..len]);
+            if let Some(section) = WasmSection::from_reader_at(&mut reader, self.file_position)? {
+                if section.section_id == WasmSectionId::Custom {
+                    self.module.parse_sec_custom(section)?;
+                } else {
+                    self.module.parse_section(section, &mut self.state)?;
+                }
+            }
+            self.buffer.drain(..len);
+            self.file_position += len;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the size of the next section including its header, if it is complete.
+    ///
+    /// The declared length is checked against the compile limits as soon as the header is read,
+    /// so an oversized section is rejected before it is buffered.
+    fn next_section_len(&self) -> Result<Option<usize>, WasmCompileErrorKind> {
+        let mut reader = Leb128Reader::from_slice(&self.buffer);
+        let header: Result<(u8, usize), ReadError> = reader
+            .read_byte()
+            .and_then(|id| reader.read().map(|length| (id, length)));
+        match header {
+            Ok((id, length)) => {
+                if WasmSectionId::from_u8(id) == Some(WasmSectionId::Code) {
+                    self.module.options.limits.check_code_section(length)?;
+                }
+                Ok((reader.remaining() >= length).then(|| reader.position() + length))
+            }
+            Err(ReadError::UnexpectedEof) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Finishes the module, or returns [`WasmCompileErrorKind::UnexpectedEof`] if it is incomplete.
+    pub fn finish(self) -> Result<WasmModule, Box<dyn Error>> {
+        if self.file_position == 0 || !self.buffer.is_empty() {
+            return Err(WasmCompileErrorKind::UnexpectedEof.into());
+        }
+        self.module.finish_sections(&self.state)
+    }
+}
+
+impl Default for WasmStreamingLoader {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -735,8 +2466,17 @@ pub struct WasmSection<'a> {
 }
 
 impl<'a> WasmSection<'a> {
+    #[inline]
     pub fn from_reader<'b>(
         reader: &'b mut Leb128Reader<'a>,
+    ) -> Result<Option<WasmSection<'a>>, WasmCompileErrorKind> {
+        Self::from_reader_at(reader, WebAssembly::MINIMAL_MOD_SIZE)
+    }
+
+    /// Same as [`WasmSection::from_reader`], for a reader whose first byte is at `base` in the file.
+    fn from_reader_at<'b>(
+        reader: &'b mut Leb128Reader<'a>,
+        base: usize,
     ) -> Result<Option<WasmSection<'a>>, WasmCompileErrorKind> {
         if reader.is_eof() {
             return Ok(None);
@@ -746,12 +2486,9 @@ impl<'a> WasmSection<'a> {
             return Err(WasmCompileErrorKind::UnexpectedToken);
         };
 
-        let magic_numer = 8;
         let length: usize = reader.read()?;
-        let file_position = reader.position() + magic_numer;
-        let _reader = reader
-            .sub_slice(length)
-            .ok_or(WasmCompileErrorKind::InternalInconsistency)?;
+        let file_position = reader.position() + base;
+        let _reader = reader.sub_reader(length)?;
 
         Ok(Some(Self {
             section_id,
@@ -844,6 +2581,7 @@ pub enum WasmValType {
     I64,
     F32,
     F64,
+    FuncRef,
 }
 
 impl WasmValType {
@@ -857,7 +2595,7 @@ impl WasmValType {
             // 0x7B => Ok(Self::V128),
             // 0x78 => Ok(Self::I8),
             // 0x77 => Ok(Self::I16),
-            // 0x70 => Ok(Self::FuncRef),
+            0x70 => Ok(Self::FuncRef),
             // 0x6F => Ok(Self::ExternRef),
             _ => Err(WasmCompileErrorKind::UnexpectedToken),
         }
@@ -873,7 +2611,7 @@ impl WasmValType {
             // -5 => Ok(Self::V128),
             // -8 => Ok(Self::I8),
             // -9 => Ok(Self::I16),
-            // -16 => Ok(Self::FuncRef),
+            -16 => Ok(Self::FuncRef),
             // -17 => Ok(Self::ExternRef),
             _ => Err(WasmCompileErrorKind::UnexpectedToken),
         }
@@ -886,12 +2624,54 @@ impl WasmValType {
             Self::I64 => 'l',
             Self::F32 => 'f',
             Self::F64 => 'd',
+            Self::FuncRef => '_',
             // Self::V128 => 'v',
             // Self::I8 => 'c',
             // Self::I16 => 'w',
-            // Self::FuncRef | Self::ExternRef => '_',
+            // Self::ExternRef => '_',
+        }
+    }
+
+    /// Inverse of [`WasmValType::mnemonic`]
+    #[inline]
+    pub const fn from_mnemonic(c: char) -> Option<Self> {
+        match c {
+            'i' => Some(Self::I32),
+            'l' => Some(Self::I64),
+            'f' => Some(Self::F32),
+            'd' => Some(Self::F64),
+            '_' => Some(Self::FuncRef),
+            _ => None,
+        }
+    }
+
+    /// Size of a value of this type in bytes
+    ///
+    /// A reference is held as a function index of the host's pointer width.
+    #[inline]
+    pub const fn size(&self) -> usize {
+        match *self {
+            Self::I32 | Self::F32 => 4,
+            Self::I64 | Self::F64 => 8,
+            Self::FuncRef => size_of::<usize>(),
         }
     }
+
+    /// Natural alignment of a value of this type in bytes
+    #[inline]
+    pub const fn natural_alignment(&self) -> usize {
+        self.size()
+    }
+
+    #[inline]
+    pub const fn is_integer(&self) -> bool {
+        matches!(*self, Self::I32 | Self::I64)
+    }
+
+    #[inline]
+    pub const fn is_float(&self) -> bool {
+        matches!(*self, Self::F32 | Self::F64)
+    }
 }
 
 impl From<i32> for WasmValType {
@@ -932,27 +2712,42 @@ impl fmt::Display for WasmValType {
                 Self::I64 => "i64",
                 Self::F32 => "f32",
                 Self::F64 => "f64",
+                Self::FuncRef => "funcref",
                 // Self::V128 => "v128",
                 // Self::I8 => "i8",
                 // Self::I16 => "i16",
-                // Self::FuncRef => "func",
                 // Self::ExternRef => "extern",
             }
         )
     }
 }
 
+impl str::FromStr for WasmValType {
+    type Err = WasmCompileErrorKind;
+
+    /// Parses the value type name used by the text format.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "i32" => Ok(Self::I32),
+            "i64" => Ok(Self::I64),
+            "f32" => Ok(Self::F32),
+            "f64" => Ok(Self::F64),
+            "funcref" => Ok(Self::FuncRef),
+            _ => Err(WasmCompileErrorKind::UnexpectedToken),
+        }
+    }
+}
+
 /// WebAssembly block types
-#[repr(isize)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum WasmBlockType {
-    Empty = -64,
-    I32 = -1,
-    I64 = -2,
-    F32 = -3,
-    F64 = -4,
-    // I8 = -8,
-    // I16 = -9,
+    Empty,
+    I32,
+    I64,
+    F32,
+    F64,
+    /// The block signature is given by an entry in the type section.
+    Type(u32),
 }
 
 impl WasmBlockType {
@@ -963,19 +2758,40 @@ impl WasmBlockType {
             -2 => Ok(Self::I64),
             -3 => Ok(Self::F32),
             -4 => Ok(Self::F64),
+            0..=0xFFFF_FFFF => Ok(Self::Type(v as u32)),
             _ => Err(WasmCompileErrorKind::InvalidData),
         }
     }
 
+    pub const fn as_i64(self) -> i64 {
+        match self {
+            WasmBlockType::Empty => -64,
+            WasmBlockType::I32 => -1,
+            WasmBlockType::I64 => -2,
+            WasmBlockType::F32 => -3,
+            WasmBlockType::F64 => -4,
+            WasmBlockType::Type(index) => index as i64,
+        }
+    }
+
+    /// Returns the result type of a block type that is not a type index.
     pub const fn into_type(self) -> Option<WasmValType> {
         match self {
-            WasmBlockType::Empty => None,
+            WasmBlockType::Empty | WasmBlockType::Type(_) => None,
             WasmBlockType::I32 => Some(WasmValType::I32),
             WasmBlockType::I64 => Some(WasmValType::I64),
             WasmBlockType::F32 => Some(WasmValType::F32),
             WasmBlockType::F64 => Some(WasmValType::F64),
         }
     }
+
+    #[inline]
+    pub const fn type_index(self) -> Option<u32> {
+        match self {
+            WasmBlockType::Type(index) => Some(index),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> ReadLeb128<'a, WasmBlockType> for Leb128Reader<'_> {
@@ -986,6 +2802,13 @@ impl<'a> ReadLeb128<'a, WasmBlockType> for Leb128Reader<'_> {
     }
 }
 
+impl WriteLeb128<WasmBlockType> for Leb128Writer {
+    #[inline]
+    fn write(&mut self, value: WasmBlockType) -> Result<(), WriteError> {
+        self.write_signed(value.as_i64())
+    }
+}
+
 /// WebAssembly memory limit
 #[derive(Debug, Copy, Clone)]
 pub struct WasmLimit {
@@ -1026,6 +2849,10 @@ impl WasmLimit {
         };
 
         let is_shared = limit_type.is_shared();
+        if is_shared && !limit_type.has_max() {
+            // a shared memory must declare its maximum size
+            return Err(WasmCompileErrorKind::InvalidData);
+        }
 
         Ok(Self {
             min,
@@ -1034,6 +2861,15 @@ impl WasmLimit {
         })
     }
 
+    #[inline]
+    pub fn new(min: u32, max: Option<u32>) -> Self {
+        Self {
+            min,
+            max: max.and_then(NonZeroU32::new),
+            is_shared: false,
+        }
+    }
+
     #[inline]
     pub const fn min(&self) -> u32 {
         self.min
@@ -1055,8 +2891,7 @@ pub struct WasmLimitType(u8);
 
 impl WasmLimitType {
     const ALL_MEMORY: u64 = (
-        Self::HAS_MAX
-        //| Self::IS_SHARED
+        Self::HAS_MAX | Self::IS_SHARED
         //| Self::IS_MEMORY64
     ) as u64;
 
@@ -1092,7 +2927,6 @@ impl WasmLimitType {
     }
 
     /// Limit Type is `shared`
-    /// TODO: **SUPPORTED IN THE FUTURE**
     #[inline]
     pub const fn is_shared(&self) -> bool {
         (self.0 & Self::IS_SHARED) != 0
@@ -1106,28 +2940,80 @@ impl WasmLimitType {
     }
 }
 
+/// Data segment, kept for `memory.init` and to restore the memory on [`WasmInstance::reset`]
+struct WasmDataSegment {
+    /// Memory index and offset of an active segment, `None` if the segment is passive
+    active: Option<(usize, usize)>,
+    data: Box<[u8]>,
+}
+
+/// Active element segment, kept to restore the table on [`WasmInstance::reset`]
+struct WasmElemSegment {
+    table_index: usize,
+    offset: usize,
+    elements: Box<[usize]>,
+}
+
+/// WebAssembly reference types
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WasmRefType {
+    FuncRef,
+    ExternRef,
+}
+
+impl WasmRefType {
+    #[inline]
+    pub const fn from_u8(v: u8) -> Result<Self, WasmCompileErrorKind> {
+        match v {
+            0x70 => Ok(Self::FuncRef),
+            0x6F => Ok(Self::ExternRef),
+            _ => Err(WasmCompileErrorKind::UnexpectedToken),
+        }
+    }
+}
+
 /// WebAssembly table object
 pub struct WasmTable {
+    elem_type: WasmRefType,
     limit: WasmLimit,
-    table: Vec<usize>,
+    table: RwLockNb<Vec<usize>>,
 }
 
-impl WasmTable {
+impl WasmTable {
+    /// An element that does not refer to any function
+    pub const NULL_REF: usize = usize::MAX;
+
+    #[inline]
+    fn from_reader(reader: &mut Leb128Reader, max_size: u32) -> Result<Self, WasmCompileErrorKind> {
+        let elem_type = WasmRefType::from_u8(reader.read_byte()?)?;
+        let limit = WasmLimit::from_reader(reader, false)?;
+        Self::new(elem_type, limit, max_size)
+    }
+
     #[inline]
-    fn from_reader(reader: &mut Leb128Reader) -> Result<Self, WasmCompileErrorKind> {
-        match reader.read_byte() {
-            Ok(0x70) => (),
-            Err(err) => return Err(err.into()),
-            _ => return Err(WasmCompileErrorKind::UnexpectedToken),
-        };
-        WasmLimit::from_reader(reader, false).map(|limit| {
+    fn new(
+        elem_type: WasmRefType,
+        limit: WasmLimit,
+        max_size: u32,
+    ) -> Result<Self, WasmCompileErrorKind> {
+        CompileLimits::check_limit(&limit, max_size, CompileLimitKind::TableSize).map(|_| {
             let size = limit.min() as usize;
             let mut table = Vec::with_capacity(size);
-            table.resize(size, 0);
-            Self { limit, table }
+            table.resize(size, Self::NULL_REF);
+            Self {
+                elem_type,
+                limit,
+                table: RwLockNb::new(table),
+            }
         })
     }
 
+    /// Returns the type of the elements.
+    #[inline]
+    pub const fn elem_type(&self) -> WasmRefType {
+        self.elem_type
+    }
+
     #[inline]
     pub const fn limit(&self) -> WasmLimit {
         self.limit
@@ -1135,7 +3021,73 @@ impl WasmTable {
 
     #[inline]
     pub fn table(&mut self) -> &mut [usize] {
-        self.table.as_mut_slice()
+        match self.table.get_mut() {
+            Ok(v) => v.as_mut_slice(),
+            Err(err) => err.into_inner().as_mut_slice(),
+        }
+    }
+
+    /// Returns the current number of elements.
+    #[inline]
+    pub fn size(&self) -> Result<usize, WasmRuntimeErrorKind> {
+        self.table
+            .try_read()
+            .map(|v| v.len())
+            .map_err(|_| WasmRuntimeErrorKind::WouldBlock)
+    }
+
+    /// Returns the function index stored in the element, or `None` if it is null.
+    #[inline]
+    pub fn get(&self, index: usize) -> Result<Option<usize>, WasmRuntimeErrorKind> {
+        let table = self
+            .table
+            .try_read()
+            .map_err(|_| WasmRuntimeErrorKind::WouldBlock)?;
+        match table.get(index) {
+            Some(&Self::NULL_REF) => Ok(None),
+            Some(v) => Ok(Some(*v)),
+            None => Err(WasmRuntimeErrorKind::OutOfBounds),
+        }
+    }
+
+    /// Replaces the elements with those supplied by the host, growing the table if needed.
+    fn import(&mut self, elements: &[usize]) -> Result<(), WasmRuntimeErrorKind> {
+        if elements.len() > self.limit.max().unwrap_or(u32::MAX) as usize {
+            return Err(WasmRuntimeErrorKind::OutOfBounds);
+        }
+        let table = match self.table.get_mut() {
+            Ok(v) => v,
+            Err(err) => err.into_inner(),
+        };
+        let len = table.len().max(elements.len());
+        table.resize(len, Self::NULL_REF);
+        table[..elements.len()].copy_from_slice(elements);
+        Ok(())
+    }
+
+    /// Shrinks the table back to its initial size and clears all elements to null.
+    fn reset(&self) -> Result<(), WasmRuntimeErrorKind> {
+        let mut table = self
+            .table
+            .try_write()
+            .map_err(|_| WasmRuntimeErrorKind::WouldBlock)?;
+        table.clear();
+        table.resize(self.limit.min() as usize, Self::NULL_REF);
+        Ok(())
+    }
+
+    /// Stores a function index, or `None` for a null reference, into the element.
+    #[inline]
+    pub fn set(&self, index: usize, value: Option<usize>) -> Result<(), WasmRuntimeErrorKind> {
+        let mut table = self
+            .table
+            .try_write()
+            .map_err(|_| WasmRuntimeErrorKind::WouldBlock)?;
+        let elem = table
+            .get_mut(index)
+            .ok_or(WasmRuntimeErrorKind::OutOfBounds)?;
+        *elem = value.unwrap_or(Self::NULL_REF);
+        Ok(())
     }
 }
 
@@ -1170,6 +3122,19 @@ impl WasmFunction {
         }
     }
 
+    /// Returns `None` if the module does not declare the type of the host function.
+    #[inline]
+    fn from_host(index: usize, types: &[WasmType], host: WasmHostFunction) -> Option<Self> {
+        let type_index = types.iter().position(|v| *v == host.func_type)?;
+        Some(Self {
+            is_external: true,
+            index,
+            type_index: WasmTypeIndex(type_index as u32),
+            func_type: host.func_type,
+            content: WasmFunctionContent::Dynamic(host.func),
+        })
+    }
+
     #[inline]
     fn internal(index: usize, type_index: WasmTypeIndex, func_type: WasmType) -> Self {
         Self {
@@ -1191,6 +3156,11 @@ impl WasmFunction {
         self.type_index
     }
 
+    #[inline]
+    pub const fn func_type(&self) -> &WasmType {
+        &self.func_type
+    }
+
     #[inline]
     pub fn param_types(&self) -> &[WasmValType] {
         self.func_type.param_types.as_slice()
@@ -1245,7 +3215,7 @@ impl WasmFunction {
 /// A type that holds the signature of a function that combines a list of argument types with a list of return types.
 ///
 /// It appears as the first section (`0x01`) in the WebAssembly binary.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WasmType {
     param_types: SmallVec<[WasmValType; 8]>,
     result_types: SmallVec<[WasmValType; 8]>,
@@ -1314,18 +3284,13 @@ impl WasmType {
     ///     `fn(int, long, float, double) -> void` -> `"vilfd"`
     #[inline]
     pub fn signature(&self) -> String {
-        let result_types = if self.result_types.is_empty() {
-            "v".to_owned()
-        } else {
-            self.result_types.iter().map(|v| v.mnemonic()).collect()
-        };
-        let param_types = if self.param_types.is_empty() {
-            "v".to_owned()
-        } else {
-            self.param_types.iter().map(|v| v.mnemonic()).collect()
-        };
+        WasmSignature::new(&self.param_types, &self.result_types).to_string()
+    }
 
-        format!("{result_types}{param_types}")
+    /// Parse a function signature in the format returned by [`WasmType::signature`]
+    #[inline]
+    pub fn from_signature(signature: &str) -> Option<Self> {
+        WasmSignature::from_signature(signature).map(|v| v.into())
     }
 }
 
@@ -1349,6 +3314,108 @@ impl fmt::Display for WasmType {
     }
 }
 
+/// Builder for function signature strings such as `"vilfd"`
+///
+/// The first character is the result type and the rest are the parameter types,
+/// and an empty list of either is written as `v`.
+/// Since only one result character is parsed, signatures with multiple results
+/// can be formatted but not parsed back.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WasmSignature {
+    param_types: SmallVec<[WasmValType; 8]>,
+    result_types: SmallVec<[WasmValType; 8]>,
+}
+
+impl WasmSignature {
+    #[inline]
+    pub fn new(param_types: &[WasmValType], result_types: &[WasmValType]) -> Self {
+        Self {
+            param_types: SmallVec::from_slice(param_types),
+            result_types: SmallVec::from_slice(result_types),
+        }
+    }
+
+    pub fn from_signature(signature: &str) -> Option<Self> {
+        let mut chars = signature.chars();
+        let mut result_types = SmallVec::new();
+        match chars.next()? {
+            'v' => (),
+            c => result_types.push(WasmValType::from_mnemonic(c)?),
+        }
+        let mut param_types = SmallVec::new();
+        match chars.as_str() {
+            "" => return None,
+            "v" => (),
+            params => {
+                for c in params.chars() {
+                    param_types.push(WasmValType::from_mnemonic(c)?);
+                }
+            }
+        }
+        Some(Self {
+            param_types,
+            result_types,
+        })
+    }
+
+    #[inline]
+    pub fn param(mut self, val_type: WasmValType) -> Self {
+        self.param_types.push(val_type);
+        self
+    }
+
+    #[inline]
+    pub fn result(mut self, val_type: WasmValType) -> Self {
+        self.result_types.push(val_type);
+        self
+    }
+
+    #[inline]
+    pub fn param_types(&self) -> &[WasmValType] {
+        &self.param_types
+    }
+
+    #[inline]
+    pub fn result_types(&self) -> &[WasmValType] {
+        &self.result_types
+    }
+}
+
+impl fmt::Display for WasmSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.result_types.is_empty() {
+            f.write_str("v")?;
+        }
+        for result in self.result_types.iter() {
+            write!(f, "{}", result.mnemonic())?;
+        }
+        if self.param_types.is_empty() {
+            f.write_str("v")?;
+        }
+        for param in self.param_types.iter() {
+            write!(f, "{}", param.mnemonic())?;
+        }
+        Ok(())
+    }
+}
+
+impl From<WasmSignature> for WasmType {
+    #[inline]
+    fn from(value: WasmSignature) -> Self {
+        Self {
+            param_types: value.param_types,
+            result_types: value.result_types,
+        }
+    }
+}
+
+impl From<&WasmType> for WasmSignature {
+    #[inline]
+    fn from(value: &WasmType) -> Self {
+        Self::new(value.param_types(), value.result_types())
+    }
+}
+
 /// WebAssembly import object
 ///
 /// It appears as the second section (`0x02`) in the WebAssembly binary.
@@ -1377,9 +3444,9 @@ impl WasmImport {
 #[derive(Debug, Copy, Clone)]
 pub enum WasmImportDescriptor {
     Function(WasmTypeIndex),
-    // Table(_),
+    Table(WasmRefType, WasmLimit),
     Memory(WasmLimit),
-    // Global(_),
+    Global(WasmValType, bool),
 }
 
 impl WasmImportDescriptor {
@@ -1391,9 +3458,20 @@ impl WasmImportDescriptor {
                 .read()
                 .map(|v| Self::Function(WasmTypeIndex(v)))
                 .map_err(|v| v.into()),
-            // 1 => reader.read().map(|v| Self::Table(v)),
+            1 => {
+                let elem_type = WasmRefType::from_u8(reader.read_byte()?)?;
+                WasmLimit::from_reader(&mut reader, false).map(|v| Self::Table(elem_type, v))
+            }
             2 => WasmLimit::from_reader(&mut reader, true).map(|v| Self::Memory(v)),
-            // 3 => reader.read().map(|v| Self::Global(v)),
+            3 => {
+                let val_type = WasmValType::from_u8(reader.read_byte()?)?;
+                let is_mutable = match reader.read_byte()? {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(WasmCompileErrorKind::UnexpectedToken),
+                };
+                Ok(Self::Global(val_type, is_mutable))
+            }
             _ => Err(WasmCompileErrorKind::UnexpectedToken),
         }
     }
@@ -1429,12 +3507,24 @@ impl WasmExportDesc {
             .read_byte()
             .map_err(|v| v.into())
             .and_then(|v| match v {
-                0 => reader
-                    .read()
-                    .map(|v| Self::Function(v))
-                    .map_err(|v| v.into()),
-                1 => reader.read().map(|v| Self::Table(v)).map_err(|v| v.into()),
-                2 => reader.read().map(|v| Self::Memory(v)).map_err(|v| v.into()),
+                0 => {
+                    let index: usize = reader.read()?;
+                    (index < module.functions.len())
+                        .then_some(Self::Function(index))
+                        .ok_or(WasmCompileErrorKind::InvalidData)
+                }
+                1 => {
+                    let index: usize = reader.read()?;
+                    (index < module.tables.len())
+                        .then_some(Self::Table(index))
+                        .ok_or(WasmCompileErrorKind::InvalidData)
+                }
+                2 => {
+                    let index: usize = reader.read()?;
+                    (index < module.memories.len())
+                        .then_some(Self::Memory(index))
+                        .ok_or(WasmCompileErrorKind::InvalidData)
+                }
                 3 => {
                     let index: u32 = reader.read()?;
                     ((index as usize) < module.globals.len())
@@ -1451,6 +3541,8 @@ pub struct WasmCompileError {
     kind: WasmCompileErrorKind,
     file_position: ExceptionPosition,
     source: CompileErrorSource,
+    byte_range: Range<usize>,
+    section: Option<WasmSectionId>,
 }
 
 #[derive(Debug, Clone)]
@@ -1467,11 +3559,31 @@ impl WasmCompileError {
         file_position: ExceptionPosition,
         source: CompileErrorSource,
     ) -> Self {
+        let position = file_position.position();
         Self {
             kind,
             file_position,
             source,
+            byte_range: position..position,
+            section: None,
+        }
+    }
+
+    /// Sets the range of bytes in the module of the construct that failed.
+    #[inline]
+    pub fn with_byte_range(mut self, byte_range: Range<usize>) -> Self {
+        self.byte_range = byte_range;
+        self
+    }
+
+    /// Sets the section that failed, and its range if no finer range is known.
+    #[inline]
+    pub(crate) fn with_section(mut self, section: WasmSectionId, range: Range<usize>) -> Self {
+        self.section = Some(section);
+        if !self.file_position.is_valid() {
+            self.byte_range = range;
         }
+        self
     }
 
     #[inline]
@@ -1479,6 +3591,21 @@ impl WasmCompileError {
         &self.kind
     }
 
+    /// Returns the range `[start, end)` of bytes in the module of the construct that failed.
+    ///
+    /// For an instruction, the range covers the opcode and all of its operands.
+    /// If the position is unknown, the range is empty.
+    #[inline]
+    pub fn byte_range(&self) -> Range<usize> {
+        self.byte_range.clone()
+    }
+
+    /// Returns the section that failed, or `None` if the error is not in a section.
+    #[inline]
+    pub const fn section(&self) -> Option<WasmSectionId> {
+        self.section
+    }
+
     #[inline]
     pub fn file_position(&self) -> &ExceptionPosition {
         &self.file_position
@@ -1560,11 +3687,11 @@ impl Error for WasmCompileError {}
 impl From<WasmCompileErrorKind> for WasmCompileError {
     #[inline]
     fn from(value: WasmCompileErrorKind) -> Self {
-        WasmCompileError {
-            kind: value,
-            file_position: ExceptionPosition::UNKNOWN,
-            source: CompileErrorSource::Unknown,
-        }
+        WasmCompileError::new(
+            value,
+            ExceptionPosition::UNKNOWN,
+            CompileErrorSource::Unknown,
+        )
     }
 }
 
@@ -1627,6 +3754,8 @@ pub enum WasmCompileErrorKind {
     OutOfMemory,
     /// Code Section
     OutOfFunction,
+    /// The number of bodies in the code section differs from the number of functions declared.
+    FunctionAndCodeSectionMismatch,
     /// The type of the value stack does not match.
     TypeMismatch,
     /// Termination of invalid blocks
@@ -1637,6 +3766,12 @@ pub enum WasmCompileErrorKind {
     ElseNotExists,
     /// Internal error
     InternalInconsistency,
+    /// The alignment of the memory access is larger than its natural alignment.
+    InvalidAlignment,
+    /// The module exceeds the limits given at compile time.
+    LimitsExceeded(CompileLimitKind),
+    /// The instruction belongs to a proposal that is not allowed by the compile options.
+    ProposalNotAllowed(WasmProposal),
     /// For debugging purposes
     ForDebug(usize),
 }
@@ -1685,6 +3820,10 @@ pub enum WasmLinkError {
     NoMethod(String),
     /// Imported module does not exist.
     NoModule(String),
+    /// Imported table has too many elements, or a host function of a type the module does not declare.
+    InvalidTable(String),
+    /// Imported global has a value of another type.
+    InvalidGlobal(String),
 
     InternalInconsistency,
 }
@@ -1707,33 +3846,86 @@ pub enum WasmRuntimeErrorKind {
     WouldBlock,
     /// (unrecoverable) Argument type mismatch (e.g., call instruction).
     InvalidParameter,
+    /// (unrecoverable) [`WasmInstance::call`] was given the wrong number of arguments
+    ArgCountMismatch {
+        expected: usize,
+        got: usize,
+    },
+    /// (unrecoverable) [`WasmInstance::call`] was given an argument of the wrong type
+    ArgTypeMismatch {
+        index: usize,
+        expected: WasmValType,
+        got: WasmValType,
+    },
     /// (unrecoverable) Intermediate code that could not be converted
     NotSupported,
     /// (unrecoverable) The Unreachable instruction was executed.
     Unreachable,
     /// (unrecoverable) Memory Boundary Errors
     OutOfBounds,
+    /// (unrecoverable) `table.get` or `table.set` accessed an element past the end of the table
+    TableOutOfBounds,
     /// (unrecoverable) The specified function cannot be found.
     NoMethod,
+    /// (unrecoverable) The specified function is imported and has no body in this module.
+    ImportedFunction,
     /// (unrecoverable) Devide by zero
     DivideByZero,
+    /// (unrecoverable) Conversion of NaN to an integer
+    InvalidConversionToInteger,
+    /// (unrecoverable) The result of an integer conversion or operation is out of range
+    IntegerOverflow,
     /// (unrecoverable) The type of call instructions do not match.
     TypeMismatch,
+    /// (unrecoverable) `call_indirect` selected an element past the end of the table
+    UndefinedElement,
+    /// (unrecoverable) `call_indirect` selected a null element
+    UninitializedElement,
+    /// (unrecoverable) The function selected by `call_indirect` has a different type than expected
+    IndirectCallTypeMismatch,
     /// (unrecoverable) Internal error
     InternalInconsistency,
     /// (unrecoverable) Out of Memory
     OutOfMemory,
+    /// (unrecoverable) The module does not declare the memory being accessed
+    NoMemoryDeclared,
+    /// (unrecoverable) Store into a memory range protected by [`WasmInstance::protect`]
+    ProtectionViolation,
+    /// (unrecoverable) A loop made no progress, see [`WasmInterpreter::set_loop_watchdog`](crate::cg::intr::WasmInterpreter::set_loop_watchdog)
+    PossibleInfiniteLoop,
 
     Wrapped(Box<dyn Error>),
 }
 
+impl WasmRuntimeErrorKind {
+    /// Returns the message that the spec tests expect for this kind of trap, if any.
+    ///
+    /// These are the messages of `assert_trap` in the `.wast` scripts.
+    pub const fn trap_message(&self) -> Option<&'static str> {
+        match self {
+            Self::Unreachable => Some("unreachable"),
+            Self::DivideByZero => Some("integer divide by zero"),
+            Self::IntegerOverflow => Some("integer overflow"),
+            Self::InvalidConversionToInteger => Some("invalid conversion to integer"),
+            Self::OutOfBounds => Some("out of bounds memory access"),
+            Self::TableOutOfBounds => Some("out of bounds table access"),
+            Self::UndefinedElement => Some("undefined element"),
+            Self::UninitializedElement => Some("uninitialized element"),
+            Self::IndirectCallTypeMismatch => Some("indirect call type mismatch"),
+            _ => None,
+        }
+    }
+}
+
 /// A type that holds a WebAssembly primitive value with a type information tag.
-#[derive(Debug, Copy, Clone)]
+#[derive(Copy, Clone)]
 pub enum WasmValue {
     I32(i32),
     I64(i64),
     F32(f32),
     F64(f64),
+    /// A reference to a function, or `None` for a null reference
+    FuncRef(Option<usize>),
 }
 
 impl WasmValue {
@@ -1744,6 +3936,7 @@ impl WasmValue {
             WasmValType::I64 => Self::I64(0),
             WasmValType::F32 => Self::F32(0.0),
             WasmValType::F64 => Self::F64(0.0),
+            WasmValType::FuncRef => Self::FuncRef(None),
         }
     }
 
@@ -1754,6 +3947,7 @@ impl WasmValue {
             WasmValue::I64(_) => WasmValType::I64,
             WasmValue::F32(_) => WasmValType::F32,
             WasmValue::F64(_) => WasmValType::F64,
+            WasmValue::FuncRef(_) => WasmValType::FuncRef,
         }
     }
 
@@ -1764,10 +3958,29 @@ impl WasmValue {
             (Self::I64(_), WasmValType::I64) => true,
             (Self::F32(_), WasmValType::F32) => true,
             (Self::F64(_), WasmValType::F64) => true,
+            (Self::FuncRef(_), WasmValType::FuncRef) => true,
             _ => false,
         }
     }
 
+    /// Converts the value to a narrower type by truncation, as `i32.wrap_i64` and `f32.demote_f64` do.
+    ///
+    /// Returns `None` unless the value already has the type or is wider than it.
+    #[inline]
+    pub const fn truncate_to(self, val_type: WasmValType) -> Option<Self> {
+        match (self, val_type) {
+            (Self::I64(v), WasmValType::I32) => Some(Self::I32(v as i32)),
+            (Self::F64(v), WasmValType::F32) => Some(Self::F32(v as f32)),
+            _ => {
+                if self.is_valid_type(val_type) {
+                    Some(self)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
     #[inline]
     pub const fn get_i32(self) -> Result<i32, WasmRuntimeErrorKind> {
         match self {
@@ -1784,6 +3997,15 @@ impl WasmValue {
         }
     }
 
+    /// Reinterprets an `i32` value as `u32` and converts it to `usize`.
+    #[inline]
+    pub const fn as_usize(self) -> Result<usize, WasmRuntimeErrorKind> {
+        match self {
+            Self::I32(a) => Ok(a as u32 as usize),
+            _ => Err(WasmRuntimeErrorKind::TypeMismatch),
+        }
+    }
+
     #[inline]
     pub const fn get_i64(self) -> Result<i64, WasmRuntimeErrorKind> {
         match self {
@@ -1800,6 +4022,82 @@ impl WasmValue {
         }
     }
 
+    /// Splits an `i64` into its low and high 32-bit halves,
+    /// for hosts that cannot pass 64-bit integers (e.g. JavaScript without BigInt).
+    #[inline]
+    pub const fn into_raw_pair(self) -> Result<(i32, i32), WasmRuntimeErrorKind> {
+        match self {
+            Self::I64(a) => Ok((a as i32, (a >> 32) as i32)),
+            _ => Err(WasmRuntimeErrorKind::TypeMismatch),
+        }
+    }
+
+    /// Reconstructs an `i64` from the halves returned by [`WasmValue::into_raw_pair`].
+    #[inline]
+    pub const fn from_raw_pair(low: i32, high: i32) -> Self {
+        Self::I64(((high as i64) << 32) | (low as u32 as i64))
+    }
+
+    /// Encodes the value as little-endian bytes along with its type.
+    ///
+    /// `i32` and `f32` occupy the first 4 bytes and the rest are zero.
+    /// A null `funcref` is encoded as all ones.
+    pub const fn to_le_bytes(self) -> ([u8; 8], WasmValType) {
+        let mut bytes = [0; 8];
+        match self {
+            Self::I32(v) => {
+                let v = v.to_le_bytes();
+                bytes[0] = v[0];
+                bytes[1] = v[1];
+                bytes[2] = v[2];
+                bytes[3] = v[3];
+            }
+            Self::F32(v) => {
+                let v = v.to_bits().to_le_bytes();
+                bytes[0] = v[0];
+                bytes[1] = v[1];
+                bytes[2] = v[2];
+                bytes[3] = v[3];
+            }
+            Self::I64(v) => bytes = v.to_le_bytes(),
+            Self::F64(v) => bytes = v.to_bits().to_le_bytes(),
+            Self::FuncRef(Some(v)) => bytes = (v as u64).to_le_bytes(),
+            Self::FuncRef(None) => bytes = u64::MAX.to_le_bytes(),
+        }
+        (bytes, self.val_type())
+    }
+
+    /// Decodes a value encoded by [`WasmValue::to_le_bytes`].
+    ///
+    /// The slice must be exactly 4 bytes for `i32` and `f32` and 8 bytes otherwise,
+    /// or [`WasmRuntimeErrorKind::InvalidParameter`] is returned.
+    pub fn from_le_bytes(val_type: WasmValType, bytes: &[u8]) -> WasmResult<Self> {
+        let value = match val_type {
+            WasmValType::I32 => Self::I32(i32::from_le_bytes(Self::le_array(bytes)?)),
+            WasmValType::F32 => {
+                Self::F32(f32::from_bits(u32::from_le_bytes(Self::le_array(bytes)?)))
+            }
+            WasmValType::I64 => Self::I64(i64::from_le_bytes(Self::le_array(bytes)?)),
+            WasmValType::F64 => {
+                Self::F64(f64::from_bits(u64::from_le_bytes(Self::le_array(bytes)?)))
+            }
+            WasmValType::FuncRef => match u64::from_le_bytes(Self::le_array(bytes)?) {
+                u64::MAX => Self::FuncRef(None),
+                v => Self::FuncRef(Some(
+                    usize::try_from(v).map_err(|_| WasmRuntimeErrorKind::InvalidParameter)?,
+                )),
+            },
+        };
+        Ok(value)
+    }
+
+    #[inline]
+    fn le_array<const N: usize>(bytes: &[u8]) -> Result<[u8; N], WasmRuntimeErrorKind> {
+        bytes
+            .try_into()
+            .map_err(|_| WasmRuntimeErrorKind::InvalidParameter)
+    }
+
     #[inline]
     pub const fn get_f32(self) -> Result<f32, WasmRuntimeErrorKind> {
         match self {
@@ -1816,6 +4114,14 @@ impl WasmValue {
         }
     }
 
+    #[inline]
+    pub const fn get_func_ref(self) -> Result<Option<usize>, WasmRuntimeErrorKind> {
+        match self {
+            Self::FuncRef(a) => Ok(a),
+            _ => Err(WasmRuntimeErrorKind::TypeMismatch),
+        }
+    }
+
     #[inline]
     pub fn map_i32<F>(self, f: F) -> Result<WasmValue, WasmRuntimeErrorKind>
     where
@@ -1916,13 +4222,68 @@ impl From<bool> for WasmValue {
     }
 }
 
+impl WasmValue {
+    /// Writes NaN in the same notation as the text format, e.g. `-nan` or `nan:0x200000`
+    fn fmt_nan(
+        f: &mut fmt::Formatter<'_>,
+        is_negative: bool,
+        payload: u64,
+        canonical: u64,
+    ) -> fmt::Result {
+        if is_negative {
+            f.write_str("-")?;
+        }
+        if payload == canonical {
+            f.write_str("nan")
+        } else {
+            write!(f, "nan:{:#x}", payload)
+        }
+    }
+}
+
+/// Formats the value with its type, e.g. `i32:42` or `f64:-0`.
+///
+/// The alternate flag (`{:#}`) prints floating point numbers as raw bits in hexadecimal.
 impl fmt::Display for WasmValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.val_type())?;
         match *self {
             Self::I32(v) => write!(f, "{}", v),
             Self::I64(v) => write!(f, "{}", v),
-            Self::F32(v) => write!(f, "{}", v),
-            Self::F64(v) => write!(f, "{}", v),
+            Self::F32(v) => {
+                if f.alternate() {
+                    write!(f, "{:#010x}", v.to_bits())
+                } else if v.is_nan() {
+                    let payload = (v.to_bits() & 0x007F_FFFF) as u64;
+                    Self::fmt_nan(f, v.is_sign_negative(), payload, 0x0040_0000)
+                } else {
+                    write!(f, "{}", v)
+                }
+            }
+            Self::F64(v) => {
+                if f.alternate() {
+                    write!(f, "{:#018x}", v.to_bits())
+                } else if v.is_nan() {
+                    let payload = v.to_bits() & 0x000F_FFFF_FFFF_FFFF;
+                    Self::fmt_nan(f, v.is_sign_negative(), payload, 0x0008_0000_0000_0000)
+                } else {
+                    write!(f, "{}", v)
+                }
+            }
+            Self::FuncRef(Some(v)) => write!(f, "{}", v),
+            Self::FuncRef(None) => f.write_str("null"),
+        }
+    }
+}
+
+impl fmt::Debug for WasmValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::I32(v) => write!(f, "I32({})", v),
+            Self::I64(v) => write!(f, "I64({})", v),
+            Self::F32(v) => write!(f, "F32({:?} [{:#010x}])", v, v.to_bits()),
+            Self::F64(v) => write!(f, "F64({:?} [{:#018x}])", v, v.to_bits()),
+            Self::FuncRef(v) => write!(f, "FuncRef({:?})", v),
         }
     }
 }
@@ -1952,6 +4313,15 @@ impl WasmUnionValue {
         Self { u64: 0 }
     }
 
+    /// Returns the default value of a local of `val_type`, which is null for references.
+    #[inline]
+    pub const fn default_for(val_type: WasmValType) -> Self {
+        match val_type {
+            WasmValType::FuncRef => Self::from_func_ref(None),
+            _ => Self::zero(),
+        }
+    }
+
     #[inline]
     pub const fn from_bool(v: bool) -> Self {
         if v {
@@ -1996,6 +4366,14 @@ impl WasmUnionValue {
         Self { f64: v }
     }
 
+    #[inline]
+    pub const fn from_func_ref(v: Option<usize>) -> Self {
+        match v {
+            Some(v) => Self::from_usize(v),
+            None => Self::from_usize(WasmTable::NULL_REF),
+        }
+    }
+
     #[inline]
     pub unsafe fn get_bool(&self) -> bool {
         unsafe { self.i32 != 0 }
@@ -2031,6 +4409,17 @@ impl WasmUnionValue {
         unsafe { self.u64 }
     }
 
+    /// # Safety
+    ///
+    /// The value must have been stored as a function reference.
+    #[inline]
+    pub unsafe fn get_func_ref(&self) -> Option<usize> {
+        match unsafe { self.usize } {
+            WasmTable::NULL_REF => None,
+            v => Some(v),
+        }
+    }
+
     #[inline]
     pub fn write_i64(&mut self, val: i64) {
         *self = Self::from(val);
@@ -2142,6 +4531,7 @@ impl WasmUnionValue {
             WasmValType::I64 => WasmValue::I64(unsafe { self.get_i64() }),
             WasmValType::F32 => WasmValue::F32(unsafe { self.get_f32() }),
             WasmValType::F64 => WasmValue::F64(unsafe { self.get_f64() }),
+            WasmValType::FuncRef => WasmValue::FuncRef(unsafe { self.get_func_ref() }),
         }
     }
 
@@ -2219,6 +4609,7 @@ impl From<WasmValue> for WasmUnionValue {
             WasmValue::I64(v) => Self::from_i64(v),
             WasmValue::F32(v) => Self::from_f32(v),
             WasmValue::F64(v) => Self::from_f64(v),
+            WasmValue::FuncRef(v) => Self::from_func_ref(v),
         }
     }
 }
@@ -2333,6 +4724,25 @@ impl WasmArgs<'_> {
             .map(|v| unsafe { UnsafeInto::unsafe_into(*v) })
             .ok_or(WasmRuntimeErrorKind::InvalidParameter.into())
     }
+
+    /// Returns the next argument without consuming it.
+    #[inline]
+    pub fn peek<U>(&self) -> WasmResult<U>
+    where
+        WasmUnionValue: UnsafeInto<U>,
+    {
+        self.iter
+            .as_slice()
+            .first()
+            .map(|v| unsafe { UnsafeInto::unsafe_into(*v) })
+            .ok_or(WasmRuntimeErrorKind::InvalidParameter.into())
+    }
+
+    /// Returns the number of arguments not consumed yet.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.iter.len()
+    }
 }
 
 /// WebAssembly global variable
@@ -2340,20 +4750,32 @@ pub struct WasmGlobal {
     data: AtomicU64,
     val_type: WasmValType,
     is_mutable: bool,
+    initial_value: WasmValue,
+    /// The global read by the initializer expression
+    init_global: Option<usize>,
 }
 
 impl WasmGlobal {
     #[inline]
     pub fn new(val: WasmValue, is_mutable: bool) -> Result<Self, WasmCompileErrorKind> {
         let val_type = val.val_type();
+        let initial_value = val;
         let val = WasmUnionValue::from(val);
         Ok(Self {
             data: AtomicU64::new(unsafe { val.get_u64() }),
             val_type,
             is_mutable,
+            initial_value,
+            init_global: None,
         })
     }
 
+    /// Sets both the current and the initial value, at instantiation.
+    fn initialize(&mut self, val: WasmValue) {
+        self.initial_value = val;
+        self.set_raw_value(val.into());
+    }
+
     #[inline]
     pub fn raw_value(&self) -> WasmUnionValue {
         unsafe { transmute(self.data.load(Ordering::Relaxed)) }
@@ -2369,6 +4791,21 @@ impl WasmGlobal {
         self.data.store(unsafe { transmute(val) }, Ordering::SeqCst);
     }
 
+    /// Sets the value of a mutable global.
+    ///
+    /// Immutable globals are [`WasmRuntimeErrorKind::InvalidParameter`],
+    /// and values of another type are [`WasmRuntimeErrorKind::TypeMismatch`].
+    pub fn set_value(&self, val: WasmValue) -> Result<(), WasmRuntimeErrorKind> {
+        if !self.is_mutable {
+            return Err(WasmRuntimeErrorKind::InvalidParameter);
+        }
+        if !val.is_valid_type(self.val_type) {
+            return Err(WasmRuntimeErrorKind::TypeMismatch);
+        }
+        self.set_raw_value(val.into());
+        Ok(())
+    }
+
     #[inline]
     pub const fn val_type(&self) -> WasmValType {
         self.val_type
@@ -2378,6 +4815,12 @@ impl WasmGlobal {
     pub const fn is_mutable(&self) -> bool {
         self.is_mutable
     }
+
+    /// Returns the value evaluated from the initializer expression.
+    #[inline]
+    pub const fn initial_value(&self) -> WasmValue {
+        self.initial_value
+    }
 }
 
 /// WebAssembly name section
@@ -2501,12 +4944,17 @@ impl WasmNameSubsectionType {
 pub struct WasmRunnable<'a> {
     function: &'a WasmFunction,
     instance: &'a WasmInstance,
+    coerce_args: bool,
 }
 
 impl<'a> WasmRunnable<'a> {
     #[inline]
     const fn new(function: &'a WasmFunction, instance: &'a WasmInstance) -> Self {
-        Self { function, instance }
+        Self {
+            function,
+            instance,
+            coerce_args: false,
+        }
     }
 }
 
@@ -2520,6 +4968,28 @@ impl WasmRunnable<'_> {
     pub const fn instance(&self) -> &WasmInstance {
         &self.instance
     }
+
+    /// The resolved type of the function, used to validate the arguments.
+    #[inline]
+    pub const fn func_type(&self) -> &WasmType {
+        self.function.func_type()
+    }
+
+    /// Accepts arguments wider than the parameter types by truncating them.
+    ///
+    /// `I64` arguments are wrapped to `i32` and `F64` arguments are rounded to `f32`.
+    /// By default, an argument of any other type than the parameter is rejected
+    /// with [`WasmRuntimeErrorKind::TypeMismatch`].
+    #[inline]
+    pub const fn with_arg_coercion(mut self) -> Self {
+        self.coerce_args = true;
+        self
+    }
+
+    #[inline]
+    pub const fn coerces_args(&self) -> bool {
+        self.coerce_args
+    }
 }
 
 impl fmt::Debug for WasmRunnable<'_> {
@@ -2529,7 +4999,16 @@ impl fmt::Debug for WasmRunnable<'_> {
 }
 
 pub trait WasmInvocation {
+    /// Invokes the function and returns its first result, if any.
     fn invoke(&self, params: &[WasmValue]) -> Result<Option<WasmValue>, Box<dyn Error>>;
+
+    /// Invokes the function and returns all of its results in order.
+    ///
+    /// The default implementation returns only the result of [`WasmInvocation::invoke`],
+    /// so implementors of functions with multiple results should override it.
+    fn invoke_multi(&self, params: &[WasmValue]) -> Result<Vec<WasmValue>, Box<dyn Error>> {
+        self.invoke(params).map(|v| v.into_iter().collect())
+    }
 }
 
 #[repr(transparent)]
@@ -2630,6 +5109,17 @@ impl<'a> ReadLeb128<'a, BrTableVec> for Leb128Reader<'_> {
     }
 }
 
+impl WriteLeb128<&BrTableVec> for Leb128Writer {
+    fn write(&mut self, value: &BrTableVec) -> Result<(), WriteError> {
+        let table_len = value.len().saturating_sub(1);
+        self.write(table_len)?;
+        for br in value.iter() {
+            self.write(*br)?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Debug for BrTableVec {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("BrTable").finish()
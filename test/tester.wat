@@ -946,16 +946,6 @@
   (func $test_unary_f32 (export "test_unary_f32") (param $fval f32) (param $i32val i32) (param $u32val i32) (param $i64val i64) (param $u64val i64) (result i32)
     (local $p i32)
 
-    i32.const 0x10
-    local.get $fval
-    i32.trunc_f32_s
-    i32.store
-
-    i32.const 0x14
-    local.get $fval
-    i32.trunc_f32_u
-    i32.store
-
     i32.const 0x18
     local.get $fval
     i32.trunc_sat_f32_s
@@ -966,16 +956,6 @@
     i32.trunc_sat_f32_u
     i32.store
 
-    i32.const 0x20
-    local.get $fval
-    i64.trunc_f32_s
-    i64.store
-
-    i32.const 0x28
-    local.get $fval
-    i64.trunc_f32_u
-    i64.store
-
     i32.const 0x30
     local.get $fval
     i64.trunc_sat_f32_s
@@ -1194,16 +1174,6 @@
   (func $test_unary_f64 (export "test_unary_f64") (param $fval f64) (param $i32val i32) (param $u32val i32) (param $i64val i64) (param $u64val i64) (result i32)
     (local $p i32)
 
-    i32.const 0x10
-    local.get $fval
-    i32.trunc_f64_s
-    i32.store
-
-    i32.const 0x14
-    local.get $fval
-    i32.trunc_f64_u
-    i32.store
-
     i32.const 0x18
     local.get $fval
     i32.trunc_sat_f64_s
@@ -1214,16 +1184,6 @@
     i32.trunc_sat_f64_u
     i32.store
 
-    i32.const 0x20
-    local.get $fval
-    i64.trunc_f64_s
-    i64.store
-
-    i32.const 0x28
-    local.get $fval
-    i64.trunc_f64_u
-    i64.store
-
     i32.const 0x30
     local.get $fval
     i64.trunc_sat_f64_s